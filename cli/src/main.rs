@@ -0,0 +1,455 @@
+//! Command-line client for scripts and cron, it works with the same database as the GUI.
+//!
+//! Commands act as the user of `--user` (`root` by default) with the password of `--password`
+//! or of the environment variable `SED_BAD_PASSWORD`, the output is a table or JSON with
+//! `--json`. See `USAGE` for the commands.
+
+extern crate chrono;
+extern crate rustc_serialize;
+extern crate sed_bad_core;
+
+mod table;
+
+use chrono::Datelike;
+use rustc_serialize::json::Json;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+
+use sed_bad_core::{Db, Storage, DbError, User, Category, Document, Comment};
+use sed_bad_core::json;
+use sed_bad_core::query::{Query, Context};
+use sed_bad_core::saved_search::SavedSearch;
+
+use table::Table;
+
+const USAGE: &'static str = "\
+Usage: sed_bad-cli [--json] [--user <name>] [--password <password>] [--database <path>]
+                   <command>
+
+The password is taken from SED_BAD_PASSWORD without --password.
+
+Commands:
+    ticket create --name <name> --category <category> --due <YYYY-MM-DD>
+                  [--responsible <user>] [--comment <text>] [--file <path>]
+    ticket list [--view <inbox|outbox|all|calendar|saved search>] [--query <query>]
+                [--limit <n>] [--offset <n>]
+    ticket show <id>
+    ticket set-status <id> <beginning|in-progress|complete>
+    comment add <ticket id> <text> [--reply-to <comment id>]
+    user add <name> <password>
+    user list
+    category add <name>
+    category list";
+
+/// Result of a command as JSON and as text for the terminal.
+struct Output {
+    json: Json,
+    text: String,
+}
+
+struct Options {
+    json: bool,
+    user: String,
+    password: Option<String>,
+    database: Option<String>,
+    command: Vec<String>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let res = parse_options(&args).and_then(|options| {
+        let output = run(&options)?;
+        if options.json {
+            println!("{}", output.json.pretty());
+        } else {
+            print!("{}", output.text);
+        }
+        Ok(())
+    });
+    if let Err(e) = res {
+        let _ = writeln!(io::stderr(), "{}", e);
+        process::exit(1);
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        json: false,
+        user: "root".to_string(),
+        password: env::var("SED_BAD_PASSWORD").ok(),
+        database: None,
+        command: Vec::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--json" => options.json = true,
+            "--user" => options.user = args.next().cloned().ok_or_else(|| USAGE.to_string())?,
+            "--password" => {
+                options.password = Some(args.next().cloned().ok_or_else(|| USAGE.to_string())?)
+            }
+            "--database" => {
+                options.database = Some(args.next().cloned().ok_or_else(|| USAGE.to_string())?)
+            }
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            _ => {
+                options.command.push(arg.clone());
+                options.command.extend(args.cloned());
+                break;
+            }
+        }
+    }
+    if options.password.is_none() {
+        return Err(format!("The password of `{}` is required.\n{}", options.user, USAGE));
+    }
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<Output, String> {
+    let db = match options.database {
+        Some(ref path) => Db::open(path),
+        None => Db::new(),
+    };
+    let db = db.and_then(|db| db.init_root())
+        .and_then(|db| db.init_saved_searches())
+        .map_err(|e| format!("Error of opening database.\n{}", e))?;
+    let password = options.password.as_ref().map(|password| password.as_str()).unwrap_or("");
+    let exists = db.user_exists(&User::new(options.user.as_str(), password))
+        .map_err(|e| error("Error of checking password", e))?;
+    if !exists {
+        return Err(format!("Wrong name or password of `{}`.", options.user));
+    }
+    let user = db.user_by_name(&options.user)
+        .map_err(|e| error(&format!("Unknown user `{}`", options.user), e))?;
+
+    let command: Vec<&str> = options.command.iter().map(|arg| arg.as_ref()).collect();
+    if command.len() < 2 {
+        return Err(USAGE.to_string());
+    }
+    let args = &command[2..];
+    match (command[0], command[1]) {
+        ("ticket", "create") => create_ticket(&db, &user, args),
+        ("ticket", "list") => list_tickets(&db, &user, args),
+        ("ticket", "show") => show_ticket(&db, &user, args),
+        ("ticket", "set-status") => set_status(&db, &user, args),
+        ("comment", "add") => add_comment(&db, &user, args),
+        ("user", "add") => add_user(&db, &user, args),
+        ("user", "list") => list_users(&db),
+        ("category", "add") => add_category(&db, args),
+        ("category", "list") => list_categories(&db),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn create_ticket(db: &Db, user: &User, args: &[&str]) -> Result<Output, String> {
+    let (mut name, mut category, mut due, mut responsible, mut comment, mut file) =
+        (None, None, None, None, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = Some(*args.next().ok_or_else(|| USAGE.to_string())?);
+        match *arg {
+            "--name" => name = value,
+            "--category" => category = value,
+            "--due" => due = value,
+            "--responsible" => responsible = value,
+            "--comment" => comment = value,
+            "--file" => file = value,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let (name, category, due) = match (name, category, due) {
+        (Some(name), Some(category), Some(due)) => (name, category, due),
+        _ => return Err(USAGE.to_string()),
+    };
+    let due = json::parse_date(due)
+        .ok_or_else(|| format!("Wrong date `{}`, YYYY-MM-DD expected.", due))?;
+    let category = match db.category_by_name(category) {
+        Ok(category) => category,
+        Err(DbError::NotFound) => {
+            let mut category = Category::new(category);
+            db.add_category(&mut category).map_err(|e| error("Error of adding category", e))?;
+            category
+        }
+        Err(e) => return Err(error("Error of getting category", e)),
+    };
+    let responsible = match responsible {
+        Some(name) => {
+            db.user_by_name(name)
+                .map_err(|e| error(&format!("Unknown responsible user `{}`", name), e))?
+        }
+        None => user.clone(),
+    };
+    let comment = comment.map(|text| Comment::new(user, text.to_string()));
+    let mut doc = Document::new(name.to_string(),
+                                user,
+                                category,
+                                responsible,
+                                (due.year() as u32, due.month(), due.day()),
+                                comment);
+    if let Some(path) = file {
+        let mut data = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|e| format!("Error of reading `{}`.\n{}", path, e))?;
+        doc.set_data(Some(data));
+    }
+    db.add_document(&mut doc).map_err(|e| error("Error of adding ticket", e))?;
+    Ok(Output {
+        json: json::document(&doc),
+        text: format!("Ticket {} created.\n", doc.id()),
+    })
+}
+
+fn list_tickets(db: &Db, user: &User, args: &[&str]) -> Result<Output, String> {
+    let (mut view, mut filter) = ("all", "");
+    let (mut limit, mut offset) = (-1, 0);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = *args.next().ok_or_else(|| USAGE.to_string())?;
+        match *arg {
+            "--view" => view = value,
+            "--query" => filter = value,
+            "--limit" => limit = value.parse().map_err(|_| USAGE.to_string())?,
+            "--offset" => offset = value.parse().map_err(|_| USAGE.to_string())?,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let searches = SavedSearch::get_for_user(db, user)
+        .map_err(|e| error("Error of getting saved searches", e))?;
+    let search = searches.iter()
+        .find(|search| search.name().to_lowercase() == view.to_lowercase())
+        .ok_or_else(|| format!("Unknown view `{}`.", view))?;
+    let mut query = search.to_query()
+        .map_err(|e| format!("Error of view `{}`.\n{}", search.name(), e))?;
+    query.extend(Query::parse(filter).map_err(|e| format!("Wrong query.\n{}", e))?);
+
+    let context = Context::new(user);
+    let summaries = db.list_documents(&query, &context, limit, offset)
+        .map_err(|e| error("Error of getting tickets", e))?;
+    let mut table = Table::new(&["ID", "Name", "Status", "Author", "Responsible", "Due"]);
+    for summary in &summaries {
+        table.push(vec![summary.id().to_string(),
+                        summary.name().to_string(),
+                        json::status_name(&summary.status()).to_string(),
+                        summary.author().to_string(),
+                        summary.responsible().to_string(),
+                        json::format_date(&summary.date_expired())]);
+    }
+    Ok(Output {
+        json: Json::Array(summaries.iter().map(json::summary).collect()),
+        text: table.to_string(),
+    })
+}
+
+fn show_ticket(db: &Db, user: &User, args: &[&str]) -> Result<Output, String> {
+    if args.len() != 1 {
+        return Err(USAGE.to_string());
+    }
+    let doc = readable_ticket(db, user, args[0])?;
+    let metadata = doc.metadata();
+    let mut text = format!("#{} {}\nStatus: {}\nCategory: {}\nAuthor: {}\nResponsible: {}\nDue: \
+                            {}\nModified: {}\n",
+                           doc.id(),
+                           doc.name(),
+                           json::status_name(&metadata.status()),
+                           metadata.category().name(),
+                           metadata.author().name(),
+                           doc.responsible().name(),
+                           json::format_date(&metadata.date_expired()),
+                           metadata.m_time().format("%Y-%m-%d %H:%M"));
+    if !doc.comments().is_empty() {
+        let mut table = Table::new(&["ID", "Author", "Time", "Comment"]);
+        for comment in doc.comments().iter().filter(|comment| !comment.is_deleted()) {
+            let id = match comment.parent_id() {
+                Some(parent_id) => format!("{} (re {})", comment.id(), parent_id),
+                None => comment.id().to_string(),
+            };
+            table.push(vec![id,
+                            comment.author().name().to_string(),
+                            comment.c_time().format("%Y-%m-%d %H:%M").to_string(),
+                            comment.text().replace('\n', " ")]);
+        }
+        text.push('\n');
+        text.push_str(&table.to_string());
+    }
+    Ok(Output {
+        json: json::document(&doc),
+        text: text,
+    })
+}
+
+fn set_status(db: &Db, user: &User, args: &[&str]) -> Result<Output, String> {
+    if args.len() != 2 {
+        return Err(USAGE.to_string());
+    }
+    let mut doc = readable_ticket(db, user, args[0])?;
+    if !user.is_access_grant(&doc) && doc.responsible() != user {
+        return Err(format!("Only the author or the responsible user may change ticket {}.",
+                           doc.id()));
+    }
+    let status = json::parse_status(args[1])
+        .ok_or_else(|| format!("Wrong status `{}`.\n{}", args[1], USAGE))?;
+    doc.metadata_mut().set_status(status);
+    // The version is checked, so the status doesn't overwrite changes saved meanwhile.
+    db.update_document(&doc, user).map_err(|e| error("Error of updating ticket", e))?;
+    // With the new version.
+    let doc = db.document_by_id(doc.id()).map_err(|e| error("Error of getting ticket", e))?;
+    Ok(Output {
+        json: json::document(&doc),
+        text: format!("Ticket {} is {}.\n", doc.id(), args[1]),
+    })
+}
+
+fn add_comment(db: &Db, user: &User, args: &[&str]) -> Result<Output, String> {
+    let (ticket, text, reply_to) = match args.len() {
+        2 => (args[0], args[1], None),
+        4 if args[2] == "--reply-to" => {
+            (args[0], args[1], Some(args[3].parse().map_err(|_| USAGE.to_string())?))
+        }
+        _ => return Err(USAGE.to_string()),
+    };
+    let doc = readable_ticket(db, user, ticket)?;
+    if !user.is_comment_grant(&doc) {
+        return Err(format!("You may not comment ticket {}.", doc.id()));
+    }
+    let mut comment = match reply_to {
+        Some(parent_id) => {
+            if !doc.comments().iter().any(|comment| comment.id() == parent_id) {
+                return Err(format!("Ticket {} has no comment {}.", doc.id(), parent_id));
+            }
+            Comment::new_reply(user, text.to_string(), parent_id)
+        }
+        None => Comment::new(user, text.to_string()),
+    };
+    db.add_comment(doc.id(), &mut comment).map_err(|e| error("Error of adding comment", e))?;
+    Ok(Output {
+        json: json::comment(&comment),
+        text: format!("Comment {} added.\n", comment.id()),
+    })
+}
+
+fn add_user(db: &Db, user: &User, args: &[&str]) -> Result<Output, String> {
+    if args.len() != 2 {
+        return Err(USAGE.to_string());
+    }
+    if !user.is_root() {
+        return Err("Only root may add users.".to_string());
+    }
+    if db.user_by_name(args[0]).is_ok() {
+        return Err(format!("User `{}` already exists.", args[0]));
+    }
+    let mut new_user = User::new(args[0], args[1]);
+    db.add_user(&mut new_user).map_err(|e| error("Error of adding user", e))?;
+    Ok(Output {
+        json: json::user(&new_user),
+        text: format!("User {} added.\n", new_user.id()),
+    })
+}
+
+fn list_users(db: &Db) -> Result<Output, String> {
+    let users = db.users().map_err(|e| error("Error of getting users", e))?;
+    let mut table = Table::new(&["ID", "Name"]);
+    for user in &users {
+        table.push(vec![user.id().to_string(), user.name().to_string()]);
+    }
+    Ok(Output {
+        json: Json::Array(users.iter().map(json::user).collect()),
+        text: table.to_string(),
+    })
+}
+
+fn add_category(db: &Db, args: &[&str]) -> Result<Output, String> {
+    if args.len() != 1 {
+        return Err(USAGE.to_string());
+    }
+    if db.category_by_name(args[0]).is_ok() {
+        return Err(format!("Category `{}` already exists.", args[0]));
+    }
+    let mut category = Category::new(args[0]);
+    db.add_category(&mut category).map_err(|e| error("Error of adding category", e))?;
+    Ok(Output {
+        json: json::category(&category),
+        text: format!("Category {} added.\n", category.id()),
+    })
+}
+
+fn list_categories(db: &Db) -> Result<Output, String> {
+    let categories = db.categories().map_err(|e| error("Error of getting categories", e))?;
+    let mut table = Table::new(&["ID", "Name"]);
+    for category in &categories {
+        table.push(vec![category.id().to_string(), category.name().to_string()]);
+    }
+    Ok(Output {
+        json: Json::Array(categories.iter().map(json::category).collect()),
+        text: table.to_string(),
+    })
+}
+
+fn readable_ticket(db: &Db, user: &User, id: &str) -> Result<Document, String> {
+    let id: i64 = id.parse().map_err(|_| format!("Wrong ticket id `{}`.", id))?;
+    let doc = match db.document_by_id(id) {
+        Ok(doc) => doc,
+        Err(DbError::NotFound) => return Err(format!("Ticket {} not found.", id)),
+        Err(e) => return Err(error("Error of getting ticket", e)),
+    };
+    if !user.is_read_grant(&doc) {
+        return Err(format!("You may not read ticket {}.", id));
+    }
+    Ok(doc)
+}
+
+fn error(context: &str, err: DbError) -> String {
+    format!("{}.\n{}", context, err)
+}
+
+#[test]
+fn script_tickets() {
+    use sed_bad_core::temp_dir::TempDir;
+
+    let dir = TempDir::new("cli-test").unwrap();
+    let path = dir.join("db.sqlite3");
+    let run_as = |user: &str, command: &str| {
+        let password = if user == "root" { "toor" } else { "secret" };
+        let mut args = vec!["--user".to_string(),
+                            user.to_string(),
+                            "--password".to_string(),
+                            password.to_string(),
+                            "--database".to_string(),
+                            path.to_string_lossy().into_owned()];
+        args.extend(command.split('|').map(|arg| arg.to_string()));
+        run(&parse_options(&args).unwrap())
+    };
+
+    run_as("root", "user|add|ivan|secret").unwrap();
+    assert!(run_as("ivan", "user|add|petr|secret").is_err());
+    assert!(run_as("petr", "user|list").unwrap_err().contains("Wrong name or password"));
+    let args = ["--password".to_string(), "toor".to_string(), "--database".to_string()];
+    assert!(parse_options(&args).unwrap_err().starts_with("Usage"));
+    let output = run_as("root",
+                        "ticket|create|--name|Contract|--category|Contracts|--due|2017-03-08|\
+                         --responsible|ivan|--comment|Sign it")
+        .unwrap();
+    assert_eq!(output.text, "Ticket 1 created.\n");
+    let output = run_as("ivan", "ticket|list|--view|outbox").unwrap();
+    assert_eq!(output.json.as_array().unwrap().len(), 1);
+    assert!(output.text.contains("Contract  beginning  root"));
+    let output = run_as("ivan", "ticket|list|--view|inbox").unwrap();
+    assert!(output.json.as_array().unwrap().is_empty());
+    let stale = Db::open(&path).unwrap().document_by_id(1).unwrap();
+    let output = run_as("ivan", "ticket|set-status|1|complete").unwrap();
+    assert_eq!(output.json.find("version").unwrap().as_i64(), Some(stale.version() + 1));
+    // The dialog opened before the status change doesn't overwrite it.
+    let root = Db::open(&path).unwrap().user_by_name("root").unwrap();
+    match Db::open(&path).unwrap().update_document(&stale, &root) {
+        Err(DbError::Conflict) => {}
+        res => panic!("{:?}", res),
+    }
+    run_as("ivan", "comment|add|1|Signed|--reply-to|1").unwrap();
+    let output = run_as("root", "ticket|show|1").unwrap();
+    assert_eq!(output.json.find("status").unwrap().as_string(), Some("complete"));
+    assert!(output.text.contains("2 (re 1)  ivan"));
+    assert!(run_as("root", "ticket|show|2").unwrap_err().contains("not found"));
+}
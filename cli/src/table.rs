@@ -0,0 +1,63 @@
+//! Plain text tables for the terminal.
+
+use std::fmt;
+
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Self {
+        Table {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    fn widths(&self) -> Vec<usize> {
+        let mut widths: Vec<usize> =
+            self.headers.iter().map(|header| header.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        widths
+    }
+}
+
+/// Columns are separated by two spaces, the last one is not padded.
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let widths = self.widths();
+        for row in Some(&self.headers).into_iter().chain(&self.rows) {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                if i + 1 == row.len() {
+                    line.push_str(cell);
+                } else {
+                    line.push_str(cell);
+                    for _ in cell.chars().count()..widths[i] + 2 {
+                        line.push(' ');
+                    }
+                }
+            }
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn format_table() {
+    let mut table = Table::new(&["ID", "Name", "Status"]);
+    table.push(vec!["1".to_string(), "Договор".to_string(), "complete".to_string()]);
+    table.push(vec!["12".to_string(), "Report".to_string(), "beginning".to_string()]);
+    assert_eq!(table.to_string(),
+               "ID  Name     Status\n1   Договор  complete\n12  Report   beginning\n");
+}
@@ -0,0 +1,201 @@
+//! Annotations ("red pencil"): comments anchored to a part of the content of a document.
+//!
+//! An anchor is a range of characters of the text content, which keeps the quoted text, or a
+//! rectangle on the image content in pixels. When the text is changed, anchors are moved to the
+//! nearest occurrence of their quoted text, an anchor is orphaned if the text is gone.
+
+use db::Db;
+use errors::DbResult;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Anchor {
+    /// Range of characters `start..end` and its text.
+    Text {
+        start: i64,
+        end: i64,
+        quote: String,
+    },
+    Rect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+}
+
+impl Anchor {
+    /// Anchor to the range of characters of the text.
+    pub fn new_text(text: &str, start: i64, end: i64) -> Self {
+        Anchor::Text {
+            start: start,
+            end: end,
+            quote: text.chars().skip(start as usize).take((end - start) as usize).collect(),
+        }
+    }
+
+    /// Find the quoted text in the changed text. Return the occurrence nearest to the old
+    /// position or `None` if the quoted text is gone. Rectangles stay as they are.
+    pub fn relocate(&self, text: &str) -> Option<Anchor> {
+        let (start, quote) = match *self {
+            Anchor::Text { start, ref quote, .. } => (start, quote),
+            Anchor::Rect { .. } => return Some(self.clone()),
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let quote_chars: Vec<char> = quote.chars().collect();
+        if quote_chars.is_empty() || quote_chars.len() > chars.len() {
+            return None;
+        }
+        let len = quote_chars.len() as i64;
+        (0..chars.len() - quote_chars.len() + 1)
+            .filter(|&i| chars[i..i + quote_chars.len()] == quote_chars[..])
+            .map(|i| i as i64)
+            .min_by_key(|&i| (i - start).abs())
+            .map(|i| {
+                Anchor::Text {
+                    start: i,
+                    end: i + len,
+                    quote: quote.clone(),
+                }
+            })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Annotation {
+    comment_id: i64,
+    anchor: Anchor,
+    // The quoted text is not found in the content anymore.
+    orphaned: bool,
+}
+
+impl Annotation {
+    pub fn new(comment_id: i64, anchor: Anchor) -> Self {
+        Annotation {
+            comment_id: comment_id,
+            anchor: anchor,
+            orphaned: false,
+        }
+    }
+
+    pub fn comment_id(&self) -> i64 {
+        self.comment_id
+    }
+
+    pub fn anchor(&self) -> &Anchor {
+        &self.anchor
+    }
+
+    pub fn orphaned(&self) -> bool {
+        self.orphaned
+    }
+
+    pub fn save_to_db(&self, db: &Db) -> DbResult<()> {
+        match self.anchor {
+            Anchor::Text { start, end, ref quote } => {
+                db.conn()
+                    .execute("
+INSERT OR REPLACE INTO annotations (comment_id, anchor_start, anchor_end, quote, orphaned)
+VALUES (?, ?, ?, ?, ?);
+",
+                             &[&self.comment_id(), &start, &end, quote, &self.orphaned()])?;
+            }
+            Anchor::Rect { x, y, width, height } => {
+                db.conn()
+                    .execute("
+INSERT OR REPLACE INTO annotations (comment_id, x, y, width, height, orphaned)
+VALUES (?, ?, ?, ?, ?, ?);
+",
+                             &[&self.comment_id(), &x, &y, &width, &height, &self.orphaned()])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get annotations of comments of the document.
+    pub fn get_by_doc_id(db: &Db, doc_id: i64) -> DbResult<Vec<Annotation>> {
+        let mut annotations: Vec<Annotation> = Vec::new();
+        let mut stmt = db.conn()
+            .prepare("
+SELECT annotations.comment_id, anchor_start, anchor_end, quote, x, y, width, height, orphaned
+FROM annotations JOIN comments ON comments.id = annotations.comment_id
+WHERE comments.doc_id = ? AND comments.deleted = 0
+ORDER BY annotations.comment_id;
+")?;
+        let mut rows = stmt.query(&[&doc_id])?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            let quote: Option<String> = row.get_checked(3)?;
+            let anchor = match quote {
+                Some(quote) => {
+                    Anchor::Text {
+                        start: row.get_checked(1)?,
+                        end: row.get_checked(2)?,
+                        quote: quote,
+                    }
+                }
+                None => {
+                    Anchor::Rect {
+                        x: row.get_checked(4)?,
+                        y: row.get_checked(5)?,
+                        width: row.get_checked(6)?,
+                        height: row.get_checked(7)?,
+                    }
+                }
+            };
+            annotations.push(Annotation {
+                comment_id: row.get_checked(0)?,
+                anchor: anchor,
+                orphaned: row.get_checked(8)?,
+            });
+        }
+        Ok(annotations)
+    }
+
+    /// Move anchors of the document after changing its text. Orphaned anchors keep their last
+    /// position and become valid again when the quoted text comes back.
+    pub fn relocate_all(db: &Db, doc_id: i64, text: &str) -> DbResult<()> {
+        for mut annotation in Annotation::get_by_doc_id(db, doc_id)? {
+            match annotation.anchor.relocate(text) {
+                Some(anchor) => {
+                    annotation.anchor = anchor;
+                    annotation.orphaned = false;
+                }
+                None => annotation.orphaned = true,
+            }
+            annotation.save_to_db(db)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn relocate_anchor() {
+    let text = "Договор поставки. Срок поставки — 10 дней.";
+    let anchor = Anchor::new_text(text, 8, 16);
+    assert_eq!(anchor,
+               Anchor::Text {
+                   start: 8,
+                   end: 16,
+                   quote: "поставки".to_string(),
+               });
+
+    // The quoted text is moved, the nearest occurrence is chosen.
+    let changed = "Новый договор поставки. Срок поставки — 10 дней.";
+    assert_eq!(anchor.relocate(changed),
+               Some(Anchor::Text {
+                   start: 14,
+                   end: 22,
+                   quote: "поставки".to_string(),
+               }));
+
+    // The quoted text is removed.
+    assert_eq!(anchor.relocate("Договор. Срок — 10 дней."), None);
+
+    let rect = Anchor::Rect {
+        x: 1.0,
+        y: 2.0,
+        width: 3.0,
+        height: 4.0,
+    };
+    assert_eq!(rect.relocate(""), Some(rect.clone()));
+}
@@ -0,0 +1,400 @@
+//! Export of the whole database to a portable archive and import from it.
+//!
+//! The archive is a tar file with `manifest.json` and the content of documents and their
+//! revisions as separate files. The manifest holds the version of the archive format, the
+//! version of the schema and rows of tables, ids are kept as they are. Users are exported
+//! without passwords.
+//!
+//! The import restores the archive into a workspace without documents. Rows get new ids and
+//! references are remapped, users and categories which already exist (e.g. root) are matched by
+//! name, other users are created with passwords nobody knows, so root has to set them.
+//! Deadlines of archives of the schema without version are shifted like by the migration of the
+//! database.
+
+use chrono::{DateTime, UTC};
+use rusqlite::types::ToSql;
+use rustc_serialize::json::{Json, Object};
+use tar;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+
+use db::{Db, ZERO_BASED_MONTHS_VERSION, shift_deadlines};
+use user::User;
+use category::Category;
+use errors::{DbResult, DbError};
+
+/// Version of the archive format.
+pub const ARCHIVE_VERSION: i64 = 1;
+const MANIFEST: &'static str = "manifest.json";
+
+/// Kind of a column of an exported table.
+#[derive(Clone, Copy, Debug)]
+enum Column {
+    /// Id of the row, new ids are given on import.
+    Id,
+    /// Id of a row of the table.
+    Ref(&'static str),
+    Int,
+    Real,
+    Text,
+    Time,
+    /// Content, stored as a separate file.
+    Data,
+}
+
+/// Exported tables in the order of import, referenced tables go first.
+const TABLES: &'static [(&'static str, &'static [(&'static str, Column)])] =
+    &[("users", &[("id", Column::Id), ("name", Column::Text)]),
+      ("categories", &[("id", Column::Id), ("name", Column::Text)]),
+      ("metadata",
+       &[("id", Column::Id),
+         ("c_time", Column::Time),
+         ("m_time", Column::Time),
+         ("author_id", Column::Ref("users")),
+         ("category_id", Column::Ref("categories")),
+         ("status", Column::Int),
+         ("date_expired", Column::Time)]),
+      ("docs",
+       &[("id", Column::Id),
+         ("name", Column::Text),
+         ("metadata", Column::Ref("metadata")),
+         ("permission", Column::Int),
+         ("data", Column::Data),
+         ("responsible", Column::Ref("users"))]),
+      ("doc_revisions",
+       &[("id", Column::Id),
+         ("doc_id", Column::Ref("docs")),
+         ("data", Column::Data),
+         ("c_time", Column::Time),
+         ("author_id", Column::Ref("users"))]),
+      ("comments",
+       &[("id", Column::Id),
+         ("author_id", Column::Ref("users")),
+         ("text", Column::Text),
+         ("c_time", Column::Time),
+         ("doc_id", Column::Ref("docs")),
+         ("parent_id", Column::Ref("comments")),
+         ("resolved", Column::Int),
+         ("m_time", Column::Time),
+         ("deleted", Column::Int)]),
+      ("comment_revisions",
+       &[("id", Column::Id),
+         ("comment_id", Column::Ref("comments")),
+         ("text", Column::Text),
+         ("c_time", Column::Time),
+         ("editor_id", Column::Ref("users"))]),
+      ("annotations",
+       &[("comment_id", Column::Ref("comments")),
+         ("anchor_start", Column::Int),
+         ("anchor_end", Column::Int),
+         ("quote", Column::Text),
+         ("x", Column::Real),
+         ("y", Column::Real),
+         ("width", Column::Real),
+         ("height", Column::Real),
+         ("orphaned", Column::Int)])];
+
+fn invalid<S: Into<String>>(msg: S) -> DbError {
+    DbError::InvalidData(msg.into())
+}
+
+fn append_file<W: Write>(builder: &mut tar::Builder<W>, path: &str, data: &[u8]) -> DbResult<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_path(path)?;
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(UTC::now().timestamp() as u64);
+    header.set_cksum();
+    builder.append(&header, data)?;
+    Ok(())
+}
+
+/// Export all tables to the archive.
+pub fn export<W: Write>(db: &Db, writer: W) -> DbResult<()> {
+    let mut builder = tar::Builder::new(writer);
+    let mut tables = Object::new();
+    for &(table, columns) in TABLES {
+        let names: Vec<&str> = columns.iter().map(|&(name, _)| name).collect();
+        let mut stmt = db.conn()
+            .prepare(&format!("SELECT {} FROM {} ORDER BY rowid;", names.join(", "), table))?;
+        let mut rows = stmt.query(&[])?;
+        let mut exported: Vec<Json> = Vec::new();
+        while let Some(row) = rows.next() {
+            let row = row?;
+            let mut object = Object::new();
+            for (i, &(name, column)) in columns.iter().enumerate() {
+                let i = i as i32;
+                let value = match column {
+                    Column::Id | Column::Ref(_) | Column::Int => {
+                        row.get_checked::<i32, Option<i64>>(i)?.map(Json::I64)
+                    }
+                    Column::Real => row.get_checked::<i32, Option<f64>>(i)?.map(Json::F64),
+                    Column::Text => row.get_checked::<i32, Option<String>>(i)?.map(Json::String),
+                    Column::Time => {
+                        row.get_checked::<i32, Option<DateTime<UTC>>>(i)?
+                            .map(|time| Json::String(time.to_rfc3339()))
+                    }
+                    Column::Data => {
+                        match row.get_checked::<i32, Option<Vec<u8>>>(i)? {
+                            Some(data) => {
+                                let path = format!("{}/{}", table, exported.len() + 1);
+                                append_file(&mut builder, &path, &data)?;
+                                Some(Json::String(path))
+                            }
+                            None => None,
+                        }
+                    }
+                };
+                object.insert(name.to_string(), value.unwrap_or(Json::Null));
+            }
+            exported.push(Json::Object(object));
+        }
+        tables.insert(table.to_string(), Json::Array(exported));
+    }
+
+    let mut manifest = Object::new();
+    manifest.insert("format".to_string(), Json::String("sed_bad".to_string()));
+    manifest.insert("version".to_string(), Json::I64(ARCHIVE_VERSION));
+    manifest.insert("schema_version".to_string(), Json::I64(db.schema_version()?));
+    manifest.insert("created".to_string(), Json::String(UTC::now().to_rfc3339()));
+    manifest.insert("tables".to_string(), Json::Object(tables));
+    append_file(&mut builder,
+                MANIFEST,
+                Json::Object(manifest).pretty().to_string().as_bytes())?;
+    builder.finish()?;
+    Ok(())
+}
+
+/// Result of the import.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Tables with numbers of imported rows.
+    pub counts: Vec<(String, usize)>,
+    /// Users without passwords.
+    pub created_users: Vec<String>,
+}
+
+impl ImportSummary {
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for &(ref table, count) in &self.counts {
+            text.push_str(&format!("{}: {}\n", table, count));
+        }
+        if !self.created_users.is_empty() {
+            text.push_str(&format!("Created users (root has to set their passwords): {}\n",
+                                   self.created_users.join(", ")));
+        }
+        text
+    }
+}
+
+/// Value of the row for the column, references are remapped by `ids` and data are taken from
+/// `files`.
+fn to_sql(value: &Json,
+          column: Column,
+          ids: &HashMap<&str, HashMap<i64, i64>>,
+          files: &HashMap<String, Vec<u8>>)
+          -> Option<Box<ToSql>> {
+    if value.is_null() {
+        return Some(Box::new(None::<i64>));
+    }
+    match column {
+        Column::Id => None,
+        Column::Ref(table) => {
+            value.as_i64()
+                .and_then(|id| ids.get(table).and_then(|ids| ids.get(&id)))
+                .map(|&id| Box::new(id) as Box<ToSql>)
+        }
+        Column::Int => value.as_i64().map(|value| Box::new(value) as Box<ToSql>),
+        Column::Real => value.as_f64().map(|value| Box::new(value) as Box<ToSql>),
+        Column::Text => value.as_string().map(|value| Box::new(value.to_string()) as Box<ToSql>),
+        Column::Time => {
+            value.as_string()
+                .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+                .map(|time| Box::new(time.with_timezone(&UTC)) as Box<ToSql>)
+        }
+        Column::Data => {
+            value.as_string()
+                .and_then(|path| files.get(path))
+                .map(|data| Box::new(data.clone()) as Box<ToSql>)
+        }
+    }
+}
+
+/// Import the archive into the workspace without documents in one transaction.
+pub fn import<R: Read>(db: &Db, reader: R) -> DbResult<ImportSummary> {
+    let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        files.insert(path, data);
+    }
+    let manifest = files.get(MANIFEST)
+        .and_then(|data| String::from_utf8(data.clone()).ok())
+        .and_then(|text| Json::from_str(&text).ok())
+        .ok_or_else(|| invalid("No manifest in the archive."))?;
+    if manifest.find("format").and_then(|format| format.as_string()) != Some("sed_bad") {
+        return Err(invalid("It's not an archive of sed_bad."));
+    }
+    match manifest.find("version").and_then(|version| version.as_i64()) {
+        Some(version) if version <= ARCHIVE_VERSION => {}
+        _ => return Err(invalid("The archive is made by a newer version of sed_bad.")),
+    }
+    let schema_version = manifest.find("schema_version")
+        .and_then(|version| version.as_i64())
+        .unwrap_or(0);
+    let tables = manifest.find("tables")
+        .and_then(|tables| tables.as_object())
+        .ok_or_else(|| invalid("No tables in the manifest."))?;
+    let docs_count: i64 = db.conn()
+        .query_row("SELECT COUNT(*) FROM docs;", &[], |row| row.get(0))?;
+    if docs_count > 0 {
+        return Err(invalid("The archive can be imported only into a workspace without \
+                            documents."));
+    }
+
+    db.transaction(|db| {
+        let mut summary = ImportSummary::default();
+        let mut ids: HashMap<&str, HashMap<i64, i64>> = HashMap::new();
+        for &(table, columns) in TABLES {
+            let rows: &[Json] = match tables.get(table).and_then(|rows| rows.as_array()) {
+                Some(rows) => rows,
+                None => &[],
+            };
+            for row in rows {
+                let old_id = row.find("id").and_then(|id| id.as_i64());
+                let name = row.find("name").and_then(|name| name.as_string());
+                let new_id = match (table, name) {
+                    ("users", Some(name)) => {
+                        let (user, created) = User::get_or_create(db, name)?;
+                        if created {
+                            summary.created_users.push(name.to_string());
+                        }
+                        user.id()
+                    }
+                    ("categories", Some(name)) => {
+                        let mut category = Category::new(name);
+                        if category.exists(db)? {
+                            Category::get_category(db, name)?.id()
+                        } else {
+                            category.save_to_db(db)?
+                        }
+                    }
+                    ("users", None) | ("categories", None) => {
+                        return Err(invalid(format!("A row of `{}` without name.", table)));
+                    }
+                    _ => {
+                        let mut names: Vec<&str> = Vec::new();
+                        let mut params: Vec<Box<ToSql>> = Vec::new();
+                        for &(name, column) in columns {
+                            if let Column::Id = column {
+                                continue;
+                            }
+                            let value = row.find(name).unwrap_or(&Json::Null);
+                            let param = to_sql(value, column, &ids, &files).ok_or_else(|| {
+                                    invalid(format!("Wrong value of `{}.{}` in the archive.",
+                                                    table,
+                                                    name))
+                                })?;
+                            names.push(name);
+                            params.push(param);
+                        }
+                        let marks: Vec<&str> = names.iter().map(|_| "?").collect();
+                        let params: Vec<&ToSql> = params.iter().map(|param| &**param).collect();
+                        db.conn().execute(&format!("INSERT INTO {} ({}) VALUES ({});",
+                                             table,
+                                             names.join(", "),
+                                             marks.join(", ")),
+                                     &params)?;
+                        db.conn().last_insert_rowid()
+                    }
+                };
+                // Right away, since replies refer to earlier comments of the same table.
+                if let Some(old_id) = old_id {
+                    ids.entry(table).or_insert_with(HashMap::new).insert(old_id, new_id);
+                }
+            }
+            summary.counts.push((table.to_string(), rows.len()));
+        }
+        if schema_version == ZERO_BASED_MONTHS_VERSION {
+            shift_deadlines(db.conn())?;
+        }
+        Ok(summary)
+    })
+}
+
+/// Export from the command line, `args` follow the `export-archive` command.
+pub fn cli_export(args: &[String]) -> Result<String, String> {
+    if args.len() != 1 {
+        return Err("Usage: sed_bad export-archive FILE".to_string());
+    }
+    let db = Db::new().map_err(|e| format!("Error of opening database.\n{}", e))?;
+    File::create(&args[0])
+        .map_err(From::from)
+        .and_then(|file| export(&db, file))
+        .map_err(|e| format!("Error of exporting.\n{}", e))?;
+    Ok(String::new())
+}
+
+/// Import from the command line, `args` follow the `import-archive` command. Return the summary
+/// to be printed.
+pub fn cli_import(args: &[String]) -> Result<String, String> {
+    if args.len() != 1 {
+        return Err("Usage: sed_bad import-archive FILE".to_string());
+    }
+    let db = Db::new()
+        .and_then(|d| d.init_root())
+        .map_err(|e| format!("Error of opening database.\n{}", e))?;
+    let summary = File::open(&args[0])
+        .map_err(From::from)
+        .and_then(|file| import(&db, file))
+        .map_err(|e| format!("Error of importing, nothing was imported.\n{}", e))?;
+    Ok(summary.to_text())
+}
+
+#[test]
+fn export_and_import_archive() {
+    use comment::Comment;
+    use document::Document;
+    use temp_dir::TempDir;
+
+    let dir = TempDir::new("archive-test").unwrap();
+    let old_db = Db::open(dir.join("old.sqlite3")).and_then(|d| d.init_root()).unwrap();
+    let mut user = User::new("ivan", "secret");
+    user.save_to_db(&old_db).unwrap();
+    let mut category = Category::new("Contracts");
+    category.save_to_db(&old_db).unwrap();
+    let mut doc = Document::new("Contract".to_string(),
+                                &user,
+                                category,
+                                user.clone(),
+                                (2017, 3, 8),
+                                Some(Comment::new(&user, "Sign it".to_string())));
+    doc.set_data(Some(b"text".to_vec()));
+    let doc_id = doc.save_to_db(&old_db).unwrap();
+    Comment::new_reply(&user, "Signed".to_string(), doc.comments()[0].id())
+        .save_to_db(&old_db, doc_id)
+        .unwrap();
+    let mut archive: Vec<u8> = Vec::new();
+    export(&old_db, &mut archive).unwrap();
+
+    let new_db = Db::open(dir.join("new.sqlite3")).and_then(|d| d.init_root()).unwrap();
+    let summary = import(&new_db, &archive[..]).unwrap();
+    assert_eq!(summary.created_users, vec!["ivan".to_string()]);
+    let docs = Document::get_docs(&new_db).unwrap();
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].data(), Some(b"text".to_vec()));
+    let comments = docs[0].comments();
+    assert_eq!(comments.len(), 2);
+    assert_eq!(comments[0].text(), "Sign it");
+    assert_eq!(comments[1].parent_id(), Some(comments[0].id()));
+    assert_eq!(docs[0].metadata().author().name(), "ivan");
+    assert!(User::get_user(&new_db, "ivan").unwrap().pass() != "secret");
+    // Only into a workspace without documents.
+    assert!(import(&new_db, &archive[..]).is_err());
+}
@@ -0,0 +1,82 @@
+//! Audit log of actions which need a trace, e.g. breaking locks of other users.
+//!
+//! Entries keep the name of the user, so they outlive the deleted users.
+
+use chrono::{DateTime, UTC};
+
+use db::Db;
+use errors::DbResult;
+
+#[derive(Clone, Debug)]
+pub struct AuditEntry {
+    id: i64,
+    c_time: DateTime<UTC>,
+    user: String,
+    action: String,
+    details: String,
+}
+
+impl AuditEntry {
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn c_time(&self) -> DateTime<UTC> {
+        self.c_time
+    }
+
+    /// Name of the user who did the action.
+    pub fn user(&self) -> &str {
+        self.user.as_ref()
+    }
+
+    /// Short name of the action, e.g. `break_lock`.
+    pub fn action(&self) -> &str {
+        self.action.as_ref()
+    }
+
+    pub fn details(&self) -> &str {
+        self.details.as_ref()
+    }
+
+    /// Entry of the action of the user now, which is not recorded, e.g. for storages without
+    /// the database.
+    pub fn new(id: i64, user: &str, action: &str, details: &str) -> AuditEntry {
+        AuditEntry {
+            id: id,
+            c_time: UTC::now(),
+            user: user.to_string(),
+            action: action.to_string(),
+            details: details.to_string(),
+        }
+    }
+
+    /// Record the action of the user now.
+    pub fn record(db: &Db, user: &str, action: &str, details: &str) -> DbResult<i64> {
+        let mut stmt = db.conn()
+            .prepare("
+INSERT INTO audit_log VALUES (NULL, ?, ?, ?, ?);
+")?;
+        stmt.insert(&[&UTC::now(), &user, &action, &details]).map_err(From::from)
+    }
+
+    /// Get entries of the log, the newest first.
+    pub fn get_all(db: &Db) -> DbResult<Vec<AuditEntry>> {
+        let mut entries: Vec<AuditEntry> = Vec::new();
+        let mut stmt = db.conn().prepare("
+SELECT id, c_time, user_name, action, details FROM audit_log ORDER BY id DESC;
+")?;
+        let mut rows = stmt.query(&[])?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            entries.push(AuditEntry {
+                id: row.get_checked(0)?,
+                c_time: row.get_checked(1)?,
+                user: row.get_checked(2)?,
+                action: row.get_checked(3)?,
+                details: row.get_checked(4)?,
+            });
+        }
+        Ok(entries)
+    }
+}
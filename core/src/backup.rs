@@ -0,0 +1,177 @@
+//! Backups of the live database.
+//!
+//! Backups are made by the SQLite online backup API into `db-<time>.sqlite3` files of the backup
+//! directory, every backup is checked by `PRAGMA integrity_check`, only the last ones are kept.
+//! The GUI makes a backup when the last one is older than `BACKUP_INTERVAL_HOURS`.
+
+use chrono::{UTC, Duration, TimeZone};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use db::Db;
+use errors::DbResult;
+
+/// Interval of scheduled backups.
+pub const BACKUP_INTERVAL_HOURS: i64 = 24;
+/// Number of kept backups.
+pub const BACKUPS_KEPT: usize = 7;
+const TIME_FORMAT: &'static str = "%Y%m%d-%H%M%S";
+
+/// Directory of backups near the database file.
+pub fn default_dir() -> DbResult<PathBuf> {
+    let db_path = Db::default_path()?;
+    let dir = db_path.parent().map(|dir| dir.join("backups")).unwrap_or_else(|| {
+        PathBuf::from("backups")
+    });
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Backups in the directory, the oldest first.
+pub fn list(dir: &Path) -> DbResult<Vec<PathBuf>> {
+    let mut backups: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_backup = path.file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.starts_with("db-") && name.ends_with(".sqlite3"))
+            .unwrap_or(false);
+        if is_backup {
+            backups.push(path);
+        }
+    }
+    // The time in names is sortable.
+    backups.sort();
+    Ok(backups)
+}
+
+/// Whether the last backup in the directory is older than the interval.
+pub fn is_due(dir: &Path) -> DbResult<bool> {
+    let last = list(dir)?
+        .last()
+        .and_then(|path| path.file_stem().and_then(|name| name.to_str()).map(String::from))
+        .and_then(|name| UTC.datetime_from_str(&name["db-".len()..], TIME_FORMAT).ok());
+    Ok(match last {
+        Some(time) => UTC::now() - time >= Duration::hours(BACKUP_INTERVAL_HOURS),
+        None => true,
+    })
+}
+
+/// Back up the database into the directory, check the backup and remove old ones except
+/// `keep` last. Return the path of the backup.
+pub fn backup(db: &Db, dir: &Path, keep: usize) -> DbResult<PathBuf> {
+    let path = dir.join(format!("db-{}.sqlite3", UTC::now().format(TIME_FORMAT)));
+    db.backup_to(&path)?;
+    if let Err(e) = Db::check_backup(&path) {
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+    let backups = list(dir)?;
+    if backups.len() > keep {
+        for old in &backups[..backups.len() - keep] {
+            fs::remove_file(old)?;
+        }
+    }
+    Ok(path)
+}
+
+/// Replace the content of the database by the backup after checking it.
+pub fn restore(db: &mut Db, path: &Path) -> DbResult<()> {
+    Db::check_backup(path)?;
+    db.restore_from(path)
+}
+
+const USAGE: &'static str = "\
+Usage: sed_bad backup [--dir DIRECTORY] [--keep N]
+       sed_bad backup --list [--dir DIRECTORY]
+       sed_bad restore FILE";
+
+/// Back up from the command line, `args` follow the `backup` command. Return paths of backups
+/// or of the new backup to be printed.
+pub fn cli_backup(args: &[String]) -> Result<String, String> {
+    let mut dir: Option<PathBuf> = None;
+    let mut keep = BACKUPS_KEPT;
+    let mut only_list = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--dir" => {
+                dir = Some(args.next().map(PathBuf::from).ok_or_else(|| USAGE.to_string())?);
+            }
+            "--keep" => {
+                keep = args.next()
+                    .and_then(|keep| keep.parse().ok())
+                    .and_then(|keep| if keep > 0 { Some(keep) } else { None })
+                    .ok_or_else(|| USAGE.to_string())?;
+            }
+            "--list" => only_list = true,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let dir = match dir {
+        Some(dir) => dir,
+        None => {
+            default_dir().map_err(|e| format!("Error of creating backup directory.\n{}", e))?
+        }
+    };
+
+    if only_list {
+        let mut text = String::new();
+        for path in list(&dir).map_err(|e| e.to_string())? {
+            text.push_str(&format!("{}\n", path.display()));
+        }
+        return Ok(text);
+    }
+    let db = Db::new().map_err(|e| format!("Error of opening database.\n{}", e))?;
+    let path = backup(&db, &dir, keep).map_err(|e| format!("Error of backing up.\n{}", e))?;
+    Ok(format!("{}\n", path.display()))
+}
+
+/// Restore from the command line, `args` follow the `restore` command.
+pub fn cli_restore(args: &[String]) -> Result<String, String> {
+    if args.len() != 1 {
+        return Err(USAGE.to_string());
+    }
+    let mut db = Db::new().map_err(|e| format!("Error of opening database.\n{}", e))?;
+    restore(&mut db, Path::new(&args[0])).map_err(|e| format!("Error of restoring.\n{}", e))?;
+    Ok(String::new())
+}
+
+#[test]
+fn backup_rotate_and_restore() {
+    use std::fs::File;
+    use std::io::Write;
+    use category::Category;
+    use temp_dir::TempDir;
+
+    let temp_dir = TempDir::new("backup-test").unwrap();
+    let dir = temp_dir.path();
+    let mut db = Db::open(dir.join("live.sqlite3")).unwrap();
+
+    Category::new("Before").save_to_db(&db).unwrap();
+    let first = backup(&db, &dir, 2).unwrap();
+    assert!(!is_due(&dir).unwrap());
+    // Make the backup old, so the next one doesn't get the same name.
+    let old = dir.join("db-20000101-000000.sqlite3");
+    fs::rename(&first, &old).unwrap();
+    assert!(is_due(&dir).unwrap());
+
+    Category::new("After").save_to_db(&db).unwrap();
+    backup(&db, &dir, 2).unwrap();
+    assert_eq!(list(&dir).unwrap().len(), 2);
+    restore(&mut db, &old).unwrap();
+    let names: Vec<String> = Category::get_categories(&db)
+        .unwrap()
+        .iter()
+        .map(|category| category.name().to_string())
+        .collect();
+    assert_eq!(names, vec!["Before".to_string()]);
+
+    backup(&db, &dir, 1).unwrap();
+    assert_eq!(list(&dir).unwrap().len(), 1);
+
+    let broken = dir.join("broken.sqlite3");
+    File::create(&broken).unwrap().write_all(b"not a database").unwrap();
+    assert!(restore(&mut db, &broken).is_err());
+}
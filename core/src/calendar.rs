@@ -0,0 +1,120 @@
+//! Deadlines of documents on the calendar.
+//!
+//! Periods of the calendar (day, week, month) and the state of a deadline.
+
+use chrono::{DateTime, UTC, TimeZone, Datelike, Duration};
+
+use metadata::Status;
+
+/// Period of the calendar around the selected date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Span {
+    Day,
+    Week,
+    Month,
+}
+
+impl Span {
+    pub fn get_num(&self) -> i64 {
+        match *self {
+            Span::Day => 0,
+            Span::Week => 1,
+            Span::Month => 2,
+        }
+    }
+
+    pub fn from_num(num: i64) -> Span {
+        match num {
+            1 => Span::Week,
+            2 => Span::Month,
+            _ => Span::Day,
+        }
+    }
+
+    /// Start (inclusive) and end (exclusive) of the period containing the date. Weeks start on
+    /// Monday.
+    pub fn bounds(&self, date: DateTime<UTC>) -> (DateTime<UTC>, DateTime<UTC>) {
+        let day = UTC.ymd(date.year(), date.month(), date.day()).and_hms(0, 0, 0);
+        match *self {
+            Span::Day => (day, day + Duration::days(1)),
+            Span::Week => {
+                let start = day - Duration::days(day.weekday().num_days_from_monday() as i64);
+                (start, start + Duration::days(7))
+            }
+            Span::Month => {
+                let start = UTC.ymd(date.year(), date.month(), 1).and_hms(0, 0, 0);
+                let end = if date.month() == 12 {
+                    UTC.ymd(date.year() + 1, 1, 1).and_hms(0, 0, 0)
+                } else {
+                    UTC.ymd(date.year(), date.month() + 1, 1).and_hms(0, 0, 0)
+                };
+                (start, end)
+            }
+        }
+    }
+}
+
+/// The same day and time of the next month, or the last day of the next month if it's shorter.
+pub fn next_month(date: DateTime<UTC>) -> DateTime<UTC> {
+    let (_, start) = Span::Month.bounds(date);
+    let (_, end) = Span::Month.bounds(start);
+    let last_day = (end - start).num_days() as u32;
+    let day = if date.day() < last_day { date.day() } else { last_day };
+    UTC.ymd(start.year(), start.month(), day).and_time(date.time()).unwrap()
+}
+
+/// State of the deadline of a document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeadlineState {
+    Complete,
+    Overdue,
+    InProgress,
+    Beginning,
+}
+
+impl DeadlineState {
+    pub fn new(status: &Status, date_expired: DateTime<UTC>, now: DateTime<UTC>) -> Self {
+        match *status {
+            Status::Complete => DeadlineState::Complete,
+            // The document is due until the end of the day.
+            _ if date_expired + Duration::days(1) <= now => DeadlineState::Overdue,
+            Status::InProgress => DeadlineState::InProgress,
+            Status::Beginning => DeadlineState::Beginning,
+        }
+    }
+}
+
+#[test]
+fn span_bounds() {
+    // Wednesday.
+    let date = UTC.ymd(2016, 12, 28).and_hms(15, 30, 0);
+    assert_eq!(Span::Day.bounds(date),
+               (UTC.ymd(2016, 12, 28).and_hms(0, 0, 0), UTC.ymd(2016, 12, 29).and_hms(0, 0, 0)));
+    assert_eq!(Span::Week.bounds(date),
+               (UTC.ymd(2016, 12, 26).and_hms(0, 0, 0), UTC.ymd(2017, 1, 2).and_hms(0, 0, 0)));
+    assert_eq!(Span::Month.bounds(date),
+               (UTC.ymd(2016, 12, 1).and_hms(0, 0, 0), UTC.ymd(2017, 1, 1).and_hms(0, 0, 0)));
+}
+
+#[test]
+fn next_month_clamped() {
+    assert_eq!(next_month(UTC.ymd(2016, 1, 31).and_hms(10, 0, 0)),
+               UTC.ymd(2016, 2, 29).and_hms(10, 0, 0));
+    assert_eq!(next_month(UTC.ymd(2017, 1, 31).and_hms(0, 0, 0)),
+               UTC.ymd(2017, 2, 28).and_hms(0, 0, 0));
+    assert_eq!(next_month(UTC.ymd(2016, 12, 15).and_hms_milli(8, 30, 0, 250)),
+               UTC.ymd(2017, 1, 15).and_hms_milli(8, 30, 0, 250));
+}
+
+#[test]
+fn deadline_state() {
+    let due = UTC.ymd(2016, 12, 28).and_hms(0, 0, 0);
+    let same_day = UTC.ymd(2016, 12, 28).and_hms(18, 0, 0);
+    let next_day = UTC.ymd(2016, 12, 29).and_hms(0, 0, 0);
+    assert_eq!(DeadlineState::new(&Status::InProgress, due, same_day),
+               DeadlineState::InProgress);
+    assert_eq!(DeadlineState::new(&Status::Beginning, due, next_day),
+               DeadlineState::Overdue);
+    assert_eq!(DeadlineState::new(&Status::Complete, due, next_day),
+               DeadlineState::Complete);
+}
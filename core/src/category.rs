@@ -5,7 +5,7 @@
 use db::Db;
 use errors::DbResult;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Category {
     id: i64,
     name: String,
@@ -23,6 +23,11 @@ impl Category {
         self.id
     }
 
+    /// Set the id assigned by a storage.
+    pub fn set_id(&mut self, id: i64) {
+        self.id = id;
+    }
+
     pub fn name(&self) -> &str {
         self.name.as_ref()
     }
@@ -0,0 +1,346 @@
+//! Comment implementation.
+//!
+//! Comments form discussions: a comment without parent starts a topic, which may be marked
+//! resolved, other comments are replies.
+//!
+//! Editing or deleting a comment keeps the previous text as a revision, a deleted comment
+//! remains as a tombstone, so replies to it are not lost.
+
+use chrono::{DateTime, UTC};
+
+use user::User;
+use db::Db;
+use errors::DbResult;
+
+#[derive(Clone, Debug)]
+pub struct Comment {
+    id: i64,
+    author: User, // TODO Reference?
+    text: String,
+    c_time: DateTime<UTC>,
+    parent_id: Option<i64>,
+    resolved: bool,
+    m_time: Option<DateTime<UTC>>,
+    deleted: bool,
+}
+
+/// Previous text of a comment.
+#[derive(Debug)]
+pub struct CommentRevision {
+    id: i64,
+    text: String,
+    // Time of replacing the text.
+    c_time: DateTime<UTC>,
+    editor: Option<User>,
+}
+
+impl CommentRevision {
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn text(&self) -> &str {
+        self.text.as_ref()
+    }
+
+    pub fn c_time(&self) -> DateTime<UTC> {
+        self.c_time
+    }
+
+    /// User who replaced the text, `None` if the user was deleted.
+    pub fn editor(&self) -> Option<&User> {
+        self.editor.as_ref()
+    }
+}
+
+impl Comment {
+    pub fn new(user: &User, text: String) -> Self {
+        Comment {
+            id: 0,
+            author: user.clone(),
+            text: text,
+            c_time: UTC::now(),
+            parent_id: None,
+            resolved: false,
+            m_time: None,
+            deleted: false,
+        }
+    }
+
+    pub fn new_reply(user: &User, text: String, parent_id: i64) -> Self {
+        let mut comment = Comment::new(user, text);
+        comment.parent_id = Some(parent_id);
+        comment
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Set the id assigned by a storage.
+    pub fn set_id(&mut self, id: i64) {
+        self.id = id;
+    }
+
+    pub fn author(&self) -> &User {
+        &self.author
+    }
+
+    pub fn text(&self) -> &str {
+        self.text.as_ref()
+    }
+
+    pub fn c_time(&self) -> DateTime<UTC> {
+        self.c_time
+    }
+
+    /// Set the time of creation, e.g. of an imported comment.
+    pub fn set_c_time(&mut self, c_time: DateTime<UTC>) {
+        self.c_time = c_time;
+    }
+
+    /// Comment which this one replies to, `None` for a topic.
+    pub fn parent_id(&self) -> Option<i64> {
+        self.parent_id
+    }
+
+    pub fn is_topic(&self) -> bool {
+        self.parent_id.is_none()
+    }
+
+    pub fn resolved(&self) -> bool {
+        self.resolved
+    }
+
+    /// Time of the last editing or deletion, `None` if the comment is not edited.
+    pub fn m_time(&self) -> Option<DateTime<UTC>> {
+        self.m_time
+    }
+
+    /// Set the time of the last editing, e.g. of a comment read from a storage.
+    pub fn set_m_time(&mut self, m_time: Option<DateTime<UTC>>) {
+        self.m_time = m_time;
+    }
+
+    pub fn is_edited(&self) -> bool {
+        self.m_time.is_some()
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
+    /// Names of users mentioned as `@name` in the text, without repeats.
+    pub fn mentions(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for (start, end) in parse_mentions(self.text()) {
+            let name = &self.text[start + 1..end];
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Only the author of the comment or root may change it.
+    pub fn is_edit_grant(&self, user: &User) -> bool {
+        !self.deleted && (&self.author == user || user.is_root())
+    }
+
+    /// Replace the text, the previous one is kept as a revision.
+    pub fn edit<S: Into<String>>(&mut self, db: &Db, editor: &User, text: S) -> DbResult<()> {
+        let mut edited = self.clone();
+        edited.set_edited(text);
+        db.transaction(|db| {
+            self.save_revision(db, editor)?;
+            db.conn()
+                .execute("UPDATE comments SET text = ?, m_time = ? WHERE id = ?;",
+                         &[&edited.text(), &edited.m_time(), &self.id()])?;
+            Ok(())
+        })?;
+        *self = edited;
+        Ok(())
+    }
+
+    /// Delete the comment leaving a tombstone, the text is kept as a revision.
+    pub fn delete(&mut self, db: &Db, editor: &User) -> DbResult<()> {
+        let mut deleted = self.clone();
+        deleted.set_deleted();
+        db.transaction(|db| {
+            self.save_revision(db, editor)?;
+            db.conn()
+                .execute("UPDATE comments SET text = '', m_time = ?, deleted = 1 WHERE id = ?;",
+                         &[&deleted.m_time(), &self.id()])?;
+            Ok(())
+        })?;
+        *self = deleted;
+        Ok(())
+    }
+
+    /// Replace the text in memory only, the time of editing is updated.
+    pub fn set_edited<S: Into<String>>(&mut self, text: S) {
+        self.text = text.into();
+        self.m_time = Some(UTC::now());
+    }
+
+    /// Turn the comment into a tombstone in memory only.
+    pub fn set_deleted(&mut self) {
+        self.text = String::new();
+        self.m_time = Some(UTC::now());
+        self.deleted = true;
+    }
+
+    /// Mark the topic as resolved or reopen it in memory only.
+    pub fn set_resolved_flag(&mut self, resolved: bool) {
+        if self.is_topic() {
+            self.resolved = resolved;
+        }
+    }
+
+    fn save_revision(&self, db: &Db, editor: &User) -> DbResult<i64> {
+        let mut stmt = db.conn()
+            .prepare("
+INSERT INTO comment_revisions VALUES (NULL, ?, ?, ?, ?);
+")?;
+        stmt.insert(&[&self.id(), &self.text(), &UTC::now(), &editor.id()])
+            .map_err(From::from)
+    }
+
+    /// Get previous texts of the comment, the oldest first.
+    pub fn get_revisions(db: &Db, id: i64) -> DbResult<Vec<CommentRevision>> {
+        let mut revisions: Vec<CommentRevision> = Vec::new();
+        let mut stmt = db.conn().prepare("
+SELECT * FROM comment_revisions WHERE comment_id = ? ORDER BY id;
+")?;
+        let mut rows = stmt.query(&[&id])?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            let editor_id: Option<i64> = row.get_checked(4)?;
+            revisions.push(CommentRevision {
+                id: row.get_checked(0)?,
+                text: row.get_checked(2)?,
+                c_time: row.get_checked(3)?,
+                editor: match editor_id {
+                    Some(editor_id) => Some(User::get_by_id(db, editor_id)?),
+                    None => None,
+                },
+            });
+        }
+        Ok(revisions)
+    }
+
+    pub fn save_to_db(&mut self, db: &Db, doc_id: i64) -> DbResult<i64> {
+        let mut stmt = db.conn()
+            .prepare("
+INSERT INTO comments (author_id, text, c_time, doc_id, parent_id, resolved)
+VALUES ((SELECT id FROM users WHERE name = ?), ?, ?, ?, ?, ?);
+")?;
+        self.id = stmt.insert(&[&self.author().name(),
+                      &self.text(),
+                      &self.c_time(),
+                      &doc_id,
+                      &self.parent_id(),
+                      &self.resolved()])?;
+        Ok(self.id())
+    }
+
+    /// Mark the topic as resolved or reopen it.
+    pub fn set_resolved(db: &Db, id: i64, resolved: bool) -> DbResult<()> {
+        let mut stmt = db.conn()
+            .prepare("
+UPDATE comments SET resolved = ? WHERE id = ? AND parent_id IS NULL;
+")?;
+        stmt.execute(&[&resolved, &id])?;
+        Ok(())
+    }
+
+    pub fn get_by_id(db: &Db, id: i64) -> DbResult<Comment> {
+        db.conn().query_row_and_then("
+SELECT id, author_id, text, c_time, parent_id, resolved, m_time, deleted FROM comments
+WHERE id = ?;
+",
+                                     &[&id],
+                                     |row| {
+            Ok(Comment {
+                id: row.get_checked(0)?,
+                author: User::get_by_id(db, row.get_checked(1)?)?,
+                text: row.get_checked(2)?,
+                c_time: row.get_checked(3)?,
+                parent_id: row.get_checked(4)?,
+                resolved: row.get_checked(5)?,
+                m_time: row.get_checked(6)?,
+                deleted: row.get_checked(7)?,
+            })
+        })
+    }
+
+    /// Get comments of the document, every reply follows its parent.
+    pub fn get_by_doc_id(db: &Db, doc_id: i64) -> DbResult<Vec<Comment>> {
+        let mut comments: Vec<Comment> = Vec::new();
+        let mut stmt = db.conn().prepare("
+SELECT id, author_id, text, c_time, parent_id, resolved, m_time, deleted FROM comments
+WHERE doc_id = ?
+ORDER BY id;
+")?;
+        let mut rows = stmt.query(&[&doc_id])?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            comments.push(Comment {
+                id: row.get_checked(0)?,
+                author: User::get_by_id(db, row.get_checked(1)?)?,
+                text: row.get_checked(2)?,
+                c_time: row.get_checked(3)?,
+                parent_id: row.get_checked(4)?,
+                resolved: row.get_checked(5)?,
+                m_time: row.get_checked(6)?,
+                deleted: row.get_checked(7)?,
+            });
+        }
+        Ok(comments)
+    }
+}
+
+/// Byte ranges of `@name` mentions in the text, `@` included. A mention starts at the beginning
+/// of the text or after a character which can't be a part of a name, so e-mail addresses are
+/// not mentions. The name may contain letters, digits, `_`, `-` and `.` but not at the end.
+pub fn parse_mentions(text: &str) -> Vec<(usize, usize)> {
+    fn is_name_char(c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+    }
+
+    let mut mentions: Vec<(usize, usize)> = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '@' && !prev.map_or(false, is_name_char) {
+            let mut end = i + 1;
+            while let Some(&(j, next)) = chars.peek() {
+                if !is_name_char(next) {
+                    break;
+                }
+                end = j + next.len_utf8();
+                prev = Some(next);
+                chars.next();
+            }
+            while text[i + 1..end].ends_with('.') {
+                end -= 1;
+            }
+            if end > i + 1 {
+                mentions.push((i, end));
+            }
+            continue;
+        }
+        prev = Some(c);
+    }
+    mentions
+}
+
+#[test]
+fn parse_mentions_in_text() {
+    let text = "@root, please ask @иванов.п. (ivanov@example.com) @ @a-b_c.";
+    let names: Vec<&str> = parse_mentions(text)
+        .into_iter()
+        .map(|(start, end)| &text[start..end])
+        .collect();
+    assert_eq!(names, vec!["@root", "@иванов.п", "@a-b_c"]);
+}
@@ -0,0 +1,365 @@
+//! Manipulate with DB.
+//!
+//! TODO Write documentation.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{self, DatabaseName};
+use rusqlite::backup::Progress;
+use chrono::{DateTime, UTC};
+
+use calendar;
+use errors::{DbResult, DbError};
+
+/// Changes of the schema after its creation, `PRAGMA user_version` is the number of applied
+/// migrations.
+const MIGRATIONS: &'static [&'static str] = &["
+ALTER TABLE comments ADD COLUMN parent_id REFERENCES comments(id) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE comments ADD COLUMN resolved INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX IF NOT EXISTS comments_parent_id ON comments(parent_id);
+", "
+ALTER TABLE comments ADD COLUMN m_time INTEGER;
+ALTER TABLE comments ADD COLUMN deleted INTEGER NOT NULL DEFAULT 0;
+CREATE TABLE IF NOT EXISTS comment_revisions (
+    id INTEGER PRIMARY KEY ASC,
+    comment_id NOT NULL REFERENCES comments(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    text TEXT NOT NULL,
+    c_time INTEGER NOT NULL,
+    editor_id REFERENCES users(id) ON UPDATE CASCADE ON DELETE SET NULL
+);
+CREATE INDEX IF NOT EXISTS comment_revisions_comment_id ON comment_revisions(comment_id);
+", "
+CREATE TABLE IF NOT EXISTS notifications (
+    id INTEGER PRIMARY KEY ASC,
+    user_id NOT NULL REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    comment_id NOT NULL REFERENCES comments(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    c_time INTEGER NOT NULL,
+    read INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (user_id, comment_id)
+);
+CREATE INDEX IF NOT EXISTS notifications_user_id ON notifications(user_id, read);
+", "
+CREATE TABLE IF NOT EXISTS annotations (
+    comment_id INTEGER PRIMARY KEY REFERENCES comments(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    anchor_start INTEGER,
+    anchor_end INTEGER,
+    quote TEXT,
+    x REAL,
+    y REAL,
+    width REAL,
+    height REAL,
+    orphaned INTEGER NOT NULL DEFAULT 0
+);
+", "
+CREATE TABLE IF NOT EXISTS doc_revisions (
+    id INTEGER PRIMARY KEY ASC,
+    doc_id NOT NULL REFERENCES docs(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    data BLOB,
+    c_time INTEGER NOT NULL,
+    author_id REFERENCES users(id) ON UPDATE CASCADE ON DELETE SET NULL
+);
+CREATE INDEX IF NOT EXISTS doc_revisions_doc_id ON doc_revisions(doc_id);
+", "
+ALTER TABLE docs ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+", "
+CREATE TABLE IF NOT EXISTS doc_locks (
+    doc_id INTEGER PRIMARY KEY REFERENCES docs(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    user_id NOT NULL REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    c_time INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY ASC,
+    c_time INTEGER NOT NULL,
+    user_name TEXT NOT NULL,
+    action TEXT NOT NULL,
+    details TEXT NOT NULL
+);
+", "
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY ASC,
+    user_name TEXT NOT NULL,
+    connection TEXT NOT NULL,
+    login_time INTEGER NOT NULL,
+    logout_time INTEGER
+);
+", "
+CREATE TABLE IF NOT EXISTS feed_tokens (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    token TEXT NOT NULL UNIQUE
+);
+"];
+
+/// Calendars saved months counted from zero while the schema had no version yet, so deadlines
+/// of databases of this version are a month early. Later versions are written by fixed calendars.
+pub const ZERO_BASED_MONTHS_VERSION: i64 = 0;
+
+/// Move all deadlines a month forward, the day is clamped to the end of the month. The dates are
+/// written back like any other, so their format doesn't change.
+pub fn shift_deadlines(conn: &rusqlite::Connection) -> DbResult<()> {
+    let mut deadlines: Vec<(i64, DateTime<UTC>)> = Vec::new();
+    {
+        let mut stmt = conn.prepare("
+SELECT id, date_expired FROM metadata WHERE date_expired IS NOT NULL;
+")?;
+        let mut rows = stmt.query(&[])?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            deadlines.push((row.get_checked(0)?, row.get_checked(1)?));
+        }
+    }
+    for (id, date_expired) in deadlines {
+        conn.execute("UPDATE metadata SET date_expired = ? WHERE id = ?;",
+                     &[&calendar::next_month(date_expired), &id])?;
+    }
+    Ok(())
+}
+
+/// Time to wait for the lock of the database held by another connection.
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+pub struct Db {
+    conn: rusqlite::Connection,
+}
+
+impl Db {
+    pub fn new() -> DbResult<Self> {
+        Db::open(&Db::default_path()?)
+    }
+
+    /// Path of the database file of the user.
+    pub fn default_path() -> DbResult<PathBuf> {
+        let db_file: PathBuf;
+        if let Ok(home_dir) = env::var("HOME") {
+            let db_dir = PathBuf::from(&home_dir).join(".config/sed_bad");
+            fs::create_dir_all(db_dir)?;
+            db_file = PathBuf::from(home_dir).join(".config/sed_bad/db.sqlite3");
+        } else {
+            db_file = env::current_dir().unwrap().join("sed_bad.sqlite3");
+        }
+        Ok(db_file)
+    }
+
+    /// Open the database file, it's created and migrated if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> DbResult<Self> {
+        rusqlite::Connection::open(path)
+            .map_err(|err| From::from(err))
+            .and_then(|conn| Db::init(conn))
+    }
+
+    fn init(conn: rusqlite::Connection) -> DbResult<Self> {
+        conn.execute_batch("
+BEGIN;
+CREATE TABLE IF NOT EXISTS users (
+    id INTEGER PRIMARY KEY ASC,
+    name TEXT NOT NULL UNIQUE,
+    pass TEXT NOT NULL,
+    pass_hash TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS categories (
+    id INTEGER PRIMARY KEY ASC,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS metadata (
+    id INTEGER PRIMARY KEY ASC,
+    c_time INTEGER NOT NULL,
+    m_time INTEGER NOT NULL,
+    author_id NOT NULL REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    category_id NOT NULL REFERENCES categories(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    status INTEGER NOT NULL,
+    date_expired INTEGER
+);
+CREATE TABLE IF NOT EXISTS docs (
+    id INTEGER PRIMARY KEY ASC,
+    name TEXT NOT NULL,
+    metadata NOT NULL REFERENCES metadata(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    permission INTEGER NOT NULL,
+    data BLOB,
+    responsible REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+CREATE TABLE IF NOT EXISTS comments (
+    id INTEGER PRIMARY KEY ASC,
+    author_id NOT NULL REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    text TEXT NOT NULL,
+    c_time INTEGER NOT NULL,
+    doc_id NOT NULL REFERENCES docs(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+CREATE TABLE IF NOT EXISTS saved_searches (
+    id INTEGER PRIMARY KEY ASC,
+    name TEXT NOT NULL,
+    query TEXT NOT NULL,
+    owner_id REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE,
+    shared INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS docs_metadata ON docs(metadata);
+CREATE INDEX IF NOT EXISTS docs_responsible ON docs(responsible);
+CREATE INDEX IF NOT EXISTS metadata_author_id ON metadata(author_id);
+CREATE INDEX IF NOT EXISTS metadata_category_id ON metadata(category_id);
+CREATE INDEX IF NOT EXISTS metadata_status ON metadata(status);
+CREATE INDEX IF NOT EXISTS metadata_date_expired ON metadata(date_expired);
+CREATE INDEX IF NOT EXISTS comments_doc_id ON comments(doc_id);
+COMMIT;")?;
+        // Other connections wait for the lock of the database instead of failing at once.
+        conn.execute_batch(&format!("PRAGMA busy_timeout = {};", BUSY_TIMEOUT_MS))?;
+        Db::migrate(&conn)?;
+        conn.execute("PRAGMA foreign_keys = ON;", &[]).unwrap();
+        Ok(Db { conn: conn })
+    }
+
+    fn migrate(conn: &rusqlite::Connection) -> DbResult<()> {
+        let version: i64 = conn.query_row("PRAGMA user_version;", &[], |row| row.get(0))?;
+        if version == ZERO_BASED_MONTHS_VERSION && !MIGRATIONS.is_empty() {
+            // Together with the first migration, so the deadlines are shifted only once.
+            conn.execute_batch("BEGIN;")?;
+            let res = shift_deadlines(conn).and_then(|_| {
+                conn.execute_batch(&format!("{}\nPRAGMA user_version = 1;", MIGRATIONS[0]))
+                    .map_err(From::from)
+            });
+            match res {
+                Ok(_) => conn.execute_batch("COMMIT;")?,
+                Err(e) => {
+                    let _ = conn.execute_batch("ROLLBACK;");
+                    return Err(e);
+                }
+            }
+        }
+        let version: i64 = conn.query_row("PRAGMA user_version;", &[], |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            conn.execute_batch(&format!("BEGIN;\n{}\nPRAGMA user_version = {};\nCOMMIT;",
+                                        migration,
+                                        i + 1))?;
+        }
+        Ok(())
+    }
+
+    /// Version of the schema, the number of applied migrations.
+    pub fn schema_version(&self) -> DbResult<i64> {
+        self.conn
+            .query_row("PRAGMA user_version;", &[], |row| row.get(0))
+            .map_err(From::from)
+    }
+
+    /// Version of the schema after all migrations.
+    pub fn latest_schema_version() -> i64 {
+        MIGRATIONS.len() as i64
+    }
+
+    /// Copy the database to the file by the online backup API, it's safe while the database is
+    /// in use.
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> DbResult<()> {
+        self.conn
+            .backup(DatabaseName::Main, path, None::<fn(Progress)>)
+            .map_err(From::from)
+    }
+
+    /// Replace the content of the database by the backup, the schema of an older backup is
+    /// migrated. The backup has to be checked by `check_backup` before.
+    pub fn restore_from<P: AsRef<Path>>(&mut self, path: P) -> DbResult<()> {
+        self.conn.restore(DatabaseName::Main, path, None::<fn(Progress)>)?;
+        Db::migrate(&self.conn)?;
+        self.conn.execute("PRAGMA foreign_keys = ON;", &[])?;
+        Ok(())
+    }
+
+    /// Check that the file is a consistent database of a known version of the schema.
+    pub fn check_backup<P: AsRef<Path>>(path: P) -> DbResult<()> {
+        let conn = rusqlite::Connection::open_with_flags(path, rusqlite::SQLITE_OPEN_READ_ONLY)?;
+        let integrity: String = conn.query_row("PRAGMA integrity_check;", &[], |row| row.get(0))?;
+        if integrity != "ok" {
+            return Err(DbError::InvalidData(format!("The database is damaged: {}", integrity)));
+        }
+        let mut stmt = conn.prepare("
+SELECT * FROM sqlite_master WHERE type = 'table' AND name = 'docs';
+")?;
+        if !stmt.exists(&[])? {
+            return Err(DbError::InvalidData("It's not a database of sed_bad.".to_string()));
+        }
+        let version: i64 = conn.query_row("PRAGMA user_version;", &[], |row| row.get(0))?;
+        if version > Db::latest_schema_version() {
+            return Err(DbError::InvalidData(format!("The schema version {} of the database is \
+                                                     newer than the supported one ({}).",
+                                                    version,
+                                                    Db::latest_schema_version())));
+        }
+        Ok(())
+    }
+
+    /// Run `f` in a transaction, which is rolled back if `f` fails. The database is locked for
+    /// writing at the start, so a transaction which reads before it writes doesn't fail when
+    /// another connection writes in between.
+    pub fn transaction<T, F>(&self, f: F) -> DbResult<T>
+        where F: FnOnce(&Db) -> DbResult<T>
+    {
+        self.conn.execute_batch("BEGIN IMMEDIATE;")?;
+        match f(self) {
+            Ok(res) => {
+                self.conn.execute_batch("COMMIT;")?;
+                Ok(res)
+            }
+            Err(e) => {
+                let _ = self.conn.execute_batch("ROLLBACK;");
+                Err(e)
+            }
+        }
+    }
+
+    pub fn conn(&self) -> &rusqlite::Connection {
+        &self.conn
+    }
+}
+
+#[test]
+fn shift_deadlines_of_unversioned_schema_only() {
+    use chrono::TimeZone;
+    use temp_dir::TempDir;
+
+    let temp_dir = TempDir::new("migration-test").unwrap();
+    let path = temp_dir.path().join("db.sqlite3");
+    {
+        // A deadline of February 29 saved by a calendar counting months from zero.
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch("
+CREATE TABLE metadata (
+    id INTEGER PRIMARY KEY ASC,
+    c_time INTEGER NOT NULL,
+    m_time INTEGER NOT NULL,
+    author_id NOT NULL,
+    category_id NOT NULL,
+    status INTEGER NOT NULL,
+    date_expired INTEGER
+);
+")
+            .unwrap();
+        let now = UTC::now();
+        conn.execute("INSERT INTO metadata VALUES (1, ?, ?, 1, 1, 0, ?);",
+                     &[&now, &now, &UTC.ymd(2016, 1, 29).and_hms_milli(10, 20, 30, 500)])
+            .unwrap();
+    }
+    let date_expired = |db: &Db, id: i64| -> DateTime<UTC> {
+        db.conn()
+            .query_row("SELECT date_expired FROM metadata WHERE id = ?;",
+                       &[&id],
+                       |row| row.get(0))
+            .unwrap()
+    };
+
+    let db = Db::open(&path).unwrap();
+    assert_eq!(db.schema_version().unwrap(), Db::latest_schema_version());
+    assert_eq!(date_expired(&db, 1), UTC.ymd(2016, 2, 29).and_hms_milli(10, 20, 30, 500));
+    // Saved by a fixed calendar.
+    let now = UTC::now();
+    db.conn()
+        .execute("INSERT INTO metadata VALUES (2, ?, ?, 1, 1, 0, ?);",
+                 &[&now, &now, &UTC.ymd(2016, 1, 31).and_hms(0, 0, 0)])
+        .unwrap();
+    drop(db);
+
+    let db = Db::open(&path).unwrap();
+    assert_eq!(date_expired(&db, 1), UTC.ymd(2016, 2, 29).and_hms_milli(10, 20, 30, 500));
+    assert_eq!(date_expired(&db, 2), UTC.ymd(2016, 1, 31).and_hms(0, 0, 0));
+
+    // Clamped to the end of the next month.
+    shift_deadlines(db.conn()).unwrap();
+    assert_eq!(date_expired(&db, 2), UTC.ymd(2016, 2, 29).and_hms(0, 0, 0));
+}
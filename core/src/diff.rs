@@ -0,0 +1,314 @@
+//! Word-level difference between two texts.
+//!
+//! Texts are split into words, runs of whitespace and single punctuation characters. Words are
+//! Unicode-aware, so Cyrillic letters, `ё`, combining stress marks and hyphenated words like
+//! `кто-то` are kept together. The difference is the longest common subsequence of tokens, found
+//! in linear memory; very long changes are compared by lines.
+
+/// Largest table of token pairs compared word by word, longer changes are compared by lines.
+const MAX_CELLS: usize = 25_000_000;
+
+/// Part of the difference.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Equal(String),
+    Delete(String),
+    Insert(String),
+}
+
+fn is_word_char(c: char) -> bool {
+    // Combining diacritical marks, e.g. the stress mark.
+    c.is_alphanumeric() || c == '_' || (c >= '\u{300}' && c <= '\u{36f}')
+}
+
+/// Split the text into tokens.
+pub fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens: Vec<&str> = Vec::new();
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        let mut j = i + 1;
+        if is_word_char(c) {
+            while j < chars.len() {
+                let next = chars[j].1;
+                // A hyphen or an apostrophe between letters belongs to the word.
+                let joiner = (next == '-' || next == '\'' || next == '’') && j + 1 < chars.len() &&
+                             is_word_char(chars[j + 1].1);
+                if is_word_char(next) {
+                    j += 1;
+                } else if joiner {
+                    j += 2;
+                } else {
+                    break;
+                }
+            }
+        } else if c.is_whitespace() {
+            while j < chars.len() && chars[j].1.is_whitespace() {
+                j += 1;
+            }
+        }
+        let end = if j < chars.len() {
+            chars[j].0
+        } else {
+            text.len()
+        };
+        tokens.push(&text[start..end]);
+        i = j;
+    }
+    tokens
+}
+
+/// Difference between the old and the new text, adjacent changes of the same kind are merged.
+pub fn diff(old: &str, new: &str) -> Vec<Change> {
+    let old_tokens = tokenize(old);
+    let new_tokens = tokenize(new);
+
+    // Common prefix and suffix don't take part in the quadratic search.
+    let prefix = old_tokens.iter().zip(new_tokens.iter()).take_while(|&(a, b)| a == b).count();
+    let suffix = old_tokens[prefix..]
+        .iter()
+        .rev()
+        .zip(new_tokens[prefix..].iter().rev())
+        .take_while(|&(a, b)| a == b)
+        .count();
+    let a = &old_tokens[prefix..old_tokens.len() - suffix];
+    let b = &new_tokens[prefix..new_tokens.len() - suffix];
+
+    let mut changes: Vec<Change> = Vec::new();
+    for token in &old_tokens[..prefix] {
+        push(&mut changes, Change::Equal(token.to_string()));
+    }
+    if a.len() * b.len() <= MAX_CELLS {
+        lcs_changes(a, b, &mut changes);
+    } else {
+        // Too long for words, lines of the changed part are compared instead.
+        let prefix_len: usize = old_tokens[..prefix].iter().map(|token| token.len()).sum();
+        let suffix_len: usize = old_tokens[old_tokens.len() - suffix..]
+            .iter()
+            .map(|token| token.len())
+            .sum();
+        let old_lines = split_lines(&old[prefix_len..old.len() - suffix_len]);
+        let new_lines = split_lines(&new[prefix_len..new.len() - suffix_len]);
+        if old_lines.len() * new_lines.len() <= MAX_CELLS {
+            lcs_changes(&old_lines, &new_lines, &mut changes);
+        } else {
+            lcs_changes(&old_lines, &[], &mut changes);
+            lcs_changes(&[], &new_lines, &mut changes);
+        }
+    }
+    for token in &old_tokens[old_tokens.len() - suffix..] {
+        push(&mut changes, Change::Equal(token.to_string()));
+    }
+    changes
+}
+
+/// Lengths of the longest common subsequences of `a` and the prefixes of `b`, both are read
+/// from the end if `reverse`. Only one row is kept, so the memory is linear.
+fn lcs_lengths(a: &[&str], b: &[&str], reverse: bool) -> Vec<usize> {
+    fn at<'a>(tokens: &[&'a str], i: usize, reverse: bool) -> &'a str {
+        if reverse {
+            tokens[tokens.len() - 1 - i]
+        } else {
+            tokens[i]
+        }
+    }
+
+    let mut row = vec![0usize; b.len() + 1];
+    for i in 0..a.len() {
+        // The value of row[j] before the update, it's lcs[i - 1][j].
+        let mut diagonal = 0;
+        for j in 0..b.len() {
+            let up = row[j + 1];
+            row[j + 1] = if at(a, i, reverse) == at(b, j, reverse) {
+                diagonal + 1
+            } else {
+                row[j + 1].max(row[j])
+            };
+            diagonal = up;
+        }
+    }
+    row
+}
+
+/// Changes turning `a` into `b` by Hirschberg's algorithm: `a` is split in halves and `b` where
+/// the common subsequences of the halves are the longest.
+fn lcs_changes(a: &[&str], b: &[&str], changes: &mut Vec<Change>) {
+    if a.is_empty() {
+        for token in b {
+            push(changes, Change::Insert(token.to_string()));
+        }
+    } else if b.is_empty() {
+        for token in a {
+            push(changes, Change::Delete(token.to_string()));
+        }
+    } else if a.len() == 1 {
+        match b.iter().position(|token| *token == a[0]) {
+            Some(j) => {
+                lcs_changes(&[], &b[..j], changes);
+                push(changes, Change::Equal(a[0].to_string()));
+                lcs_changes(&[], &b[j + 1..], changes);
+            }
+            None => {
+                lcs_changes(a, &[], changes);
+                lcs_changes(&[], b, changes);
+            }
+        }
+    } else {
+        let middle = a.len() / 2;
+        let left = lcs_lengths(&a[..middle], b, false);
+        let right = lcs_lengths(&a[middle..], b, true);
+        let split = (0..b.len() + 1).max_by_key(|&j| left[j] + right[b.len() - j]).unwrap_or(0);
+        lcs_changes(&a[..middle], &b[..split], changes);
+        lcs_changes(&a[middle..], &b[split..], changes);
+    }
+}
+
+/// Lines of the text with their line breaks.
+fn split_lines(text: &str) -> Vec<&str> {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if c == '\n' {
+            lines.push(&text[start..i + 1]);
+            start = i + 1;
+        }
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+    lines
+}
+
+fn push(changes: &mut Vec<Change>, change: Change) {
+    // Deletions go before insertions between the same equal parts.
+    let after_insert = match (changes.last(), &change) {
+        (Some(&Change::Insert(_)), &Change::Delete(_)) => true,
+        _ => false,
+    };
+    if after_insert {
+        let insert = changes.pop().unwrap();
+        push(changes, change);
+        changes.push(insert);
+        return;
+    }
+    if let Some(last) = changes.last_mut() {
+        match (last, &change) {
+            (&mut Change::Equal(ref mut text), &Change::Equal(ref more)) |
+            (&mut Change::Delete(ref mut text), &Change::Delete(ref more)) |
+            (&mut Change::Insert(ref mut text), &Change::Insert(ref more)) => {
+                text.push_str(more);
+                return;
+            }
+            _ => {}
+        }
+    }
+    changes.push(change);
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Export the difference as an HTML page, inline or side by side.
+pub fn to_html(changes: &[Change], title: &str, side_by_side: bool) -> String {
+    let mut old = String::new();
+    let mut new = String::new();
+    let mut inline = String::new();
+    for change in changes {
+        match *change {
+            Change::Equal(ref text) => {
+                old.push_str(&escape_html(text));
+                new.push_str(&escape_html(text));
+                inline.push_str(&escape_html(text));
+            }
+            Change::Delete(ref text) => {
+                let html = format!("<del>{}</del>", escape_html(text));
+                old.push_str(&html);
+                inline.push_str(&html);
+            }
+            Change::Insert(ref text) => {
+                let html = format!("<ins>{}</ins>", escape_html(text));
+                new.push_str(&html);
+                inline.push_str(&html);
+            }
+        }
+    }
+    let body = if side_by_side {
+        format!("<table>\n<tr><td>{}</td><td>{}</td></tr>\n</table>", old, new)
+    } else {
+        format!("<div>{}</div>", inline)
+    };
+    format!("<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{0}</title>
+<style>
+td, div {{ white-space: pre-wrap; vertical-align: top; width: 50%; }}
+del {{ background: #ffcccc; color: #cc0000; }}
+ins {{ background: #d7f5c4; color: #4e9a06; text-decoration: none; }}
+</style>
+</head>
+<body>
+<h1>{0}</h1>
+{1}
+</body>
+</html>
+",
+            escape_html(title),
+            body)
+}
+
+#[test]
+fn tokenize_text() {
+    assert_eq!(tokenize("Кто-то сказа\u{301}л: «ёлка»,  ok!"),
+               vec!["Кто-то", " ", "сказа\u{301}л", ":", " ", "«", "ёлка", "»", ",", "  ", "ok",
+                    "!"]);
+}
+
+#[test]
+fn diff_words() {
+    assert_eq!(diff("Договор поставки товара.", "Договор поставки услуг."),
+               vec![Change::Equal("Договор поставки ".to_string()),
+                    Change::Delete("товара".to_string()),
+                    Change::Insert("услуг".to_string()),
+                    Change::Equal(".".to_string())]);
+    assert_eq!(diff("", "Новый"), vec![Change::Insert("Новый".to_string())]);
+    assert_eq!(diff("a b", "a b"), vec![Change::Equal("a b".to_string())]);
+}
+
+#[test]
+fn diff_to_html() {
+    let html = to_html(&diff("a <b>", "a c"), "Diff", false);
+    assert!(html.contains("a <del>&lt;b&gt;</del><ins>c</ins>"));
+}
+
+#[test]
+fn diff_subsequence() {
+    let old = "the quick brown fox jumps over the lazy dog";
+    let new = "a quick red fox jumped over lazy dogs and the cat";
+    let changes = diff(old, new);
+    let (mut from, mut to, mut common) = (String::new(), String::new(), 0);
+    for change in &changes {
+        match *change {
+            Change::Equal(ref text) => {
+                from.push_str(text);
+                to.push_str(text);
+                common += tokenize(text).len();
+            }
+            Change::Delete(ref text) => from.push_str(text),
+            Change::Insert(ref text) => to.push_str(text),
+        }
+    }
+    assert_eq!((from.as_str(), to.as_str()), (old, new));
+    assert_eq!(common, 11);
+
+    // Too many words, the lines are replaced.
+    let old: String = ::std::iter::repeat("x ").take(3000).collect();
+    let new: String = ::std::iter::repeat("y ").take(3000).collect();
+    assert_eq!(diff(&old, &new),
+               vec![Change::Delete(old[..old.len() - 1].to_string()),
+                    Change::Insert(new[..new.len() - 1].to_string()),
+                    Change::Equal(" ".to_string())]);
+}
@@ -0,0 +1,379 @@
+//! Document structure.
+//!
+//! TODO Write documentation.
+
+use chrono::{DateTime, UTC};
+use rusqlite::types::ToSql;
+
+use metadata::{Metadata, Status};
+use permission::Permission;
+use comment::Comment;
+use notification::Notification;
+use user::User;
+use category::Category;
+use errors::{DbResult, DbError};
+use db::Db;
+use query::{Query, Context};
+
+/// Tables joined for filtering documents, see `query::Query::to_sql`.
+pub const DOCS_FROM_CLAUSE: &'static str = "
+FROM docs
+JOIN metadata ON metadata.id = docs.metadata
+JOIN users AS author ON author.id = metadata.author_id
+JOIN users AS responsible ON responsible.id = docs.responsible
+JOIN categories ON categories.id = metadata.category_id
+";
+
+/// Columns of `docs` read by `get_docs` and `get_by_id`.
+const DOCS_COLUMNS: &'static str = "id, name, metadata, permission, data, responsible, version";
+
+#[derive(Clone, Debug)]
+pub struct Document {
+    id: i64,
+    name: String,
+    metadata: Metadata,
+    permission: Permission,
+    data: Option<Vec<u8>>,
+    comments: Vec<Comment>,
+    responsible: User, // TODO Reference?
+    /// Version of the stored row, it grows with every saving.
+    version: i64,
+}
+
+/// Projection of a document for the list of tickets, read by one query without comments and
+/// data.
+#[derive(Debug)]
+pub struct DocumentSummary {
+    id: i64,
+    name: String,
+    status: Status,
+    author: String,
+    c_time: DateTime<UTC>,
+    m_time: DateTime<UTC>,
+    responsible: String,
+    date_expired: DateTime<UTC>,
+}
+
+impl DocumentSummary {
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    pub fn status(&self) -> Status {
+        self.status.clone()
+    }
+
+    /// Name of the author.
+    pub fn author(&self) -> &str {
+        self.author.as_ref()
+    }
+
+    pub fn c_time(&self) -> DateTime<UTC> {
+        self.c_time
+    }
+
+    pub fn m_time(&self) -> DateTime<UTC> {
+        self.m_time
+    }
+
+    /// Name of the responsible user.
+    pub fn responsible(&self) -> &str {
+        self.responsible.as_ref()
+    }
+
+    pub fn date_expired(&self) -> DateTime<UTC> {
+        self.date_expired
+    }
+}
+
+impl<'a> From<&'a Document> for DocumentSummary {
+    fn from(doc: &Document) -> Self {
+        DocumentSummary {
+            id: doc.id(),
+            name: doc.name().to_string(),
+            status: doc.metadata().status(),
+            author: doc.metadata().author().name().to_string(),
+            c_time: doc.metadata().c_time(),
+            m_time: doc.metadata().m_time(),
+            responsible: doc.responsible().name().to_string(),
+            date_expired: doc.metadata().date_expired(),
+        }
+    }
+}
+
+impl Document {
+    pub fn new(name: String,
+               author: &User,
+               category: Category,
+               responsible_user: User,
+               expired_date: (u32, u32, u32),
+               comment: Option<Comment>)
+               -> Self {
+        Document {
+            id: 0,
+            name: name.to_string(),
+            metadata: Metadata::new(&author, category, expired_date),
+            permission: Permission::new(),
+            data: None,
+            comments: if let Some(comment) = comment {
+                vec![comment]
+            } else {
+                vec![]
+            },
+            responsible: responsible_user,
+            version: 1,
+        }
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Set the id assigned by a storage.
+    pub fn set_id(&mut self, id: i64) {
+        self.id = id;
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
+        self.name = name.into();
+        self.metadata_mut().set_m_time(UTC::now());
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    pub fn permission(&self) -> Permission {
+        self.permission
+    }
+
+    pub fn set_permission(&mut self, permission: Permission) {
+        self.permission = permission;
+        self.metadata_mut().set_m_time(UTC::now());
+    }
+
+    pub fn data(&self) -> Option<Vec<u8>> {
+        self.data.clone()
+    }
+
+    pub fn set_data(&mut self, data: Option<Vec<u8>>) {
+        self.data = data;
+        self.metadata_mut().set_m_time(UTC::now());
+    }
+
+    /// Whether the content is an image (PNG, JPEG, GIF or TIFF).
+    pub fn is_image(&self) -> bool {
+        match self.data {
+            Some(ref data) => is_image(data),
+            None => false,
+        }
+    }
+
+    /// Text content, `None` if the content is empty or is not a text in UTF-8.
+    pub fn text(&self) -> Option<String> {
+        match self.data {
+            Some(ref data) if !self.is_image() => String::from_utf8(data.clone()).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn comments(&self) -> &Vec<Comment> {
+        self.comments.as_ref()
+    }
+
+    pub fn comments_mut(&mut self) -> &mut Vec<Comment> {
+        self.comments.as_mut()
+    }
+
+    pub fn add_comment(&mut self, db: &Db, mut comment: Comment) -> DbResult<i64> {
+        self.metadata_mut().set_m_time(UTC::now());
+        self.metadata_mut().update(db)?;
+        let id = comment.save_to_db(db, self.id)?;
+        Notification::notify_mentions(db, self, &comment)?;
+        Ok(id)
+    }
+
+    pub fn responsible(&self) -> &User {
+        &self.responsible
+    }
+
+    pub fn set_responsible(&mut self, responsible: User) {
+        self.responsible = responsible;
+        self.metadata_mut().set_m_time(UTC::now());
+    }
+
+    /// Version of the document when it was read, see `update`.
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    pub fn set_version(&mut self, version: i64) {
+        self.version = version;
+    }
+
+    /// Save the document if the stored one has the same version and increase the version.
+    /// `DbError::Conflict` means that somebody else saved the document after it was read.
+    pub fn update(&self, db: &Db) -> DbResult<i32> {
+        let updated = db.conn()
+            .execute("
+UPDATE docs SET name = ?, permission = ?, data = ?, responsible = ?, version = version + 1
+WHERE id = ? AND version = ?;
+",
+                     &[&self.name(),
+                       &self.permission().get_int(),
+                       &self.data(),
+                       &self.responsible().id(),
+                       &self.id(),
+                       &self.version()])?;
+        if updated == 0 {
+            db.conn().query_row("SELECT id FROM docs WHERE id = ?;", &[&self.id()], |_| ())?;
+            return Err(DbError::Conflict);
+        }
+        Ok(updated)
+    }
+
+    pub fn save_to_db(&mut self, db: &Db) -> DbResult<i64> {
+        let metadata_id = self.metadata_mut().save_to_db(db)?;
+        let mut stmt = db.conn()
+            .prepare("
+INSERT INTO docs (name, metadata, permission, data, responsible) VALUES (?, ?, ?, ?, ?);
+")?;
+        self.id = stmt.insert(&[&self.name(),
+                      &metadata_id,
+                      &self.permission().get_int(),
+                      &self.data(),
+                      &self.responsible().id()])?;
+        self.version = 1;
+        let doc_id = self.id();
+        for comment in self.comments_mut() {
+            comment.save_to_db(db, doc_id)?;
+        }
+        Ok(self.id())
+    }
+
+    pub fn get_docs(db: &Db) -> DbResult<Vec<Document>> {
+        let mut docs: Vec<Document> = Vec::new();
+        let mut stmt = db.conn().prepare(&format!("SELECT {} FROM docs;", DOCS_COLUMNS))?;
+        let mut rows = stmt.query(&[])?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            docs.push(Document {
+                id: row.get_checked(0)?,
+                name: row.get_checked(1)?,
+                metadata: Metadata::get_by_id(db, row.get_checked(2)?)?,
+                permission: Permission::from_int(row.get_checked(3)?),
+                data: row.get_checked(4)?,
+                comments: Comment::get_by_doc_id(db, row.get_checked(0)?)?,
+                responsible: User::get_by_id(db, row.get_checked(5)?)?,
+                version: row.get_checked(6)?,
+            });
+        }
+        Ok(docs)
+    }
+
+    /// Get a page of summaries of documents matching the query, ordered by id.
+    pub fn list(db: &Db,
+                query: &Query,
+                context: &Context,
+                limit: i64,
+                offset: i64)
+                -> DbResult<Vec<DocumentSummary>> {
+        Document::list_ordered(db, query, context, "docs.id", limit, offset)
+    }
+
+    /// Get summaries of documents matching the query, ordered by due date.
+    pub fn list_by_due_date(db: &Db,
+                            query: &Query,
+                            context: &Context)
+                            -> DbResult<Vec<DocumentSummary>> {
+        Document::list_ordered(db, query, context, "metadata.date_expired, docs.id", -1, 0)
+    }
+
+    fn list_ordered(db: &Db,
+                    query: &Query,
+                    context: &Context,
+                    order_by: &str,
+                    limit: i64,
+                    offset: i64)
+                    -> DbResult<Vec<DocumentSummary>> {
+        let (condition, params) = query.to_sql(context);
+        let mut params: Vec<&ToSql> = params.iter().map(|param| &**param).collect();
+        params.push(&limit);
+        params.push(&offset);
+        let mut summaries: Vec<DocumentSummary> = Vec::new();
+        let mut stmt = db.conn()
+            .prepare(&format!("
+SELECT docs.id, docs.name, metadata.status, author.name, metadata.c_time, metadata.m_time,
+       responsible.name, metadata.date_expired
+{}
+WHERE {}
+ORDER BY {}
+LIMIT ? OFFSET ?;
+",
+                              DOCS_FROM_CLAUSE,
+                              condition,
+                              order_by))?;
+        let mut rows = stmt.query(&params)?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            summaries.push(DocumentSummary {
+                id: row.get_checked(0)?,
+                name: row.get_checked(1)?,
+                status: Status::from_num(row.get_checked(2)?),
+                author: row.get_checked(3)?,
+                c_time: row.get_checked(4)?,
+                m_time: row.get_checked(5)?,
+                responsible: row.get_checked(6)?,
+                date_expired: row.get_checked(7)?,
+            });
+        }
+        Ok(summaries)
+    }
+
+    /// Count documents matching the query.
+    pub fn count(db: &Db, query: &Query, context: &Context) -> DbResult<i64> {
+        let (condition, params) = query.to_sql(context);
+        let params: Vec<&ToSql> = params.iter().map(|param| &**param).collect();
+        db.conn()
+            .query_row_and_then(&format!("SELECT COUNT(*) {} WHERE {};",
+                                         DOCS_FROM_CLAUSE,
+                                         condition),
+                                &params,
+                                |row| row.get_checked(0))
+            .map_err(From::from)
+    }
+
+    pub fn get_by_id(db: &Db, doc_id: i64) -> DbResult<Document> {
+        let sql = format!("SELECT {} FROM docs WHERE id = ?", DOCS_COLUMNS);
+        db.conn().query_row_and_then(&sql, &[&doc_id], |row| {
+            Ok(Document {
+                id: row.get_checked(0)?,
+                name: row.get_checked(1)?,
+                metadata: Metadata::get_by_id(db, row.get_checked(2)?)?,
+                permission: Permission::from_int(row.get_checked(3)?),
+                data: row.get_checked(4)?,
+                comments: Comment::get_by_doc_id(db, row.get_checked(0)?)?,
+                responsible: User::get_by_id(db, row.get_checked(5)?)?,
+                version: row.get_checked(6)?,
+            })
+        })
+    }
+}
+
+/// Whether the data is an image (PNG, JPEG, GIF or TIFF) by its signature.
+pub fn is_image(data: &[u8]) -> bool {
+    data.starts_with(b"\x89PNG\r\n\x1a\n") || data.starts_with(b"\xff\xd8\xff") ||
+    data.starts_with(b"GIF8") || data.starts_with(b"II*\x00") || data.starts_with(b"MM\x00*")
+}
@@ -0,0 +1,119 @@
+//! Implementing of some types of errors.
+//!
+//! TODO Write docs.
+
+use rusqlite;
+#[cfg(feature = "postgres")]
+use postgres;
+
+use std::io;
+use std::fmt;
+use std::error::Error;
+
+pub type DbResult<T> = Result<T, DbError>;
+
+#[derive(Debug)]
+pub enum DbError {
+    /// The requested row doesn't exist, whatever the storage is.
+    NotFound,
+    /// The document was changed by another user after it was read, its version differs.
+    Conflict,
+    /// The document is checked out by another user, the message names the user.
+    Locked(String),
+    /// The user may not do it, e.g. only root may break locks of other users.
+    Forbidden(String),
+    SqliteError(rusqlite::Error),
+    IoError(io::Error),
+    #[cfg(feature = "postgres")]
+    PostgresError(postgres::error::Error),
+    #[cfg(feature = "postgres")]
+    PostgresConnectError(postgres::error::ConnectError),
+    /// Data which can't be stored, e.g. a broken archive.
+    InvalidData(String),
+    /// Error answered by the server of `remote::RemoteStorage`, with the HTTP status.
+    ServerError(u16, String),
+}
+
+impl From<rusqlite::Error> for DbError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err {
+            rusqlite::Error::QueryReturnedNoRows => DbError::NotFound,
+            err => DbError::SqliteError(err),
+        }
+    }
+}
+
+impl From<io::Error> for DbError {
+    fn from(err: io::Error) -> Self {
+        DbError::IoError(err)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<postgres::error::Error> for DbError {
+    fn from(err: postgres::error::Error) -> Self {
+        DbError::PostgresError(err)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<postgres::error::ConnectError> for DbError {
+    fn from(err: postgres::error::ConnectError) -> Self {
+        DbError::PostgresConnectError(err)
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DbError::NotFound => f.write_str("Not found"),
+            DbError::Conflict => f.write_str("The document was changed by another user"),
+            DbError::Locked(ref msg) => msg.fmt(f),
+            DbError::Forbidden(ref msg) => msg.fmt(f),
+            DbError::SqliteError(ref err) => err.fmt(f),
+            DbError::IoError(ref err) => err.fmt(f),
+            #[cfg(feature = "postgres")]
+            DbError::PostgresError(ref err) => err.fmt(f),
+            #[cfg(feature = "postgres")]
+            DbError::PostgresConnectError(ref err) => err.fmt(f),
+            DbError::InvalidData(ref msg) => msg.fmt(f),
+            DbError::ServerError(status, ref msg) => write!(f, "{} (HTTP {})", msg, status),
+        }
+    }
+}
+
+impl Error for DbError {
+    fn description(&self) -> &str {
+        match *self {
+            DbError::NotFound => "Not found",
+            DbError::Conflict => "The document was changed by another user",
+            DbError::Locked(ref msg) => msg,
+            DbError::Forbidden(ref msg) => msg,
+            DbError::SqliteError(ref err) => err.description(),
+            DbError::IoError(ref err) => err.description(),
+            #[cfg(feature = "postgres")]
+            DbError::PostgresError(ref err) => err.description(),
+            #[cfg(feature = "postgres")]
+            DbError::PostgresConnectError(ref err) => err.description(),
+            DbError::InvalidData(ref msg) => msg,
+            DbError::ServerError(_, ref msg) => msg,
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            DbError::NotFound => None,
+            DbError::Conflict => None,
+            DbError::Locked(_) => None,
+            DbError::Forbidden(_) => None,
+            DbError::SqliteError(ref err) => Some(err),
+            DbError::IoError(ref err) => Some(err),
+            #[cfg(feature = "postgres")]
+            DbError::PostgresError(ref err) => Some(err),
+            #[cfg(feature = "postgres")]
+            DbError::PostgresConnectError(ref err) => Some(err),
+            DbError::InvalidData(_) => None,
+            DbError::ServerError(..) => None,
+        }
+    }
+}
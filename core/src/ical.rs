@@ -0,0 +1,270 @@
+//! Export of deadlines of documents to iCalendar (RFC 5545).
+//!
+//! Every document becomes a `VTODO` due at `Metadata::date_expired` or an all-day `VEVENT` at
+//! that date. The same feed is served over HTTP by `serve`, so calendar applications can
+//! subscribe to `http://127.0.0.1:<port>/<token>.ics` (`?component=event` for events). The
+//! token is a secret of the user given by `feed_token`, other local users can't guess it.
+
+use chrono::{UTC, Duration};
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::str;
+
+use db::Db;
+use user::User;
+use errors::{DbResult, DbError};
+use document::{Document, DocumentSummary};
+use metadata::Status;
+use query::{Query, Context, Term, Field, Op, Value};
+
+/// Kind of calendar component of a document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Component {
+    Todo,
+    Event,
+}
+
+/// Build the calendar of documents.
+pub fn calendar(docs: &[DocumentSummary], component: Component) -> String {
+    let now = UTC::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines: Vec<String> = vec!["BEGIN:VCALENDAR".to_string(),
+                                      "VERSION:2.0".to_string(),
+                                      "PRODID:-//saruman9//sed_bad//EN".to_string(),
+                                      "CALSCALE:GREGORIAN".to_string()];
+    for doc in docs {
+        let due = doc.date_expired();
+        match component {
+            Component::Todo => {
+                lines.push("BEGIN:VTODO".to_string());
+                lines.push(format!("DUE;VALUE=DATE:{}", due.format("%Y%m%d")));
+                lines.push(format!("STATUS:{}",
+                                   match doc.status() {
+                                       Status::Beginning => "NEEDS-ACTION",
+                                       Status::InProgress => "IN-PROCESS",
+                                       Status::Complete => "COMPLETED",
+                                   }));
+            }
+            Component::Event => {
+                lines.push("BEGIN:VEVENT".to_string());
+                lines.push(format!("DTSTART;VALUE=DATE:{}", due.format("%Y%m%d")));
+                lines.push(format!("DTEND;VALUE=DATE:{}",
+                                   (due + Duration::days(1)).format("%Y%m%d")));
+                lines.push("TRANSP:TRANSPARENT".to_string());
+            }
+        }
+        lines.push(format!("UID:doc-{}@sed_bad", doc.id()));
+        lines.push(format!("DTSTAMP:{}", now));
+        lines.push(format!("LAST-MODIFIED:{}", doc.m_time().format("%Y%m%dT%H%M%SZ")));
+        lines.push(format!("SUMMARY:{}", escape(doc.name())));
+        lines.push(format!("DESCRIPTION:{}",
+                           escape(&format!("Ticket #{}\nAuthor: {}\nResponsible: {}",
+                                           doc.id(),
+                                           doc.author(),
+                                           doc.responsible()))));
+        lines.push(format!("URL:sed_bad://ticket/{}", doc.id()));
+        lines.push(match component {
+            Component::Todo => "END:VTODO".to_string(),
+            Component::Event => "END:VEVENT".to_string(),
+        });
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut calendar = String::new();
+    for line in lines {
+        calendar.push_str(&fold(&line));
+        calendar.push_str("\r\n");
+    }
+    calendar
+}
+
+/// Build the calendar of documents assigned to the user.
+pub fn user_calendar(db: &Db, user: &User, component: Component) -> DbResult<String> {
+    let mut query = Query::default();
+    query.push(Term {
+        field: Field::Responsible,
+        op: Op::Eq,
+        value: Value::Me,
+    });
+    let docs = Document::list_by_due_date(db, &query, &Context::new(user))?;
+    Ok(calendar(&docs, component))
+}
+
+/// Secret token of the calendar feed of the user, it's created on the first call.
+pub fn feed_token(db: &Db, user: &User) -> DbResult<String> {
+    let token: DbResult<String> = db.conn()
+        .query_row_and_then("SELECT token FROM feed_tokens WHERE user_id = ?;",
+                            &[&user.id()],
+                            |row| row.get_checked(0))
+        .map_err(DbError::from);
+    match token {
+        Ok(token) => Ok(token),
+        Err(DbError::NotFound) => {
+            let mut bytes = [0u8; 16];
+            File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+            let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            db.conn()
+                .execute("INSERT INTO feed_tokens VALUES (?, ?);", &[&user.id(), &token])?;
+            Ok(token)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// User of the feed token, `DbError::NotFound` for a wrong one.
+fn user_by_feed_token(db: &Db, token: &str) -> DbResult<User> {
+    let id: i64 = db.conn()
+        .query_row_and_then("SELECT user_id FROM feed_tokens WHERE token = ?;",
+                            &[&token],
+                            |row| row.get_checked(0))
+        .map_err(DbError::from)?;
+    User::get_by_id(db, id)
+}
+
+/// Escape a text value.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Fold a content line longer than 75 octets, multi-byte characters are not split.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded
+}
+
+/// Serve calendars of users on the address in the background, every connection opens its own
+/// connection to the database. Errors of connections are passed to `on_error`.
+pub fn serve<F>(addr: &str, on_error: F) -> io::Result<()>
+    where F: Fn(io::Error) + Send + 'static
+{
+    let listener = TcpListener::bind(addr)?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Err(e) = stream.and_then(handle_connection) {
+                on_error(e);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream) -> io::Result<()> {
+    let mut request_line = String::new();
+    {
+        let mut reader = BufReader::new(&stream);
+        reader.read_line(&mut request_line)?;
+        // Skip headers.
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let mut path_query = parts.next().unwrap_or("").splitn(2, '?');
+    let path = path_query.next().unwrap_or("");
+    let component = if path_query.next() == Some("component=event") {
+        Component::Event
+    } else {
+        Component::Todo
+    };
+
+    let (status, content_type, body) = if method != "GET" {
+        ("405 Method Not Allowed", "text/plain", "Method not allowed.".to_string())
+    } else if !path.starts_with('/') || !path.ends_with(".ics") {
+        ("404 Not Found", "text/plain", "Not found.".to_string())
+    } else {
+        let token = percent_decode(&path[1..path.len() - 4]);
+        match Db::new().and_then(|db| {
+            let user = user_by_feed_token(&db, &token)?;
+            user_calendar(&db, &user, component)
+        }) {
+            Ok(calendar) => ("200 OK", "text/calendar; charset=utf-8", calendar),
+            Err(_) => ("404 Not Found", "text/plain", "Not found.".to_string()),
+        }
+    };
+    write!(stream,
+           "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+           status,
+           content_type,
+           body.len())?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
+
+/// Decode `%XX` sequences of the path.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[test]
+fn escape_and_fold() {
+    assert_eq!(escape("Contract; draft, v2\\3\nnext"),
+               "Contract\\; draft\\, v2\\\\3\\nnext");
+    let line = format!("SUMMARY:{}", (0..10).map(|_| "Договор").collect::<String>());
+    let folded = fold(&line);
+    for part in folded.split("\r\n") {
+        assert!(part.len() <= 75);
+    }
+    assert_eq!(folded.replace("\r\n ", ""), line);
+}
+
+#[test]
+fn feed_tokens() {
+    use temp_dir::TempDir;
+
+    let dir = TempDir::new("ical-test").unwrap();
+    let db = Db::open(dir.join("db.sqlite3")).unwrap().init_root().unwrap();
+    let root = User::get_user(&db, "root").unwrap();
+    let token = feed_token(&db, &root).unwrap();
+    assert_eq!(token.len(), 32);
+    assert_eq!(feed_token(&db, &root).unwrap(), token);
+    assert_eq!(user_by_feed_token(&db, &token).unwrap().name(), "root");
+    match user_by_feed_token(&db, "root") {
+        Err(DbError::NotFound) => {}
+        res => panic!("{:?}", res),
+    }
+}
+
+#[test]
+fn decode_path() {
+    assert_eq!(percent_decode("%D0%B8%D0%B2%D0%B0%D0%BD%D0%BE%D0%B2"), "иванов");
+    assert_eq!(percent_decode("root%2"), "root%2");
+}
@@ -0,0 +1,220 @@
+//! Comparison of graphical documents.
+//!
+//! Images are RGB bitmaps. The second image is scaled to the size of the first one and shifted
+//! to match it (scans are rarely placed the same way), then the pixel difference gives a heatmap
+//! and a similarity score.
+
+/// RGB image, 3 bytes per pixel without padding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bitmap {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Bitmap {
+    pub fn new(width: usize, height: usize, pixels: Vec<u8>) -> Self {
+        assert_eq!(pixels.len(), width * height * 3);
+        Bitmap {
+            width: width,
+            height: height,
+            pixels: pixels,
+        }
+    }
+
+    /// White image.
+    pub fn blank(width: usize, height: usize) -> Self {
+        Bitmap::new(width, height, vec![255; width * height * 3])
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let i = (y * self.width + x) * 3;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, pixel: [u8; 3]) {
+        let i = (y * self.width + x) * 3;
+        self.pixels[i..i + 3].copy_from_slice(&pixel);
+    }
+
+    fn gray(&self, x: usize, y: usize) -> i32 {
+        let pixel = self.pixel(x, y);
+        (pixel[0] as i32 * 30 + pixel[1] as i32 * 59 + pixel[2] as i32 * 11) / 100
+    }
+
+    /// Scale by the nearest neighbour.
+    pub fn resize(&self, width: usize, height: usize) -> Bitmap {
+        if width == self.width && height == self.height {
+            return self.clone();
+        }
+        let mut resized = Bitmap::blank(width, height);
+        if self.width == 0 || self.height == 0 {
+            return resized;
+        }
+        for y in 0..height {
+            for x in 0..width {
+                resized.set_pixel(x, y,
+                                  self.pixel(x * self.width / width, y * self.height / height));
+            }
+        }
+        resized
+    }
+
+    /// Move the image by `dx`, `dy` pixels, uncovered pixels are white.
+    pub fn shift(&self, dx: i32, dy: i32) -> Bitmap {
+        let mut shifted = Bitmap::blank(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (src_x, src_y) = (x as i32 - dx, y as i32 - dy);
+                if src_x >= 0 && src_y >= 0 && (src_x as usize) < self.width &&
+                   (src_y as usize) < self.height {
+                    shifted.set_pixel(x, y, self.pixel(src_x as usize, src_y as usize));
+                }
+            }
+        }
+        shifted
+    }
+}
+
+/// Shift of the other image (of the same size) which matches it to the base one best, at most
+/// `max_shift` pixels in every direction. Large images are compared on a coarse grid.
+pub fn align(base: &Bitmap, other: &Bitmap, max_shift: i32) -> (i32, i32) {
+    let step = ((base.width.max(base.height) / 200).max(1)) as i32;
+    let range = max_shift / step;
+    let (width, height) = (base.width as i32, base.height as i32);
+    let mut best = (0, 0);
+    let mut best_diff = ::std::f64::MAX;
+    for dy in -range..range + 1 {
+        for dx in -range..range + 1 {
+            let (dx, dy) = (dx * step, dy * step);
+            let mut sum = 0i64;
+            let mut count = 0i64;
+            let mut y = dy.max(0);
+            while y < height.min(height + dy) {
+                let mut x = dx.max(0);
+                while x < width.min(width + dx) {
+                    sum += (base.gray(x as usize, y as usize) -
+                            other.gray((x - dx) as usize, (y - dy) as usize))
+                        .abs() as i64;
+                    count += 1;
+                    x += step;
+                }
+                y += step;
+            }
+            if count == 0 {
+                continue;
+            }
+            let diff = sum as f64 / count as f64;
+            // The smallest shift wins among equal ones.
+            if diff < best_diff ||
+               diff == best_diff && dx.abs() + dy.abs() < best.0.abs() + best.1.abs() {
+                best_diff = diff;
+                best = (dx, dy);
+            }
+        }
+    }
+    best
+}
+
+/// Scale the other image to the size of the base one and align it.
+pub fn match_to(base: &Bitmap, other: &Bitmap, max_shift: i32) -> Bitmap {
+    let resized = other.resize(base.width, base.height);
+    let (dx, dy) = align(base, &resized, max_shift);
+    resized.shift(dx, dy)
+}
+
+/// Similarity of images of the same size from 0 (inverted) to 1 (equal).
+pub fn similarity(a: &Bitmap, b: &Bitmap) -> f64 {
+    if a.pixels.is_empty() {
+        return 1.0;
+    }
+    let diff: u64 = a.pixels
+        .iter()
+        .zip(b.pixels.iter())
+        .map(|(&x, &y)| (x as i32 - y as i32).abs() as u64)
+        .sum();
+    1.0 - diff as f64 / (255.0 * a.pixels.len() as f64)
+}
+
+/// The base image in gray with differing pixels in red, the more difference the redder.
+pub fn heatmap(a: &Bitmap, b: &Bitmap) -> Bitmap {
+    let mut heatmap = Bitmap::blank(a.width, a.height);
+    for y in 0..a.height {
+        for x in 0..a.width {
+            let (p, q) = (a.pixel(x, y), b.pixel(x, y));
+            let diff = (0..3).map(|i| (p[i] as i32 - q[i] as i32).abs()).max().unwrap_or(0);
+            let alpha = (diff as f64 * 2.0 / 255.0).min(1.0);
+            // Lightened gray, so red stands out.
+            let gray = (128.0 + a.gray(x, y) as f64 / 2.0) * (1.0 - alpha);
+            heatmap.set_pixel(x, y, [(gray + 255.0 * alpha) as u8, gray as u8, gray as u8]);
+        }
+    }
+    heatmap
+}
+
+/// Mix images, `t` is the weight of the second one.
+pub fn blend(a: &Bitmap, b: &Bitmap, t: f64) -> Bitmap {
+    Bitmap::new(a.width,
+                a.height,
+                a.pixels
+                    .iter()
+                    .zip(b.pixels.iter())
+                    .map(|(&x, &y)| (x as f64 * (1.0 - t) + y as f64 * t).round() as u8)
+                    .collect())
+}
+
+/// The first image left of the column `split`, the second one right of it.
+pub fn split(a: &Bitmap, b: &Bitmap, split: usize) -> Bitmap {
+    let mut result = a.clone();
+    for y in 0..a.height {
+        for x in split.min(a.width)..a.width {
+            result.set_pixel(x, y, b.pixel(x, y));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+fn square(x0: usize, y0: usize) -> Bitmap {
+    let mut bitmap = Bitmap::blank(20, 20);
+    for y in y0..y0 + 5 {
+        for x in x0..x0 + 5 {
+            bitmap.set_pixel(x, y, [0, 0, 0]);
+        }
+    }
+    bitmap
+}
+
+#[test]
+fn align_images() {
+    let base = square(5, 5);
+    let other = square(7, 6);
+    assert_eq!(align(&base, &other, 4), (-2, -1));
+    let matched = match_to(&base, &other, 4);
+    assert_eq!(matched, base);
+    assert_eq!(similarity(&base, &matched), 1.0);
+    assert!(similarity(&base, &other) < 1.0);
+}
+
+#[test]
+fn compose_images() {
+    let a = Bitmap::new(2, 1, vec![0, 0, 0, 0, 0, 0]);
+    let b = Bitmap::new(2, 1, vec![255, 255, 255, 255, 255, 255]);
+    assert_eq!(blend(&a, &b, 0.5).pixels(), &[128, 128, 128, 128, 128, 128]);
+    assert_eq!(split(&a, &b, 1).pixels(), &[0, 0, 0, 255, 255, 255]);
+    assert_eq!(heatmap(&a, &b).pixel(0, 0), [255, 0, 0]);
+    assert_eq!(heatmap(&a, &a).pixel(0, 0), [128, 128, 128]);
+    assert_eq!(similarity(&a, &b), 0.0);
+}
@@ -0,0 +1,384 @@
+//! Bulk import of documents from a directory tree.
+//!
+//! Every file becomes a document (or every folder, with `ImportOptions::per_folder`), the file
+//! is its content. The relative path of the folder holding the file is the category of the
+//! document, files in the root folder get the default category. The tree is scanned first, the
+//! report of the scan is the dry run, then the whole import runs in one transaction.
+
+use chrono::{UTC, Duration, Datelike};
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use db::Db;
+use user::User;
+use category::Category;
+use document::{self, Document};
+use errors::DbResult;
+use json;
+
+pub struct ImportOptions {
+    pub author: User,
+    pub responsible: User,
+    /// Category of files in the root folder.
+    pub default_category: String,
+    pub date_expired: (u32, u32, u32),
+    /// One document per folder instead of one per file.
+    pub per_folder: bool,
+}
+
+/// Document to create.
+#[derive(Debug)]
+pub struct ImportItem {
+    name: String,
+    category: String,
+    files: Vec<PathBuf>,
+}
+
+impl ImportItem {
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    pub fn category(&self) -> &str {
+        self.category.as_ref()
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Content of the document, text files of a folder are joined.
+    fn read(&self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        for (i, path) in self.files.iter().enumerate() {
+            if i > 0 {
+                data.extend_from_slice(b"\n\n");
+            }
+            data.extend_from_slice(&read_file(path)?);
+        }
+        Ok(data)
+    }
+}
+
+/// Result of scanning a directory tree.
+#[derive(Debug)]
+pub struct Report {
+    items: Vec<ImportItem>,
+    /// Files which won't be imported with the reason.
+    skipped: Vec<(PathBuf, String)>,
+}
+
+impl Report {
+    pub fn items(&self) -> &[ImportItem] {
+        &self.items
+    }
+
+    pub fn skipped(&self) -> &[(PathBuf, String)] {
+        &self.skipped
+    }
+
+    /// Human-readable report, categories which will be created are marked.
+    pub fn to_text(&self, db: &Db) -> DbResult<String> {
+        let mut text = format!("Documents to import: {}\n", self.items.len());
+        for item in &self.items {
+            let new = if Category::new(item.category()).exists(db)? {
+                ""
+            } else {
+                " (new category)"
+            };
+            text.push_str(&format!("  {} [{}{}]: {} file(s)\n",
+                                   item.name(),
+                                   item.category(),
+                                   new,
+                                   item.files().len()));
+        }
+        if !self.skipped.is_empty() {
+            text.push_str(&format!("Skipped: {}\n", self.skipped.len()));
+            for &(ref path, ref reason) in &self.skipped {
+                text.push_str(&format!("  {}: {}\n", path.display(), reason));
+            }
+        }
+        Ok(text)
+    }
+}
+
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Entries of the folder sorted by name, hidden ones are ignored.
+fn read_dir_sorted(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path.file_name()
+            .map(|name| name.to_string_lossy().starts_with('.'))
+            .unwrap_or(true);
+        if !hidden {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Category of the folder relative to the root, `None` for the root itself.
+fn folder_category(root: &Path, dir: &Path) -> Option<String> {
+    dir.strip_prefix(root)
+        .ok()
+        .map(|relative| {
+            relative.components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<String>>()
+                .join("/")
+        })
+        .and_then(|category| if category.is_empty() {
+            None
+        } else {
+            Some(category)
+        })
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(String::new)
+}
+
+/// Find documents to import in the directory tree.
+pub fn scan(root: &Path, options: &ImportOptions) -> io::Result<Report> {
+    let mut report = Report {
+        items: Vec::new(),
+        skipped: Vec::new(),
+    };
+    scan_dir(root, root, options, &mut report)?;
+    Ok(report)
+}
+
+fn scan_dir(root: &Path,
+            dir: &Path,
+            options: &ImportOptions,
+            report: &mut Report)
+            -> io::Result<()> {
+    let category = folder_category(root, dir);
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in read_dir_sorted(dir)? {
+        if path.is_dir() {
+            scan_dir(root, &path, options, report)?;
+        } else if options.per_folder {
+            files.push(path);
+        } else {
+            report.items.push(ImportItem {
+                name: file_name(&path),
+                category: category.clone().unwrap_or_else(|| options.default_category.clone()),
+                files: vec![path],
+            });
+        }
+    }
+    if !options.per_folder || files.is_empty() {
+        return Ok(());
+    }
+    if category.is_none() {
+        for path in files {
+            report.skipped.push((path, "not in a folder".to_string()));
+        }
+        return Ok(());
+    }
+
+    // Several files are joined, so only text ones are taken.
+    if files.len() > 1 {
+        let mut texts: Vec<PathBuf> = Vec::new();
+        for path in files {
+            let data = read_file(&path)?;
+            if document::is_image(&data) || String::from_utf8(data).is_err() {
+                report.skipped
+                    .push((path, "not a text file in a folder of several files".to_string()));
+            } else {
+                texts.push(path);
+            }
+        }
+        files = texts;
+        if files.is_empty() {
+            return Ok(());
+        }
+    }
+    let parent_category = dir.parent().and_then(|parent| folder_category(root, parent));
+    report.items.push(ImportItem {
+        name: file_name(dir),
+        category: parent_category.unwrap_or_else(|| options.default_category.clone()),
+        files: files,
+    });
+    Ok(())
+}
+
+fn get_or_create_category(db: &Db, name: &str) -> DbResult<Category> {
+    let mut category = Category::new(name);
+    if category.exists(db)? {
+        return Category::get_category(db, name);
+    }
+    category.save_to_db(db)?;
+    Ok(category)
+}
+
+/// Create documents of the report in one transaction, nothing is created on error. `progress`
+/// gets the number of imported documents and the total number. Return ids of new documents.
+pub fn import(db: &Db,
+              report: &Report,
+              options: &ImportOptions,
+              progress: &mut FnMut(usize, usize))
+              -> DbResult<Vec<i64>> {
+    db.transaction(|db| {
+        let mut ids: Vec<i64> = Vec::new();
+        for (i, item) in report.items.iter().enumerate() {
+            let category = get_or_create_category(db, item.category())?;
+            let mut doc = Document::new(item.name().to_string(),
+                                        &options.author,
+                                        category,
+                                        options.responsible.clone(),
+                                        options.date_expired,
+                                        None);
+            doc.set_data(Some(item.read()?));
+            ids.push(doc.save_to_db(db)?);
+            progress(i + 1, report.items.len());
+        }
+        Ok(ids)
+    })
+}
+
+const USAGE: &'static str = "\
+Usage: sed_bad import [OPTIONS] DIRECTORY
+
+Options:
+    --author NAME        author of documents (required)
+    --responsible NAME   responsible user, the author by default
+    --category NAME      category of files in DIRECTORY, \"Imported\" by default
+    --expires YYYY-MM-DD expired date, in 30 days by default
+    --per-folder         one document per folder instead of one per file
+    --dry-run            only print the report";
+
+/// `None` for dates which don't exist, e.g. `2017-02-30`.
+fn parse_date(date: &str) -> Option<(u32, u32, u32)> {
+    json::parse_date(date).map(|date| (date.year() as u32, date.month(), date.day()))
+}
+
+/// Import from the command line, `args` follow the `import` command. `progress` is called as by
+/// `import`, the report of the scan and the result are returned to be printed.
+pub fn cli(args: &[String], progress: &mut FnMut(usize, usize)) -> Result<String, String> {
+    let mut author: Option<String> = None;
+    let mut responsible: Option<String> = None;
+    let mut category = "Imported".to_string();
+    let expires = UTC::now() + Duration::days(30);
+    let mut date_expired = (expires.year() as u32, expires.month(), expires.day());
+    let mut per_folder = false;
+    let mut dry_run = false;
+    let mut dir: Option<PathBuf> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--author" => author = args.next().cloned(),
+            "--responsible" => responsible = args.next().cloned(),
+            "--category" => {
+                category = args.next().cloned().ok_or_else(|| USAGE.to_string())?;
+            }
+            "--expires" => {
+                date_expired = args.next()
+                    .and_then(|date| parse_date(date))
+                    .ok_or_else(|| format!("Wrong expired date.\n{}", USAGE))?;
+            }
+            "--per-folder" => per_folder = true,
+            "--dry-run" => dry_run = true,
+            _ if arg.starts_with("--") || dir.is_some() => return Err(USAGE.to_string()),
+            _ => dir = Some(PathBuf::from(arg)),
+        }
+    }
+    let (author, dir) = match (author, dir) {
+        (Some(author), Some(dir)) => (author, dir),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let db = Db::new()
+        .and_then(|d| d.init_root())
+        .map_err(|e| format!("Error of opening database.\n{}", e))?;
+    let author = User::get_user(&db, &author).map_err(|e| format!("Unknown author.\n{}", e))?;
+    let responsible = match responsible {
+        Some(name) => {
+            User::get_user(&db, &name).map_err(|e| format!("Unknown responsible user.\n{}", e))?
+        }
+        None => author.clone(),
+    };
+    let options = ImportOptions {
+        author: author,
+        responsible: responsible,
+        default_category: category,
+        date_expired: date_expired,
+        per_folder: per_folder,
+    };
+
+    let report = scan(&dir, &options)
+        .map_err(|e| format!("Error of scanning {}.\n{}", dir.display(), e))?;
+    let mut text = report.to_text(&db).map_err(|e| e.to_string())?;
+    if dry_run {
+        return Ok(text);
+    }
+    let ids = import(&db, &report, &options, progress)
+        .map_err(|e| format!("\nError of importing, nothing was imported.\n{}", e))?;
+    text.push_str(&format!("Done, {} document(s) created.\n", ids.len()));
+    Ok(text)
+}
+
+#[test]
+fn scan_directory_tree() {
+    use std::io::Write;
+    use temp_dir::TempDir;
+
+    let temp_dir = TempDir::new("import-test").unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("Contracts/2017")).unwrap();
+    File::create(root.join("readme.txt")).unwrap().write_all(b"readme").unwrap();
+    File::create(root.join("Contracts/a.txt")).unwrap().write_all(b"a").unwrap();
+    File::create(root.join("Contracts/2017/b.txt")).unwrap().write_all(b"b").unwrap();
+    File::create(root.join("Contracts/2017/c.png"))
+        .unwrap()
+        .write_all(b"\x89PNG\r\n\x1a\n")
+        .unwrap();
+
+    let mut options = ImportOptions {
+        author: User::new("root", "toor"),
+        responsible: User::new("root", "toor"),
+        default_category: "Imported".to_string(),
+        date_expired: (2017, 1, 1),
+        per_folder: false,
+    };
+    let report = scan(&root, &options).unwrap();
+    let items: Vec<(&str, &str)> = report.items()
+        .iter()
+        .map(|item| (item.name(), item.category()))
+        .collect();
+    assert_eq!(items,
+               vec![("b.txt", "Contracts/2017"),
+                    ("c.png", "Contracts/2017"),
+                    ("a.txt", "Contracts"),
+                    ("readme.txt", "Imported")]);
+
+    options.per_folder = true;
+    let report = scan(&root, &options).unwrap();
+    let items: Vec<(&str, &str, usize)> = report.items()
+        .iter()
+        .map(|item| (item.name(), item.category(), item.files().len()))
+        .collect();
+    assert_eq!(items, vec![("2017", "Contracts", 1), ("Contracts", "Imported", 1)]);
+    assert_eq!(report.skipped().len(), 2);
+    assert_eq!(report.items()[0].read().unwrap(), b"b");
+}
+
+#[test]
+fn parse_expired_date() {
+    assert_eq!(parse_date("2017-03-08"), Some((2017, 3, 8)));
+    assert_eq!(parse_date("2017-13-08"), None);
+    assert_eq!(parse_date("2017-02-30"), None);
+    assert_eq!(parse_date("2017-04-31"), None);
+    assert_eq!(parse_date("08.03.2017"), None);
+}
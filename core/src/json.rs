@@ -0,0 +1,297 @@
+//! JSON representation of users, categories, documents, comments and locks.
+//!
+//! Statuses are `beginning`, `in-progress` and `complete`, dates of expiry are `YYYY-MM-DD`
+//! and times are RFC 3339. Users are parsed without passwords. Content of documents, which may
+//! be binary, is base64.
+
+use chrono::{DateTime, UTC, TimeZone, NaiveDate, Datelike};
+use rustc_serialize::json::Json;
+use rustc_serialize::base64::{ToBase64, FromBase64, STANDARD};
+
+use user::User;
+use category::Category;
+use document::{Document, DocumentSummary};
+use comment::Comment;
+use lock::Lock;
+use metadata::{Metadata, Status};
+use permission::Permission;
+use errors::{DbResult, DbError};
+
+pub fn status_name(status: &Status) -> &'static str {
+    match *status {
+        Status::Beginning => "beginning",
+        Status::InProgress => "in-progress",
+        Status::Complete => "complete",
+    }
+}
+
+pub fn parse_status(status: &str) -> Option<Status> {
+    match status {
+        "beginning" => Some(Status::Beginning),
+        "in-progress" => Some(Status::InProgress),
+        "complete" => Some(Status::Complete),
+        _ => None,
+    }
+}
+
+/// Parse the date of expiry, `YYYY-MM-DD`.
+pub fn parse_date(date: &str) -> Option<DateTime<UTC>> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .map(|date| UTC.ymd(date.year(), date.month(), date.day()).and_hms(0, 0, 0))
+}
+
+pub fn format_date(date: &DateTime<UTC>) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+pub fn user(user: &User) -> Json {
+    object(vec![("id", Json::I64(user.id())), ("name", string(user.name()))])
+}
+
+pub fn category(category: &Category) -> Json {
+    object(vec![("id", Json::I64(category.id())), ("name", string(category.name()))])
+}
+
+pub fn summary(summary: &DocumentSummary) -> Json {
+    object(vec![("id", Json::I64(summary.id())),
+                ("name", string(summary.name())),
+                ("status", string(status_name(&summary.status()))),
+                ("author", string(summary.author())),
+                ("responsible", string(summary.responsible())),
+                ("c_time", string(summary.c_time().to_rfc3339())),
+                ("m_time", string(summary.m_time().to_rfc3339())),
+                ("date_expired", string(format_date(&summary.date_expired())))])
+}
+
+pub fn metadata(metadata: &Metadata) -> Json {
+    object(vec![("id", Json::I64(metadata.id())),
+                ("status", string(status_name(&metadata.status()))),
+                ("author", string(metadata.author().name())),
+                ("author_id", Json::I64(metadata.author().id())),
+                ("category", string(metadata.category().name())),
+                ("category_id", Json::I64(metadata.category().id())),
+                ("c_time", string(metadata.c_time().to_rfc3339())),
+                ("m_time", string(metadata.m_time().to_rfc3339())),
+                ("date_expired", string(format_date(&metadata.date_expired())))])
+}
+
+/// Document with its metadata and comments, without the content.
+pub fn document(doc: &Document) -> Json {
+    let metadata = doc.metadata();
+    object(vec![("id", Json::I64(doc.id())),
+                ("name", string(doc.name())),
+                ("metadata_id", Json::I64(metadata.id())),
+                ("status", string(status_name(&metadata.status()))),
+                ("author", string(metadata.author().name())),
+                ("author_id", Json::I64(metadata.author().id())),
+                ("responsible", string(doc.responsible().name())),
+                ("responsible_id", Json::I64(doc.responsible().id())),
+                ("category", string(metadata.category().name())),
+                ("category_id", Json::I64(metadata.category().id())),
+                ("c_time", string(metadata.c_time().to_rfc3339())),
+                ("m_time", string(metadata.m_time().to_rfc3339())),
+                ("date_expired", string(format_date(&metadata.date_expired()))),
+                ("permission", Json::I64(doc.permission().get_int())),
+                ("version", Json::I64(doc.version())),
+                ("has_data", Json::Boolean(doc.data().is_some())),
+                ("is_image", Json::Boolean(doc.is_image())),
+                ("comments", Json::Array(doc.comments().iter().map(comment).collect()))])
+}
+
+pub fn comment(comment: &Comment) -> Json {
+    object(vec![("id", Json::I64(comment.id())),
+                ("author", string(comment.author().name())),
+                ("author_id", Json::I64(comment.author().id())),
+                ("text", string(comment.text())),
+                ("c_time", string(comment.c_time().to_rfc3339())),
+                ("m_time",
+                 comment.m_time().map(|time| string(time.to_rfc3339())).unwrap_or(Json::Null)),
+                ("parent_id", comment.parent_id().map(Json::I64).unwrap_or(Json::Null)),
+                ("resolved", Json::Boolean(comment.resolved())),
+                ("deleted", Json::Boolean(comment.is_deleted()))])
+}
+
+pub fn content(data: &[u8]) -> Json {
+    string(data.to_base64(STANDARD))
+}
+
+pub fn lock(lock: &Lock) -> Json {
+    object(vec![("doc_id", Json::I64(lock.doc_id())),
+                ("user", string(lock.user().name())),
+                ("user_id", Json::I64(lock.user().id())),
+                ("c_time", string(lock.c_time().to_rfc3339()))])
+}
+
+pub fn parse_user(json: &Json) -> DbResult<User> {
+    let mut user = User::new(str_field(json, "name")?, "");
+    user.set_id(int_field(json, "id")?);
+    Ok(user)
+}
+
+pub fn parse_category(json: &Json) -> DbResult<Category> {
+    let mut category = Category::new(str_field(json, "name")?);
+    category.set_id(int_field(json, "id")?);
+    Ok(category)
+}
+
+pub fn parse_summary(json: &Json) -> DbResult<DocumentSummary> {
+    let date_expired = date_field(json, "date_expired")?;
+    let mut doc = Document::new(str_field(json, "name")?.to_string(),
+                                &User::new(str_field(json, "author")?, ""),
+                                Category::new(""),
+                                User::new(str_field(json, "responsible")?, ""),
+                                (date_expired.year() as u32,
+                                 date_expired.month(),
+                                 date_expired.day()),
+                                None);
+    doc.set_id(int_field(json, "id")?);
+    doc.metadata_mut().set_status(status_field(json)?);
+    doc.metadata_mut().set_c_time(time_field(json, "c_time")?);
+    doc.metadata_mut().set_m_time(time_field(json, "m_time")?);
+    Ok(DocumentSummary::from(&doc))
+}
+
+pub fn parse_metadata(json: &Json) -> DbResult<Metadata> {
+    let date_expired = date_field(json, "date_expired")?;
+    let mut author = User::new(str_field(json, "author")?, "");
+    author.set_id(int_field(json, "author_id")?);
+    let mut category = Category::new(str_field(json, "category")?);
+    category.set_id(int_field(json, "category_id")?);
+    let mut metadata = Metadata::new(&author,
+                                     category,
+                                     (date_expired.year() as u32,
+                                      date_expired.month(),
+                                      date_expired.day()));
+    metadata.set_id(int_field(json, "id")?);
+    metadata.set_status(status_field(json)?);
+    metadata.set_c_time(time_field(json, "c_time")?);
+    metadata.set_m_time(time_field(json, "m_time")?);
+    Ok(metadata)
+}
+
+/// Parse the document without the content.
+pub fn parse_document(json: &Json) -> DbResult<Document> {
+    let mut metadata_json = json.clone();
+    if let Json::Object(ref mut object) = metadata_json {
+        object.insert("id".to_string(), Json::I64(int_field(json, "metadata_id")?));
+    }
+    let metadata = parse_metadata(&metadata_json)?;
+    let mut responsible = User::new(str_field(json, "responsible")?, "");
+    responsible.set_id(int_field(json, "responsible_id")?);
+    let mut doc = Document::new(str_field(json, "name")?.to_string(),
+                                metadata.author(),
+                                metadata.category().clone(),
+                                responsible,
+                                (1970, 1, 1),
+                                None);
+    doc.set_id(int_field(json, "id")?);
+    doc.set_permission(Permission::from_int(int_field(json, "permission")?));
+    doc.set_version(int_field(json, "version")?);
+    *doc.comments_mut() = match json.find("comments").and_then(|comments| comments.as_array()) {
+        Some(comments) => comments.iter().map(parse_comment).collect::<DbResult<_>>()?,
+        None => return Err(invalid("comments")),
+    };
+    *doc.metadata_mut() = metadata;
+    Ok(doc)
+}
+
+pub fn parse_comment(json: &Json) -> DbResult<Comment> {
+    let mut author = User::new(str_field(json, "author")?, "");
+    author.set_id(int_field(json, "author_id")?);
+    let text = str_field(json, "text")?.to_string();
+    let mut comment = match json.find("parent_id") {
+        Some(&Json::Null) | None => Comment::new(&author, text),
+        Some(_) => Comment::new_reply(&author, text, int_field(json, "parent_id")?),
+    };
+    comment.set_id(int_field(json, "id")?);
+    comment.set_c_time(time_field(json, "c_time")?);
+    if json.find("deleted").and_then(|deleted| deleted.as_boolean()) == Some(true) {
+        comment.set_deleted();
+    }
+    comment.set_resolved_flag(json.find("resolved").and_then(|resolved| resolved.as_boolean()) ==
+                              Some(true));
+    comment.set_m_time(match json.find("m_time") {
+        Some(&Json::Null) | None => None,
+        Some(_) => Some(time_field(json, "m_time")?),
+    });
+    Ok(comment)
+}
+
+pub fn parse_content(json: &Json) -> DbResult<Vec<u8>> {
+    json.as_string()
+        .and_then(|content| content.from_base64().ok())
+        .ok_or_else(|| DbError::InvalidData("The content must be base64.".to_string()))
+}
+
+pub fn parse_lock(json: &Json) -> DbResult<Lock> {
+    let mut user = User::new(str_field(json, "user")?, "");
+    user.set_id(int_field(json, "user_id")?);
+    Ok(Lock::new(int_field(json, "doc_id")?, user, time_field(json, "c_time")?))
+}
+
+fn invalid(name: &str) -> DbError {
+    DbError::InvalidData(format!("Wrong or missing field `{}` in JSON.", name))
+}
+
+fn str_field<'a>(json: &'a Json, name: &str) -> DbResult<&'a str> {
+    json.find(name).and_then(|value| value.as_string()).ok_or_else(|| invalid(name))
+}
+
+fn int_field(json: &Json, name: &str) -> DbResult<i64> {
+    json.find(name).and_then(|value| value.as_i64()).ok_or_else(|| invalid(name))
+}
+
+fn time_field(json: &Json, name: &str) -> DbResult<DateTime<UTC>> {
+    DateTime::parse_from_rfc3339(str_field(json, name)?)
+        .map(|time| time.with_timezone(&UTC))
+        .map_err(|_| invalid(name))
+}
+
+fn date_field(json: &Json, name: &str) -> DbResult<DateTime<UTC>> {
+    parse_date(str_field(json, name)?).ok_or_else(|| invalid(name))
+}
+
+fn status_field(json: &Json) -> DbResult<Status> {
+    parse_status(str_field(json, "status")?).ok_or_else(|| invalid("status"))
+}
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect())
+}
+
+fn string<S: Into<String>>(text: S) -> Json {
+    Json::String(text.into())
+}
+
+#[test]
+fn document_to_json() {
+    let author = User::new("ivan", "secret");
+    let doc = Document::new("Contract".to_string(),
+                            &author,
+                            Category::new("Contracts"),
+                            author.clone(),
+                            (2017, 3, 8),
+                            Some(Comment::new(&author, "Sign it".to_string())));
+    let json = document(&doc);
+    assert_eq!(json.find("status").unwrap().as_string(), Some("beginning"));
+    assert_eq!(json.find("date_expired").unwrap().as_string(), Some("2017-03-08"));
+    assert_eq!(json.find("comments").unwrap().as_array().unwrap().len(), 1);
+    for status in &[Status::Beginning, Status::InProgress, Status::Complete] {
+        assert_eq!(parse_status(status_name(status)).as_ref(), Some(status));
+    }
+    assert_eq!(parse_date("2017-03-08").map(|date| format_date(&date)),
+               Some("2017-03-08".to_string()));
+    assert!(parse_date("08.03.2017").is_none());
+    assert_eq!(parse_content(&content(b"\x89PNG")).unwrap(), b"\x89PNG");
+    assert!(parse_content(&string("not base64!")).is_err());
+
+    let parsed = parse_document(&json).unwrap();
+    assert_eq!(parsed.name(), "Contract");
+    assert_eq!(parsed.metadata().date_expired(), doc.metadata().date_expired());
+    assert_eq!(parsed.comments()[0].text(), "Sign it");
+    assert_eq!(document(&parsed), json);
+    assert_eq!(parse_summary(&summary(&DocumentSummary::from(&doc))).unwrap().name(),
+               "Contract");
+    assert!(parse_comment(&user(&author)).is_err());
+}
@@ -0,0 +1,609 @@
+//! Import from exports of legacy document management systems.
+//!
+//! A mapping file tells where the fields of documents are in a CSV or XML export. It consists of
+//! `key = value` lines, `#` starts a comment:
+//!
+//! ```text
+//! format = xml
+//! records = /export/document
+//! name = title
+//! category = folder
+//! author = @owner
+//! c_time = created
+//! comments = notes/note
+//! comment.text = .
+//! comment.author = @by
+//! ```
+//!
+//! For CSV values are names of columns (the first line), for XML they are XPath expressions,
+//! `records` selects records and `comments` selects comments of a record, the expressions of
+//! fields are relative to them. Missing users and categories are created, created and
+//! modification times are preserved, records which can't be imported are rejected with the
+//! reason and the rest are imported in one transaction.
+
+use chrono::{DateTime, UTC, TimeZone, NaiveDate, Datelike};
+use sxd_document::parser;
+use sxd_xpath::{Factory, Context, Value, XPath};
+use sxd_xpath::nodeset::Node;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use db::Db;
+use user::User;
+use category::Category;
+use comment::Comment;
+use document::Document;
+use metadata::Status;
+use errors::{DbResult, DbError};
+
+/// Fields of documents, which may be mapped.
+const DOC_FIELDS: &'static [&'static str] = &["name",
+                                              "category",
+                                              "author",
+                                              "responsible",
+                                              "status",
+                                              "c_time",
+                                              "m_time",
+                                              "date_expired",
+                                              "data",
+                                              "data_file"];
+/// Fields of comments, which may be mapped.
+const COMMENT_FIELDS: &'static [&'static str] = &["comment.author",
+                                                  "comment.text",
+                                                  "comment.c_time"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Xml,
+}
+
+#[derive(Debug)]
+pub struct Mapping {
+    format: Format,
+    /// XPath of records.
+    records: String,
+    /// XPath of comments of a record.
+    comments: Option<String>,
+    delimiter: char,
+    time_format: String,
+    date_format: String,
+    fields: HashMap<String, String>,
+}
+
+impl Mapping {
+    pub fn parse(text: &str) -> Result<Mapping, String> {
+        let mut format: Option<Format> = None;
+        let mut mapping = Mapping {
+            format: Format::Csv,
+            records: String::new(),
+            comments: None,
+            delimiter: ',',
+            time_format: "%Y-%m-%d %H:%M:%S".to_string(),
+            date_format: "%Y-%m-%d".to_string(),
+            fields: HashMap::new(),
+        };
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(pos) => (line[..pos].trim(), line[pos + 1..].trim()),
+                None => return Err(format!("Line {}: `key = value` is expected.", i + 1)),
+            };
+            match key {
+                "format" => {
+                    format = match value {
+                        "csv" => Some(Format::Csv),
+                        "xml" => Some(Format::Xml),
+                        _ => return Err(format!("Line {}: unknown format `{}`.", i + 1, value)),
+                    }
+                }
+                "records" => mapping.records = value.to_string(),
+                "comments" => mapping.comments = Some(value.to_string()),
+                "delimiter" => {
+                    let mut chars = value.chars();
+                    mapping.delimiter = match (chars.next(), chars.next()) {
+                        (Some(c), None) => c,
+                        _ if value == "\\t" => '\t',
+                        _ => return Err(format!("Line {}: one character is expected.", i + 1)),
+                    }
+                }
+                "time_format" => mapping.time_format = value.to_string(),
+                "date_format" => mapping.date_format = value.to_string(),
+                _ if DOC_FIELDS.contains(&key) || COMMENT_FIELDS.contains(&key) => {
+                    mapping.fields.insert(key.to_string(), value.to_string());
+                }
+                _ => return Err(format!("Line {}: unknown key `{}`.", i + 1, key)),
+            }
+        }
+        mapping.format = format.ok_or_else(|| "The format isn't set.".to_string())?;
+        if !mapping.fields.contains_key("name") {
+            return Err("The name of documents isn't mapped.".to_string());
+        }
+        if mapping.format == Format::Xml && mapping.records.is_empty() {
+            return Err("Records of the XML export aren't set.".to_string());
+        }
+        Ok(mapping)
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Parse the time by the time format, or the date by the date format.
+    fn parse_time(&self, value: &str) -> Result<DateTime<UTC>, String> {
+        UTC.datetime_from_str(value, &self.time_format)
+            .or_else(|_| {
+                NaiveDate::parse_from_str(value, &self.date_format)
+                    .map(|date| UTC.from_utc_datetime(&date.and_hms(0, 0, 0)))
+            })
+            .map_err(|_| format!("wrong time `{}`", value))
+    }
+}
+
+/// Values of mapped fields of a record of the export.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Record {
+    fields: HashMap<String, String>,
+    comments: Vec<HashMap<String, String>>,
+}
+
+impl Record {
+    /// Non-empty value of the field.
+    fn get(&self, field: &str) -> Option<&str> {
+        self.fields.get(field).map(|value| value.trim()).and_then(|value| if value.is_empty() {
+            None
+        } else {
+            Some(value)
+        })
+    }
+}
+
+/// Split CSV text into rows (RFC 4180, quoted fields may contain delimiters and line breaks).
+fn parse_csv(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut row: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    quoted = false;
+                }
+            } else {
+                field.push(c);
+            }
+        } else if c == '"' {
+            quoted = true;
+        } else if c == delimiter {
+            row.push(field);
+            field = String::new();
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            row.push(field);
+            field = String::new();
+            rows.push(row);
+            row = Vec::new();
+        } else {
+            field.push(c);
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Read records of the CSV export, the first row holds names of columns.
+pub fn read_csv(text: &str, mapping: &Mapping) -> Result<Vec<Record>, String> {
+    let mut rows = parse_csv(text.trim_left_matches('\u{feff}'), mapping.delimiter).into_iter();
+    let header = rows.next().unwrap_or_else(Vec::new);
+    let mut columns: Vec<(String, usize)> = Vec::new();
+    for (field, column) in &mapping.fields {
+        match header.iter().position(|name| name.trim() == column) {
+            Some(index) => columns.push((field.clone(), index)),
+            None => return Err(format!("No column `{}` in the export.", column)),
+        }
+    }
+
+    let mut records: Vec<Record> = Vec::new();
+    for row in rows {
+        if row.iter().all(|value| value.is_empty()) {
+            continue;
+        }
+        let mut record = Record::default();
+        let mut comment: HashMap<String, String> = HashMap::new();
+        for &(ref field, index) in &columns {
+            let value = row.get(index).cloned().unwrap_or_else(String::new);
+            if field.starts_with("comment.") {
+                comment.insert(field.clone(), value);
+            } else {
+                record.fields.insert(field.clone(), value);
+            }
+        }
+        if comment.get("comment.text").map(|text| !text.trim().is_empty()).unwrap_or(false) {
+            record.comments.push(comment);
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn build_xpath(factory: &Factory, expr: &str) -> Result<XPath, String> {
+    match factory.build(expr) {
+        Ok(Some(xpath)) => Ok(xpath),
+        Ok(None) => Err(format!("Empty XPath expression `{}`.", expr)),
+        Err(e) => Err(format!("Wrong XPath expression `{}`: {:?}.", expr, e)),
+    }
+}
+
+/// Nodes selected by the XPath in the context of the node.
+fn select_nodes<'d, N>(xpath: &XPath,
+                       context: &Context<'d>,
+                       node: N)
+                       -> Result<Vec<Node<'d>>, String>
+    where N: Into<Node<'d>>
+{
+    match xpath.evaluate(context, node) {
+        Ok(Value::Nodeset(nodes)) => Ok(nodes.document_order()),
+        Ok(_) => Err("XPath of records or comments doesn't select nodes.".to_string()),
+        Err(e) => Err(format!("Error of evaluating XPath: {:?}.", e)),
+    }
+}
+
+/// Read records of the XML export.
+pub fn read_xml(text: &str, mapping: &Mapping) -> Result<Vec<Record>, String> {
+    let package = parser::parse(text).map_err(|e| format!("Error of parsing XML: {:?}.", e))?;
+    let document = package.as_document();
+    let factory = Factory::new();
+    let context = Context::new();
+
+    let mut doc_fields: Vec<(String, XPath)> = Vec::new();
+    let mut comment_fields: Vec<(String, XPath)> = Vec::new();
+    for (field, expr) in &mapping.fields {
+        let xpath = build_xpath(&factory, expr)?;
+        if field.starts_with("comment.") {
+            comment_fields.push((field.clone(), xpath));
+        } else {
+            doc_fields.push((field.clone(), xpath));
+        }
+    }
+    let comments_xpath = match mapping.comments {
+        Some(ref expr) => Some(build_xpath(&factory, expr)?),
+        None => None,
+    };
+
+    let mut records: Vec<Record> = Vec::new();
+    let nodes = select_nodes(&build_xpath(&factory, &mapping.records)?,
+                             &context,
+                             document.root())?;
+    for node in nodes {
+        let mut record = Record::default();
+        for &(ref field, ref xpath) in &doc_fields {
+            let value = xpath.evaluate(&context, node)
+                .map_err(|e| format!("Error of evaluating XPath: {:?}.", e))?;
+            record.fields.insert(field.clone(), value.string());
+        }
+        if let Some(ref comments_xpath) = comments_xpath {
+            for comment_node in select_nodes(comments_xpath, &context, node)? {
+                let mut comment: HashMap<String, String> = HashMap::new();
+                for &(ref field, ref xpath) in &comment_fields {
+                    let value = xpath.evaluate(&context, comment_node)
+                        .map_err(|e| format!("Error of evaluating XPath: {:?}.", e))?;
+                    comment.insert(field.clone(), value.string());
+                }
+                record.comments.push(comment);
+            }
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Result of the import.
+#[derive(Debug, Default)]
+pub struct ImportLog {
+    pub imported: Vec<i64>,
+    pub created_users: Vec<String>,
+    pub created_categories: Vec<String>,
+    /// Numbers of records (from 1) with reasons of rejecting.
+    pub rejected: Vec<(usize, String)>,
+}
+
+impl ImportLog {
+    pub fn to_text(&self) -> String {
+        let mut text = format!("Imported documents: {}\n", self.imported.len());
+        if !self.created_users.is_empty() {
+            text.push_str(&format!("Created users (root has to set their passwords): {}\n",
+                                   self.created_users.join(", ")));
+        }
+        if !self.created_categories.is_empty() {
+            text.push_str(&format!("Created categories: {}\n",
+                                   self.created_categories.join(", ")));
+        }
+        if !self.rejected.is_empty() {
+            text.push_str(&format!("Rejected records: {}\n", self.rejected.len()));
+            for &(number, ref reason) in &self.rejected {
+                text.push_str(&format!("  record {}: {}\n", number, reason));
+            }
+        }
+        text
+    }
+}
+
+fn parse_status(value: &str) -> Result<Status, String> {
+    match value.to_lowercase().replace(' ', "").replace('_', "").as_ref() {
+        "0" | "beginning" | "new" => Ok(Status::Beginning),
+        "1" | "inprogress" => Ok(Status::InProgress),
+        "2" | "complete" | "completed" | "done" => Ok(Status::Complete),
+        _ => Err(format!("unknown status `{}`", value)),
+    }
+}
+
+fn get_or_create_user(db: &Db, name: &str, log: &mut ImportLog) -> DbResult<User> {
+    let (user, created) = User::get_or_create(db, name)?;
+    if created {
+        log.created_users.push(name.to_string());
+    }
+    Ok(user)
+}
+
+fn get_or_create_category(db: &Db, name: &str, log: &mut ImportLog) -> DbResult<Category> {
+    let mut category = Category::new(name);
+    if category.exists(db)? {
+        return Category::get_category(db, name);
+    }
+    category.save_to_db(db)?;
+    log.created_categories.push(name.to_string());
+    Ok(category)
+}
+
+/// Document of the record, `Err(Ok(reason))` if the record is rejected. `base_dir` is the
+/// directory of the export, `data_file` paths are relative to it.
+fn build_document(db: &Db,
+                  record: &Record,
+                  mapping: &Mapping,
+                  base_dir: &Path,
+                  default_user: &User,
+                  log: &mut ImportLog)
+                  -> Result<Document, Result<String, DbError>> {
+    let name = match record.get("name") {
+        Some(name) => name.to_string(),
+        None => return Err(Ok("empty name".to_string())),
+    };
+    let parse_time = |field: &str| -> Result<Option<DateTime<UTC>>, String> {
+        match record.get(field) {
+            Some(value) => mapping.parse_time(value).map(Some),
+            None => Ok(None),
+        }
+    };
+    let (c_time, m_time, date_expired) = match (parse_time("c_time"),
+                                                parse_time("m_time"),
+                                                parse_time("date_expired")) {
+        (Ok(c_time), Ok(m_time), Ok(date_expired)) => (c_time, m_time, date_expired),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => return Err(Ok(e)),
+    };
+    let status = match record.get("status").map(parse_status) {
+        Some(Ok(status)) => Some(status),
+        Some(Err(e)) => return Err(Ok(e)),
+        None => None,
+    };
+    let data = match (record.get("data"), record.get("data_file")) {
+        (_, Some(path)) => {
+            let mut data = Vec::new();
+            let res = File::open(base_dir.join(path))
+                .and_then(|mut file| file.read_to_end(&mut data));
+            match res {
+                Ok(_) => Some(data),
+                Err(e) => return Err(Ok(format!("error of reading `{}`: {}", path, e))),
+            }
+        }
+        (Some(text), None) => Some(text.as_bytes().to_vec()),
+        (None, None) => None,
+    };
+    // Comments are checked before creating anything.
+    let mut comments: Vec<(String, Option<DateTime<UTC>>, Option<&str>)> = Vec::new();
+    for fields in &record.comments {
+        let get = |field: &str| {
+            fields.get(field).map(|value| value.trim()).and_then(|value| if value.is_empty() {
+                None
+            } else {
+                Some(value)
+            })
+        };
+        let text = match get("comment.text") {
+            Some(text) => text.to_string(),
+            None => continue,
+        };
+        let c_time = match get("comment.c_time").map(|value| mapping.parse_time(value)) {
+            Some(Ok(c_time)) => Some(c_time),
+            Some(Err(e)) => return Err(Ok(format!("comment: {}", e))),
+            None => None,
+        };
+        comments.push((text, c_time, get("comment.author")));
+    }
+
+    let user = |field: &str, log: &mut ImportLog| match record.get(field) {
+        Some(name) => get_or_create_user(db, name, log),
+        None => Ok(default_user.clone()),
+    };
+    let author = user("author", log).map_err(Err)?;
+    let responsible = user("responsible", log).map_err(Err)?;
+    let category = match record.get("category") {
+        Some(name) => get_or_create_category(db, name, log),
+        None => get_or_create_category(db, "Imported", log),
+    };
+    let category = category.map_err(Err)?;
+    let expired = date_expired.unwrap_or_else(UTC::now).date();
+    let mut doc = Document::new(name,
+                                &author,
+                                category,
+                                responsible,
+                                (expired.year() as u32, expired.month(), expired.day()),
+                                None);
+    doc.set_data(data);
+    for (text, c_time, author) in comments {
+        let author = match author {
+            Some(name) => get_or_create_user(db, name, log).map_err(Err)?,
+            None => default_user.clone(),
+        };
+        let mut comment = Comment::new(&author, text);
+        if let Some(c_time) = c_time {
+            comment.set_c_time(c_time);
+        }
+        doc.comments_mut().push(comment);
+    }
+    if let Some(status) = status {
+        doc.metadata_mut().set_status(status);
+    }
+    // Times are set after all changes, which touch the modification time.
+    let c_time = c_time.unwrap_or_else(UTC::now);
+    doc.metadata_mut().set_c_time(c_time);
+    doc.metadata_mut().set_m_time(m_time.unwrap_or(c_time));
+    Ok(doc)
+}
+
+/// Import records in one transaction, rejected records are skipped and logged. Documents,
+/// comments without an author and records without a category get `default_user` and the
+/// `Imported` category.
+pub fn import(db: &Db,
+              records: &[Record],
+              mapping: &Mapping,
+              base_dir: &Path,
+              default_user: &User)
+              -> DbResult<ImportLog> {
+    db.transaction(|db| {
+        let mut log = ImportLog::default();
+        for (i, record) in records.iter().enumerate() {
+            match build_document(db, record, mapping, base_dir, default_user, &mut log) {
+                Ok(mut doc) => log.imported.push(doc.save_to_db(db)?),
+                Err(Ok(reason)) => log.rejected.push((i + 1, reason)),
+                Err(Err(e)) => return Err(e),
+            }
+        }
+        Ok(log)
+    })
+}
+
+const USAGE: &'static str = "\
+Usage: sed_bad import-legacy [OPTIONS] MAPPING EXPORT
+
+Options:
+    --user NAME   author of records without one, root by default
+    --log FILE    write the log of the import to the file";
+
+fn read_to_string(path: &Path) -> io::Result<String> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Import from the command line, `args` follow the `import-legacy` command. Return the log to
+/// be printed.
+pub fn cli(args: &[String]) -> Result<String, String> {
+    let mut user = "root".to_string();
+    let mut log_path: Option<String> = None;
+    let mut paths: Vec<&String> = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--user" => user = args.next().cloned().ok_or_else(|| USAGE.to_string())?,
+            "--log" => log_path = Some(args.next().cloned().ok_or_else(|| USAGE.to_string())?),
+            _ if arg.starts_with("--") => return Err(USAGE.to_string()),
+            _ => paths.push(arg),
+        }
+    }
+    if paths.len() != 2 {
+        return Err(USAGE.to_string());
+    }
+    let (mapping_path, export_path) = (Path::new(paths[0]), Path::new(paths[1]));
+
+    let mapping = read_to_string(mapping_path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| Mapping::parse(&text))
+        .map_err(|e| format!("Error of reading the mapping file.\n{}", e))?;
+    let export = read_to_string(export_path)
+        .map_err(|e| format!("Error of reading the export.\n{}", e))?;
+    let records = match mapping.format() {
+        Format::Csv => read_csv(&export, &mapping),
+        Format::Xml => read_xml(&export, &mapping),
+    };
+    let records = records.map_err(|e| format!("Error of reading the export.\n{}", e))?;
+
+    let db = Db::new()
+        .and_then(|d| d.init_root())
+        .map_err(|e| format!("Error of opening database.\n{}", e))?;
+    let user = User::get_user(&db, &user).map_err(|e| format!("Unknown user.\n{}", e))?;
+    let base_dir = export_path.parent().unwrap_or_else(|| Path::new("."));
+    let log = import(&db, &records, &mapping, base_dir, &user)
+        .map_err(|e| format!("Error of importing, nothing was imported.\n{}", e))?;
+    let text = log.to_text();
+    if let Some(log_path) = log_path {
+        File::create(&log_path)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .map_err(|e| format!("Error of writing the log.\n{}", e))?;
+    }
+    Ok(text)
+}
+
+#[test]
+fn parse_csv_rows() {
+    assert_eq!(parse_csv("a;\"b;\"\"c\"\"\"\r\n\"multi\nline\";d", ';'),
+               vec![vec!["a".to_string(), "b;\"c\"".to_string()],
+                    vec!["multi\nline".to_string(), "d".to_string()]]);
+}
+
+#[test]
+fn read_mapped_records() {
+    let mapping = Mapping::parse("
+# Export of the old system.
+format = csv
+delimiter = ;
+name = Title
+c_time = Created
+comment.text = Note
+")
+        .unwrap();
+    let records = read_csv("Title;Created;Note\nContract;2016-05-01;Signed\nAct;;\n", &mapping)
+        .unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].get("name"), Some("Contract"));
+    assert_eq!(mapping.parse_time(records[0].get("c_time").unwrap()),
+               Ok(UTC.ymd(2016, 5, 1).and_hms(0, 0, 0)));
+    assert_eq!(records[0].comments.len(), 1);
+    assert_eq!(records[1].get("c_time"), None);
+    assert!(records[1].comments.is_empty());
+
+    let mapping = Mapping::parse("
+format = xml
+records = /export/doc
+name = @title
+comments = note
+comment.text = .
+comment.author = @by
+")
+        .unwrap();
+    let records = read_xml("<export><doc title=\"Contract\"><note by=\"ivan\">Signed</note>\
+                            </doc></export>",
+                           &mapping)
+        .unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].get("name"), Some("Contract"));
+    assert_eq!(records[0].comments[0].get("comment.author").map(|s| s.as_ref()),
+               Some("ivan"));
+
+    assert!(Mapping::parse("format = csv\nnam = Title").is_err());
+}
@@ -0,0 +1,59 @@
+//! Domain model and storage of SED-BAD.
+//!
+//! The crate doesn't depend on GTK, so scripts, servers and tests can use the same documents,
+//! users and database as the GUI application.
+
+extern crate chrono;
+extern crate md5;
+extern crate rusqlite;
+extern crate sxd_document;
+extern crate sxd_xpath;
+extern crate rustc_serialize;
+extern crate tar;
+#[cfg(feature = "postgres")]
+extern crate postgres;
+
+pub mod document;
+pub mod metadata;
+pub mod user;
+pub mod category;
+pub mod comment;
+pub mod notification;
+pub mod annotation;
+pub mod revision;
+pub mod lock;
+pub mod audit;
+pub mod session;
+pub mod diff;
+pub mod merge;
+pub mod image_diff;
+pub mod permission;
+pub mod errors;
+pub mod db;
+pub mod query;
+pub mod saved_search;
+pub mod calendar;
+pub mod ical;
+pub mod import;
+pub mod legacy_import;
+pub mod archive;
+pub mod backup;
+pub mod storage;
+pub mod config;
+pub mod json;
+pub mod remote;
+pub mod temp_dir;
+#[cfg(feature = "postgres")]
+pub mod pg;
+
+pub use db::Db;
+pub use storage::{Storage, MemoryStorage};
+pub use remote::RemoteStorage;
+pub use config::Config;
+pub use errors::{DbError, DbResult};
+pub use document::Document;
+pub use metadata::{Metadata, Status};
+pub use user::User;
+pub use category::Category;
+pub use comment::Comment;
+pub use permission::Permission;
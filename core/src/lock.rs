@@ -0,0 +1,167 @@
+//! Check-out/check-in locks of documents.
+//!
+//! A user checks out the document to change its content, other users can only read it until
+//! the check-in. Root may break a lock left by somebody else, which is recorded in the audit
+//! log.
+
+use chrono::{DateTime, UTC};
+
+use user::User;
+use db::Db;
+use errors::{DbResult, DbError};
+use audit::AuditEntry;
+
+#[derive(Clone, Debug)]
+pub struct Lock {
+    doc_id: i64,
+    user: User,
+    // Time of the check-out.
+    c_time: DateTime<UTC>,
+}
+
+impl Lock {
+    pub fn new(doc_id: i64, user: User, c_time: DateTime<UTC>) -> Lock {
+        Lock {
+            doc_id: doc_id,
+            user: user,
+            c_time: c_time,
+        }
+    }
+
+    pub fn doc_id(&self) -> i64 {
+        self.doc_id
+    }
+
+    /// User who checked out the document.
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+
+    pub fn c_time(&self) -> DateTime<UTC> {
+        self.c_time
+    }
+
+    /// Whether the document is checked out by the user.
+    pub fn is_held_by(&self, user: &User) -> bool {
+        self.user.id() == user.id()
+    }
+
+    /// `DbError::Locked` unless the document is checked out by the user.
+    pub fn check_held_by(&self, user: &User) -> DbResult<()> {
+        if self.is_held_by(user) {
+            return Ok(());
+        }
+        Err(DbError::Locked(format!("The document is checked out by {}.", self.user.name())))
+    }
+
+    /// `DbError::Forbidden` unless the user is root, only root breaks locks.
+    pub fn check_breaker(admin: &User) -> DbResult<()> {
+        if admin.is_root() {
+            return Ok(());
+        }
+        Err(DbError::Forbidden("Only root may break locks of other users.".to_string()))
+    }
+
+    /// Details of breaking the lock for the audit log.
+    pub fn audit_details(&self) -> String {
+        format!("Ticket #{} checked out by {} at {}",
+                self.doc_id,
+                self.user.name(),
+                self.c_time.to_rfc3339())
+    }
+
+    /// Lock of the document, `None` if it isn't checked out.
+    pub fn get_by_doc_id(db: &Db, doc_id: i64) -> DbResult<Option<Lock>> {
+        let mut stmt = db.conn().prepare("
+SELECT doc_id, user_id, c_time FROM doc_locks WHERE doc_id = ?;
+")?;
+        let mut rows = stmt.query(&[&doc_id])?;
+        match rows.next() {
+            Some(row) => {
+                let row = row?;
+                Ok(Some(Lock {
+                    doc_id: row.get_checked(0)?,
+                    user: User::get_by_id(db, row.get_checked(1)?)?,
+                    c_time: row.get_checked(2)?,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Check out the document for the user. Return the lock of the document, which is held by
+    /// somebody else if the document was checked out before.
+    pub fn check_out(db: &Db, doc_id: i64, user: &User) -> DbResult<Lock> {
+        db.conn()
+            .execute("INSERT OR IGNORE INTO doc_locks VALUES (?, ?, ?);",
+                     &[&doc_id, &user.id(), &UTC::now()])?;
+        Lock::get_by_doc_id(db, doc_id)?.ok_or(DbError::NotFound)
+    }
+
+    /// Release the lock of the user, `DbError::NotFound` if the user doesn't hold it.
+    pub fn check_in(db: &Db, doc_id: i64, user: &User) -> DbResult<()> {
+        let released = db.conn()
+            .execute("DELETE FROM doc_locks WHERE doc_id = ? AND user_id = ?;",
+                     &[&doc_id, &user.id()])?;
+        if released == 0 {
+            return Err(DbError::NotFound);
+        }
+        Ok(())
+    }
+
+    /// Release the lock of another user on behalf of root.
+    pub fn break_lock(&self, db: &Db, admin: &User) -> DbResult<()> {
+        Lock::check_breaker(admin)?;
+        db.transaction(|db| {
+            Lock::check_in(db, self.doc_id, &self.user)?;
+            AuditEntry::record(db, admin.name(), "break_lock", &self.audit_details())?;
+            Ok(())
+        })
+    }
+}
+
+#[test]
+fn check_out_and_in() {
+    use storage::Storage;
+    use category::Category;
+    use document::Document;
+    use temp_dir::TempDir;
+
+    let dir = TempDir::new("lock-test").unwrap();
+    let db = Db::open(dir.join("db.sqlite3")).unwrap().init_root().unwrap();
+    let root = db.user_by_name("root").unwrap();
+    let mut ivan = User::new("ivan", "secret");
+    db.add_user(&mut ivan).unwrap();
+    db.add_category(&mut Category::new("Contracts")).unwrap();
+    let mut doc = Document::new("Contract".to_string(),
+                                &ivan,
+                                Category::new("Contracts"),
+                                ivan.clone(),
+                                (2017, 3, 8),
+                                None);
+    let doc_id = db.add_document(&mut doc).unwrap();
+
+    assert!(Lock::get_by_doc_id(&db, doc_id).unwrap().is_none());
+    assert!(Lock::check_out(&db, doc_id, &ivan).unwrap().is_held_by(&ivan));
+    // The document stays checked out by the first user.
+    let lock = Lock::check_out(&db, doc_id, &root).unwrap();
+    assert!(lock.is_held_by(&ivan));
+    match Lock::check_in(&db, doc_id, &root) {
+        Err(DbError::NotFound) => {}
+        res => panic!("{:?}", res),
+    }
+    match lock.break_lock(&db, &ivan) {
+        Err(DbError::Forbidden(_)) => {}
+        res => panic!("{:?}", res),
+    }
+
+    lock.break_lock(&db, &root).unwrap();
+    assert!(Lock::get_by_doc_id(&db, doc_id).unwrap().is_none());
+    let entries = AuditEntry::get_all(&db).unwrap();
+    assert_eq!(entries[0].user(), "root");
+    assert_eq!(entries[0].action(), "break_lock");
+
+    Lock::check_out(&db, doc_id, &root).unwrap();
+    Lock::check_in(&db, doc_id, &root).unwrap();
+    assert!(Lock::get_by_doc_id(&db, doc_id).unwrap().is_none());
+}
@@ -0,0 +1,175 @@
+//! Merging of concurrent changes of a document field by field.
+//!
+//! When a document was saved by another user after it was read, the user chooses for every
+//! differing field whether their own value or the saved one is kept.
+
+use document::Document;
+use metadata::Status;
+use permission::NaivePermission;
+
+/// Fields of a ticket merged one by one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Name,
+    Author,
+    Category,
+    Status,
+    DateExpired,
+    Permission,
+    Responsible,
+    Content,
+}
+
+const FIELDS: &'static [Field] = &[Field::Name,
+                                   Field::Author,
+                                   Field::Category,
+                                   Field::Status,
+                                   Field::DateExpired,
+                                   Field::Permission,
+                                   Field::Responsible,
+                                   Field::Content];
+
+impl Field {
+    pub fn title(&self) -> &'static str {
+        match *self {
+            Field::Name => "Name",
+            Field::Author => "Author",
+            Field::Category => "Category",
+            Field::Status => "Status",
+            Field::DateExpired => "Date expired",
+            Field::Permission => "Permission",
+            Field::Responsible => "Responsible",
+            Field::Content => "Content",
+        }
+    }
+
+    /// Value of the field as shown to the user.
+    pub fn value(&self, doc: &Document) -> String {
+        let metadata = doc.metadata();
+        match *self {
+            Field::Name => doc.name().to_string(),
+            Field::Author => metadata.author().name().to_string(),
+            Field::Category => metadata.category().name().to_string(),
+            Field::Status => status_name(&metadata.status()).to_string(),
+            Field::DateExpired => metadata.date_expired().format("%d %b %Y").to_string(),
+            Field::Permission => {
+                let permission = doc.permission();
+                format!("author: {}; responsible: {}; others: {}",
+                        naive_permission(permission.author()),
+                        naive_permission(permission.responsible()),
+                        naive_permission(permission.others()))
+            }
+            Field::Responsible => doc.responsible().name().to_string(),
+            Field::Content => {
+                match (doc.text(), doc.data()) {
+                    (Some(text), _) => text,
+                    (None, Some(data)) => format!("Binary content, {} bytes", data.len()),
+                    (None, None) => "No content".to_string(),
+                }
+            }
+        }
+    }
+
+    /// Whether the field differs in the documents.
+    pub fn differs(&self, a: &Document, b: &Document) -> bool {
+        match *self {
+            Field::Content => a.data() != b.data(),
+            _ => self.value(a) != self.value(b),
+        }
+    }
+
+    /// Set the field of `doc` to the value of `from`.
+    pub fn copy(&self, from: &Document, doc: &mut Document) {
+        match *self {
+            Field::Name => doc.set_name(from.name()),
+            Field::Author => doc.metadata_mut().set_author(from.metadata().author().clone()),
+            Field::Category => doc.metadata_mut().set_category(from.metadata().category().clone()),
+            Field::Status => doc.metadata_mut().set_status(from.metadata().status()),
+            Field::DateExpired => {
+                doc.metadata_mut().set_date_expired(from.metadata().date_expired())
+            }
+            Field::Permission => doc.set_permission(from.permission()),
+            Field::Responsible => doc.set_responsible(from.responsible().clone()),
+            Field::Content => doc.set_data(from.data()),
+        }
+    }
+}
+
+fn status_name(status: &Status) -> &'static str {
+    match *status {
+        Status::Beginning => "Beginning",
+        Status::InProgress => "In progress",
+        Status::Complete => "Complete",
+    }
+}
+
+fn naive_permission(permission: NaivePermission) -> String {
+    let mut rights = Vec::new();
+    if permission.read() {
+        rights.push("read");
+    }
+    if permission.write() {
+        rights.push("write");
+    }
+    if permission.comment() {
+        rights.push("comment");
+    }
+    if rights.is_empty() {
+        "none".to_string()
+    } else {
+        rights.join(", ")
+    }
+}
+
+/// Fields which differ in the documents.
+pub fn changed_fields(a: &Document, b: &Document) -> Vec<Field> {
+    FIELDS.iter().cloned().filter(|field| field.differs(a, b)).collect()
+}
+
+/// The saved document `theirs` with the `fields` of `mine`, it has the version of `theirs`.
+pub fn merge(mine: &Document, theirs: &Document, fields: &[Field]) -> Document {
+    let mut doc = theirs.clone();
+    for field in fields {
+        field.copy(mine, &mut doc);
+    }
+    doc
+}
+
+#[test]
+fn merge_changes() {
+    use user::User;
+    use category::Category;
+
+    let ivan = User::new("ivan", "secret");
+    let mut base = Document::new("Contract".to_string(),
+                                 &ivan,
+                                 Category::new("Contracts"),
+                                 ivan.clone(),
+                                 (2017, 3, 8),
+                                 None);
+    base.set_data(Some(b"draft".to_vec()));
+    let mut mine = base.clone();
+    mine.set_name("Signed contract");
+    mine.set_data(Some(b"signed".to_vec()));
+    let mut theirs = base.clone();
+    theirs.set_name("Old contract");
+    theirs.metadata_mut().set_status(Status::Complete);
+    theirs.set_version(base.version() + 1);
+
+    assert!(changed_fields(&base, &base).is_empty());
+    assert_eq!(changed_fields(&mine, &theirs),
+               vec![Field::Name, Field::Status, Field::Content]);
+    assert!(Field::Status.differs(&base, &theirs));
+    assert!(!Field::Status.differs(&base, &mine));
+    assert_eq!(Field::Content.value(&mine), "signed");
+
+    let merged = merge(&mine, &theirs, &[Field::Name, Field::Content]);
+    assert_eq!(merged.name(), "Signed contract");
+    assert_eq!(merged.metadata().status(), Status::Complete);
+    assert_eq!(merged.data(), Some(b"signed".to_vec()));
+    assert_eq!(merged.version(), theirs.version());
+    assert_eq!(changed_fields(&merged, &theirs), vec![Field::Name, Field::Content]);
+
+    let merged = merge(&mine, &theirs, &[]);
+    assert!(changed_fields(&merged, &theirs).is_empty());
+}
@@ -9,7 +9,7 @@ use category::Category;
 use errors::DbResult;
 use db::Db;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Metadata {
     id: i64,
     c_time: DateTime<UTC>,
@@ -63,10 +63,19 @@ impl Metadata {
         self.id
     }
 
+    /// Set the id assigned by a storage.
+    pub fn set_id(&mut self, id: i64) {
+        self.id = id;
+    }
+
     pub fn c_time(&self) -> DateTime<UTC> {
         self.c_time
     }
 
+    pub fn set_c_time(&mut self, c_time: DateTime<UTC>) {
+        self.c_time = c_time;
+    }
+
     pub fn m_time(&self) -> DateTime<UTC> {
         self.m_time
     }
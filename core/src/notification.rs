@@ -0,0 +1,138 @@
+//! Notifications of users mentioned in comments.
+//!
+//! A comment mentioning `@name` notifies the user with that name if the user may read the
+//! document, the notification is shown until the user reads it.
+
+use chrono::{DateTime, UTC};
+
+use user::User;
+use db::Db;
+use errors::DbResult;
+use document::Document;
+use comment::Comment;
+
+#[derive(Clone, Debug)]
+pub struct Notification {
+    id: i64,
+    comment_id: i64,
+    // Author of the comment.
+    author: String,
+    doc_id: i64,
+    doc_name: String,
+    c_time: DateTime<UTC>,
+    read: bool,
+}
+
+impl Notification {
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn comment_id(&self) -> i64 {
+        self.comment_id
+    }
+
+    pub fn author(&self) -> &str {
+        self.author.as_ref()
+    }
+
+    pub fn doc_id(&self) -> i64 {
+        self.doc_id
+    }
+
+    pub fn doc_name(&self) -> &str {
+        self.doc_name.as_ref()
+    }
+
+    pub fn c_time(&self) -> DateTime<UTC> {
+        self.c_time
+    }
+
+    pub fn read(&self) -> bool {
+        self.read
+    }
+
+    /// Users mentioned in the comment of the document who are notified about it. Unknown names,
+    /// the author of the comment and users without the right to read the document are skipped.
+    pub fn mentioned_users(users: &[User], doc: &Document, comment: &Comment) -> Vec<User> {
+        let mut mentioned: Vec<User> = Vec::new();
+        for name in comment.mentions() {
+            let user = match users.iter().find(|user| user.name() == name) {
+                Some(user) => user,
+                None => continue,
+            };
+            if user == comment.author() || !user.is_read_grant(doc) {
+                continue;
+            }
+            mentioned.push(user.clone());
+        }
+        mentioned
+    }
+
+    /// Notify users mentioned in the comment of the document, see `mentioned_users`. A user is
+    /// notified about a comment once. Return notified users.
+    pub fn notify_mentions(db: &Db, doc: &Document, comment: &Comment) -> DbResult<Vec<User>> {
+        let users = User::get_users(db)?;
+        let mut notified: Vec<User> = Vec::new();
+        for user in Notification::mentioned_users(&users, doc, comment) {
+            let inserted = db.conn()
+                .execute("
+INSERT OR IGNORE INTO notifications VALUES (NULL, ?, ?, ?, 0);
+",
+                         &[&user.id(), &comment.id(), &UTC::now()])?;
+            if inserted > 0 {
+                notified.push(user);
+            }
+        }
+        Ok(notified)
+    }
+
+    /// Get notifications of the user, the newest first.
+    pub fn get_for_user(db: &Db, user: &User) -> DbResult<Vec<Notification>> {
+        let mut notifications: Vec<Notification> = Vec::new();
+        let mut stmt = db.conn()
+            .prepare("
+SELECT notifications.id, comments.id, users.name, docs.id, docs.name, notifications.c_time,
+       notifications.read
+FROM notifications
+JOIN comments ON comments.id = notifications.comment_id
+JOIN users ON users.id = comments.author_id
+JOIN docs ON docs.id = comments.doc_id
+WHERE notifications.user_id = ?
+ORDER BY notifications.id DESC;
+")?;
+        let mut rows = stmt.query(&[&user.id()])?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            notifications.push(Notification {
+                id: row.get_checked(0)?,
+                comment_id: row.get_checked(1)?,
+                author: row.get_checked(2)?,
+                doc_id: row.get_checked(3)?,
+                doc_name: row.get_checked(4)?,
+                c_time: row.get_checked(5)?,
+                read: row.get_checked(6)?,
+            });
+        }
+        Ok(notifications)
+    }
+
+    pub fn count_unread(db: &Db, user: &User) -> DbResult<i64> {
+        db.conn()
+            .query_row("SELECT COUNT(*) FROM notifications WHERE user_id = ? AND read = 0;",
+                       &[&user.id()],
+                       |row| row.get(0))
+            .map_err(From::from)
+    }
+
+    pub fn mark_read(db: &Db, id: i64) -> DbResult<()> {
+        db.conn().execute("UPDATE notifications SET read = 1 WHERE id = ?;", &[&id])?;
+        Ok(())
+    }
+
+    pub fn mark_all_read(db: &Db, user: &User) -> DbResult<()> {
+        db.conn().execute("UPDATE notifications SET read = 1 WHERE user_id = ?;",
+                     &[&user.id()])?;
+        Ok(())
+    }
+}
@@ -0,0 +1,653 @@
+//! Structured query language for filtering tickets.
+//!
+//! A query is a list of terms separated by whitespace, all of them must match. A term is
+//! either `field:value` or a bare word, which is searched in the name of the ticket. Values
+//! with spaces are quoted, dates are written as `YYYY-MM-DD` and may be compared with `<`,
+//! `<=`, `>`, `>=` or `=`:
+//!
+//! ```text
+//! status:complete author:ivanov category:"Contracts" due:<2016-12-31 responsible:me
+//! ```
+//!
+//! `me` as a value of `author`, `responsible` or `involved` means the current user, a user
+//! named so is written quoted as `"me"`. `today`
+//! as a value of `due` means the current date and `selected` the day, week or month selected
+//! in the calendar. `involved:me` matches documents where the user is the author or the
+//! responsible, root is involved in all documents. `readable:me` matches documents which the
+//! user may read according to their permissions.
+
+use chrono::{DateTime, UTC, TimeZone, NaiveDate, Duration};
+use rusqlite::types::ToSql;
+
+use std::fmt;
+use std::error::Error;
+
+use metadata::Status;
+use document::Document;
+use user::User;
+use calendar::Span;
+
+/// Error of parsing a query, `start` and `end` are positions (in characters) of the offending
+/// token in the query string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl QueryError {
+    fn new<S: Into<String>>(message: S, start: usize, end: usize) -> Self {
+        QueryError {
+            message: message.into(),
+            start: start,
+            end: end,
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (at {}..{})", self.message, self.start, self.end)
+    }
+}
+
+impl Error for QueryError {
+    fn description(&self) -> &str {
+        self.message.as_ref()
+    }
+}
+
+/// Values which are resolved only at the time of compiling a query.
+pub struct Context<'a> {
+    pub current_user: &'a User,
+    /// Start (inclusive) and end (exclusive) of the period selected in the calendar.
+    pub selected_range: (DateTime<UTC>, DateTime<UTC>),
+}
+
+impl<'a> Context<'a> {
+    pub fn new(current_user: &'a User) -> Self {
+        Context {
+            current_user: current_user,
+            selected_range: Span::Day.bounds(UTC::now()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Status,
+    Author,
+    Responsible,
+    Involved,
+    Readable,
+    Category,
+    Due,
+}
+
+impl Field {
+    fn from_str(name: &str) -> Option<Field> {
+        match name.to_lowercase().as_ref() {
+            "name" => Some(Field::Name),
+            "status" => Some(Field::Status),
+            "author" => Some(Field::Author),
+            "responsible" => Some(Field::Responsible),
+            "involved" => Some(Field::Involved),
+            "readable" => Some(Field::Readable),
+            "category" => Some(Field::Category),
+            "due" => Some(Field::Due),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Field::Name => "name",
+            Field::Status => "status",
+            Field::Author => "author",
+            Field::Responsible => "responsible",
+            Field::Involved => "involved",
+            Field::Readable => "readable",
+            Field::Category => "category",
+            Field::Due => "due",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn sql(&self) -> &'static str {
+        match *self {
+            Op::Eq => "=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Text(String),
+    Me,
+    Status(Status),
+    Date(DateTime<UTC>),
+    SelectedDate,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Term {
+    pub field: Field,
+    pub op: Op,
+    pub value: Value,
+}
+
+/// Parameter of a compiled query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Param {
+    Text(String),
+    Int(i64),
+    Time(DateTime<UTC>),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+/// The query in its language, so it can be sent to a server and parsed there again.
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, term) in self.terms.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            let op = if term.op == Op::Eq { "" } else { term.op.sql() };
+            let value = match term.value {
+                Value::Text(ref text) => format!("\"{}\"", text),
+                Value::Me => "me".to_string(),
+                Value::Status(ref status) => {
+                    match *status {
+                        Status::Beginning => "beginning",
+                        Status::InProgress => "in-progress",
+                        Status::Complete => "complete",
+                    }
+                    .to_string()
+                }
+                Value::Date(date) => date.format("%Y-%m-%d").to_string(),
+                Value::SelectedDate => "selected".to_string(),
+            };
+            write!(f, "{}:{}{}", term.field.name(), op, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Raw term of the query with its position.
+struct Token {
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Query, QueryError> {
+        let mut terms = Vec::new();
+        for token in tokenize(input)? {
+            terms.push(parse_term(&token)?);
+        }
+        Ok(Query { terms: terms })
+    }
+
+    pub fn terms(&self) -> &Vec<Term> {
+        self.terms.as_ref()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn push(&mut self, term: Term) {
+        self.terms.push(term);
+    }
+
+    /// Add terms of other query, so both of them must match.
+    pub fn extend(&mut self, other: Query) {
+        self.terms.extend(other.terms);
+    }
+
+    /// Compile the query to the condition of `WHERE` clause and its parameters for SQLite.
+    ///
+    /// The condition refers to the tables of `document::DOCS_FROM_CLAUSE`.
+    pub fn to_sql(&self, context: &Context) -> (String, Vec<Box<ToSql>>) {
+        let (condition, params) = self.to_sql_params(context);
+        let params = params.into_iter()
+            .map(|param| -> Box<ToSql> {
+                match param {
+                    Param::Text(text) => Box::new(text),
+                    Param::Int(num) => Box::new(num),
+                    Param::Time(time) => Box::new(time),
+                }
+            })
+            .collect();
+        (condition, params)
+    }
+
+    /// Compile the query to the condition with `?` placeholders and parameters of any database.
+    pub fn to_sql_params(&self, context: &Context) -> (String, Vec<Param>) {
+        let current_user = context.current_user;
+        let mut conditions: Vec<String> = Vec::new();
+        let mut params: Vec<Param> = Vec::new();
+        for term in &self.terms {
+            match (term.field, &term.value) {
+                (Field::Name, &Value::Text(ref text)) => {
+                    conditions.push("LOWER(docs.name) LIKE LOWER(?) ESCAPE '\\'".to_string());
+                    params.push(Param::Text(format!("%{}%", escape_like(text))));
+                }
+                (Field::Status, &Value::Status(ref status)) => {
+                    conditions.push("metadata.status = ?".to_string());
+                    params.push(Param::Int(status.get_num()));
+                }
+                (Field::Author, value) => {
+                    conditions.push("author.name = ?".to_string());
+                    params.push(Param::Text(user_name(value, current_user)));
+                }
+                (Field::Responsible, value) => {
+                    conditions.push("responsible.name = ?".to_string());
+                    params.push(Param::Text(user_name(value, current_user)));
+                }
+                (Field::Involved, value) => {
+                    if value == &Value::Me && current_user.is_root() {
+                        continue;
+                    }
+                    conditions.push("(author.name = ? OR responsible.name = ?)".to_string());
+                    params.push(Param::Text(user_name(value, current_user)));
+                    params.push(Param::Text(user_name(value, current_user)));
+                }
+                (Field::Readable, value) => {
+                    // Digits of the permission are rights of the author, the responsible and
+                    // others, 4 is reading. Root has rights of the author.
+                    conditions.push("(CASE WHEN ? = 'root' OR author.name = ? \
+                                     THEN docs.permission / 100 \
+                                     WHEN responsible.name = ? THEN docs.permission / 10 % 10 \
+                                     ELSE docs.permission % 10 END) >= 4"
+                        .to_string());
+                    for _ in 0..3 {
+                        params.push(Param::Text(user_name(value, current_user)));
+                    }
+                }
+                (Field::Category, &Value::Text(ref text)) => {
+                    conditions.push("categories.name = ?".to_string());
+                    params.push(Param::Text(text.clone()));
+                }
+                (Field::Due, value) => {
+                    // Every date is a period, a day or the period selected in the calendar.
+                    let (start, end) = match *value {
+                        Value::Date(date) => (date, date + Duration::days(1)),
+                        Value::SelectedDate => context.selected_range,
+                        _ => unreachable!(),
+                    };
+                    match term.op {
+                        Op::Eq => {
+                            conditions.push("metadata.date_expired >= ? AND \
+                                             metadata.date_expired < ?"
+                                .to_string());
+                            params.push(Param::Time(start));
+                            params.push(Param::Time(end));
+                        }
+                        Op::Lt | Op::Ge => {
+                            conditions.push(format!("metadata.date_expired {} ?", term.op.sql()));
+                            params.push(Param::Time(start));
+                        }
+                        Op::Le => {
+                            conditions.push("metadata.date_expired < ?".to_string());
+                            params.push(Param::Time(end));
+                        }
+                        Op::Gt => {
+                            conditions.push("metadata.date_expired >= ?".to_string());
+                            params.push(Param::Time(end));
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+        if conditions.is_empty() {
+            ("1".to_string(), params)
+        } else {
+            (conditions.join(" AND "), params)
+        }
+    }
+
+    /// Whether the document matches the query, the same as `to_sql` for storages without SQL.
+    pub fn matches(&self, doc: &Document, context: &Context) -> bool {
+        let current_user = context.current_user;
+        let author = doc.metadata().author().name();
+        let responsible = doc.responsible().name();
+        let date_expired = doc.metadata().date_expired();
+        self.terms.iter().all(|term| {
+            match (term.field, &term.value) {
+                (Field::Name, &Value::Text(ref text)) => {
+                    doc.name().to_lowercase().contains(&text.to_lowercase())
+                }
+                (Field::Status, &Value::Status(ref status)) => &doc.metadata().status() == status,
+                (Field::Author, value) => author == user_name(value, current_user),
+                (Field::Responsible, value) => responsible == user_name(value, current_user),
+                (Field::Involved, value) => {
+                    if value == &Value::Me && current_user.is_root() {
+                        return true;
+                    }
+                    let name = user_name(value, current_user);
+                    author == name || responsible == name
+                }
+                (Field::Readable, value) => {
+                    let name = user_name(value, current_user);
+                    let permission = doc.permission();
+                    let rights = if name == "root" || author == name {
+                        permission.author()
+                    } else if responsible == name {
+                        permission.responsible()
+                    } else {
+                        permission.others()
+                    };
+                    rights.read()
+                }
+                (Field::Category, &Value::Text(ref text)) => {
+                    doc.metadata().category().name() == text
+                }
+                (Field::Due, value) => {
+                    let (start, end) = match *value {
+                        Value::Date(date) => (date, date + Duration::days(1)),
+                        Value::SelectedDate => context.selected_range,
+                        _ => unreachable!(),
+                    };
+                    match term.op {
+                        Op::Eq => date_expired >= start && date_expired < end,
+                        Op::Lt => date_expired < start,
+                        Op::Ge => date_expired >= start,
+                        Op::Le => date_expired < end,
+                        Op::Gt => date_expired >= end,
+                    }
+                }
+                _ => unreachable!(),
+            }
+        })
+    }
+}
+
+fn user_name(value: &Value, current_user: &User) -> String {
+    match *value {
+        Value::Me => current_user.name().to_string(),
+        Value::Text(ref text) => text.clone(),
+        _ => unreachable!(),
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        if chars[pos].is_whitespace() {
+            pos += 1;
+            continue;
+        }
+        let start = pos;
+        let mut quote_start = None;
+        while pos < chars.len() {
+            if chars[pos] == '"' {
+                quote_start = if quote_start.is_some() { None } else { Some(pos) };
+            } else if chars[pos].is_whitespace() && quote_start.is_none() {
+                break;
+            }
+            pos += 1;
+        }
+        if let Some(quote_start) = quote_start {
+            return Err(QueryError::new("Unterminated quote", quote_start, chars.len()));
+        }
+        tokens.push(Token {
+            text: chars[start..pos].iter().cloned().collect(),
+            start: start,
+            end: pos,
+        });
+    }
+    Ok(tokens)
+}
+
+/// Escape wildcards of `LIKE`, so the text matches itself only.
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\\' || c == '%' || c == '_' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn unquote(text: &str) -> String {
+    text.chars().filter(|&c| c != '"').collect()
+}
+
+fn parse_term(token: &Token) -> Result<Term, QueryError> {
+    let colon = if token.text.starts_with('"') {
+        None
+    } else {
+        token.text.find(':')
+    };
+    let (field_str, rest) = match colon {
+        Some(colon) => (&token.text[..colon], &token.text[colon + 1..]),
+        None => {
+            return Ok(Term {
+                field: Field::Name,
+                op: Op::Eq,
+                value: Value::Text(unquote(&token.text)),
+            });
+        }
+    };
+    let field = match Field::from_str(field_str) {
+        Some(field) => field,
+        None => {
+            return Err(QueryError::new(format!("Unknown field `{}`", field_str),
+                                       token.start,
+                                       token.start + field_str.chars().count()));
+        }
+    };
+    let value_start = token.start + field_str.chars().count() + 1;
+
+    let (op, value_str) = if rest.starts_with("<=") {
+        (Op::Le, &rest[2..])
+    } else if rest.starts_with(">=") {
+        (Op::Ge, &rest[2..])
+    } else if rest.starts_with('<') {
+        (Op::Lt, &rest[1..])
+    } else if rest.starts_with('>') {
+        (Op::Gt, &rest[1..])
+    } else if rest.starts_with('=') {
+        (Op::Eq, &rest[1..])
+    } else {
+        (Op::Eq, rest)
+    };
+    if op != Op::Eq && field != Field::Due {
+        return Err(QueryError::new(format!("Comparison `{}` is allowed only for `due`",
+                                           op.sql()),
+                                   value_start,
+                                   token.end));
+    }
+    // Quoted `"me"` is the name of a user.
+    let is_me = value_str == "me";
+    let value_str = unquote(value_str);
+    if value_str.is_empty() {
+        return Err(QueryError::new(format!("Empty value of `{}`", field_str),
+                                   token.start,
+                                   token.end));
+    }
+
+    let value = match field {
+        Field::Name | Field::Category => Value::Text(value_str),
+        Field::Author | Field::Responsible | Field::Involved | Field::Readable => {
+            if is_me {
+                Value::Me
+            } else {
+                Value::Text(value_str)
+            }
+        }
+        Field::Status => {
+            match value_str.to_lowercase().as_ref() {
+                "beginning" | "new" => Value::Status(Status::Beginning),
+                "in-progress" | "inprogress" | "progress" => Value::Status(Status::InProgress),
+                "complete" | "completed" | "done" => Value::Status(Status::Complete),
+                _ => {
+                    return Err(QueryError::new(format!("Unknown status `{}`, expected \
+                                                        beginning, in-progress or complete",
+                                                       value_str),
+                                               value_start,
+                                               token.end));
+                }
+            }
+        }
+        Field::Due => {
+            if value_str == "today" {
+                Value::Date(UTC::today().and_hms(0, 0, 0))
+            } else if value_str == "selected" {
+                Value::SelectedDate
+            } else {
+                match NaiveDate::parse_from_str(&value_str, "%Y-%m-%d") {
+                    Ok(date) => Value::Date(UTC.from_utc_date(&date).and_hms(0, 0, 0)),
+                    Err(_) => {
+                        return Err(QueryError::new(format!("Invalid date `{}`, expected \
+                                                            YYYY-MM-DD",
+                                                           value_str),
+                                                   value_start,
+                                                   token.end));
+                    }
+                }
+            }
+        }
+    };
+
+    Ok(Term {
+        field: field,
+        op: op,
+        value: value,
+    })
+}
+
+#[test]
+fn parse_query() {
+    let query = Query::parse("status:complete author:ivanov category:\"Contracts 2016\" \
+                              due:<2016-12-31 responsible:me report")
+        .unwrap();
+    assert_eq!(query.terms(),
+               &vec![Term {
+                         field: Field::Status,
+                         op: Op::Eq,
+                         value: Value::Status(Status::Complete),
+                     },
+                     Term {
+                         field: Field::Author,
+                         op: Op::Eq,
+                         value: Value::Text("ivanov".to_string()),
+                     },
+                     Term {
+                         field: Field::Category,
+                         op: Op::Eq,
+                         value: Value::Text("Contracts 2016".to_string()),
+                     },
+                     Term {
+                         field: Field::Due,
+                         op: Op::Lt,
+                         value: Value::Date(UTC.ymd(2016, 12, 31).and_hms(0, 0, 0)),
+                     },
+                     Term {
+                         field: Field::Responsible,
+                         op: Op::Eq,
+                         value: Value::Me,
+                     },
+                     Term {
+                         field: Field::Name,
+                         op: Op::Eq,
+                         value: Value::Text("report".to_string()),
+                     }]);
+    assert!(Query::parse("   ").unwrap().is_empty());
+}
+
+#[test]
+fn query_to_string() {
+    let text = "status:in-progress author:me category:\"Contracts 2016\" due:<2016-12-31 \
+                due:selected name:\"report\"";
+    let query = Query::parse(text).unwrap();
+    assert_eq!(query.to_string(), text);
+    assert_eq!(Query::parse(&query.to_string()).unwrap(), query);
+
+    let query = Query::parse("author:\"me\" responsible:me").unwrap();
+    assert_eq!(query.terms()[0].value, Value::Text("me".to_string()));
+    assert_eq!(query.terms()[1].value, Value::Me);
+    assert_eq!(query.to_string(), "author:\"me\" responsible:me");
+    assert_eq!(Query::parse(&query.to_string()).unwrap(), query);
+}
+
+#[test]
+fn parse_query_errors() {
+    assert_eq!(Query::parse("status:complete autor:ivanov").unwrap_err(),
+               QueryError::new("Unknown field `autor`", 16, 21));
+    assert_eq!(Query::parse("due:<2016-13-01").unwrap_err(),
+               QueryError::new("Invalid date `2016-13-01`, expected YYYY-MM-DD", 4, 15));
+    assert_eq!(Query::parse("author:<ivanov").unwrap_err(),
+               QueryError::new("Comparison `<` is allowed only for `due`", 7, 14));
+    assert_eq!(Query::parse("category:\"Contracts").unwrap_err(),
+               QueryError::new("Unterminated quote", 9, 19));
+}
+
+#[test]
+fn query_to_sql() {
+    let user = User::new("ivanov", "pass");
+    let query = Query::parse("author:me status:beginning").unwrap();
+    let (sql, params) = query.to_sql(&Context::new(&user));
+    assert_eq!(sql, "author.name = ? AND metadata.status = ?");
+    assert_eq!(params.len(), 2);
+    assert_eq!(Query::default().to_sql(&Context::new(&user)).0, "1");
+    let (sql, params) = Query::parse("100%_done").unwrap().to_sql_params(&Context::new(&user));
+    assert_eq!(sql, "LOWER(docs.name) LIKE LOWER(?) ESCAPE '\\'");
+    assert_eq!(params, vec![Param::Text("%100\\%\\_done%".to_string())]);
+
+    let root = User::new("root", "toor");
+    let query = Query::parse("involved:me due:selected").unwrap();
+    assert_eq!(query.to_sql(&Context::new(&user)).0,
+               "(author.name = ? OR responsible.name = ?) AND metadata.date_expired >= ? AND \
+                metadata.date_expired < ?");
+    assert_eq!(query.to_sql(&Context::new(&root)).0,
+               "metadata.date_expired >= ? AND metadata.date_expired < ?");
+}
+
+#[test]
+fn query_matches_document() {
+    use category::Category;
+
+    let user = User::new("ivanov", "pass");
+    let doc = Document::new("Annual report".to_string(),
+                            &user,
+                            Category::new("Reports"),
+                            User::new("petrov", "pass"),
+                            (2016, 12, 20),
+                            None);
+    let context = Context::new(&user);
+    let matches = |input: &str| Query::parse(input).unwrap().matches(&doc, &context);
+    assert!(matches("REPORT author:me category:Reports status:beginning"));
+    assert!(matches("involved:petrov due:2016-12-20 due:<2016-12-21 due:>=2016-12-20"));
+    assert!(!matches("responsible:me"));
+    assert!(!matches("due:>2016-12-20"));
+    assert!(matches("readable:me readable:petrov readable:root"));
+    assert!(!matches("readable:sidorov"));
+}
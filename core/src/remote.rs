@@ -0,0 +1,629 @@
+//! Storage on a server of `sed_bad-server`.
+//!
+//! `RemoteStorage` sends every call to the HTTP/JSON API of the server, so the GUI works with
+//! the documents of the server as with the local database. The server checks permissions as
+//! the logged in user. A lost session, e.g. after a restart of the server, is renewed by the
+//! next call. When the server is unreachable calls fail with `DbError::IoError` in
+//! `CONNECT_TIMEOUT_SECS`.
+
+use chrono::{DateTime, UTC};
+use rustc_serialize::json::{Json, Object};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::str;
+use std::time::Duration;
+
+use user::User;
+use category::Category;
+use metadata::Metadata;
+use document::{Document, DocumentSummary};
+use comment::Comment;
+use lock::Lock;
+use query::{Query, Context};
+use storage::Storage;
+use errors::{DbResult, DbError};
+use json;
+
+/// Largest page of documents the server gives.
+const PAGE_LIMIT: i64 = 500;
+const TIMEOUT_SECS: u64 = 30;
+const CONNECT_TIMEOUT_SECS: u64 = 5;
+
+pub struct RemoteStorage {
+    /// `host:port` of the server.
+    addr: String,
+    name: String,
+    password: String,
+    token: RefCell<String>,
+    /// Documents of metadata and of comments got from the server, metadata are changed
+    /// through their documents.
+    metadata_docs: RefCell<HashMap<i64, i64>>,
+    comment_docs: RefCell<HashMap<i64, i64>>,
+    /// Content of documents as it's on the server, unchanged content is neither downloaded nor
+    /// uploaded again.
+    contents: RefCell<HashMap<i64, Content>>,
+}
+
+/// Content of the document of the version and the time of modification.
+struct Content {
+    version: i64,
+    m_time: DateTime<UTC>,
+    data: Option<Vec<u8>>,
+}
+
+impl RemoteStorage {
+    /// Whether the location of the storage is a server, e.g. `http://sed.example.com:8080`.
+    pub fn is_url(location: &str) -> bool {
+        location.starts_with("http://")
+    }
+
+    /// Log in to the server, `url` is `http://host[:port]`.
+    pub fn login(url: &str, name: &str, password: &str) -> DbResult<RemoteStorage> {
+        let addr = url.trim_left_matches("http://").trim_right_matches('/');
+        if !RemoteStorage::is_url(url) || addr.is_empty() || addr.contains('/') {
+            return Err(DbError::InvalidData(format!("Wrong address of the server `{}`, \
+                                                     http://host[:port] expected.",
+                                                    url)));
+        }
+        let addr = if addr.contains(':') {
+            addr.to_string()
+        } else {
+            format!("{}:80", addr)
+        };
+        let storage = RemoteStorage {
+            addr: addr,
+            name: name.to_string(),
+            password: password.to_string(),
+            token: RefCell::new(String::new()),
+            metadata_docs: RefCell::new(HashMap::new()),
+            comment_docs: RefCell::new(HashMap::new()),
+            contents: RefCell::new(HashMap::new()),
+        };
+        storage.renew_token()?;
+        Ok(storage)
+    }
+
+    /// Storage of the same session with its own caches, e.g. to poll the server in another
+    /// thread.
+    pub fn detached(&self) -> RemoteStorage {
+        RemoteStorage {
+            addr: self.addr.clone(),
+            name: self.name.clone(),
+            password: self.password.clone(),
+            token: self.token.clone(),
+            metadata_docs: RefCell::new(HashMap::new()),
+            comment_docs: RefCell::new(HashMap::new()),
+            contents: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Address of the server, `host:port`.
+    pub fn addr(&self) -> &str {
+        self.addr.as_ref()
+    }
+
+    /// Forget the session on the server.
+    pub fn logout(&self) -> DbResult<()> {
+        self.call("POST", "/api/logout", Some(Json::Object(Object::new()))).map(|_| ())
+    }
+
+    fn renew_token(&self) -> DbResult<()> {
+        let (status, response) = self.send_login(&self.name, &self.password)?;
+        let json = check(status, &response)?;
+        let token = json.find("token").and_then(|token| token.as_string()).ok_or_else(|| {
+                DbError::InvalidData("No token in the answer of the server.".to_string())
+            })?;
+        *self.token.borrow_mut() = token.to_string();
+        Ok(())
+    }
+
+    fn send_login(&self, name: &str, password: &str) -> DbResult<(u16, Vec<u8>)> {
+        let body = object(vec![("name", string(name)), ("password", string(password))]);
+        self.send("POST", "/api/login", "", &body.to_string().into_bytes())
+    }
+
+    /// Send the request with the token, the session is renewed once if the server lost it.
+    fn call_raw(&self, method: &str, path: &str, body: &[u8]) -> DbResult<Vec<u8>> {
+        let (mut status, mut response) = self.send(method, path, &self.token.borrow(), body)?;
+        if status == 401 {
+            self.renew_token()?;
+            let (new_status, new_response) = self.send(method, path, &self.token.borrow(), body)?;
+            status = new_status;
+            response = new_response;
+        }
+        check(status, &response)?;
+        Ok(response)
+    }
+
+    fn call(&self, method: &str, path: &str, body: Option<Json>) -> DbResult<Json> {
+        let body = body.map(|body| body.to_string().into_bytes()).unwrap_or_else(Vec::new);
+        let response = self.call_raw(method, path, &body)?;
+        if response.is_empty() {
+            return Ok(Json::Null);
+        }
+        parse_json(&response)
+    }
+
+    fn send(&self,
+            method: &str,
+            path: &str,
+            token: &str,
+            body: &[u8])
+            -> DbResult<(u16, Vec<u8>)> {
+        let mut stream = connect(&self.addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SECS)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(TIMEOUT_SECS)))?;
+        let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: \
+                                close\r\n",
+                               method,
+                               path,
+                               self.addr,
+                               body.len());
+        if !token.is_empty() {
+            head.push_str(&format!("Authorization: Bearer {}\r\n", token));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(body)?;
+        stream.flush()?;
+
+        let mut reader = BufReader::new(stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line)?;
+        let status = status_line.split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| DbError::InvalidData(format!("Wrong answer of the server `{}`.",
+                                                        status_line.trim())))?;
+        let mut len: Option<usize> = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some(pos) = line.find(':') {
+                if line[..pos].trim().to_lowercase() == "content-length" {
+                    len = line[pos + 1..].trim().parse().ok();
+                }
+            }
+        }
+        let mut response = Vec::new();
+        match len {
+            Some(len) => {
+                response.resize(len, 0);
+                reader.read_exact(&mut response)?;
+            }
+            None => {
+                reader.read_to_end(&mut response)?;
+            }
+        }
+        Ok((status, response))
+    }
+
+    fn document_from_json(&self, json: &Json) -> DbResult<Document> {
+        let mut doc = json::parse_document(json)?;
+        self.remember(&doc);
+        let has_data = json.find("has_data").and_then(|has_data| has_data.as_boolean()) ==
+                       Some(true);
+        let m_time = doc.metadata().m_time();
+        let cached = match self.contents.borrow().get(&doc.id()) {
+            Some(content) if content.version == doc.version() && content.m_time == m_time => {
+                Some(content.data.clone())
+            }
+            _ => None,
+        };
+        let data = match cached {
+            Some(data) => data,
+            None if has_data => {
+                Some(self.call_raw("GET", &format!("/api/documents/{}/data", doc.id()), &[])?)
+            }
+            None => None,
+        };
+        if data.is_some() {
+            doc.set_data(data.clone());
+            doc.metadata_mut().set_m_time(m_time);
+        }
+        self.remember_content(&doc, data);
+        Ok(doc)
+    }
+
+    /// Content of the document got from the server or saved on it.
+    fn remember_content(&self, doc: &Document, data: Option<Vec<u8>>) {
+        self.contents.borrow_mut().insert(doc.id(),
+                                          Content {
+                                              version: doc.version(),
+                                              m_time: doc.metadata().m_time(),
+                                              data: data,
+                                          });
+    }
+
+    fn remember(&self, doc: &Document) {
+        self.metadata_docs.borrow_mut().insert(doc.metadata().id(), doc.id());
+        let mut comment_docs = self.comment_docs.borrow_mut();
+        for comment in doc.comments() {
+            comment_docs.insert(comment.id(), doc.id());
+        }
+    }
+
+    fn metadata_doc_id(&self, metadata_id: i64) -> DbResult<i64> {
+        self.metadata_docs.borrow().get(&metadata_id).cloned().ok_or(DbError::NotFound)
+    }
+
+    fn list_page(&self,
+                 query: &Query,
+                 context: &Context,
+                 limit: i64,
+                 offset: i64)
+                 -> DbResult<(i64, Vec<DocumentSummary>)> {
+        let (from, to) = context.selected_range;
+        let path = format!("/api/documents?q={}&limit={}&offset={}&from={}&to={}",
+                           encode(&query.to_string()),
+                           limit,
+                           offset,
+                           encode(&from.to_rfc3339()),
+                           encode(&to.to_rfc3339()));
+        let json = self.call("GET", &path, None)?;
+        let total = json.find("total").and_then(|total| total.as_i64()).ok_or_else(|| {
+                DbError::InvalidData("No total in the answer of the server.".to_string())
+            })?;
+        let summaries = match json.find("items").and_then(|items| items.as_array()) {
+            Some(items) => items.iter().map(json::parse_summary).collect::<DbResult<_>>()?,
+            None => Vec::new(),
+        };
+        Ok((total, summaries))
+    }
+}
+
+impl Storage for RemoteStorage {
+    fn users(&self) -> DbResult<Vec<User>> {
+        array(&self.call("GET", "/api/users", None)?, json::parse_user)
+    }
+
+    fn user_by_id(&self, id: i64) -> DbResult<User> {
+        json::parse_user(&self.call("GET", &format!("/api/users/{}", id), None)?)
+    }
+
+    fn user_by_name(&self, name: &str) -> DbResult<User> {
+        self.users()?.into_iter().find(|user| user.name() == name).ok_or(DbError::NotFound)
+    }
+
+    fn user_exists(&self, user: &User) -> DbResult<bool> {
+        let (status, response) = self.send_login(user.name(), user.pass())?;
+        if status == 401 {
+            return Ok(false);
+        }
+        let json = check(status, &response)?;
+        // The session is not needed, only the password is checked.
+        if let Some(token) = json.find("token").and_then(|token| token.as_string()) {
+            self.send("POST", "/api/logout", token, &[])?;
+        }
+        Ok(true)
+    }
+
+    fn add_user(&self, user: &mut User) -> DbResult<i64> {
+        let body = object(vec![("name", string(user.name())), ("password", string(user.pass()))]);
+        let added = json::parse_user(&self.call("POST", "/api/users", Some(body))?)?;
+        user.set_id(added.id());
+        Ok(added.id())
+    }
+
+    fn update_user(&self, id: i64, name: &str, pass: &str) -> DbResult<()> {
+        let body = object(vec![("name", string(name)), ("password", string(pass))]);
+        self.call("PUT", &format!("/api/users/{}", id), Some(body)).map(|_| ())
+    }
+
+    fn delete_user(&self, id: i64) -> DbResult<()> {
+        self.call("DELETE", &format!("/api/users/{}", id), None).map(|_| ())
+    }
+
+    fn categories(&self) -> DbResult<Vec<Category>> {
+        array(&self.call("GET", "/api/categories", None)?, json::parse_category)
+    }
+
+    fn category_by_id(&self, id: i64) -> DbResult<Category> {
+        self.categories()?.into_iter().find(|category| category.id() == id).ok_or(DbError::NotFound)
+    }
+
+    fn category_by_name(&self, name: &str) -> DbResult<Category> {
+        self.categories()?
+            .into_iter()
+            .find(|category| category.name() == name)
+            .ok_or(DbError::NotFound)
+    }
+
+    fn add_category(&self, category: &mut Category) -> DbResult<i64> {
+        let body = object(vec![("name", string(category.name()))]);
+        let added = json::parse_category(&self.call("POST", "/api/categories", Some(body))?)?;
+        category.set_id(added.id());
+        Ok(added.id())
+    }
+
+    /// Documents readable by the user.
+    fn documents(&self) -> DbResult<Vec<Document>> {
+        let user = self.user_by_name(&self.name)?;
+        let summaries = self.list_documents(&Query::default(), &Context::new(&user), -1, 0)?;
+        let docs = summaries.iter().map(|summary| self.document_by_id(summary.id())).collect();
+        docs
+    }
+
+    fn document_by_id(&self, id: i64) -> DbResult<Document> {
+        let json = self.call("GET", &format!("/api/documents/{}", id), None)?;
+        self.document_from_json(&json)
+    }
+
+    fn list_documents(&self,
+                      query: &Query,
+                      context: &Context,
+                      limit: i64,
+                      offset: i64)
+                      -> DbResult<Vec<DocumentSummary>> {
+        if limit >= 0 && limit <= PAGE_LIMIT {
+            return self.list_page(query, context, limit, offset).map(|(_, summaries)| summaries);
+        }
+        let mut summaries: Vec<DocumentSummary> = Vec::new();
+        loop {
+            let page_limit = if limit < 0 {
+                PAGE_LIMIT
+            } else {
+                PAGE_LIMIT.min(limit - summaries.len() as i64)
+            };
+            let (_, page) = self.list_page(query,
+                                           context,
+                                           page_limit,
+                                           offset + summaries.len() as i64)?;
+            let last = (page.len() as i64) < page_limit;
+            summaries.extend(page);
+            if last || summaries.len() as i64 == limit {
+                return Ok(summaries);
+            }
+        }
+    }
+
+    fn count_documents(&self, query: &Query, context: &Context) -> DbResult<i64> {
+        self.list_page(query, context, 0, 0).map(|(total, _)| total)
+    }
+
+    /// Add the document as the logged in user, its comments are added after it.
+    fn add_document(&self, doc: &mut Document) -> DbResult<i64> {
+        let content = doc.data().map(|data| json::content(&data)).unwrap_or(Json::Null);
+        let body = object(vec![("name", string(doc.name())),
+                               ("category", string(doc.metadata().category().name())),
+                               ("responsible", string(doc.responsible().name())),
+                               ("date_expired",
+                                string(json::format_date(&doc.metadata().date_expired()))),
+                               ("permission", Json::I64(doc.permission().get_int())),
+                               ("data_base64", content)]);
+        let added = json::parse_document(&self.call("POST", "/api/documents", Some(body))?)?;
+        self.remember(&added);
+        doc.set_id(added.id());
+        doc.metadata_mut().set_id(added.metadata().id());
+        self.remember_content(&added, doc.data());
+        let doc_id = doc.id();
+        for comment in doc.comments_mut() {
+            self.add_comment(doc_id, comment)?;
+        }
+        Ok(doc_id)
+    }
+
+    /// The server saves the document as the logged in user. The content is sent with the
+    /// version in the same request if it differs from the one got from the server.
+    fn update_document(&self, doc: &Document, _editor: &User) -> DbResult<()> {
+        let metadata = doc.metadata();
+        let mut fields = vec![("name", string(doc.name())),
+                              ("status", string(json::status_name(&metadata.status()))),
+                              ("author", string(metadata.author().name())),
+                              ("responsible", string(doc.responsible().name())),
+                              ("category", string(metadata.category().name())),
+                              ("date_expired",
+                               string(json::format_date(&metadata.date_expired()))),
+                              ("permission", Json::I64(doc.permission().get_int())),
+                              ("version", Json::I64(doc.version()))];
+        let data = doc.data();
+        let changed = match self.contents.borrow().get(&doc.id()) {
+            Some(content) => content.data != data,
+            None => true,
+        };
+        if changed {
+            match data {
+                Some(ref data) => fields.push(("data_base64", json::content(data))),
+                None => fields.push(("data", Json::Null)),
+            }
+        }
+        let updated = json::parse_document(&self.call("PUT",
+                                                      &format!("/api/documents/{}", doc.id()),
+                                                      Some(object(fields)))?)?;
+        self.remember_content(&updated, data);
+        Ok(())
+    }
+
+    /// Only metadata of documents got from this storage are known.
+    fn metadata_by_id(&self, id: i64) -> DbResult<Metadata> {
+        let doc_id = self.metadata_doc_id(id)?;
+        self.document_by_id(doc_id).map(|doc| doc.metadata().clone())
+    }
+
+    fn comments_by_doc_id(&self, doc_id: i64) -> DbResult<Vec<Comment>> {
+        let comments = array(&self.call("GET",
+                                        &format!("/api/documents/{}/comments", doc_id),
+                                        None)?,
+                             json::parse_comment)?;
+        let mut comment_docs = self.comment_docs.borrow_mut();
+        for comment in &comments {
+            comment_docs.insert(comment.id(), doc_id);
+        }
+        Ok(comments)
+    }
+
+    fn comment_by_id(&self, id: i64) -> DbResult<Comment> {
+        json::parse_comment(&self.call("GET", &format!("/api/comments/{}", id), None)?)
+    }
+
+    /// Only documents of comments got from this storage are known.
+    fn comment_doc_id(&self, id: i64) -> DbResult<i64> {
+        self.comment_docs.borrow().get(&id).cloned().ok_or(DbError::NotFound)
+    }
+
+    fn add_comment(&self, doc_id: i64, comment: &mut Comment) -> DbResult<i64> {
+        let body = object(vec![("text", string(comment.text())),
+                               ("parent_id",
+                                comment.parent_id().map(Json::I64).unwrap_or(Json::Null))]);
+        let added = json::parse_comment(&self.call("POST",
+                                                   &format!("/api/documents/{}/comments",
+                                                            doc_id),
+                                                   Some(body))?)?;
+        self.comment_docs.borrow_mut().insert(added.id(), doc_id);
+        *comment = added;
+        Ok(comment.id())
+    }
+
+    fn edit_comment(&self, comment: &mut Comment, _editor: &User, text: &str) -> DbResult<()> {
+        let body = object(vec![("text", string(text))]);
+        *comment = json::parse_comment(&self.call("PUT",
+                                                  &format!("/api/comments/{}", comment.id()),
+                                                  Some(body))?)?;
+        Ok(())
+    }
+
+    fn delete_comment(&self, comment: &mut Comment, _editor: &User) -> DbResult<()> {
+        self.call("DELETE", &format!("/api/comments/{}", comment.id()), None)?;
+        comment.set_deleted();
+        Ok(())
+    }
+
+    fn set_comment_resolved(&self, id: i64, resolved: bool) -> DbResult<()> {
+        let body = object(vec![("resolved", Json::Boolean(resolved))]);
+        self.call("PUT", &format!("/api/comments/{}", id), Some(body)).map(|_| ())
+    }
+
+    fn document_lock(&self, doc_id: i64) -> DbResult<Option<Lock>> {
+        match self.call("GET", &format!("/api/documents/{}/lock", doc_id), None)? {
+            Json::Null => Ok(None),
+            lock => json::parse_lock(&lock).map(Some),
+        }
+    }
+
+    /// Check out the document as the logged in user.
+    fn check_out(&self, doc_id: i64, _user: &User) -> DbResult<Lock> {
+        json::parse_lock(&self.call("POST", &format!("/api/documents/{}/lock", doc_id), None)?)
+    }
+
+    /// Release the lock of the logged in user.
+    fn check_in(&self, doc_id: i64, _user: &User) -> DbResult<()> {
+        self.call("DELETE", &format!("/api/documents/{}/lock", doc_id), None).map(|_| ())
+    }
+
+    /// Break the lock as the logged in user, the server checks that the lock is still held by
+    /// the same user.
+    fn break_lock(&self, lock: &Lock, admin: &User) -> DbResult<()> {
+        Lock::check_breaker(admin)?;
+        let body = object(vec![("user_id", Json::I64(lock.user().id()))]);
+        self.call("POST",
+                  &format!("/api/documents/{}/lock/break", lock.doc_id()),
+                  Some(body))
+            .map(|_| ())
+    }
+
+    /// Number of changes made through the server.
+    fn data_version(&self) -> DbResult<i64> {
+        let json = self.call("GET", "/api/changes", None)?;
+        json.find("version").and_then(|version| version.as_i64()).ok_or_else(|| {
+            DbError::InvalidData("No version in the answer of the server.".to_string())
+        })
+    }
+}
+
+/// Connect to the first address of the server which answers in time.
+fn connect(addr: &str) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound,
+                                      format!("No address of the server `{}`.", addr));
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, Duration::from_secs(CONNECT_TIMEOUT_SECS)) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
+}
+
+/// Error of the answer of the server, else its JSON.
+fn check(status: u16, response: &[u8]) -> DbResult<Json> {
+    let json = if response.is_empty() {
+        Json::Null
+    } else {
+        parse_json(response).unwrap_or(Json::Null)
+    };
+    if status < 300 {
+        return Ok(json);
+    }
+    let message = json.find("error")
+        .and_then(|message| message.as_string())
+        .unwrap_or("Error of the server.")
+        .to_string();
+    match status {
+        404 => Err(DbError::NotFound),
+        409 => Err(DbError::Conflict),
+        423 => Err(DbError::Locked(message)),
+        400 => Err(DbError::InvalidData(message)),
+        _ => Err(DbError::ServerError(status, message)),
+    }
+}
+
+fn parse_json(response: &[u8]) -> DbResult<Json> {
+    str::from_utf8(response)
+        .ok()
+        .and_then(|text| Json::from_str(text).ok())
+        .ok_or_else(|| DbError::InvalidData("The answer of the server is not JSON.".to_string()))
+}
+
+fn array<T, F: Fn(&Json) -> DbResult<T>>(json: &Json, f: F) -> DbResult<Vec<T>> {
+    match json.as_array() {
+        Some(items) => items.iter().map(f).collect(),
+        None => Err(DbError::InvalidData("The answer of the server is not a list.".to_string())),
+    }
+}
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect())
+}
+
+fn string<S: Into<String>>(text: S) -> Json {
+    Json::String(text.into())
+}
+
+/// Percent-encode the parameter of the query string.
+fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[test]
+fn encode_params() {
+    assert_eq!(encode("status:complete \"Отчёт\""),
+               "status%3Acomplete%20%22%D0%9E%D1%82%D1%87%D1%91%D1%82%22");
+    assert!(RemoteStorage::login("ftp://localhost", "root", "toor").is_err());
+    match check(403, b"{\"error\":\"Only root may do it.\"}") {
+        Err(DbError::ServerError(403, ref message)) => assert_eq!(message, "Only root may do it."),
+        res => panic!("{:?}", res),
+    }
+    match check(404, b"") {
+        Err(DbError::NotFound) => {}
+        res => panic!("{:?}", res),
+    }
+    match check(409, b"{\"error\":\"Changed.\"}") {
+        Err(DbError::Conflict) => {}
+        res => panic!("{:?}", res),
+    }
+    match check(423, b"{\"error\":\"Checked out by ivan.\"}") {
+        Err(DbError::Locked(ref message)) => assert_eq!(message, "Checked out by ivan."),
+        res => panic!("{:?}", res),
+    }
+}
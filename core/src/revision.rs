@@ -0,0 +1,96 @@
+//! Revisions of the content of documents.
+//!
+//! A revision keeps the content which was replaced, the current content is in `docs.data`.
+
+use chrono::{DateTime, UTC};
+
+use user::User;
+use db::Db;
+use errors::DbResult;
+
+#[derive(Clone, Debug)]
+pub struct Revision {
+    id: i64,
+    doc_id: i64,
+    data: Option<Vec<u8>>,
+    // Time of replacing the content.
+    c_time: DateTime<UTC>,
+    author: Option<User>,
+}
+
+impl Revision {
+    pub fn new(doc_id: i64, data: Option<Vec<u8>>, author: &User) -> Self {
+        Revision {
+            id: 0,
+            doc_id: doc_id,
+            data: data,
+            c_time: UTC::now(),
+            author: Some(author.clone()),
+        }
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn doc_id(&self) -> i64 {
+        self.doc_id
+    }
+
+    pub fn data(&self) -> Option<Vec<u8>> {
+        self.data.clone()
+    }
+
+    /// Text of the revision, `None` if it's not a text in UTF-8. Empty content is an empty text.
+    pub fn text(&self) -> Option<String> {
+        match self.data {
+            Some(ref data) => String::from_utf8(data.clone()).ok(),
+            None => Some(String::new()),
+        }
+    }
+
+    pub fn c_time(&self) -> DateTime<UTC> {
+        self.c_time
+    }
+
+    /// User who replaced the content, `None` if the user was deleted.
+    pub fn author(&self) -> Option<&User> {
+        self.author.as_ref()
+    }
+
+    pub fn save_to_db(&mut self, db: &Db) -> DbResult<i64> {
+        let mut stmt = db.conn()
+            .prepare("
+INSERT INTO doc_revisions VALUES (NULL, ?, ?, ?, ?);
+")?;
+        self.id = stmt.insert(&[&self.doc_id(),
+                      &self.data(),
+                      &self.c_time(),
+                      &self.author().map(|author| author.id())])?;
+        Ok(self.id())
+    }
+
+    /// Get revisions of the document, the oldest first.
+    pub fn get_by_doc_id(db: &Db, doc_id: i64) -> DbResult<Vec<Revision>> {
+        let mut revisions: Vec<Revision> = Vec::new();
+        let mut stmt = db.conn().prepare("
+SELECT * FROM doc_revisions WHERE doc_id = ? ORDER BY id;
+")?;
+        let mut rows = stmt.query(&[&doc_id])?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            let author_id: Option<i64> = row.get_checked(4)?;
+            revisions.push(Revision {
+                id: row.get_checked(0)?,
+                doc_id: row.get_checked(1)?,
+                data: row.get_checked(2)?,
+                c_time: row.get_checked(3)?,
+                author: match author_id {
+                    Some(author_id) => Some(User::get_by_id(db, author_id)?),
+                    None => None,
+                },
+            });
+        }
+        Ok(revisions)
+    }
+}
@@ -0,0 +1,203 @@
+//! Saved searches (smart folders).
+//!
+//! A saved search is a named query of `query::Query` shown in the left pane of the main
+//! window. The built-in Inbox, Outbox, All and Calendar are predefined saved searches without
+//! owner, searches of users may be shared with other users.
+
+use user::User;
+use db::Db;
+use errors::{DbResult, DbError};
+use query::{Query, QueryError, Context, Term, Field, Op, Value};
+use storage::Storage;
+
+/// Name and query of predefined saved searches.
+const PREDEFINED: [(&'static str, &'static str); 4] = [("Inbox", "author:me"),
+                                                       ("Outbox", "responsible:me"),
+                                                       ("All", "involved:me"),
+                                                       ("Calendar", "involved:me due:selected")];
+
+fn involved_me() -> Term {
+    Term {
+        field: Field::Involved,
+        op: Op::Eq,
+        value: Value::Me,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SavedSearch {
+    id: i64,
+    name: String,
+    query: String,
+    owner: Option<User>,
+    shared: bool,
+}
+
+impl SavedSearch {
+    pub fn new<S: Into<String>>(name: S, query: S, owner: &User, shared: bool) -> Self {
+        SavedSearch {
+            id: 0,
+            name: name.into(),
+            query: query.into(),
+            owner: Some(owner.clone()),
+            shared: shared,
+        }
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    pub fn query(&self) -> &str {
+        self.query.as_ref()
+    }
+
+    /// Owner of the search, `None` for predefined searches.
+    pub fn owner(&self) -> Option<&User> {
+        self.owner.as_ref()
+    }
+
+    pub fn shared(&self) -> bool {
+        self.shared
+    }
+
+    pub fn is_predefined(&self) -> bool {
+        self.owner.is_none()
+    }
+
+    pub fn is_deletion_grant(&self, user: &User) -> bool {
+        match self.owner {
+            Some(ref owner) => owner == user || user.is_root(),
+            None => false,
+        }
+    }
+
+    /// Query of the search limited to documents where the current user is involved, since
+    /// searches shared by other users may match any documents. Root is involved in all of them.
+    pub fn to_query(&self) -> Result<Query, QueryError> {
+        let mut query = Query::parse(&self.query)?;
+        query.push(involved_me());
+        Ok(query)
+    }
+
+    /// Count documents matching the search and the additional query of the filter bar,
+    /// `DbError::InvalidData` if the query of the search is wrong.
+    pub fn count(&self, storage: &Storage, filter: &Query, context: &Context) -> DbResult<i64> {
+        let mut query = self.to_query()
+            .map_err(|e| {
+                DbError::InvalidData(format!("Wrong query of directory `{}`: {}", self.name, e))
+            })?;
+        query.extend(filter.clone());
+        storage.count_documents(&query, context)
+    }
+
+    pub fn save_to_db(&mut self, db: &Db) -> DbResult<i64> {
+        let mut stmt = db.conn()
+            .prepare("
+INSERT INTO saved_searches VALUES (NULL, ?, ?, ?, ?);
+")?;
+        self.id = stmt.insert(&[&self.name(),
+                      &self.query(),
+                      &self.owner().map(|owner| owner.id()),
+                      &self.shared()])?;
+        Ok(self.id())
+    }
+
+    /// Get predefined searches, searches of the user and searches shared by other users.
+    pub fn get_for_user(db: &Db, user: &User) -> DbResult<Vec<SavedSearch>> {
+        let mut searches: Vec<SavedSearch> = Vec::new();
+        let mut stmt = db.conn()
+            .prepare("
+SELECT * FROM saved_searches WHERE owner_id IS NULL OR owner_id = ? OR shared = 1
+ORDER BY owner_id IS NOT NULL, name;
+")?;
+        let mut rows = stmt.query(&[&user.id()])?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            let owner_id: Option<i64> = row.get_checked(3)?;
+            searches.push(SavedSearch {
+                id: row.get_checked(0)?,
+                name: row.get_checked(1)?,
+                query: row.get_checked(2)?,
+                owner: match owner_id {
+                    Some(owner_id) => Some(User::get_by_id(db, owner_id)?),
+                    None => None,
+                },
+                shared: row.get_checked(4)?,
+            });
+        }
+        // Predefined searches keep their order.
+        let mut predefined: Vec<SavedSearch> = Vec::new();
+        for &(name, _) in PREDEFINED.iter() {
+            if let Some(pos) = searches.iter()
+                .position(|search| search.is_predefined() && search.name() == name) {
+                predefined.push(searches.remove(pos));
+            }
+        }
+        predefined.extend(searches);
+        Ok(predefined)
+    }
+
+    pub fn delete_by_id(db: &Db, id: i64) -> DbResult<()> {
+        let mut stmt = db.conn()
+            .prepare("
+DELETE FROM saved_searches WHERE id = ? AND owner_id IS NOT NULL;
+")?;
+        stmt.execute(&[&id])?;
+        Ok(())
+    }
+}
+
+impl Db {
+    pub fn init_saved_searches(self) -> DbResult<Db> {
+        for &(name, query) in PREDEFINED.iter() {
+            let exists = self.conn()
+                .prepare("
+SELECT * FROM saved_searches WHERE name = ? AND owner_id IS NULL;
+")?
+                .exists(&[&name])?;
+            if !exists {
+                self.conn()
+                    .execute("INSERT INTO saved_searches VALUES (NULL, ?, ?, NULL, 1);",
+                             &[&name, &query])?;
+            }
+        }
+        Ok(self)
+    }
+}
+
+#[test]
+fn only_involved_documents() {
+    use storage::MemoryStorage;
+    use category::Category;
+    use document::Document;
+
+    let storage = MemoryStorage::new();
+    let mut ivan = User::new("ivan", "pass");
+    let mut petr = User::new("petr", "pass");
+    storage.add_user(&mut ivan).unwrap();
+    storage.add_user(&mut petr).unwrap();
+    storage.add_category(&mut Category::new("Contracts")).unwrap();
+    let mut doc = Document::new("Contract".to_string(),
+                                &ivan,
+                                Category::new("Contracts"),
+                                ivan.clone(),
+                                (2017, 3, 8),
+                                None);
+    storage.add_document(&mut doc).unwrap();
+
+    let search = SavedSearch::new("Contracts", "category:Contracts", &ivan, true);
+    let filter = Query::default();
+    assert_eq!(search.count(&storage, &filter, &Context::new(&ivan)).unwrap(), 1);
+    assert_eq!(search.count(&storage, &filter, &Context::new(&petr)).unwrap(), 0);
+    assert_eq!(search.to_query().unwrap().to_string(), "category:\"Contracts\" involved:me");
+    let broken = SavedSearch::new("Broken", "autor:ivan", &ivan, false);
+    match broken.count(&storage, &filter, &Context::new(&ivan)) {
+        Err(DbError::InvalidData(_)) => {}
+        res => panic!("{:?}", res),
+    }
+}
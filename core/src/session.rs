@@ -0,0 +1,122 @@
+//! Sessions of users: times of login and logout and failed attempts to log in.
+//!
+//! Like the audit log, sessions keep the name of the user, so they outlive the deleted users
+//! and sessions of servers can be kept in the local database.
+
+use chrono::{DateTime, UTC};
+
+use db::Db;
+use errors::DbResult;
+use audit::AuditEntry;
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    id: i64,
+    user: String,
+    // The local database or the address of the server.
+    connection: String,
+    login_time: DateTime<UTC>,
+    logout_time: Option<DateTime<UTC>>,
+}
+
+impl Session {
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Name of the logged in user.
+    pub fn user(&self) -> &str {
+        self.user.as_ref()
+    }
+
+    pub fn connection(&self) -> &str {
+        self.connection.as_ref()
+    }
+
+    pub fn login_time(&self) -> DateTime<UTC> {
+        self.login_time
+    }
+
+    /// Time of the logout, `None` while the session lasts or if the program was killed.
+    pub fn logout_time(&self) -> Option<DateTime<UTC>> {
+        self.logout_time
+    }
+
+    /// Record the login of the user now.
+    pub fn start(db: &Db, user: &str, connection: &str) -> DbResult<Session> {
+        let login_time = UTC::now();
+        let mut stmt = db.conn()
+            .prepare("
+INSERT INTO sessions VALUES (NULL, ?, ?, ?, NULL);
+")?;
+        let id = stmt.insert(&[&user, &connection, &login_time])?;
+        Ok(Session {
+            id: id,
+            user: user.to_string(),
+            connection: connection.to_string(),
+            login_time: login_time,
+            logout_time: None,
+        })
+    }
+
+    /// Record the logout now.
+    pub fn end(&mut self, db: &Db) -> DbResult<()> {
+        let logout_time = UTC::now();
+        db.conn()
+            .execute("UPDATE sessions SET logout_time = ? WHERE id = ?;",
+                     &[&logout_time, &self.id])?;
+        self.logout_time = Some(logout_time);
+        Ok(())
+    }
+
+    /// Get sessions, the latest first.
+    pub fn get_all(db: &Db) -> DbResult<Vec<Session>> {
+        let mut sessions: Vec<Session> = Vec::new();
+        let mut stmt = db.conn().prepare("
+SELECT id, user_name, connection, login_time, logout_time FROM sessions ORDER BY id DESC;
+")?;
+        let mut rows = stmt.query(&[])?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            sessions.push(Session {
+                id: row.get_checked(0)?,
+                user: row.get_checked(1)?,
+                connection: row.get_checked(2)?,
+                login_time: row.get_checked(3)?,
+                logout_time: row.get_checked(4)?,
+            });
+        }
+        Ok(sessions)
+    }
+
+    /// Record a wrong password of the user in the audit log.
+    pub fn record_failed_login(db: &Db, user: &str, connection: &str) -> DbResult<i64> {
+        AuditEntry::record(db, user, "login_failed", &format!("Connection: {}", connection))
+    }
+}
+
+#[test]
+fn start_and_end() {
+    use temp_dir::TempDir;
+
+    let dir = TempDir::new("session-test").unwrap();
+    let db = Db::open(dir.join("db.sqlite3")).unwrap().init_root().unwrap();
+
+    let mut session = Session::start(&db, "root", "Local database").unwrap();
+    assert!(session.logout_time().is_none());
+    Session::start(&db, "ivan", "http://localhost:8080").unwrap();
+    session.end(&db).unwrap();
+    assert!(session.logout_time().is_some());
+
+    let sessions = Session::get_all(&db).unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0].user(), "ivan");
+    assert!(sessions[0].logout_time().is_none());
+    assert_eq!(sessions[1].connection(), "Local database");
+    assert!(sessions[1].logout_time().is_some());
+
+    Session::record_failed_login(&db, "ivan", "Local database").unwrap();
+    let entries = AuditEntry::get_all(&db).unwrap();
+    assert_eq!(entries[0].user(), "ivan");
+    assert_eq!(entries[0].action(), "login_failed");
+}
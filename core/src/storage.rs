@@ -0,0 +1,727 @@
+//! Storage of documents, users, categories, comments and metadata.
+//!
+//! `Storage` is implemented by the SQLite database `Db`, by `MemoryStorage`, which keeps
+//! everything in memory, e.g. for tests, by `remote::RemoteStorage` on a server and by
+//! `pg::PgStorage` with the `postgres` feature.
+//! Code written against the trait runs with any of them and with new backends.
+
+use chrono::UTC;
+
+use std::cell::RefCell;
+
+use user::User;
+use category::Category;
+use metadata::Metadata;
+use document::{Document, DocumentSummary};
+use comment::Comment;
+use lock::Lock;
+use audit::AuditEntry;
+use notification::Notification;
+use query::{Query, Context};
+use db::Db;
+use config::Config;
+use errors::{DbResult, DbError};
+#[cfg(feature = "postgres")]
+use pg::PgStorage;
+
+pub trait Storage {
+    fn users(&self) -> DbResult<Vec<User>>;
+    fn user_by_id(&self, id: i64) -> DbResult<User>;
+    fn user_by_name(&self, name: &str) -> DbResult<User>;
+    /// Whether the user with the same name and password exists.
+    fn user_exists(&self, user: &User) -> DbResult<bool>;
+    fn add_user(&self, user: &mut User) -> DbResult<i64>;
+    fn update_user(&self, id: i64, name: &str, pass: &str) -> DbResult<()>;
+    /// Delete the user with documents and comments of the user.
+    fn delete_user(&self, id: i64) -> DbResult<()>;
+
+    fn categories(&self) -> DbResult<Vec<Category>>;
+    fn category_by_id(&self, id: i64) -> DbResult<Category>;
+    fn category_by_name(&self, name: &str) -> DbResult<Category>;
+    fn add_category(&self, category: &mut Category) -> DbResult<i64>;
+
+    /// Documents with their metadata and comments.
+    fn documents(&self) -> DbResult<Vec<Document>>;
+    fn document_by_id(&self, id: i64) -> DbResult<Document>;
+    /// Page of summaries of documents matching the query ordered by id, a negative `limit`
+    /// means all of them.
+    fn list_documents(&self,
+                      query: &Query,
+                      context: &Context,
+                      limit: i64,
+                      offset: i64)
+                      -> DbResult<Vec<DocumentSummary>>;
+    fn count_documents(&self, query: &Query, context: &Context) -> DbResult<i64>;
+    /// Add the document with its metadata and comments, the author and the category have to be
+    /// stored before.
+    fn add_document(&self, doc: &mut Document) -> DbResult<i64>;
+    /// Save changes of the document and its metadata by the editor, `DbError::Conflict` if the
+    /// document was saved by somebody else after it was read and `DbError::Locked` if it's
+    /// checked out by another user. The stored version is increased.
+    fn update_document(&self, doc: &Document, editor: &User) -> DbResult<()>;
+    /// Metadata are changed by `update_document` only, so they are checked for conflicts too.
+    fn metadata_by_id(&self, id: i64) -> DbResult<Metadata>;
+
+    /// Comments of the document, every reply follows its parent.
+    fn comments_by_doc_id(&self, doc_id: i64) -> DbResult<Vec<Comment>>;
+    fn comment_by_id(&self, id: i64) -> DbResult<Comment>;
+    /// Id of the document of the comment.
+    fn comment_doc_id(&self, id: i64) -> DbResult<i64>;
+    /// Add the comment and notify users mentioned in it, if the storage keeps notifications.
+    fn add_comment(&self, doc_id: i64, comment: &mut Comment) -> DbResult<i64>;
+    /// Change the text of the comment, users newly mentioned in it are notified like by
+    /// `add_comment`.
+    fn edit_comment(&self, comment: &mut Comment, editor: &User, text: &str) -> DbResult<()>;
+    fn delete_comment(&self, comment: &mut Comment, editor: &User) -> DbResult<()>;
+    fn set_comment_resolved(&self, id: i64, resolved: bool) -> DbResult<()>;
+
+    /// Lock of the document, `None` if it isn't checked out.
+    fn document_lock(&self, doc_id: i64) -> DbResult<Option<Lock>>;
+    /// Check out the document for the user. Return the lock of the document, which is held by
+    /// somebody else if the document was checked out before.
+    fn check_out(&self, doc_id: i64, user: &User) -> DbResult<Lock>;
+    /// Release the lock of the user, `DbError::NotFound` if the user doesn't hold it.
+    fn check_in(&self, doc_id: i64, user: &User) -> DbResult<()>;
+    /// Release the lock of another user on behalf of root, it's recorded in the audit log.
+    fn break_lock(&self, lock: &Lock, admin: &User) -> DbResult<()>;
+
+    /// Number which changes when the data are changed by others, e.g. by other connections to
+    /// the database. Views poll it to show changes of other users.
+    fn data_version(&self) -> DbResult<i64>;
+}
+
+/// Open the storage of the configuration, a new one has the root user.
+pub fn open(config: &Config) -> DbResult<Box<Storage>> {
+    match config.database() {
+        Some(url) if config.is_postgres() => open_postgres(url),
+        Some(path) => Ok(Box::new(Db::open(path).and_then(|db| db.init_root())?)),
+        None => Ok(Box::new(Db::new().and_then(|db| db.init_root())?)),
+    }
+}
+
+#[cfg(feature = "postgres")]
+fn open_postgres(url: &str) -> DbResult<Box<Storage>> {
+    Ok(Box::new(PgStorage::connect(url)?))
+}
+
+#[cfg(not(feature = "postgres"))]
+fn open_postgres(_url: &str) -> DbResult<Box<Storage>> {
+    Err(DbError::InvalidData("PostgreSQL is not supported, build with the `postgres` feature."
+        .to_string()))
+}
+
+impl Storage for Db {
+    fn users(&self) -> DbResult<Vec<User>> {
+        User::get_users(self)
+    }
+
+    fn user_by_id(&self, id: i64) -> DbResult<User> {
+        User::get_by_id(self, id)
+    }
+
+    fn user_by_name(&self, name: &str) -> DbResult<User> {
+        User::get_user(self, name)
+    }
+
+    fn user_exists(&self, user: &User) -> DbResult<bool> {
+        user.exists(self)
+    }
+
+    fn add_user(&self, user: &mut User) -> DbResult<i64> {
+        user.save_to_db(self)
+    }
+
+    fn update_user(&self, id: i64, name: &str, pass: &str) -> DbResult<()> {
+        User::update_by_id(self, id, name, pass)
+    }
+
+    fn delete_user(&self, id: i64) -> DbResult<()> {
+        User::delete_by_id(self, id)
+    }
+
+    fn categories(&self) -> DbResult<Vec<Category>> {
+        Category::get_categories(self)
+    }
+
+    fn category_by_id(&self, id: i64) -> DbResult<Category> {
+        Category::get_by_id(self, id)
+    }
+
+    fn category_by_name(&self, name: &str) -> DbResult<Category> {
+        Category::get_category(self, name)
+    }
+
+    fn add_category(&self, category: &mut Category) -> DbResult<i64> {
+        category.save_to_db(self)
+    }
+
+    fn documents(&self) -> DbResult<Vec<Document>> {
+        Document::get_docs(self)
+    }
+
+    fn document_by_id(&self, id: i64) -> DbResult<Document> {
+        Document::get_by_id(self, id)
+    }
+
+    fn list_documents(&self,
+                      query: &Query,
+                      context: &Context,
+                      limit: i64,
+                      offset: i64)
+                      -> DbResult<Vec<DocumentSummary>> {
+        Document::list(self, query, context, limit, offset)
+    }
+
+    fn count_documents(&self, query: &Query, context: &Context) -> DbResult<i64> {
+        Document::count(self, query, context)
+    }
+
+    fn add_document(&self, doc: &mut Document) -> DbResult<i64> {
+        doc.save_to_db(self)
+    }
+
+    fn update_document(&self, doc: &Document, editor: &User) -> DbResult<()> {
+        self.transaction(|db| {
+            if let Some(lock) = Lock::get_by_doc_id(db, doc.id())? {
+                lock.check_held_by(editor)?;
+            }
+            doc.update(db)?;
+            doc.metadata().update(db)?;
+            Ok(())
+        })
+    }
+
+    fn metadata_by_id(&self, id: i64) -> DbResult<Metadata> {
+        Metadata::get_by_id(self, id)
+    }
+
+    fn comments_by_doc_id(&self, doc_id: i64) -> DbResult<Vec<Comment>> {
+        Comment::get_by_doc_id(self, doc_id)
+    }
+
+    fn comment_by_id(&self, id: i64) -> DbResult<Comment> {
+        Comment::get_by_id(self, id)
+    }
+
+    fn comment_doc_id(&self, id: i64) -> DbResult<i64> {
+        self.conn()
+            .query_row_and_then("SELECT doc_id FROM comments WHERE id = ?;",
+                                &[&id],
+                                |row| row.get_checked(0))
+            .map_err(From::from)
+    }
+
+    fn add_comment(&self, doc_id: i64, comment: &mut Comment) -> DbResult<i64> {
+        self.transaction(|db| {
+            let id = comment.save_to_db(db, doc_id)?;
+            Notification::notify_mentions(db, &db.document_by_id(doc_id)?, comment)?;
+            Ok(id)
+        })
+    }
+
+    fn edit_comment(&self, comment: &mut Comment, editor: &User, text: &str) -> DbResult<()> {
+        comment.edit(self, editor, text)?;
+        let doc = self.document_by_id(self.comment_doc_id(comment.id())?)?;
+        Notification::notify_mentions(self, &doc, comment).map(|_| ())
+    }
+
+    fn delete_comment(&self, comment: &mut Comment, editor: &User) -> DbResult<()> {
+        comment.delete(self, editor)
+    }
+
+    fn set_comment_resolved(&self, id: i64, resolved: bool) -> DbResult<()> {
+        Comment::set_resolved(self, id, resolved)
+    }
+
+    fn document_lock(&self, doc_id: i64) -> DbResult<Option<Lock>> {
+        Lock::get_by_doc_id(self, doc_id)
+    }
+
+    fn check_out(&self, doc_id: i64, user: &User) -> DbResult<Lock> {
+        Lock::check_out(self, doc_id, user)
+    }
+
+    fn check_in(&self, doc_id: i64, user: &User) -> DbResult<()> {
+        Lock::check_in(self, doc_id, user)
+    }
+
+    fn break_lock(&self, lock: &Lock, admin: &User) -> DbResult<()> {
+        lock.break_lock(self, admin)
+    }
+
+    /// `PRAGMA data_version`, it doesn't count changes made by this connection.
+    fn data_version(&self) -> DbResult<i64> {
+        self.conn()
+            .query_row_and_then("PRAGMA data_version;", &[], |row| row.get_checked(0))
+            .map_err(From::from)
+    }
+}
+
+/// Storage in memory, it's empty when created and lost when dropped. Previous texts of edited
+/// comments are not kept.
+#[derive(Default)]
+pub struct MemoryStorage {
+    tables: RefCell<Tables>,
+}
+
+#[derive(Default)]
+struct Tables {
+    last_id: i64,
+    users: Vec<User>,
+    categories: Vec<Category>,
+    // Documents without comments.
+    docs: Vec<Document>,
+    // Comments with ids of their documents.
+    comments: Vec<(i64, Comment)>,
+    locks: Vec<Lock>,
+    // The newest entry is the last.
+    audit_log: Vec<AuditEntry>,
+}
+
+impl Tables {
+    fn next_id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn with_comments(&self, doc: &Document) -> Document {
+        let id = doc.id();
+        let mut doc = doc.clone();
+        *doc.comments_mut() = self.comments
+            .iter()
+            .filter(|&&(doc_id, _)| doc_id == id)
+            .map(|&(_, ref comment)| comment.clone())
+            .collect();
+        doc
+    }
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        MemoryStorage::default()
+    }
+
+    /// Entries of the audit log, the newest first.
+    pub fn audit_log(&self) -> Vec<AuditEntry> {
+        self.tables.borrow().audit_log.iter().rev().cloned().collect()
+    }
+
+    fn update_comment<F: FnOnce(&mut Comment)>(&self, id: i64, f: F) -> DbResult<()> {
+        let mut tables = self.tables.borrow_mut();
+        let entry = found(tables.comments.iter_mut().find(|entry| entry.1.id() == id))?;
+        f(&mut entry.1);
+        Ok(())
+    }
+}
+
+fn found<T>(item: Option<T>) -> DbResult<T> {
+    item.ok_or(DbError::NotFound)
+}
+
+impl Storage for MemoryStorage {
+    fn users(&self) -> DbResult<Vec<User>> {
+        Ok(self.tables.borrow().users.clone())
+    }
+
+    fn user_by_id(&self, id: i64) -> DbResult<User> {
+        found(self.tables.borrow().users.iter().find(|user| user.id() == id).cloned())
+    }
+
+    fn user_by_name(&self, name: &str) -> DbResult<User> {
+        found(self.tables.borrow().users.iter().find(|user| user.name() == name).cloned())
+    }
+
+    fn user_exists(&self, user: &User) -> DbResult<bool> {
+        Ok(self.tables.borrow().users.iter().any(|stored| {
+            stored.name() == user.name() && stored.pass() == user.pass() &&
+            stored.pass_hash() == user.pass_hash()
+        }))
+    }
+
+    fn add_user(&self, user: &mut User) -> DbResult<i64> {
+        let mut tables = self.tables.borrow_mut();
+        if tables.users.iter().any(|stored| stored.name() == user.name()) {
+            return Err(DbError::InvalidData(format!("The user {} already exists.", user.name())));
+        }
+        let id = tables.next_id();
+        user.set_id(id);
+        tables.users.push(user.clone());
+        Ok(id)
+    }
+
+    fn update_user(&self, id: i64, name: &str, pass: &str) -> DbResult<()> {
+        let mut tables = self.tables.borrow_mut();
+        let user = found(tables.users.iter_mut().find(|user| user.id() == id))?;
+        let mut updated_user = User::new(name, pass);
+        updated_user.set_id(id);
+        user.set(updated_user);
+        Ok(())
+    }
+
+    fn delete_user(&self, id: i64) -> DbResult<()> {
+        let mut tables = self.tables.borrow_mut();
+        tables.users.retain(|user| user.id() != id);
+        let deleted_docs: Vec<i64> = tables.docs
+            .iter()
+            .filter(|doc| doc.metadata().author().id() == id || doc.responsible().id() == id)
+            .map(|doc| doc.id())
+            .collect();
+        tables.docs.retain(|doc| !deleted_docs.contains(&doc.id()));
+        tables.comments
+            .retain(|&(doc_id, ref comment)| {
+                !deleted_docs.contains(&doc_id) && comment.author().id() != id
+            });
+        Ok(())
+    }
+
+    fn categories(&self) -> DbResult<Vec<Category>> {
+        Ok(self.tables.borrow().categories.clone())
+    }
+
+    fn category_by_id(&self, id: i64) -> DbResult<Category> {
+        found(self.tables
+            .borrow()
+            .categories
+            .iter()
+            .find(|category| category.id() == id)
+            .cloned())
+    }
+
+    fn category_by_name(&self, name: &str) -> DbResult<Category> {
+        found(self.tables
+            .borrow()
+            .categories
+            .iter()
+            .find(|category| category.name() == name)
+            .cloned())
+    }
+
+    fn add_category(&self, category: &mut Category) -> DbResult<i64> {
+        let mut tables = self.tables.borrow_mut();
+        if tables.categories.iter().any(|stored| stored.name() == category.name()) {
+            return Err(DbError::InvalidData(format!("The category {} already exists.",
+                                                    category.name())));
+        }
+        let id = tables.next_id();
+        category.set_id(id);
+        tables.categories.push(category.clone());
+        Ok(id)
+    }
+
+    fn documents(&self) -> DbResult<Vec<Document>> {
+        let tables = self.tables.borrow();
+        Ok(tables.docs.iter().map(|doc| tables.with_comments(doc)).collect())
+    }
+
+    fn document_by_id(&self, id: i64) -> DbResult<Document> {
+        let tables = self.tables.borrow();
+        found(tables.docs.iter().find(|doc| doc.id() == id).map(|doc| tables.with_comments(doc)))
+    }
+
+    fn list_documents(&self,
+                      query: &Query,
+                      context: &Context,
+                      limit: i64,
+                      offset: i64)
+                      -> DbResult<Vec<DocumentSummary>> {
+        let limit = if limit < 0 { usize::max_value() } else { limit as usize };
+        Ok(self.tables
+            .borrow()
+            .docs
+            .iter()
+            .filter(|doc| query.matches(doc, context))
+            .skip(offset as usize)
+            .take(limit)
+            .map(DocumentSummary::from)
+            .collect())
+    }
+
+    fn count_documents(&self, query: &Query, context: &Context) -> DbResult<i64> {
+        let tables = self.tables.borrow();
+        let count = tables.docs.iter().filter(|doc| query.matches(doc, context)).count();
+        Ok(count as i64)
+    }
+
+    fn add_document(&self, doc: &mut Document) -> DbResult<i64> {
+        let mut tables = self.tables.borrow_mut();
+        // Like the database, the author and the category are found by names.
+        let author = found(tables.users
+                .iter()
+                .find(|user| user.name() == doc.metadata().author().name())
+                .cloned())?;
+        let category = found(tables.categories
+                .iter()
+                .find(|category| category.name() == doc.metadata().category().name())
+                .cloned())?;
+        let m_time = doc.metadata().m_time();
+        let metadata_id = tables.next_id();
+        let doc_id = tables.next_id();
+        {
+            let metadata = doc.metadata_mut();
+            metadata.set_id(metadata_id);
+            metadata.set_author(author);
+            metadata.set_category(category);
+            metadata.set_m_time(m_time);
+        }
+        doc.set_id(doc_id);
+        for comment in doc.comments_mut() {
+            let id = tables.next_id();
+            comment.set_id(id);
+            tables.comments.push((doc_id, comment.clone()));
+        }
+        let mut stored = doc.clone();
+        stored.comments_mut().clear();
+        tables.docs.push(stored);
+        Ok(doc_id)
+    }
+
+    fn update_document(&self, doc: &Document, editor: &User) -> DbResult<()> {
+        let mut tables = self.tables.borrow_mut();
+        if let Some(lock) = tables.locks.iter().find(|lock| lock.doc_id() == doc.id()) {
+            lock.check_held_by(editor)?;
+        }
+        let stored = found(tables.docs.iter_mut().find(|stored| stored.id() == doc.id()))?;
+        if stored.version() != doc.version() {
+            return Err(DbError::Conflict);
+        }
+        *stored = doc.clone();
+        stored.comments_mut().clear();
+        stored.set_version(doc.version() + 1);
+        Ok(())
+    }
+
+    fn metadata_by_id(&self, id: i64) -> DbResult<Metadata> {
+        found(self.tables
+            .borrow()
+            .docs
+            .iter()
+            .find(|doc| doc.metadata().id() == id)
+            .map(|doc| doc.metadata().clone()))
+    }
+
+    fn comments_by_doc_id(&self, doc_id: i64) -> DbResult<Vec<Comment>> {
+        Ok(self.tables
+            .borrow()
+            .comments
+            .iter()
+            .filter(|&&(id, _)| id == doc_id)
+            .map(|&(_, ref comment)| comment.clone())
+            .collect())
+    }
+
+    fn comment_by_id(&self, id: i64) -> DbResult<Comment> {
+        found(self.tables
+            .borrow()
+            .comments
+            .iter()
+            .find(|&&(_, ref comment)| comment.id() == id)
+            .map(|&(_, ref comment)| comment.clone()))
+    }
+
+    fn comment_doc_id(&self, id: i64) -> DbResult<i64> {
+        found(self.tables
+            .borrow()
+            .comments
+            .iter()
+            .find(|&&(_, ref comment)| comment.id() == id)
+            .map(|&(doc_id, _)| doc_id))
+    }
+
+    fn add_comment(&self, doc_id: i64, comment: &mut Comment) -> DbResult<i64> {
+        let mut tables = self.tables.borrow_mut();
+        if !tables.docs.iter().any(|doc| doc.id() == doc_id) {
+            return Err(DbError::NotFound);
+        }
+        let id = tables.next_id();
+        comment.set_id(id);
+        tables.comments.push((doc_id, comment.clone()));
+        Ok(id)
+    }
+
+    fn edit_comment(&self, comment: &mut Comment, _editor: &User, text: &str) -> DbResult<()> {
+        self.update_comment(comment.id(), |stored| stored.set_edited(text))?;
+        comment.set_edited(text);
+        Ok(())
+    }
+
+    fn delete_comment(&self, comment: &mut Comment, _editor: &User) -> DbResult<()> {
+        self.update_comment(comment.id(), |stored| stored.set_deleted())?;
+        comment.set_deleted();
+        Ok(())
+    }
+
+    fn set_comment_resolved(&self, id: i64, resolved: bool) -> DbResult<()> {
+        self.update_comment(id, |stored| stored.set_resolved_flag(resolved))
+    }
+
+    fn document_lock(&self, doc_id: i64) -> DbResult<Option<Lock>> {
+        Ok(self.tables.borrow().locks.iter().find(|lock| lock.doc_id() == doc_id).cloned())
+    }
+
+    fn check_out(&self, doc_id: i64, user: &User) -> DbResult<Lock> {
+        let mut tables = self.tables.borrow_mut();
+        if !tables.docs.iter().any(|doc| doc.id() == doc_id) {
+            return Err(DbError::NotFound);
+        }
+        if let Some(lock) = tables.locks.iter().find(|lock| lock.doc_id() == doc_id) {
+            return Ok(lock.clone());
+        }
+        let lock = Lock::new(doc_id, user.clone(), UTC::now());
+        tables.locks.push(lock.clone());
+        Ok(lock)
+    }
+
+    fn check_in(&self, doc_id: i64, user: &User) -> DbResult<()> {
+        let mut tables = self.tables.borrow_mut();
+        let pos = found(tables.locks
+            .iter()
+            .position(|lock| lock.doc_id() == doc_id && lock.is_held_by(user)))?;
+        tables.locks.remove(pos);
+        Ok(())
+    }
+
+    fn break_lock(&self, lock: &Lock, admin: &User) -> DbResult<()> {
+        Lock::check_breaker(admin)?;
+        self.check_in(lock.doc_id(), lock.user())?;
+        let mut tables = self.tables.borrow_mut();
+        let id = tables.next_id();
+        let entry = AuditEntry::new(id, admin.name(), "break_lock", &lock.audit_details());
+        tables.audit_log.push(entry);
+        Ok(())
+    }
+
+    /// No one else changes the memory.
+    fn data_version(&self) -> DbResult<i64> {
+        Ok(0)
+    }
+}
+
+/// Common checks of storages, the storage has to be empty.
+#[cfg(test)]
+pub fn check_storage<S: Storage>(storage: &S) {
+    let mut ivan = User::new("ivan", "secret");
+    storage.add_user(&mut ivan).unwrap();
+    let mut petr = User::new("petr", "secret");
+    storage.add_user(&mut petr).unwrap();
+    assert!(storage.add_user(&mut User::new("ivan", "other")).is_err());
+    assert!(storage.user_exists(&User::new("ivan", "secret")).unwrap());
+    assert_eq!(storage.user_by_name("petr").unwrap(), petr);
+    match storage.user_by_name("nobody") {
+        Err(DbError::NotFound) => {}
+        res => panic!("{:?}", res),
+    }
+
+    let mut category = Category::new("Contracts");
+    storage.add_category(&mut category).unwrap();
+    assert_eq!(storage.category_by_id(category.id()).unwrap().name(), "Contracts");
+
+    let mut doc = Document::new("Contract".to_string(),
+                                &ivan,
+                                category,
+                                petr.clone(),
+                                (2017, 3, 8),
+                                Some(Comment::new(&ivan, "Sign it".to_string())));
+    let doc_id = storage.add_document(&mut doc).unwrap();
+    let mut reply = Comment::new_reply(&petr, "Done".to_string(), doc.comments()[0].id());
+    storage.add_comment(doc_id, &mut reply).unwrap();
+    storage.set_comment_resolved(doc.comments()[0].id(), true).unwrap();
+    storage.edit_comment(&mut reply, &petr, "Signed").unwrap();
+
+    let mut doc = storage.document_by_id(doc_id).unwrap();
+    assert_eq!(doc.metadata().author(), &ivan);
+    assert_eq!(doc.comments().len(), 2);
+    assert!(doc.comments()[0].resolved());
+    assert_eq!(storage.comment_by_id(reply.id()).unwrap().text(), "Signed");
+    assert_eq!(storage.comment_doc_id(reply.id()).unwrap(), doc_id);
+
+    doc.set_name("Signed contract");
+    doc.metadata_mut().set_status(::metadata::Status::Complete);
+    storage.update_document(&doc, &ivan).unwrap();
+    assert_eq!(storage.document_by_id(doc_id).unwrap().version(), doc.version() + 1);
+    // The stale copy doesn't overwrite the saved changes.
+    doc.set_name("Old contract");
+    match storage.update_document(&doc, &ivan) {
+        Err(DbError::Conflict) => {}
+        res => panic!("{:?}", res),
+    }
+    // Only the holder of the lock saves the checked out document.
+    let mut doc = storage.document_by_id(doc_id).unwrap();
+    assert!(storage.document_lock(doc_id).unwrap().is_none());
+    assert!(storage.check_out(doc_id, &petr).unwrap().is_held_by(&petr));
+    let lock = storage.check_out(doc_id, &ivan).unwrap();
+    assert!(lock.is_held_by(&petr));
+    match storage.update_document(&doc, &ivan) {
+        Err(DbError::Locked(_)) => {}
+        res => panic!("{:?}", res),
+    }
+    assert!(storage.check_in(doc_id, &ivan).is_err());
+    storage.update_document(&doc, &petr).unwrap();
+    doc.set_version(doc.version() + 1);
+    match storage.break_lock(&lock, &ivan) {
+        Err(DbError::Forbidden(_)) => {}
+        res => panic!("{:?}", res),
+    }
+    assert!(storage.document_lock(doc_id).unwrap().unwrap().is_held_by(&petr));
+    storage.break_lock(&lock, &User::new("root", "toor")).unwrap();
+    assert!(storage.document_lock(doc_id).unwrap().is_none());
+    storage.check_out(doc_id, &ivan).unwrap();
+    storage.update_document(&doc, &ivan).unwrap();
+    storage.check_in(doc_id, &ivan).unwrap();
+    let context = Context::new(&ivan);
+    let query = Query::parse("author:me status:complete signed").unwrap();
+    let summaries = storage.list_documents(&query, &context, -1, 0).unwrap();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0].name(), "Signed contract");
+    assert_eq!(storage.count_documents(&Query::parse("responsible:me").unwrap(), &context)
+                   .unwrap(),
+               0);
+    let readable = Query::parse("readable:me").unwrap();
+    assert_eq!(storage.count_documents(&readable, &Context::new(&petr)).unwrap(), 1);
+    let sidorov = User::new("sidorov", "pass");
+    assert_eq!(storage.count_documents(&readable, &Context::new(&sidorov)).unwrap(), 0);
+
+    storage.delete_user(petr.id()).unwrap();
+    assert_eq!(storage.users().unwrap(), vec![ivan]);
+    assert!(storage.documents().unwrap().is_empty());
+}
+
+#[test]
+fn memory_storage() {
+    let storage = MemoryStorage::new();
+    check_storage(&storage);
+    let entries = storage.audit_log();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].user(), "root");
+    assert_eq!(entries[0].action(), "break_lock");
+}
+
+#[test]
+fn sqlite_storage() {
+    use temp_dir::TempDir;
+
+    let dir = TempDir::new("storage-test").unwrap();
+    let path = dir.join("db.sqlite3");
+    let db = Db::open(&path).unwrap();
+    check_storage(&db);
+    assert_eq!(AuditEntry::get_all(&db).unwrap()[0].user(), "root");
+    // Users mentioned in added and edited comments are notified once.
+    let ivan = db.user_by_name("ivan").unwrap();
+    let mut sidorov = User::new("sidorov", "pass");
+    db.add_user(&mut sidorov).unwrap();
+    let mut doc = Document::new("Act".to_string(),
+                                &ivan,
+                                db.category_by_name("Contracts").unwrap(),
+                                sidorov.clone(),
+                                (2017, 3, 9),
+                                None);
+    let doc_id = db.add_document(&mut doc).unwrap();
+    let mut comment = Comment::new(&ivan, "@sidorov, sign it".to_string());
+    db.add_comment(doc_id, &mut comment).unwrap();
+    assert_eq!(Notification::count_unread(&db, &sidorov).unwrap(), 1);
+    db.edit_comment(&mut comment, &ivan, "@sidorov, sign it today").unwrap();
+    assert_eq!(Notification::count_unread(&db, &sidorov).unwrap(), 1);
+    // Changes of other connections are seen.
+    let version = db.data_version().unwrap();
+    Db::open(&path).unwrap().add_category(&mut Category::new("Other")).unwrap();
+    assert!(db.data_version().unwrap() != version);
+}
@@ -0,0 +1,61 @@
+//! Temporary directories of tests.
+//!
+//! Every directory has a unique name, so concurrent test runs don't share files, and it's
+//! removed on drop, also when the test fails.
+
+use std::env;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+static COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create `sed_bad-<prefix>-<process id>-<counter>` in the temporary directory of the system.
+    pub fn new(prefix: &str) -> io::Result<TempDir> {
+        loop {
+            let path = env::temp_dir().join(format!("sed_bad-{}-{}-{}",
+                                                    prefix,
+                                                    process::id(),
+                                                    COUNTER.fetch_add(1, Ordering::SeqCst)));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(TempDir { path: path }),
+                // Left by a killed run with the same process id.
+                Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path of the file in the directory.
+    pub fn join<P: AsRef<Path>>(&self, name: P) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[test]
+fn unique_and_removed() {
+    let first = TempDir::new("temp-dir-test").unwrap();
+    let second = TempDir::new("temp-dir-test").unwrap();
+    assert!(first.path() != second.path());
+    fs::File::create(first.join("file")).unwrap();
+    let path = first.path().to_path_buf();
+    drop(first);
+    assert!(!path.exists());
+}
@@ -4,7 +4,10 @@
 
 use md5;
 
-use errors::DbResult;
+use std::fs::File;
+use std::io::Read;
+
+use errors::{DbResult, DbError};
 use db::Db;
 use document::Document;
 
@@ -45,6 +48,11 @@ impl User {
         self.id
     }
 
+    /// Set the id assigned by a storage.
+    pub fn set_id(&mut self, id: i64) {
+        self.id = id;
+    }
+
     pub fn name(&self) -> &str {
         self.name.as_ref()
     }
@@ -65,6 +73,30 @@ impl User {
         doc.metadata().author() == self || self.is_root()
     }
 
+    /// Whether the user may read the document according to its permission.
+    pub fn is_read_grant(&self, doc: &Document) -> bool {
+        let permission = doc.permission();
+        if self.is_access_grant(doc) {
+            permission.author().read()
+        } else if doc.responsible() == self {
+            permission.responsible().read()
+        } else {
+            permission.others().read()
+        }
+    }
+
+    /// Whether the user may comment the document according to its permission.
+    pub fn is_comment_grant(&self, doc: &Document) -> bool {
+        let permission = doc.permission();
+        if self.is_access_grant(doc) {
+            permission.author().comment()
+        } else if doc.responsible() == self {
+            permission.responsible().comment()
+        } else {
+            permission.others().comment()
+        }
+    }
+
     pub fn exists(&self, db: &Db) -> DbResult<bool> {
         let mut stmt = db.conn()
             .prepare("
@@ -94,6 +126,23 @@ INSERT INTO users VALUES (NULL, $1, $2, $3);
         })
     }
 
+    /// Get the user, a missing one is created with a random password nobody knows, so root has
+    /// to set it. The flag tells whether the user is created.
+    pub fn get_or_create(db: &Db, name: &str) -> DbResult<(User, bool)> {
+        match User::get_user(db, name) {
+            Ok(user) => Ok((user, false)),
+            Err(DbError::NotFound) => {
+                let mut bytes = [0u8; 16];
+                File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+                let pass: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                let mut user = User::new(name.to_string(), pass);
+                user.save_to_db(db)?;
+                Ok((user, true))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn get_by_id(db: &Db, id: i64) -> DbResult<User> {
         db.conn().query_row_and_then("SELECT * FROM users WHERE id = ?;", &[&id], |row| {
             Ok(User {
@@ -214,3 +263,19 @@ SELECT * FROM users WHERE name = ?;
                    })
         .unwrap();
 }
+
+#[test]
+fn create_missing_user() {
+    use temp_dir::TempDir;
+
+    let dir = TempDir::new("user-test").unwrap();
+    let db = Db::open(dir.join("db.sqlite3")).unwrap();
+    let (ivan, created) = User::get_or_create(&db, "ivan").unwrap();
+    assert!(created);
+    assert_eq!(ivan.pass.len(), 32);
+    let (petr, _) = User::get_or_create(&db, "petr").unwrap();
+    assert!(ivan.pass != petr.pass);
+    let (same, created) = User::get_or_create(&db, "ivan").unwrap();
+    assert!(!created);
+    assert_eq!(same, ivan);
+}
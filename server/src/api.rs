@@ -0,0 +1,844 @@
+//! Resources of the API.
+//!
+//! Bodies are JSON, every request except the login needs the header
+//! `Authorization: Bearer <token>` with the token given by the login:
+//!
+//! - `POST /api/login` with `{"name", "password"}` gives `{"token", "user"}`,
+//!   `POST /api/logout` forgets the token. A token unused for `TOKEN_IDLE_SECS` expires.
+//! - `GET /api/documents?q=<query>&limit=<n>&offset=<n>` gives a page of summaries filtered by
+//!   the query language of `sed_bad_core::query`, RFC 3339 times `from` and `to` set the
+//!   period of `due:selected`. Users except root get only documents which they may read.
+//!   `POST /api/documents` creates a document.
+//! - `GET` and `PUT /api/documents/<id>` for the document with metadata and comments, the
+//!   `version` given by `PUT` has to be the stored one, else somebody else has saved the
+//!   document after it was read. `POST` and `PUT` take the content as text in `data` or as
+//!   base64 in `data_base64`. `GET` and `PUT /api/documents/<id>/data` for the content
+//!   (attachment) as is, `PUT` checks the `version` parameter if it's given. A document
+//!   checked out by another user can't be changed.
+//! - `GET /api/documents/<id>/lock` gives the lock `{"doc_id", "user", "user_id", "c_time"}`
+//!   or `null`, `POST` checks the document out and gives the lock, which is of another user if
+//!   the document was checked out before, `DELETE` checks it in. Root breaks the lock of
+//!   another user by `POST /api/documents/<id>/lock/break` with `{"user_id"}` of the holder.
+//! - `GET` and `POST /api/documents/<id>/comments`, `GET`, `PUT` (`text` or `resolved`) and
+//!   `DELETE /api/comments/<id>`.
+//! - `GET` and `POST /api/categories`.
+//! - `GET` and `POST /api/users`, `GET`, `PUT` and `DELETE /api/users/<id>`, only root may
+//!   change other users.
+//! - `GET /api/changes` gives `{"version"}`, the number of changes made through the server.
+//!   Clients poll it to refresh their views.
+//!
+//! Errors are `{"error": message}`: `DbError::NotFound` is `404`, `DbError::Conflict` `409`,
+//! `DbError::Locked` `423`, invalid data `400` and other errors of the storage `500`.
+
+use chrono::{DateTime, UTC, Datelike};
+use rustc_serialize::json::{Json, Object};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use sed_bad_core::{Storage, DbError, User, Category, Document, Comment, Status, Permission};
+use sed_bad_core::json;
+use sed_bad_core::query::{Query, Context, Term, Field, Op, Value};
+
+use http::{Request, Response};
+
+/// Page size of documents by default and the largest one.
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+/// Tokens unused for this time expire.
+const TOKEN_IDLE_SECS: u64 = 8 * 60 * 60;
+
+/// Tokens of logged in users, they are valid until the logout, the restart of the server or
+/// `TOKEN_IDLE_SECS` without requests.
+#[derive(Clone)]
+pub struct Sessions {
+    /// User ids with times of the last requests by tokens.
+    tokens: Arc<Mutex<HashMap<String, (i64, Instant)>>>,
+    idle: Duration,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Sessions::with_idle(Duration::from_secs(TOKEN_IDLE_SECS))
+    }
+
+    fn with_idle(idle: Duration) -> Self {
+        Sessions {
+            tokens: Arc::new(Mutex::new(HashMap::new())),
+            idle: idle,
+        }
+    }
+
+    fn create(&self, user: &User) -> Result<String, ApiError> {
+        let mut random = [0u8; 16];
+        File::open("/dev/urandom")
+            .and_then(|mut file| file.read_exact(&mut random))
+            .map_err(|e| ApiError(500, format!("Error of creating token.\n{}", e)))?;
+        let token: String = random.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let now = Instant::now();
+        let mut tokens = self.tokens.lock().unwrap();
+        // Forget expired tokens, which aren't used any more.
+        let expired: Vec<String> = tokens.iter()
+            .filter(|&(_, &(_, used))| now.duration_since(used) >= self.idle)
+            .map(|(token, _)| token.clone())
+            .collect();
+        for token in expired {
+            tokens.remove(&token);
+        }
+        tokens.insert(token.clone(), (user.id(), now));
+        Ok(token)
+    }
+
+    /// User of the token, which is valid for `idle` from now on.
+    fn user_id(&self, token: &str) -> Option<i64> {
+        let now = Instant::now();
+        let mut tokens = self.tokens.lock().unwrap();
+        match tokens.get_mut(token) {
+            Some(entry) => {
+                if now.duration_since(entry.1) < self.idle {
+                    entry.1 = now;
+                    return Some(entry.0);
+                }
+            }
+            None => return None,
+        }
+        tokens.remove(token);
+        None
+    }
+
+    fn remove(&self, token: &str) {
+        self.tokens.lock().unwrap().remove(token);
+    }
+}
+
+/// Counter of changes made through the server since its start.
+#[derive(Clone, Default)]
+pub struct Changes {
+    version: Arc<AtomicUsize>,
+}
+
+impl Changes {
+    pub fn new() -> Self {
+        Changes::default()
+    }
+
+    fn version(&self) -> i64 {
+        self.version.load(Ordering::SeqCst) as i64
+    }
+
+    fn increase(&self) {
+        self.version.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Status and message of a failed request.
+#[derive(Debug)]
+pub struct ApiError(u16, String);
+
+impl From<DbError> for ApiError {
+    fn from(err: DbError) -> Self {
+        let status = match err {
+            DbError::NotFound => 404,
+            DbError::Conflict => 409,
+            DbError::Locked(_) => 423,
+            DbError::Forbidden(_) => 403,
+            DbError::InvalidData(_) => 400,
+            _ => 500,
+        };
+        ApiError(status, err.to_string())
+    }
+}
+
+impl ApiError {
+    fn response(&self) -> Response {
+        Response::error(self.0, self.1.clone())
+    }
+}
+
+type ApiResult = Result<Response, ApiError>;
+
+pub fn handle(storage: &Storage,
+              sessions: &Sessions,
+              changes: &Changes,
+              request: &Request)
+              -> Response {
+    match route(storage, sessions, changes, request) {
+        Ok(response) => response,
+        Err(e) => e.response(),
+    }
+}
+
+fn route(storage: &Storage,
+         sessions: &Sessions,
+         changes: &Changes,
+         request: &Request)
+         -> ApiResult {
+    let segments = request.segments();
+    if segments.first() != Some(&"api") {
+        return Err(ApiError(404, "Not found.".to_string()));
+    }
+    // Ids in the path are replaced by `:id`, e.g. `documents/:id/comments`.
+    let mut ids: Vec<i64> = Vec::new();
+    let shape: Vec<&str> = segments[1..]
+        .iter()
+        .map(|segment| match segment.parse() {
+            Ok(id) => {
+                ids.push(id);
+                ":id"
+            }
+            Err(_) => *segment,
+        })
+        .collect();
+    let shape = shape.join("/");
+    let method = &request.method[..];
+
+    if (method, &shape[..]) == ("POST", "login") {
+        return login(storage, sessions, request);
+    }
+    let token = request.header("authorization")
+        .and_then(|value| if value.starts_with("Bearer ") {
+            Some(value["Bearer ".len()..].trim())
+        } else {
+            None
+        })
+        .unwrap_or("");
+    let user = match sessions.user_id(token).map(|id| storage.user_by_id(id)) {
+        Some(Ok(user)) => user,
+        Some(Err(DbError::NotFound)) | None => {
+            return Err(ApiError(401, "Log in to get a token.".to_string()))
+        }
+        Some(Err(e)) => return Err(From::from(e)),
+    };
+
+    let response = match (method, &shape[..]) {
+        ("POST", "logout") => {
+            sessions.remove(token);
+            Ok(Response::no_content())
+        }
+        ("GET", "documents") => list_documents(storage, &user, request),
+        ("POST", "documents") => create_document(storage, &user, request),
+        ("GET", "documents/:id") => {
+            let doc = readable_document(storage, &user, ids[0])?;
+            Ok(Response::json(200, &json::document(&doc)))
+        }
+        ("PUT", "documents/:id") => update_document(storage, &user, ids[0], request),
+        ("GET", "documents/:id/data") => {
+            let doc = readable_document(storage, &user, ids[0])?;
+            match doc.data() {
+                Some(data) => Ok(Response::data(data)),
+                None => Err(ApiError(404, "The document has no content.".to_string())),
+            }
+        }
+        ("PUT", "documents/:id/data") => {
+            let mut doc = editable_document(storage, &user, ids[0])?;
+            if request.param("version").is_some() {
+                doc.set_version(int_param(request, "version", 0)?);
+            }
+            doc.set_data(Some(request.body.clone()));
+            storage.update_document(&doc, &user)?;
+            Ok(Response::no_content())
+        }
+        ("GET", "documents/:id/lock") => {
+            readable_document(storage, &user, ids[0])?;
+            let lock = storage.document_lock(ids[0])?;
+            Ok(Response::json(200, &lock.as_ref().map(json::lock).unwrap_or(Json::Null)))
+        }
+        ("POST", "documents/:id/lock") => {
+            editable_document(storage, &user, ids[0])?;
+            Ok(Response::json(200, &json::lock(&storage.check_out(ids[0], &user)?)))
+        }
+        ("DELETE", "documents/:id/lock") => {
+            storage.check_in(ids[0], &user)?;
+            Ok(Response::no_content())
+        }
+        ("POST", "documents/:id/lock/break") => {
+            only_root(&user)?;
+            let body = body_json(request)?;
+            let holder_id = optional_int(&body, "user_id")?
+                .ok_or_else(|| ApiError(400, "The field `user_id` is required.".to_string()))?;
+            let lock = storage.document_lock(ids[0])?.ok_or(DbError::NotFound)?;
+            if lock.user().id() != holder_id {
+                return Err(ApiError(409, "The document is checked out by another user now."
+                    .to_string()));
+            }
+            storage.break_lock(&lock, &user)?;
+            Ok(Response::no_content())
+        }
+        ("GET", "documents/:id/comments") => {
+            let doc = readable_document(storage, &user, ids[0])?;
+            let comments = doc.comments().iter().map(json::comment).collect();
+            Ok(Response::json(200, &Json::Array(comments)))
+        }
+        ("POST", "documents/:id/comments") => add_comment(storage, &user, ids[0], request),
+        ("GET", "comments/:id") => {
+            let comment = storage.comment_by_id(ids[0])?;
+            readable_document(storage, &user, storage.comment_doc_id(ids[0])?)?;
+            Ok(Response::json(200, &json::comment(&comment)))
+        }
+        ("PUT", "comments/:id") => update_comment(storage, &user, ids[0], request),
+        ("DELETE", "comments/:id") => {
+            let mut comment = editable_comment(storage, &user, ids[0])?;
+            storage.delete_comment(&mut comment, &user)?;
+            Ok(Response::no_content())
+        }
+        ("GET", "categories") => {
+            let categories = storage.categories()?;
+            Ok(Response::json(200, &Json::Array(categories.iter().map(json::category).collect())))
+        }
+        ("POST", "categories") => {
+            let body = body_json(request)?;
+            let mut category = Category::new(required_str(&body, "name")?);
+            if storage.category_by_name(category.name()).is_ok() {
+                return Err(ApiError(409, "The category already exists.".to_string()));
+            }
+            storage.add_category(&mut category)?;
+            Ok(Response::json(201, &json::category(&category)))
+        }
+        ("GET", "users") => {
+            let users = storage.users()?;
+            Ok(Response::json(200, &Json::Array(users.iter().map(json::user).collect())))
+        }
+        ("POST", "users") => {
+            only_root(&user)?;
+            let body = body_json(request)?;
+            let mut new_user = User::new(required_str(&body, "name")?,
+                                         required_str(&body, "password")?);
+            if storage.user_by_name(new_user.name()).is_ok() {
+                return Err(ApiError(409, "The user already exists.".to_string()));
+            }
+            storage.add_user(&mut new_user)?;
+            Ok(Response::json(201, &json::user(&new_user)))
+        }
+        ("GET", "users/:id") => Ok(Response::json(200, &json::user(&storage.user_by_id(ids[0])?))),
+        ("PUT", "users/:id") => {
+            if user.id() != ids[0] {
+                only_root(&user)?;
+            }
+            let body = body_json(request)?;
+            storage.user_by_id(ids[0])?;
+            storage.update_user(ids[0],
+                             required_str(&body, "name")?,
+                             required_str(&body, "password")?)?;
+            Ok(Response::json(200, &json::user(&storage.user_by_id(ids[0])?)))
+        }
+        ("DELETE", "users/:id") => {
+            only_root(&user)?;
+            if storage.user_by_id(ids[0])?.is_root() {
+                return Err(ApiError(409, "Root can't be deleted.".to_string()));
+            }
+            storage.delete_user(ids[0])?;
+            Ok(Response::no_content())
+        }
+        ("GET", "changes") => {
+            let mut object = Object::new();
+            object.insert("version".to_string(), Json::I64(changes.version()));
+            Ok(Response::json(200, &Json::Object(object)))
+        }
+        _ => Err(ApiError(404, "Not found.".to_string())),
+    }?;
+    if method != "GET" && shape != "logout" {
+        changes.increase();
+    }
+    Ok(response)
+}
+
+fn login(storage: &Storage, sessions: &Sessions, request: &Request) -> ApiResult {
+    let body = body_json(request)?;
+    let name = required_str(&body, "name")?;
+    if !storage.user_exists(&User::new(name, required_str(&body, "password")?))? {
+        return Err(ApiError(401, "Wrong name or password.".to_string()));
+    }
+    let user = storage.user_by_name(name)?;
+    let mut object = Object::new();
+    object.insert("token".to_string(), Json::String(sessions.create(&user)?));
+    object.insert("user".to_string(), json::user(&user));
+    Ok(Response::json(200, &Json::Object(object)))
+}
+
+fn list_documents(storage: &Storage, user: &User, request: &Request) -> ApiResult {
+    let mut query = Query::parse(request.param("q").unwrap_or(""))
+        .map_err(|e| ApiError(400, format!("Wrong query: {}", e)))?;
+    // Other users get only the documents which they may read, in the page and in the total.
+    if !user.is_root() {
+        query.push(Term {
+            field: Field::Readable,
+            op: Op::Eq,
+            value: Value::Me,
+        });
+    }
+    let limit = int_param(request, "limit", DEFAULT_LIMIT)?;
+    let offset = int_param(request, "offset", 0)?;
+    if limit < 0 || limit > MAX_LIMIT || offset < 0 {
+        return Err(ApiError(400,
+                            format!("The limit must be from 0 to {} and the offset must not be \
+                                     negative.",
+                                    MAX_LIMIT)));
+    }
+    let mut context = Context::new(user);
+    if let (Some(from), Some(to)) = (request.param("from"), request.param("to")) {
+        context.selected_range = (parse_time(from)?, parse_time(to)?);
+    }
+    let total = storage.count_documents(&query, &context)?;
+    let summaries = storage.list_documents(&query, &context, limit, offset)?;
+    let mut object = Object::new();
+    object.insert("total".to_string(), Json::I64(total));
+    object.insert("limit".to_string(), Json::I64(limit));
+    object.insert("offset".to_string(), Json::I64(offset));
+    object.insert("items".to_string(),
+                  Json::Array(summaries.iter().map(json::summary).collect()));
+    Ok(Response::json(200, &Json::Object(object)))
+}
+
+fn create_document(storage: &Storage, user: &User, request: &Request) -> ApiResult {
+    let body = body_json(request)?;
+    let name = required_str(&body, "name")?;
+    let category = category_or_new(storage, required_str(&body, "category")?)?;
+    let responsible = match optional_str(&body, "responsible")? {
+        Some(name) => storage.user_by_name(name)?,
+        None => user.clone(),
+    };
+    let date_expired = parse_date(required_str(&body, "date_expired")?)?;
+    let comment = optional_str(&body, "comment")?
+        .map(|text| Comment::new(user, text.to_string()));
+    let mut doc = Document::new(name.to_string(),
+                                user,
+                                category,
+                                responsible,
+                                (date_expired.year() as u32,
+                                 date_expired.month(),
+                                 date_expired.day()),
+                                comment);
+    if let Some(data) = optional_str(&body, "data")? {
+        doc.set_data(Some(data.as_bytes().to_vec()));
+    }
+    if let Some(data) = optional_content(&body)? {
+        doc.set_data(Some(data));
+    }
+    if let Some(permission) = optional_int(&body, "permission")? {
+        doc.set_permission(Permission::from_int(permission));
+    }
+    storage.add_document(&mut doc)?;
+    let doc = storage.document_by_id(doc.id())?;
+    Ok(Response::json(201, &json::document(&doc)))
+}
+
+fn update_document(storage: &Storage, user: &User, id: i64, request: &Request) -> ApiResult {
+    let mut doc = editable_document(storage, user, id)?;
+    let body = body_json(request)?;
+    if let Some(name) = optional_str(&body, "name")? {
+        doc.set_name(name);
+    }
+    if let Some(status) = optional_str(&body, "status")? {
+        doc.metadata_mut().set_status(parse_status(status)?);
+    }
+    if let Some(name) = optional_str(&body, "author")? {
+        doc.metadata_mut().set_author(storage.user_by_name(name)?);
+    }
+    if let Some(name) = optional_str(&body, "responsible")? {
+        doc.set_responsible(storage.user_by_name(name)?);
+    }
+    if let Some(name) = optional_str(&body, "category")? {
+        doc.metadata_mut().set_category(category_or_new(storage, name)?);
+    }
+    if let Some(date) = optional_str(&body, "date_expired")? {
+        doc.metadata_mut().set_date_expired(parse_date(date)?);
+    }
+    if let Some(permission) = optional_int(&body, "permission")? {
+        doc.set_permission(Permission::from_int(permission));
+    }
+    if let Some(version) = optional_int(&body, "version")? {
+        doc.set_version(version);
+    }
+    match body.find("data") {
+        Some(&Json::Null) => doc.set_data(None),
+        Some(&Json::String(ref data)) => doc.set_data(Some(data.as_bytes().to_vec())),
+        Some(_) => return Err(ApiError(400, "The field `data` must be a string.".to_string())),
+        None => {}
+    }
+    if let Some(data) = optional_content(&body)? {
+        doc.set_data(Some(data));
+    }
+    storage.update_document(&doc, user)?;
+    Ok(Response::json(200, &json::document(&storage.document_by_id(id)?)))
+}
+
+fn add_comment(storage: &Storage, user: &User, doc_id: i64, request: &Request) -> ApiResult {
+    let doc = readable_document(storage, user, doc_id)?;
+    if !user.is_comment_grant(&doc) {
+        return Err(ApiError(403, "You may not comment the document.".to_string()));
+    }
+    let body = body_json(request)?;
+    let text = required_str(&body, "text")?.to_string();
+    let mut comment = match optional_int(&body, "parent_id")? {
+        Some(parent_id) => Comment::new_reply(user, text, parent_id),
+        None => Comment::new(user, text),
+    };
+    if let Some(parent_id) = comment.parent_id() {
+        if !doc.comments().iter().any(|parent| parent.id() == parent_id) {
+            return Err(ApiError(400, "The parent comment is not of the document.".to_string()));
+        }
+    }
+    storage.add_comment(doc_id, &mut comment)?;
+    Ok(Response::json(201, &json::comment(&comment)))
+}
+
+fn update_comment(storage: &Storage, user: &User, id: i64, request: &Request) -> ApiResult {
+    let body = body_json(request)?;
+    if let Some(text) = optional_str(&body, "text")? {
+        let mut comment = editable_comment(storage, user, id)?;
+        storage.edit_comment(&mut comment, user, text)?;
+    }
+    match body.find("resolved") {
+        Some(&Json::Boolean(resolved)) => {
+            // The author of the topic, the author of the document or root.
+            let topic = storage.comment_by_id(id)?;
+            let doc = readable_document(storage, user, storage.comment_doc_id(id)?)?;
+            if !topic.is_topic() || !(topic.author() == user || user.is_access_grant(&doc)) {
+                return Err(ApiError(403, "You may not resolve the topic.".to_string()));
+            }
+            storage.set_comment_resolved(id, resolved)?;
+        }
+        Some(_) => return Err(ApiError(400, "The field `resolved` must be a boolean.".to_string())),
+        None => {}
+    }
+    Ok(Response::json(200, &json::comment(&storage.comment_by_id(id)?)))
+}
+
+/// Only the author of the comment or root may change it, if the document may be commented.
+fn editable_comment(storage: &Storage, user: &User, id: i64) -> Result<Comment, ApiError> {
+    let comment = storage.comment_by_id(id)?;
+    let doc = readable_document(storage, user, storage.comment_doc_id(id)?)?;
+    if !comment.is_edit_grant(user) || !user.is_comment_grant(&doc) {
+        return Err(ApiError(403, "Only the author of the comment can change it.".to_string()));
+    }
+    Ok(comment)
+}
+
+fn readable_document(storage: &Storage, user: &User, id: i64) -> Result<Document, ApiError> {
+    let doc = storage.document_by_id(id)?;
+    if !user.is_read_grant(&doc) {
+        return Err(ApiError(403, "You may not read the document.".to_string()));
+    }
+    Ok(doc)
+}
+
+/// Only the author or root may change the document.
+fn editable_document(storage: &Storage, user: &User, id: i64) -> Result<Document, ApiError> {
+    let doc = storage.document_by_id(id)?;
+    if !user.is_access_grant(&doc) {
+        return Err(ApiError(403, "You may not change the document.".to_string()));
+    }
+    Ok(doc)
+}
+
+fn only_root(user: &User) -> Result<(), ApiError> {
+    if user.is_root() {
+        Ok(())
+    } else {
+        Err(ApiError(403, "Only root may do it.".to_string()))
+    }
+}
+
+fn category_or_new(storage: &Storage, name: &str) -> Result<Category, ApiError> {
+    match storage.category_by_name(name) {
+        Ok(category) => Ok(category),
+        Err(DbError::NotFound) => {
+            let mut category = Category::new(name);
+            storage.add_category(&mut category)?;
+            Ok(category)
+        }
+        Err(e) => Err(From::from(e)),
+    }
+}
+
+fn body_json(request: &Request) -> Result<Json, ApiError> {
+    match request.json() {
+        Ok(json @ Json::Object(_)) => Ok(json),
+        Ok(_) => Err(ApiError(400, "The body must be a JSON object.".to_string())),
+        Err(e) => Err(ApiError(400, e)),
+    }
+}
+
+fn required_str<'a>(json: &'a Json, name: &str) -> Result<&'a str, ApiError> {
+    optional_str(json, name)?
+        .ok_or_else(|| ApiError(400, format!("The field `{}` is required.", name)))
+}
+
+fn optional_str<'a>(json: &'a Json, name: &str) -> Result<Option<&'a str>, ApiError> {
+    match json.find(name) {
+        Some(&Json::String(ref value)) => Ok(Some(value.as_ref())),
+        Some(&Json::Null) | None => Ok(None),
+        Some(_) => Err(ApiError(400, format!("The field `{}` must be a string.", name))),
+    }
+}
+
+fn optional_int(json: &Json, name: &str) -> Result<Option<i64>, ApiError> {
+    match json.find(name) {
+        Some(&Json::Null) | None => Ok(None),
+        Some(value) => {
+            value.as_i64()
+                .map(Some)
+                .ok_or_else(|| ApiError(400, format!("The field `{}` must be a number.", name)))
+        }
+    }
+}
+
+/// Content given as base64 in `data_base64`.
+fn optional_content(json: &Json) -> Result<Option<Vec<u8>>, ApiError> {
+    match json.find("data_base64") {
+        Some(&Json::Null) | None => Ok(None),
+        Some(content) => Ok(Some(json::parse_content(content)?)),
+    }
+}
+
+fn int_param(request: &Request, name: &str, default: i64) -> Result<i64, ApiError> {
+    match request.param(name) {
+        Some(value) => {
+            value.parse()
+                .map_err(|_| ApiError(400, format!("The parameter `{}` must be a number.", name)))
+        }
+        None => Ok(default),
+    }
+}
+
+fn parse_date(date: &str) -> Result<DateTime<UTC>, ApiError> {
+    json::parse_date(date)
+        .ok_or_else(|| ApiError(400, format!("Wrong date `{}`, YYYY-MM-DD expected.", date)))
+}
+
+fn parse_time(time: &str) -> Result<DateTime<UTC>, ApiError> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&UTC))
+        .map_err(|_| ApiError(400, format!("Wrong time `{}`, RFC 3339 expected.", time)))
+}
+
+fn parse_status(status: &str) -> Result<Status, ApiError> {
+    json::parse_status(status).ok_or_else(|| {
+        ApiError(400,
+                 format!("Wrong status `{}`, beginning, in-progress or complete expected.",
+                         status))
+    })
+}
+
+#[cfg(test)]
+fn request(method: &str, path: &str, token: Option<&str>, body: &str) -> Request {
+    let mut raw = format!("{} {} HTTP/1.1\r\nContent-Length: {}\r\n", method, path, body.len());
+    if let Some(token) = token {
+        raw.push_str(&format!("Authorization: Bearer {}\r\n", token));
+    }
+    raw.push_str("\r\n");
+    raw.push_str(body);
+    Request::read(&mut raw.as_bytes()).unwrap()
+}
+
+#[test]
+fn documents_api() {
+    use sed_bad_core::MemoryStorage;
+
+    let storage = MemoryStorage::new();
+    storage.add_user(&mut User::new("root", "toor")).unwrap();
+    let sessions = Sessions::new();
+    let changes = Changes::new();
+    let send = |method: &str, path: &str, token: Option<&str>, body: &str| {
+        let response = handle(&storage,
+                              &sessions,
+                              &changes,
+                              &request(method, path, token, body));
+        let json = Json::from_str(&String::from_utf8(response.body).unwrap()).ok();
+        (response.status, json)
+    };
+
+    assert_eq!(send("POST", "/api/login", None, r#"{"name":"root","password":"x"}"#).0,
+               401);
+    let (status, json) = send("POST", "/api/login", None, r#"{"name":"root","password":"toor"}"#);
+    assert_eq!(status, 200);
+    let token = json.unwrap().find("token").unwrap().as_string().unwrap().to_string();
+    let token = Some(token.as_ref());
+    assert_eq!(send("GET", "/api/documents", None, "").0, 401);
+
+    assert_eq!(send("POST", "/api/users", token, r#"{"name":"ivan","password":"secret"}"#).0,
+               201);
+    for name in &["Contract", "Report", "Annual report"] {
+        let body = format!(r#"{{"name":"{}","category":"Reports","responsible":"ivan",
+                              "date_expired":"2017-03-08","comment":"Sign it"}}"#,
+                           name);
+        assert_eq!(send("POST", "/api/documents", token, &body).0, 201);
+    }
+    let (status, json) = send("GET", "/api/documents?q=report&limit=1&offset=1", token, "");
+    assert_eq!(status, 200);
+    let json = json.unwrap();
+    assert_eq!(json.find("total").unwrap().as_i64(), Some(2));
+    let items = json.find("items").unwrap().as_array().unwrap();
+    assert_eq!(items[0].find("name").unwrap().as_string(), Some("Annual report"));
+    assert_eq!(send("GET", "/api/documents?q=autor:me", token, "").0, 400);
+
+    let id = items[0].find("id").unwrap().as_i64().unwrap();
+    let (status, json) = send("PUT",
+                              &format!("/api/documents/{}", id),
+                              token,
+                              r#"{"status":"complete","data":"text"}"#);
+    assert_eq!(status, 200);
+    let json = json.unwrap();
+    assert_eq!(json.find("status").unwrap().as_string(), Some("complete"));
+    assert_eq!(json.find("version").unwrap().as_i64(), Some(2));
+    // The version read before the change is stale.
+    assert_eq!(send("PUT",
+                    &format!("/api/documents/{}", id),
+                    token,
+                    r#"{"name":"Report","version":1}"#)
+                   .0,
+               409);
+    assert_eq!(send("GET", &format!("/api/documents/{}/data", id), token, "").0, 200);
+    let (status, json) = send("PUT",
+                              &format!("/api/documents/{}", id),
+                              token,
+                              r#"{"data_base64":"iVBORw==","version":2}"#);
+    assert_eq!(status, 200);
+    assert_eq!(json.unwrap().find("version").unwrap().as_i64(), Some(3));
+    assert_eq!(send("PUT", &format!("/api/documents/{}/data?version=2", id), token, "x").0,
+               409);
+    let response = handle(&storage,
+                          &sessions,
+                          &changes,
+                          &request("GET", &format!("/api/documents/{}/data", id), token, ""));
+    assert_eq!(response.body, b"\x89PNG");
+    assert_eq!(send("POST",
+                    &format!("/api/documents/{}/comments", id),
+                    token,
+                    r#"{"text":"Done"}"#)
+                   .0,
+               201);
+    let (status, json) = send("GET", &format!("/api/documents/{}/comments", id), token, "");
+    assert_eq!(status, 200);
+    let comment_id = json.unwrap()[1].find("id").unwrap().as_i64().unwrap();
+    let comment_path = format!("/api/comments/{}", comment_id);
+    let (status, json) = send("PUT", &comment_path, token, r#"{"text":"Done!"}"#);
+    assert_eq!(status, 200);
+    assert_eq!(json.unwrap().find("text").unwrap().as_string(), Some("Done!"));
+    let (status, json) = send("PUT", &comment_path, token, r#"{"resolved":true}"#);
+    assert_eq!(status, 200);
+    assert_eq!(json.unwrap().find("resolved").unwrap().as_boolean(), Some(true));
+    assert_eq!(send("DELETE", &comment_path, token, "").0, 204);
+    let (status, json) = send("GET", "/api/changes", token, "");
+    assert_eq!(status, 200);
+    assert_eq!(json.unwrap().find("version").unwrap().as_i64(), Some(10));
+    assert_eq!(send("GET", "/api/documents/1000", token, "").0, 404);
+    assert_eq!(send("POST", "/api/logout", token, "").0, 204);
+    assert_eq!(send("GET", "/api/users", token, "").0, 401);
+}
+
+#[test]
+fn documents_of_other_users() {
+    use sed_bad_core::MemoryStorage;
+
+    let storage = MemoryStorage::new();
+    storage.add_user(&mut User::new("root", "toor")).unwrap();
+    let sessions = Sessions::new();
+    let changes = Changes::new();
+    let send = |method: &str, path: &str, token: Option<&str>, body: &str| {
+        let response = handle(&storage,
+                              &sessions,
+                              &changes,
+                              &request(method, path, token, body));
+        let json = Json::from_str(&String::from_utf8(response.body).unwrap()).ok();
+        (response.status, json)
+    };
+    let log_in = |body: &str| {
+        let json = send("POST", "/api/login", None, body).1.unwrap();
+        json.find("token").unwrap().as_string().unwrap().to_string()
+    };
+
+    let root = log_in(r#"{"name":"root","password":"toor"}"#);
+    let root = Some(root.as_ref());
+    for name in &["ivan", "petr"] {
+        let body = format!(r#"{{"name":"{}","password":"secret"}}"#, name);
+        assert_eq!(send("POST", "/api/users", root, &body).0, 201);
+    }
+    for &(name, responsible) in &[("Contract", "ivan"), ("Salary of petr", "petr")] {
+        let body = format!(r#"{{"name":"{}","category":"Private","responsible":"{}",
+                              "date_expired":"2017-03-08"}}"#,
+                           name,
+                           responsible);
+        assert_eq!(send("POST", "/api/documents", root, &body).0, 201);
+    }
+
+    let ivan = log_in(r#"{"name":"ivan","password":"secret"}"#);
+    let (status, json) = send("GET", "/api/documents", Some(ivan.as_ref()), "");
+    assert_eq!(status, 200);
+    let json = json.unwrap();
+    assert_eq!(json.find("total").unwrap().as_i64(), Some(1));
+    let items = json.find("items").unwrap().as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].find("name").unwrap().as_string(), Some("Contract"));
+    let (_, json) = send("GET", "/api/documents", root, "");
+    assert_eq!(json.unwrap().find("total").unwrap().as_i64(), Some(2));
+}
+
+#[test]
+fn checked_out_documents() {
+    use sed_bad_core::MemoryStorage;
+
+    let storage = MemoryStorage::new();
+    storage.add_user(&mut User::new("root", "toor")).unwrap();
+    let sessions = Sessions::new();
+    let changes = Changes::new();
+    let send = |method: &str, path: &str, token: Option<&str>, body: &str| {
+        let response = handle(&storage,
+                              &sessions,
+                              &changes,
+                              &request(method, path, token, body));
+        let json = Json::from_str(&String::from_utf8(response.body).unwrap()).ok();
+        (response.status, json)
+    };
+    let log_in = |body: &str| {
+        let json = send("POST", "/api/login", None, body).1.unwrap();
+        json.find("token").unwrap().as_string().unwrap().to_string()
+    };
+
+    let root = log_in(r#"{"name":"root","password":"toor"}"#);
+    let root = Some(root.as_ref());
+    let (_, json) = send("POST", "/api/users", root, r#"{"name":"ivan","password":"secret"}"#);
+    let ivan_id = json.unwrap().find("id").unwrap().as_i64().unwrap();
+    let ivan = log_in(r#"{"name":"ivan","password":"secret"}"#);
+    let ivan = Some(ivan.as_ref());
+    let body = r#"{"name":"Contract","category":"Contracts","date_expired":"2017-03-08"}"#;
+    let (_, json) = send("POST", "/api/documents", ivan, body);
+    let path = format!("/api/documents/{}", json.unwrap().find("id").unwrap().as_i64().unwrap());
+    let lock_path = format!("{}/lock", path);
+
+    assert_eq!(send("GET", &lock_path, ivan, "").1, Some(Json::Null));
+    let (status, json) = send("POST", &lock_path, ivan, "");
+    assert_eq!(status, 200);
+    assert_eq!(json.unwrap().find("user").unwrap().as_string(), Some("ivan"));
+    // The document checked out by ivan is changed only by him.
+    assert_eq!(send("PUT", &path, root, r#"{"status":"complete"}"#).0, 423);
+    assert_eq!(send("PUT", &format!("{}/data", path), root, "text").0, 423);
+    assert_eq!(send("DELETE", &lock_path, root, "").0, 404);
+    assert_eq!(send("POST", &format!("{}/break", lock_path), ivan, "{}").0, 403);
+    assert_eq!(send("POST", &format!("{}/break", lock_path), root, r#"{"user_id":1000}"#).0,
+               409);
+    let body = format!(r#"{{"user_id":{}}}"#, ivan_id);
+    assert_eq!(send("POST", &format!("{}/break", lock_path), root, &body).0, 204);
+    assert_eq!(send("PUT", &path, root, r#"{"status":"complete"}"#).0, 200);
+    assert_eq!(send("DELETE", &lock_path, ivan, "").0, 404);
+}
+
+#[test]
+fn sessions_expire() {
+    let mut user = User::new("ivan", "secret");
+    user.set_id(2);
+    let sessions = Sessions::new();
+    let token = sessions.create(&user).unwrap();
+    assert_eq!(sessions.user_id(&token), Some(2));
+    sessions.remove(&token);
+    assert_eq!(sessions.user_id(&token), None);
+
+    let sessions = Sessions::with_idle(Duration::from_secs(0));
+    let token = sessions.create(&user).unwrap();
+    assert_eq!(sessions.user_id(&token), None);
+    assert!(sessions.tokens.lock().unwrap().is_empty());
+}
@@ -0,0 +1,235 @@
+//! Minimal HTTP/1.1: one request per connection, the body is read by `Content-Length`.
+//!
+//! Lines of the request and the head as a whole are limited, so a client can't make the server
+//! read without end.
+
+use rustc_serialize::json::{Json, Object};
+
+use std::cmp;
+use std::io::{self, BufRead, Read, Write};
+use std::str;
+
+/// Largest accepted body, e.g. content of a document.
+const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+/// Longest accepted line of the request line and headers.
+const MAX_LINE_LEN: usize = 8 * 1024;
+/// Largest accepted head, the request line with all headers.
+const MAX_HEAD_LEN: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Decoded parameters of the query string.
+    pub params: Vec<(String, String)>,
+    /// Headers with lowercase names.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn read<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+        let mut head_len = 0;
+        let request_line = read_line(reader, &mut head_len)?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or("").to_string();
+        let mut target = parts.next().unwrap_or("").splitn(2, '?');
+        let path = decode(target.next().unwrap_or(""), false);
+        let params = target.next().map(parse_params).unwrap_or_else(Vec::new);
+
+        let mut headers: Vec<(String, String)> = Vec::new();
+        loop {
+            let line = read_line(reader, &mut head_len)?;
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some(pos) = line.find(':') {
+                let name = line[..pos].trim().to_lowercase();
+                headers.push((name, line[pos + 1..].trim().to_string()));
+            }
+        }
+
+        let mut request = Request {
+            method: method,
+            path: path,
+            params: params,
+            headers: headers,
+            body: Vec::new(),
+        };
+        let len = request.header("content-length").and_then(|len| len.parse().ok()).unwrap_or(0);
+        if len > MAX_BODY_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "The body is too large."));
+        }
+        request.body = vec![0; len];
+        reader.read_exact(&mut request.body)?;
+        Ok(request)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|&&(ref key, _)| key == name)
+            .map(|&(_, ref value)| value.as_ref())
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|&&(ref key, _)| key == name)
+            .map(|&(_, ref value)| value.as_ref())
+    }
+
+    /// Segments of the path, `/api/documents/1` gives `["api", "documents", "1"]`.
+    pub fn segments(&self) -> Vec<&str> {
+        self.path.split('/').filter(|segment| !segment.is_empty()).collect()
+    }
+
+    pub fn json(&self) -> Result<Json, String> {
+        let text = str::from_utf8(&self.body).map_err(|_| "The body is not UTF-8.".to_string())?;
+        Json::from_str(text).map_err(|e| format!("The body is not JSON: {}", e))
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json(status: u16, json: &Json) -> Response {
+        Response {
+            status: status,
+            content_type: "application/json; charset=utf-8",
+            body: json.to_string().into_bytes(),
+        }
+    }
+
+    pub fn data(data: Vec<u8>) -> Response {
+        Response {
+            status: 200,
+            content_type: "application/octet-stream",
+            body: data,
+        }
+    }
+
+    pub fn no_content() -> Response {
+        Response {
+            status: 204,
+            content_type: "text/plain",
+            body: Vec::new(),
+        }
+    }
+
+    /// Error as `{"error": message}`.
+    pub fn error<S: Into<String>>(status: u16, message: S) -> Response {
+        let mut object = Object::new();
+        object.insert("error".to_string(), Json::String(message.into()));
+        Response::json(status, &Json::Object(object))
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer,
+               "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: \
+                close\r\n\r\n",
+               self.status,
+               reason(self.status),
+               self.content_type,
+               self.body.len())?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        422 => "Unprocessable Entity",
+        423 => "Locked",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Read a line of the head, `head_len` is the length of the head read before. An empty string
+/// is returned at the end of the input.
+fn read_line<R: BufRead>(reader: &mut R, head_len: &mut usize) -> io::Result<String> {
+    let limit = cmp::min(MAX_LINE_LEN, MAX_HEAD_LEN - *head_len);
+    let mut line = String::new();
+    reader.by_ref().take(limit as u64 + 1).read_line(&mut line)?;
+    if line.len() > limit {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "The request head is too long."));
+    }
+    *head_len += line.len();
+    Ok(line)
+}
+
+fn parse_params(query: &str) -> Vec<(String, String)> {
+    query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut pair = pair.splitn(2, '=');
+            (decode(pair.next().unwrap_or(""), true), decode(pair.next().unwrap_or(""), true))
+        })
+        .collect()
+}
+
+/// Decode `%XX` sequences, `+` is a space in the query string.
+fn decode(text: &str, plus_is_space: bool) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(if plus_is_space && bytes[i] == b'+' { b' ' } else { bytes[i] });
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[test]
+fn read_request() {
+    let mut raw: &[u8] = b"POST /api/documents?q=status%3Acomplete+report&limit=10 HTTP/1.1\r\n\
+                           Host: localhost\r\nAuthorization: Bearer abc\r\nContent-Length: 2\r\n\
+                           \r\n{}";
+    let request = Request::read(&mut raw).unwrap();
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.segments(), vec!["api", "documents"]);
+    assert_eq!(request.param("q"), Some("status:complete report"));
+    assert_eq!(request.param("limit"), Some("10"));
+    assert_eq!(request.header("authorization"), Some("Bearer abc"));
+    assert_eq!(request.body, b"{}");
+    assert_eq!(decode("100%", false), "100%");
+}
+
+#[test]
+fn reject_long_head() {
+    use std::iter;
+
+    let long = |len| iter::repeat('a').take(len).collect::<String>();
+    let line = format!("GET /{} HTTP/1.1\r\n\r\n", long(MAX_LINE_LEN));
+    assert!(Request::read(&mut line.as_bytes()).is_err());
+    let header = format!("X-Header: {}\r\n", long(MAX_LINE_LEN - 100));
+    let mut raw = "GET / HTTP/1.1\r\n".to_string();
+    for _ in 0..MAX_HEAD_LEN / header.len() + 1 {
+        raw.push_str(&header);
+    }
+    raw.push_str("\r\n");
+    assert!(Request::read(&mut raw.as_bytes()).is_err());
+}
@@ -0,0 +1,157 @@
+//! Headless server of the documents, see `api` for the resources.
+//!
+//! Usage: `sed_bad-server [--listen <address>] [--database <path or URL>]`, the database is
+//! taken from the configuration by default.
+//!
+//! Requests are served by `WORKERS` threads, each one with its own storage opened at the
+//! start. When `QUEUE_LEN` connections already wait for a worker, new ones are answered `503`.
+//! The whole request has to be read in `TIMEOUT_SECS`, so slow clients don't hold workers.
+
+extern crate chrono;
+extern crate rustc_serialize;
+extern crate sed_bad_core;
+
+mod api;
+mod http;
+
+use std::env;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sed_bad_core::{storage, Config, Storage};
+
+use api::{Sessions, Changes};
+use http::{Request, Response};
+
+const USAGE: &'static str = "Usage: sed_bad-server [--listen <address>] [--database <path or \
+                             URL>]";
+const DEFAULT_LISTEN: &'static str = "127.0.0.1:8080";
+/// Requests served at once.
+const WORKERS: usize = 8;
+/// Accepted connections waiting for a worker.
+const QUEUE_LEN: usize = 32;
+/// Time to read the whole request and to write the response.
+const TIMEOUT_SECS: u64 = 30;
+
+fn main() {
+    if let Err(e) = run() {
+        let _ = writeln!(io::stderr(), "{}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut listen = DEFAULT_LISTEN.to_string();
+    let mut config = Config::load().map_err(|e| format!("Error of configuration.\n{}", e))?;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--listen" => listen = args.next().ok_or_else(|| USAGE.to_string())?,
+            "--database" => config.set_database(args.next().ok_or_else(|| USAGE.to_string())?),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    // Fail early with a wrong database, it's migrated here once.
+    storage::open(&config).map_err(|e| format!("Error of opening database.\n{}", e))?;
+
+    let listener = TcpListener::bind(listen.as_str())
+        .map_err(|e| format!("Error of listening {}.\n{}", listen, e))?;
+    println!("Listening on http://{}/api", listen);
+    let sessions = Sessions::new();
+    let changes = Changes::new();
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_LEN);
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..WORKERS {
+        let config = config.clone();
+        let receiver = receiver.clone();
+        let sessions = sessions.clone();
+        let changes = changes.clone();
+        thread::spawn(move || serve(&config, &receiver, &sessions, &changes));
+    }
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Error of connection.\n{}", e);
+                continue;
+            }
+        };
+        if let Err(e) = set_timeouts(&stream) {
+            println!("Error of connection.\n{}", e);
+            continue;
+        }
+        if let Err(TrySendError::Full(stream)) = sender.try_send(stream) {
+            let _ = Response::error(503, "The server is busy, try again later.")
+                .write_to(&mut &stream);
+        }
+    }
+    Ok(())
+}
+
+fn set_timeouts(stream: &TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SECS)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(TIMEOUT_SECS)))
+}
+
+/// Serve connections of the queue with the storage of the worker.
+fn serve(config: &Config,
+         receiver: &Mutex<Receiver<TcpStream>>,
+         sessions: &Sessions,
+         changes: &Changes) {
+    let storage = match storage::open(config) {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("Error of opening database.\n{}", e);
+            return;
+        }
+    };
+    loop {
+        let stream = match receiver.lock().unwrap().recv() {
+            Ok(stream) => stream,
+            Err(_) => return,
+        };
+        if let Err(e) = handle_connection(stream, &*storage, sessions, changes) {
+            println!("Error of serving request.\n{}", e);
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream,
+                     storage: &Storage,
+                     sessions: &Sessions,
+                     changes: &Changes)
+                     -> io::Result<()> {
+    let deadline = Instant::now() + Duration::from_secs(TIMEOUT_SECS);
+    let reader = DeadlineReader {
+        stream: &stream,
+        deadline: deadline,
+    };
+    let request = match Request::read(&mut BufReader::new(reader)) {
+        Ok(request) => request,
+        Err(e) => return Response::error(400, e.to_string()).write_to(&mut &stream),
+    };
+    api::handle(storage, sessions, changes, &request).write_to(&mut &stream)
+}
+
+/// Reader of the stream which fails after the deadline, however slowly the data come.
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl<'a> Read for DeadlineReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "The request took too long."));
+        }
+        self.stream.set_read_timeout(Some(self.deadline - now))?;
+        let mut stream = self.stream;
+        stream.read(buf)
+    }
+}
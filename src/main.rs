@@ -1,21 +1,50 @@
 extern crate chrono;
-extern crate md5;
 extern crate gtk;
-extern crate rusqlite;
+extern crate gdk;
+extern crate gdk_pixbuf;
+extern crate cairo;
+extern crate sed_bad_core;
 
-mod document;
-mod metadata;
-mod user;
-mod category;
-mod comment;
-mod permission;
 mod ui;
-mod errors;
-mod db;
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use sed_bad_core::{import, legacy_import, archive, backup};
 
 use ui::MainUI;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let res = match args.first().map(|arg| arg.as_ref()) {
+        Some("import") => {
+            Some(import::cli(&args[1..], &mut |done, total| {
+                print!("\rImported {}/{}", done, total);
+                if done == total {
+                    println!("");
+                }
+                let _ = io::stdout().flush();
+            }))
+        }
+        Some("import-legacy") => Some(legacy_import::cli(&args[1..])),
+        Some("export-archive") => Some(archive::cli_export(&args[1..])),
+        Some("import-archive") => Some(archive::cli_import(&args[1..])),
+        Some("backup") => Some(backup::cli_backup(&args[1..])),
+        Some("restore") => Some(backup::cli_restore(&args[1..])),
+        _ => None,
+    };
+    if let Some(res) = res {
+        match res {
+            Ok(text) => print!("{}", text),
+            Err(e) => {
+                let _ = writeln!(io::stderr(), "{}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
     let ui = MainUI::init();
     ui.run();
 }
@@ -0,0 +1,362 @@
+//! Dialog of agenda: documents of the current user grouped by due date.
+//!
+//! TODO Write docs.
+
+use gtk;
+use chrono::{DateTime, UTC, Duration};
+
+use std::rc::Rc;
+use std::cell::Cell;
+
+use super::MainUI;
+use super::utils::{show_error_dialog, calendar_date};
+use super::edit_ticket::EditTicket;
+use sed_bad_core::query::{Query, Term, Field, Op, Value};
+use sed_bad_core::calendar::{Span, DeadlineState};
+use sed_bad_core::metadata::Status;
+
+#[derive(Clone)]
+pub struct Agenda {
+    main_ui: MainUI,
+    // Any date of the shown period.
+    date: Rc<Cell<DateTime<UTC>>>,
+
+    dialog: gtk::Dialog,
+
+    header_box: gtk::Box,
+    prev_button: gtk::Button,
+    period_label: gtk::Label,
+    next_button: gtk::Button,
+    span_combobox: gtk::ComboBoxText,
+
+    scrolled_window: gtk::ScrolledWindow,
+    tree_view: gtk::TreeView,
+    tree_store: gtk::TreeStore,
+
+    due_column: gtk::TreeViewColumn,
+    name_column: gtk::TreeViewColumn,
+    status_column: gtk::TreeViewColumn,
+    responsible_column: gtk::TreeViewColumn,
+
+    due_cell: gtk::CellRendererText,
+    name_cell: gtk::CellRendererText,
+    status_cell: gtk::CellRendererText,
+    responsible_cell: gtk::CellRendererText,
+}
+
+impl Agenda {
+    pub fn new(main_ui: MainUI) -> Self {
+        let tmp = Agenda {
+            main_ui: main_ui.clone(),
+            date: Rc::new(Cell::new(calendar_date(&main_ui.calendar))),
+
+            dialog: gtk::Dialog::new_with_buttons(Some("Agenda"),
+                                                  Some(&main_ui.window),
+                                                  gtk::DIALOG_MODAL,
+                                                  &[("Close", gtk::ResponseType::Close.into())]),
+
+            header_box: gtk::Box::new(gtk::Orientation::Horizontal, 5),
+            prev_button: gtk::Button::new_with_label("<"),
+            period_label: gtk::Label::new(None),
+            next_button: gtk::Button::new_with_label(">"),
+            span_combobox: gtk::ComboBoxText::new(),
+
+            scrolled_window: gtk::ScrolledWindow::new(None, None),
+            tree_view: gtk::TreeView::new(),
+            tree_store: gtk::TreeStore::new(&[gtk::Type::I64, // Id.
+                                              gtk::Type::String, // Due date.
+                                              gtk::Type::String, // Name.
+                                              gtk::Type::String, // Status.
+                                              gtk::Type::String, // Responsible.
+                                              gtk::Type::String]), // Color.
+
+            due_column: gtk::TreeViewColumn::new(),
+            name_column: gtk::TreeViewColumn::new(),
+            status_column: gtk::TreeViewColumn::new(),
+            responsible_column: gtk::TreeViewColumn::new(),
+
+            due_cell: gtk::CellRendererText::new(),
+            name_cell: gtk::CellRendererText::new(),
+            status_cell: gtk::CellRendererText::new(),
+            responsible_cell: gtk::CellRendererText::new(),
+        };
+        tmp.setup();
+        tmp.connect_signals();
+        tmp.pack_and_show();
+
+        tmp
+    }
+
+    fn setup(&self) {
+        self.setup_columns();
+        self.setup_span_combobox();
+        self.setup_tree_store();
+        self.setup_dialog();
+    }
+
+    fn setup_columns(&self) {
+        // Set title.
+        self.due_column.set_title("Due");
+        self.name_column.set_title("Name");
+        self.status_column.set_title("Status");
+        self.responsible_column.set_title("Responsible");
+
+        // Set resizable.
+        self.due_column.set_resizable(true);
+        self.name_column.set_resizable(true);
+        self.status_column.set_resizable(true);
+        self.responsible_column.set_resizable(true);
+
+        // Attach cell to column.
+        self.due_column.pack_start(&self.due_cell, true);
+        self.name_column.pack_start(&self.name_cell, true);
+        self.status_column.pack_start(&self.status_cell, true);
+        self.responsible_column.pack_start(&self.responsible_cell, true);
+
+        // Add attribute.
+        self.due_column.add_attribute(&self.due_cell, "text", 1);
+        self.name_column.add_attribute(&self.name_cell, "text", 2);
+        self.status_column.add_attribute(&self.status_cell, "text", 3);
+        self.responsible_column.add_attribute(&self.responsible_cell, "text", 4);
+
+        // Color by status and overdue state.
+        self.due_column.add_attribute(&self.due_cell, "foreground", 5);
+        self.name_column.add_attribute(&self.name_cell, "foreground", 5);
+        self.status_column.add_attribute(&self.status_cell, "foreground", 5);
+        self.responsible_column.add_attribute(&self.responsible_cell, "foreground", 5);
+
+        self.tree_view.append_column(&self.due_column);
+        self.tree_view.append_column(&self.name_column);
+        self.tree_view.append_column(&self.status_column);
+        self.tree_view.append_column(&self.responsible_column);
+    }
+
+    fn setup_span_combobox(&self) {
+        use gtk::ComboBoxExt;
+
+        self.span_combobox.append_text("Day");
+        self.span_combobox.append_text("Week");
+        self.span_combobox.append_text("Month");
+        self.span_combobox.set_active(Span::Week.get_num() as i32);
+    }
+
+    fn span(&self) -> Span {
+        use gtk::ComboBoxExt;
+
+        Span::from_num(self.span_combobox.get_active() as i64)
+    }
+
+    fn setup_tree_store(&self) {
+        let (start, end) = self.span().bounds(self.date.get());
+        self.period_label.set_text(&format!("{} — {}",
+                                            start.format("%d %b %Y"),
+                                            (end - Duration::days(1)).format("%d %b %Y")));
+
+        let mut query = Query::default();
+        query.push(Term {
+            field: Field::Involved,
+            op: Op::Eq,
+            value: Value::Me,
+        });
+        query.push(Term {
+            field: Field::Due,
+            op: Op::Ge,
+            value: Value::Date(start),
+        });
+        query.push(Term {
+            field: Field::Due,
+            op: Op::Lt,
+            value: Value::Date(end),
+        });
+
+        let current_user = self.main_ui.current_user.borrow();
+        let res = self.main_ui
+            .storage
+            .borrow()
+            .list_documents(&query, &self.main_ui.query_context(&current_user), -1, 0);
+        let summaries = match res {
+            Ok(mut summaries) => {
+                summaries.sort_by_key(|summary| (summary.date_expired(), summary.id()));
+                summaries
+            }
+            Err(e) => {
+                show_error_dialog(&self.dialog,
+                                  &format!("Error of reading from database (`docs` table).\n{}",
+                                           e));
+                return;
+            }
+        };
+
+        let now = UTC::now();
+        let mut day_iter: Option<(DateTime<UTC>, gtk::TreeIter)> = None;
+        for summary in summaries {
+            let due = summary.date_expired();
+            let new_day = match day_iter {
+                Some((day, _)) => day != due,
+                None => true,
+            };
+            if new_day {
+                let iter = self.tree_store.insert_with_values(None,
+                                                              None,
+                                                              &[0, 1, 5],
+                                                              &[&0i64,
+                                                                &due.format("%a, %d %b %Y")
+                                                                    .to_string(),
+                                                                &"black"]);
+                day_iter = Some((due, iter));
+            }
+            let state = DeadlineState::new(&summary.status(), due, now);
+            if let Some((_, ref parent)) = day_iter {
+                self.tree_store.insert_with_values(Some(parent),
+                                                   None,
+                                                   &[0, 2, 3, 4, 5],
+                                                   &[&summary.id(),
+                                                     &summary.name(),
+                                                     &status_text(&summary.status(), state),
+                                                     &summary.responsible(),
+                                                     &state_color(state)]);
+            }
+        }
+        self.tree_view.set_model(Some(&self.tree_store));
+        self.tree_view.expand_all();
+    }
+
+    fn setup_dialog(&self) {
+        use gtk::WindowExt;
+
+        self.dialog.set_default_size(600, 500);
+        self.main_ui.register_dialog(&self.dialog);
+    }
+
+    fn connect_signals(&self) {
+        self.connect_signals_dialog();
+        self.connect_signals_period_buttons();
+        self.connect_signals_span_combobox();
+        self.connect_signals_tree_view();
+    }
+
+    fn connect_signals_dialog(&self) {
+        use gtk::{DialogExt, WidgetExt};
+
+        self.dialog.connect_response(|dialog, _| {
+            dialog.destroy();
+        });
+    }
+
+    fn connect_signals_period_buttons(&self) {
+        use gtk::ButtonExt;
+
+        {
+            let rc = self.clone();
+            self.prev_button.connect_clicked(move |_| {
+                let (start, _) = rc.span().bounds(rc.date.get());
+                rc.date.set(start - Duration::days(1));
+                rc.update_ui();
+            });
+        }
+        {
+            let rc = self.clone();
+            self.next_button.connect_clicked(move |_| {
+                let (_, end) = rc.span().bounds(rc.date.get());
+                rc.date.set(end);
+                rc.update_ui();
+            });
+        }
+    }
+
+    fn connect_signals_span_combobox(&self) {
+        use gtk::ComboBoxExt;
+
+        let rc = self.clone();
+        self.span_combobox.connect_changed(move |_| {
+            rc.update_ui();
+        });
+    }
+
+    fn connect_signals_tree_view(&self) {
+        use gtk::{TreeViewSignals, TreeModelExt};
+
+        let rc = self.clone();
+        self.tree_view.connect_row_activated(move |_, _, _| {
+            if let Some((tree_model, tree_iter)) = rc.tree_view.get_selection().get_selected() {
+                let doc_id = tree_model.get_value(&tree_iter, 0)
+                    .get::<i64>()
+                    .unwrap_or(0);
+                // Rows of days have no document.
+                if doc_id == 0 {
+                    return;
+                }
+                let res = rc.main_ui.storage.borrow().document_by_id(doc_id);
+                match res {
+                    Ok(doc) => {
+                        EditTicket::new(rc.main_ui.clone(), doc);
+                    }
+                    Err(e) => {
+                        show_error_dialog(&rc.dialog,
+                                          &format!("Error of reading selected doc from \
+                                                    database.\n{}",
+                                                   e));
+                    }
+                }
+            }
+        });
+    }
+
+    fn pack_and_show(&self) {
+        self.pack_header_box();
+        self.pack_scrolled_window();
+        self.pack_dialog();
+    }
+
+    fn pack_header_box(&self) {
+        use gtk::BoxExt;
+
+        self.header_box.pack_start(&self.prev_button, false, false, 0);
+        self.header_box.pack_start(&self.period_label, true, true, 0);
+        self.header_box.pack_start(&self.next_button, false, false, 0);
+        self.header_box.pack_start(&self.span_combobox, false, false, 0);
+    }
+
+    fn pack_scrolled_window(&self) {
+        use gtk::ContainerExt;
+
+        self.scrolled_window.add(&self.tree_view);
+    }
+
+    fn pack_dialog(&self) {
+        use gtk::{DialogExt, BoxExt, WidgetExt};
+
+        let area = self.dialog.get_content_area();
+        area.pack_start(&self.header_box, false, false, 5);
+        area.pack_start(&self.scrolled_window, true, true, 5);
+
+        self.dialog.show_all();
+    }
+
+    fn update_ui(&self) {
+        self.tree_store.clear();
+        self.setup_tree_store();
+    }
+}
+
+fn status_text(status: &Status, state: DeadlineState) -> String {
+    let text = match *status {
+        Status::Beginning => "Beginning",
+        Status::InProgress => "In progress",
+        Status::Complete => "Complete",
+    };
+    if state == DeadlineState::Overdue {
+        format!("{} (overdue)", text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn state_color(state: DeadlineState) -> &'static str {
+    match state {
+        DeadlineState::Complete => "#4e9a06",
+        DeadlineState::Overdue => "#cc0000",
+        DeadlineState::InProgress => "#3465a4",
+        DeadlineState::Beginning => "black",
+    }
+}
@@ -4,7 +4,14 @@
 use gtk;
 
 use super::MainUI;
-use user::User;
+use sed_bad_core::user::User;
+use sed_bad_core::storage::Storage;
+use sed_bad_core::remote::RemoteStorage;
+use sed_bad_core::errors::DbError;
+use sed_bad_core::config::Config;
+
+/// Item of the connection combobox for the local database, other items are servers.
+pub const LOCAL_DATABASE: &'static str = "Local database";
 
 #[derive(Clone)]
 pub struct AuthUI {
@@ -15,6 +22,8 @@ pub struct AuthUI {
     info_label: gtk::Label,
 
     grid: gtk::Grid,
+    connection_label: gtk::Label,
+    connection_combobox: gtk::ComboBoxText,
     user_label: gtk::Label,
     user_entry: gtk::Entry,
     pass_label: gtk::Label,
@@ -35,6 +44,8 @@ impl AuthUI {
             info_label: gtk::Label::new(Some("")),
 
             grid: gtk::Grid::new(),
+            connection_label: gtk::Label::new(Some("Connection:")),
+            connection_combobox: gtk::ComboBoxText::new_with_entry(),
             user_label: gtk::Label::new(Some("User:")),
             user_entry: gtk::Entry::new(),
             pass_label: gtk::Label::new(Some("Password:")),
@@ -54,6 +65,7 @@ impl AuthUI {
         self.setup_info_bar();
         self.setup_labels();
         self.setup_entries();
+        self.setup_connection_combobox();
         self.setup_grid();
         self.setup_button_box();
         self.setup_dialog();
@@ -68,6 +80,7 @@ impl AuthUI {
     fn setup_labels(&self) {
         use gtk::WidgetExt;
 
+        self.connection_label.set_halign(gtk::Align::Start);
         self.user_label.set_halign(gtk::Align::Start);
         self.pass_label.set_halign(gtk::Align::Start);
     }
@@ -79,6 +92,34 @@ impl AuthUI {
         self.pass_entry.set_input_purpose(gtk::InputPurpose::Password);
     }
 
+    /// The local database, the server of the configuration and the current server are offered,
+    /// any other address may be typed.
+    fn setup_connection_combobox(&self) {
+        use gtk::ComboBoxExt;
+
+        self.connection_combobox.append_text(LOCAL_DATABASE);
+        let mut servers = Vec::new();
+        if let Some(server) = Config::load().ok().and_then(|config| {
+            config.server().map(|server| server.to_string())
+        }) {
+            servers.push(server);
+        }
+        let current = self.main_ui.server.borrow().clone();
+        if let Some(ref server) = current {
+            if !servers.contains(server) {
+                servers.push(server.clone());
+            }
+        }
+        for server in &servers {
+            self.connection_combobox.append_text(server);
+        }
+        let active = current.and_then(|current| {
+                servers.iter().position(|server| server == &current).map(|i| i + 1)
+            })
+            .unwrap_or(0);
+        self.connection_combobox.set_active(active as i32);
+    }
+
     fn setup_grid(&self) {
         self.grid.set_row_spacing(5);
         self.grid.set_column_spacing(5);
@@ -158,27 +199,19 @@ impl AuthUI {
                         rc.info_bar.show();
                         return;
                     }
-                    // TODO Rewrite logic!
-                    let new_user = User::new(name.clone(), pass);
-                    match new_user.exists(&rc.main_ui.db.borrow()) {
-                        Ok(b) => {
-                            if b {
-                                rc.main_ui
-                                    .current_user
-                                    .borrow_mut()
-                                    .set(User::get_user(&rc.main_ui.db.borrow(), &name).unwrap());
-                            } else {
-                                rc.info_label.set_label("Error of authorization.");
-                                rc.info_bar.show();
-                                return;
-                            }
-                        }
-                        Err(e) => {
-                            rc.info_label.set_label(format!("Error of database.\n{}", e).as_ref());
-                            rc.info_bar.show();
-                            return;
-                        }
-
+                    let connection = rc.connection_combobox
+                        .get_active_text()
+                        .map(|text| text.trim().to_string())
+                        .unwrap_or_default();
+                    let res = if connection.is_empty() || connection == LOCAL_DATABASE {
+                        rc.log_in_local(&name, &pass)
+                    } else {
+                        rc.log_in_server(&connection, &name, &pass)
+                    };
+                    if let Err(e) = res {
+                        rc.info_label.set_label(&e);
+                        rc.info_bar.show();
+                        return;
                     }
                     rc.main_ui.update_ui();
                     rc.dialog.destroy();
@@ -187,8 +220,52 @@ impl AuthUI {
         });
     }
 
+    fn log_in_local(&self, name: &str, pass: &str) -> Result<(), String> {
+        let storage = MainUI::local_storage().map_err(|e| format!("Error of database.\n{}", e))?;
+        match storage.user_exists(&User::new(name, pass)) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.main_ui.record_failed_login(name, LOCAL_DATABASE);
+                return Err("Error of authorization.".to_string());
+            }
+            Err(e) => return Err(format!("Error of database.\n{}", e)),
+        }
+        let user = storage.user_by_name(name).map_err(|e| format!("Error of database.\n{}", e))?;
+        self.main_ui.set_storage(storage, None);
+        self.main_ui.current_user.borrow_mut().set(user);
+        self.main_ui.start_session();
+        Ok(())
+    }
+
+    /// The server checks the password, on success documents are read from the server. Sessions
+    /// and failed attempts are recorded in the local database all the same.
+    fn log_in_server(&self, url: &str, name: &str, pass: &str) -> Result<(), String> {
+        let storage = match RemoteStorage::login(url, name, pass) {
+            Ok(storage) => storage,
+            Err(DbError::ServerError(401, _)) => {
+                self.main_ui.record_failed_login(name, url);
+                return Err("Error of authorization.".to_string());
+            }
+            Err(DbError::IoError(e)) => {
+                return Err(format!("The server {} is unreachable, choose the local database \
+                                    to work offline.\n{}",
+                                   url,
+                                   e))
+            }
+            Err(e) => return Err(format!("Error of server.\n{}", e)),
+        };
+        let user = storage.user_by_name(name).map_err(|e| format!("Error of server.\n{}", e))?;
+        let watcher = storage.detached();
+        self.main_ui.set_storage(Box::new(storage), Some(url.to_string()));
+        self.main_ui.watch_server(watcher);
+        self.main_ui.current_user.borrow_mut().set(user);
+        self.main_ui.start_session();
+        Ok(())
+    }
+
     fn pack_and_show(&self) {
         self.pack_info_bar();
+        self.pack_connection();
         self.pack_user();
         self.pack_pass();
         self.pack_button_box();
@@ -202,14 +279,19 @@ impl AuthUI {
         self.info_label.show();
     }
 
+    fn pack_connection(&self) {
+        self.grid.attach(&self.connection_label, 0, 0, 1, 1);
+        self.grid.attach(&self.connection_combobox, 1, 0, 1, 1);
+    }
+
     fn pack_user(&self) {
-        self.grid.attach(&self.user_label, 0, 0, 1, 1);
-        self.grid.attach(&self.user_entry, 1, 0, 1, 1);
+        self.grid.attach(&self.user_label, 0, 1, 1, 1);
+        self.grid.attach(&self.user_entry, 1, 1, 1, 1);
     }
 
     fn pack_pass(&self) {
-        self.grid.attach(&self.pass_label, 0, 1, 1, 1);
-        self.grid.attach(&self.pass_entry, 1, 1, 1, 1);
+        self.grid.attach(&self.pass_label, 0, 2, 1, 1);
+        self.grid.attach(&self.pass_entry, 1, 2, 1, 1);
     }
 
     fn pack_button_box(&self) {
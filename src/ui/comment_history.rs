@@ -0,0 +1,148 @@
+//! Dialog of history of a comment: previous texts, the current one is the last.
+//!
+//! TODO Write docs.
+
+use gtk;
+
+use super::edit_ticket::EditTicket;
+use super::utils::show_error_dialog;
+use sed_bad_core::comment::Comment;
+
+#[derive(Clone)]
+pub struct CommentHistory {
+    edit_ticket: EditTicket,
+    comment_id: i64,
+
+    dialog: gtk::Dialog,
+
+    scrolled_window: gtk::ScrolledWindow,
+    tree_view: gtk::TreeView,
+    list_store: gtk::ListStore,
+
+    time_column: gtk::TreeViewColumn,
+    editor_column: gtk::TreeViewColumn,
+    text_column: gtk::TreeViewColumn,
+
+    time_cell: gtk::CellRendererText,
+    editor_cell: gtk::CellRendererText,
+    text_cell: gtk::CellRendererText,
+}
+
+impl CommentHistory {
+    pub fn new(edit_ticket: EditTicket, comment_id: i64) -> Self {
+        let tmp = CommentHistory {
+            edit_ticket: edit_ticket.clone(),
+            comment_id: comment_id,
+
+            dialog: gtk::Dialog::new_with_buttons(Some("History of comment"),
+                                                  Some(&edit_ticket.dialog),
+                                                  gtk::DIALOG_MODAL,
+                                                  &[("Close", gtk::ResponseType::Close.into())]),
+
+            scrolled_window: gtk::ScrolledWindow::new(None, None),
+            tree_view: gtk::TreeView::new(),
+            list_store: gtk::ListStore::new(&[gtk::Type::String, // Time.
+                                              gtk::Type::String, // Editor.
+                                              gtk::Type::String]), // Text.
+
+            time_column: gtk::TreeViewColumn::new(),
+            editor_column: gtk::TreeViewColumn::new(),
+            text_column: gtk::TreeViewColumn::new(),
+
+            time_cell: gtk::CellRendererText::new(),
+            editor_cell: gtk::CellRendererText::new(),
+            text_cell: gtk::CellRendererText::new(),
+        };
+        tmp.setup();
+        tmp.connect_signals();
+        tmp.pack_and_show();
+
+        tmp
+    }
+
+    fn setup(&self) {
+        self.setup_columns();
+        self.setup_list_store();
+        self.setup_dialog();
+    }
+
+    fn setup_columns(&self) {
+        self.time_column.set_title("Replaced at");
+        self.editor_column.set_title("By");
+        self.text_column.set_title("Text");
+
+        self.time_column.set_resizable(true);
+        self.editor_column.set_resizable(true);
+        self.text_column.set_resizable(true);
+
+        self.time_column.pack_start(&self.time_cell, true);
+        self.editor_column.pack_start(&self.editor_cell, true);
+        self.text_column.pack_start(&self.text_cell, true);
+
+        self.time_column.add_attribute(&self.time_cell, "text", 0);
+        self.editor_column.add_attribute(&self.editor_cell, "text", 1);
+        self.text_column.add_attribute(&self.text_cell, "text", 2);
+
+        self.tree_view.append_column(&self.time_column);
+        self.tree_view.append_column(&self.editor_column);
+        self.tree_view.append_column(&self.text_column);
+    }
+
+    fn setup_list_store(&self) {
+        let db = self.edit_ticket.main_ui.db.borrow();
+        let res = Comment::get_revisions(&db, self.comment_id)
+            .and_then(|revisions| {
+                Comment::get_by_id(&db, self.comment_id).map(|comment| (revisions, comment))
+            });
+        match res {
+            Ok((revisions, comment)) => {
+                for revision in revisions {
+                    self.list_store.insert_with_values(None,
+                                                       &[0, 1, 2],
+                                                       &[&revision.c_time().to_rfc2822(),
+                                                         &revision.editor()
+                                                             .map(|editor| editor.name())
+                                                             .unwrap_or("[deleted user]"),
+                                                         &revision.text()]);
+                }
+                let current = if comment.is_deleted() {
+                    "[deleted]"
+                } else {
+                    comment.text()
+                };
+                self.list_store.insert_with_values(None, &[0, 1, 2], &[&"Current", &"", &current]);
+                self.tree_view.set_model(Some(&self.list_store));
+            }
+            Err(e) => {
+                show_error_dialog(&self.edit_ticket.dialog,
+                                  &format!("Error of reading history of the comment.\n{}", e));
+            }
+        }
+    }
+
+    fn setup_dialog(&self) {
+        use gtk::WindowExt;
+
+        self.dialog.set_default_size(600, 400);
+        self.edit_ticket.main_ui.register_dialog(&self.dialog);
+    }
+
+    fn connect_signals(&self) {
+        use gtk::{DialogExt, WidgetExt};
+
+        self.dialog.connect_response(|dialog, _| {
+            dialog.destroy();
+        });
+    }
+
+    fn pack_and_show(&self) {
+        use gtk::{ContainerExt, DialogExt, BoxExt, WidgetExt};
+
+        self.scrolled_window.add(&self.tree_view);
+
+        let area = self.dialog.get_content_area();
+        area.pack_start(&self.scrolled_window, true, true, 5);
+
+        self.dialog.show_all();
+    }
+}
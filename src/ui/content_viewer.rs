@@ -0,0 +1,259 @@
+//! Viewer of the content of a document with annotations ("red pencil").
+//!
+//! Text content is editable, annotated ranges are highlighted in red. Rectangles of annotations
+//! on image content are drawn over the image, a new rectangle is selected by dragging.
+
+use gtk;
+use gdk;
+use cairo;
+
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+
+use super::MainUI;
+use super::utils::pixbuf_from_data;
+use sed_bad_core::document::Document;
+use sed_bad_core::annotation::{Annotation, Anchor};
+
+#[derive(Clone)]
+pub struct ContentViewer {
+    pub widget: gtk::ScrolledWindow,
+    annotations: Rc<RefCell<Vec<Annotation>>>,
+
+    text_buffer: gtk::TextBuffer,
+    text_view: gtk::TextView,
+    red_pencil_tag: gtk::TextTag,
+
+    // `None` for text content.
+    image_overlay: Option<gtk::Overlay>,
+    drawing_area: gtk::DrawingArea,
+    drag_start: Rc<Cell<Option<(f64, f64)>>>,
+    // Rectangle selected on the image.
+    selection: Rc<Cell<Option<(f64, f64, f64, f64)>>>,
+}
+
+impl ContentViewer {
+    pub fn new(doc: &Document) -> Self {
+        let tag_table = gtk::TextTagTable::new();
+        let text_buffer = gtk::TextBuffer::new(Some(&tag_table));
+        let tmp = ContentViewer {
+            widget: gtk::ScrolledWindow::new(None, None),
+            annotations: Rc::new(RefCell::new(Vec::new())),
+
+            text_view: gtk::TextView::new_with_buffer(&text_buffer),
+            text_buffer: text_buffer,
+            red_pencil_tag: gtk::TextTag::new(Some("red-pencil")),
+
+            image_overlay: if doc.is_image() {
+                Some(gtk::Overlay::new())
+            } else {
+                None
+            },
+            drawing_area: gtk::DrawingArea::new(),
+            drag_start: Rc::new(Cell::new(None)),
+            selection: Rc::new(Cell::new(None)),
+        };
+        tag_table.add(&tmp.red_pencil_tag);
+
+        tmp.setup(doc);
+        tmp.connect_signals();
+        tmp.pack(doc);
+
+        tmp
+    }
+
+    fn setup(&self, doc: &Document) {
+        use gtk::{ToValue, ObjectExt, WidgetExt};
+
+        let _ = self.red_pencil_tag.set_property("background", &"#ffcccc".to_value());
+        let _ = self.red_pencil_tag.set_property("foreground", &"#cc0000".to_value());
+        self.text_view.set_wrap_mode(gtk::WrapMode::Word);
+        self.text_buffer.set_text(&doc.text().unwrap_or_else(String::new));
+        self.widget.set_size_request(-1, 200);
+
+        if self.image_overlay.is_some() {
+            self.drawing_area.add_events((gdk::BUTTON_PRESS_MASK | gdk::BUTTON_RELEASE_MASK |
+                                          gdk::BUTTON1_MOTION_MASK)
+                .bits() as i32);
+        }
+    }
+
+    fn connect_signals(&self) {
+        use gtk::WidgetExt;
+
+        {
+            let rc = self.clone();
+            self.drawing_area.connect_draw(move |_, cr| {
+                rc.draw(cr);
+                gtk::Inhibit(false)
+            });
+        }
+        {
+            let rc = self.clone();
+            self.drawing_area.connect_button_press_event(move |_, event| {
+                rc.drag_start.set(Some(event.get_position()));
+                rc.selection.set(None);
+                rc.drawing_area.queue_draw();
+                gtk::Inhibit(true)
+            });
+        }
+        {
+            let rc = self.clone();
+            self.drawing_area.connect_motion_notify_event(move |_, event| {
+                if let Some((x0, y0)) = rc.drag_start.get() {
+                    let (x1, y1) = event.get_position();
+                    rc.selection.set(Some((x0.min(x1),
+                                           y0.min(y1),
+                                           (x1 - x0).abs(),
+                                           (y1 - y0).abs())));
+                    rc.drawing_area.queue_draw();
+                }
+                gtk::Inhibit(true)
+            });
+        }
+        {
+            let rc = self.clone();
+            self.drawing_area.connect_button_release_event(move |_, _| {
+                rc.drag_start.set(None);
+                gtk::Inhibit(true)
+            });
+        }
+    }
+
+    /// Draw rectangles of annotations and the selected rectangle.
+    fn draw(&self, cr: &cairo::Context) {
+        cr.set_line_width(2.0);
+        for annotation in self.annotations.borrow().iter() {
+            if let Anchor::Rect { x, y, width, height } = *annotation.anchor() {
+                cr.set_source_rgba(0.8, 0.0, 0.0, 0.9);
+                cr.rectangle(x, y, width, height);
+                cr.stroke_preserve();
+                cr.set_source_rgba(1.0, 0.8, 0.8, 0.3);
+                cr.fill();
+            }
+        }
+        if let Some((x, y, width, height)) = self.selection.get() {
+            cr.set_source_rgba(0.2, 0.4, 0.65, 0.9);
+            cr.rectangle(x, y, width, height);
+            cr.stroke();
+        }
+    }
+
+    fn pack(&self, doc: &Document) {
+        use gtk::ContainerExt;
+
+        match self.image_overlay {
+            Some(ref overlay) => {
+                self.widget.add(overlay);
+                self.pack_image(doc);
+            }
+            None => self.widget.add(&self.text_view),
+        }
+    }
+
+    /// Show the image content, it's decoded in memory.
+    fn pack_image(&self, doc: &Document) {
+        use gtk::{ContainerExt, WidgetExt};
+
+        if let (Some(overlay), Some(data)) = (self.image_overlay.as_ref(), doc.data()) {
+            if let Some(pixbuf) = pixbuf_from_data(&data) {
+                let image = gtk::Image::new_from_pixbuf(Some(&pixbuf));
+                image.set_halign(gtk::Align::Start);
+                image.set_valign(gtk::Align::Start);
+                overlay.add(&image);
+                overlay.add_overlay(&self.drawing_area);
+            }
+        }
+    }
+
+    /// Clicks in the text and on the image are handled here and don't reach the dialog, so the
+    /// activity of the user is watched in them too.
+    pub fn watch_activity(&self, main_ui: &MainUI) {
+        main_ui.watch_activity(&self.text_view);
+        main_ui.watch_activity(&self.drawing_area);
+    }
+
+    /// Replace the text content, annotations have to be set again.
+    pub fn set_text(&self, text: &str) {
+        if self.image_overlay.is_none() {
+            self.text_buffer.set_text(text);
+        }
+    }
+
+    /// Whether the user may change the text content.
+    pub fn set_editable(&self, editable: bool) {
+        self.text_view.set_editable(editable);
+    }
+
+    /// Show annotations, text ranges are highlighted.
+    pub fn set_annotations(&self, annotations: Vec<Annotation>) {
+        use gtk::WidgetExt;
+
+        let (start, end) = self.text_buffer.get_bounds();
+        self.text_buffer.remove_tag(&self.red_pencil_tag, &start, &end);
+        for annotation in &annotations {
+            if let (&Anchor::Text { start, end, .. }, false) = (annotation.anchor(),
+                                                                annotation.orphaned()) {
+                let start_iter = self.text_buffer.get_iter_at_offset(start as i32);
+                let end_iter = self.text_buffer.get_iter_at_offset(end as i32);
+                self.text_buffer.apply_tag(&self.red_pencil_tag, &start_iter, &end_iter);
+            }
+        }
+        *self.annotations.borrow_mut() = annotations;
+        self.drawing_area.queue_draw();
+    }
+
+    /// Select the annotated part of the content.
+    pub fn show_annotation(&self, comment_id: i64) {
+        let annotations = self.annotations.borrow();
+        let annotation = match annotations.iter().find(|a| a.comment_id() == comment_id) {
+            Some(annotation) => annotation,
+            None => return,
+        };
+        match *annotation.anchor() {
+            Anchor::Text { start, end, .. } => {
+                let start_iter = self.text_buffer.get_iter_at_offset(start as i32);
+                let mut end_iter = self.text_buffer.get_iter_at_offset(end as i32);
+                self.text_buffer.select_range(&start_iter, &end_iter);
+                self.text_view.scroll_to_iter(&mut end_iter, 0.0, false, 0.0, 0.0);
+            }
+            Anchor::Rect { x, y, width, height } => {
+                use gtk::WidgetExt;
+
+                self.selection.set(Some((x, y, width, height)));
+                self.drawing_area.queue_draw();
+            }
+        }
+    }
+
+    /// Anchor to the selected range of the text or the selected rectangle on the image.
+    pub fn selected_anchor(&self) -> Option<Anchor> {
+        if self.image_overlay.is_some() {
+            return self.selection
+                .get()
+                .and_then(|(x, y, width, height)| if width >= 1.0 && height >= 1.0 {
+                    Some(Anchor::Rect {
+                        x: x,
+                        y: y,
+                        width: width,
+                        height: height,
+                    })
+                } else {
+                    None
+                });
+        }
+        self.text_buffer.get_selection_bounds().map(|(start, end)| {
+            let text = self.text().unwrap_or_else(String::new);
+            Anchor::new_text(&text, start.get_offset() as i64, end.get_offset() as i64)
+        })
+    }
+
+    /// Text of the content, `None` for image content.
+    pub fn text(&self) -> Option<String> {
+        if self.image_overlay.is_some() {
+            return None;
+        }
+        let (start, end) = self.text_buffer.get_bounds();
+        self.text_buffer.get_text(&start, &end, false)
+    }
+}
@@ -0,0 +1,366 @@
+//! Dialog of comparing the content of a document with its revisions or with other documents.
+//!
+//! TODO Write docs.
+
+use gtk;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Write;
+
+use super::edit_ticket::EditTicket;
+use super::utils::show_error_dialog;
+use sed_bad_core::document::{Document, is_image};
+use sed_bad_core::revision::Revision;
+use sed_bad_core::query::{Query, Context};
+use sed_bad_core::diff::{self, Change};
+use sed_bad_core::errors::DbResult;
+
+/// Content to compare.
+#[derive(Clone)]
+pub enum Source {
+    Revision(Revision),
+    Document(i64),
+}
+
+impl Source {
+    /// Content of the source, `None` if the user may not read it.
+    pub fn load(&self, edit_ticket: &EditTicket) -> DbResult<Option<Option<Vec<u8>>>> {
+        match *self {
+            Source::Revision(ref revision) => Ok(Some(revision.data())),
+            Source::Document(doc_id) => {
+                let doc = Document::get_by_id(&edit_ticket.main_ui.db.borrow(), doc_id)?;
+                if edit_ticket.main_ui.current_user.borrow().is_read_grant(&doc) {
+                    Ok(Some(doc.data()))
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
+}
+
+/// Revisions of the document, its current content and other documents with labels. Return the
+/// index of the current content too.
+pub fn list_sources(edit_ticket: &EditTicket) -> DbResult<(Vec<(String, Source)>, usize)> {
+    let db = edit_ticket.main_ui.db.borrow();
+    let current_user = edit_ticket.main_ui.current_user.borrow();
+    let doc_id = edit_ticket.doc.borrow().id();
+    let revisions = Revision::get_by_doc_id(&db, doc_id)?;
+    let summaries = Document::list(&db, &Query::default(), &Context::new(&current_user), -1, 0)?;
+
+    let mut sources: Vec<(String, Source)> = Vec::new();
+    for (i, revision) in revisions.into_iter().enumerate() {
+        let label = format!("Revision {} (replaced at {} by {})",
+                            i + 1,
+                            revision.c_time().format("%d %b %Y %H:%M"),
+                            revision.author().map(|author| author.name()).unwrap_or("?"));
+        sources.push((label, Source::Revision(revision)));
+    }
+    let current = sources.len();
+    sources.push(("Current content".to_string(), Source::Document(doc_id)));
+    for summary in summaries {
+        if summary.id() != doc_id {
+            sources.push((format!("Ticket #{}: {}", summary.id(), summary.name()),
+                          Source::Document(summary.id())));
+        }
+    }
+    Ok((sources, current))
+}
+
+#[derive(Clone)]
+pub struct DiffViewer {
+    edit_ticket: EditTicket,
+    sources: Rc<RefCell<Vec<Source>>>,
+    changes: Rc<RefCell<Vec<Change>>>,
+
+    dialog: gtk::Dialog,
+
+    header_box: gtk::Box,
+    old_combobox: gtk::ComboBoxText,
+    new_combobox: gtk::ComboBoxText,
+    mode_combobox: gtk::ComboBoxText,
+    export_button: gtk::Button,
+
+    stack: gtk::Stack,
+    paned: gtk::Paned,
+    old_scrolled_window: gtk::ScrolledWindow,
+    old_text_view: gtk::TextView,
+    new_scrolled_window: gtk::ScrolledWindow,
+    new_text_view: gtk::TextView,
+    inline_scrolled_window: gtk::ScrolledWindow,
+    inline_text_view: gtk::TextView,
+}
+
+impl DiffViewer {
+    pub fn new(edit_ticket: EditTicket) -> Self {
+        let tmp = DiffViewer {
+            edit_ticket: edit_ticket.clone(),
+            sources: Rc::new(RefCell::new(Vec::new())),
+            changes: Rc::new(RefCell::new(Vec::new())),
+
+            dialog: gtk::Dialog::new_with_buttons(Some("Compare"),
+                                                  Some(&edit_ticket.dialog),
+                                                  gtk::DIALOG_MODAL,
+                                                  &[("Close", gtk::ResponseType::Close.into())]),
+
+            header_box: gtk::Box::new(gtk::Orientation::Horizontal, 5),
+            old_combobox: gtk::ComboBoxText::new(),
+            new_combobox: gtk::ComboBoxText::new(),
+            mode_combobox: gtk::ComboBoxText::new(),
+            export_button: gtk::Button::new_with_mnemonic("_Export to HTML..."),
+
+            stack: gtk::Stack::new(),
+            paned: gtk::Paned::new(gtk::Orientation::Horizontal),
+            old_scrolled_window: gtk::ScrolledWindow::new(None, None),
+            old_text_view: gtk::TextView::new(),
+            new_scrolled_window: gtk::ScrolledWindow::new(None, None),
+            new_text_view: gtk::TextView::new(),
+            inline_scrolled_window: gtk::ScrolledWindow::new(None, None),
+            inline_text_view: gtk::TextView::new(),
+        };
+        tmp.setup();
+        tmp.connect_signals();
+        tmp.pack_and_show();
+        tmp.update_diff();
+
+        tmp
+    }
+
+    fn setup(&self) {
+        self.setup_sources();
+        self.setup_mode_combobox();
+        self.setup_text_views();
+        self.setup_dialog();
+    }
+
+    /// Revisions of the document, its current content and other documents.
+    fn setup_sources(&self) {
+        use gtk::ComboBoxExt;
+
+        let (sources, current) = match list_sources(&self.edit_ticket) {
+            Ok(res) => res,
+            Err(e) => {
+                show_error_dialog(&self.edit_ticket.dialog,
+                                  &format!("Error of reading revisions from database.\n{}", e));
+                return;
+            }
+        };
+        for (label, source) in sources {
+            self.old_combobox.append_text(&label);
+            self.new_combobox.append_text(&label);
+            self.sources.borrow_mut().push(source);
+        }
+
+        // The last revision against the current content.
+        let current = current as i32;
+        self.old_combobox.set_active(if current > 0 { current - 1 } else { current });
+        self.new_combobox.set_active(current);
+    }
+
+    fn setup_mode_combobox(&self) {
+        use gtk::ComboBoxExt;
+
+        self.mode_combobox.append_text("Side by side");
+        self.mode_combobox.append_text("Inline");
+        self.mode_combobox.set_active(0);
+    }
+
+    fn setup_text_views(&self) {
+        for text_view in &[&self.old_text_view, &self.new_text_view, &self.inline_text_view] {
+            text_view.set_editable(false);
+            text_view.set_wrap_mode(gtk::WrapMode::Word);
+            if let Some(buffer) = text_view.get_buffer() {
+                add_diff_tags(&buffer);
+            }
+        }
+    }
+
+    fn setup_dialog(&self) {
+        use gtk::WindowExt;
+
+        self.dialog.set_default_size(900, 600);
+        self.edit_ticket.main_ui.register_dialog(&self.dialog);
+    }
+
+    fn is_side_by_side(&self) -> bool {
+        use gtk::ComboBoxExt;
+
+        self.mode_combobox.get_active() == 0
+    }
+
+    /// Text of the source, `None` if it's not a text or the user may not read it.
+    fn load_text(&self, source: &Source) -> DbResult<Option<String>> {
+        Ok(source.load(&self.edit_ticket)?.and_then(|data| match data {
+            Some(ref data) if is_image(data) => None,
+            Some(data) => String::from_utf8(data).ok(),
+            None => Some(String::new()),
+        }))
+    }
+
+    fn update_diff(&self) {
+        use gtk::ComboBoxExt;
+
+        let sources = self.sources.borrow();
+        let old = sources.get(self.old_combobox.get_active() as usize);
+        let new = sources.get(self.new_combobox.get_active() as usize);
+        let (old, new) = match (old, new) {
+            (Some(old), Some(new)) => (old, new),
+            _ => return,
+        };
+        let texts = self.load_text(old).and_then(|old| self.load_text(new).map(|new| (old, new)));
+        let changes = match texts {
+            Ok((Some(old), Some(new))) => diff::diff(&old, &new),
+            Ok(_) => {
+                show_error_dialog(&self.dialog,
+                                  "Only text content can be compared, or you may not read the \
+                                   document.");
+                Vec::new()
+            }
+            Err(e) => {
+                show_error_dialog(&self.dialog,
+                                  &format!("Error of reading the content from database.\n{}", e));
+                Vec::new()
+            }
+        };
+
+        for text_view in &[&self.old_text_view, &self.new_text_view, &self.inline_text_view] {
+            if let Some(buffer) = text_view.get_buffer() {
+                buffer.set_text("");
+            }
+        }
+        for change in &changes {
+            let (text, old_tag, new_tag) = match *change {
+                Change::Equal(ref text) => (text, Some(None), Some(None)),
+                Change::Delete(ref text) => (text, Some(Some("delete")), None),
+                Change::Insert(ref text) => (text, None, Some(Some("insert"))),
+            };
+            if let Some(tag) = old_tag {
+                append(&self.old_text_view, text, tag);
+            }
+            if let Some(tag) = new_tag {
+                append(&self.new_text_view, text, tag);
+            }
+            append(&self.inline_text_view, text, old_tag.or(new_tag).and_then(|tag| tag));
+        }
+        *self.changes.borrow_mut() = changes;
+    }
+
+    fn update_mode(&self) {
+        if self.is_side_by_side() {
+            self.stack.set_visible_child_name("side-by-side");
+        } else {
+            self.stack.set_visible_child_name("inline");
+        }
+    }
+
+    fn connect_signals(&self) {
+        use gtk::{ComboBoxExt, ButtonExt, DialogExt, WidgetExt, FileChooserExt, Cast};
+
+        {
+            let rc = self.clone();
+            self.old_combobox.connect_changed(move |_| rc.update_diff());
+        }
+        {
+            let rc = self.clone();
+            self.new_combobox.connect_changed(move |_| rc.update_diff());
+        }
+        {
+            let rc = self.clone();
+            self.mode_combobox.connect_changed(move |_| rc.update_mode());
+        }
+        {
+            let rc = self.clone();
+            self.export_button.connect_clicked(move |_| {
+                let dialog = gtk::FileChooserDialog::new(Some("Export difference"),
+                                                         Some(&rc.dialog),
+                                                         gtk::FileChooserAction::Save);
+                dialog.add_buttons(&[("Cancel", gtk::ResponseType::Cancel.into()),
+                                     ("Export", gtk::ResponseType::Accept.into())]);
+                dialog.set_current_name("diff.html");
+                dialog.set_do_overwrite_confirmation(true);
+
+                    rc.edit_ticket.main_ui.register_dialog(&dialog.clone().upcast::<gtk::Dialog>());
+                if dialog.run() == gtk::ResponseType::Accept.into() {
+                    if let Some(path) = dialog.get_filename() {
+                        let title = format!("{}: {} — {}",
+                                            rc.edit_ticket.doc.borrow().name(),
+                                            rc.old_combobox
+                                                .get_active_text()
+                                                .unwrap_or_else(String::new),
+                                            rc.new_combobox
+                                                .get_active_text()
+                                                .unwrap_or_else(String::new));
+                        let html = diff::to_html(&rc.changes.borrow(),
+                                                 &title,
+                                                 rc.is_side_by_side());
+                        let res = File::create(&path)
+                            .and_then(|mut file| file.write_all(html.as_bytes()));
+                        if let Err(e) = res {
+                            show_error_dialog(&rc.dialog,
+                                              &format!("Error of exporting the difference.\n{}",
+                                                       e));
+                        }
+                    }
+                }
+                dialog.destroy();
+            });
+        }
+        self.dialog.connect_response(|dialog, _| {
+            dialog.destroy();
+        });
+    }
+
+    fn pack_and_show(&self) {
+        use gtk::{ContainerExt, DialogExt, BoxExt, WidgetExt};
+
+        self.header_box.pack_start(&self.old_combobox, true, true, 0);
+        self.header_box.pack_start(&self.new_combobox, true, true, 0);
+        self.header_box.pack_start(&self.mode_combobox, false, false, 0);
+        self.header_box.pack_start(&self.export_button, false, false, 0);
+
+        self.old_scrolled_window.add(&self.old_text_view);
+        self.new_scrolled_window.add(&self.new_text_view);
+        self.inline_scrolled_window.add(&self.inline_text_view);
+        self.paned.pack1(&self.old_scrolled_window, true, false);
+        self.paned.pack2(&self.new_scrolled_window, true, false);
+        self.stack.add_named(&self.paned, "side-by-side");
+        self.stack.add_named(&self.inline_scrolled_window, "inline");
+
+        let area = self.dialog.get_content_area();
+        area.pack_start(&self.header_box, false, false, 5);
+        area.pack_start(&self.stack, true, true, 5);
+
+        self.dialog.show_all();
+        self.update_mode();
+    }
+}
+
+/// Tags of deleted and inserted text.
+fn add_diff_tags(buffer: &gtk::TextBuffer) {
+    use gtk::{ToValue, ObjectExt};
+
+    if let Some(tag_table) = buffer.get_tag_table() {
+        let delete_tag = gtk::TextTag::new(Some("delete"));
+        let _ = delete_tag.set_property("background", &"#ffcccc".to_value());
+        let _ = delete_tag.set_property("strikethrough", &true.to_value());
+        let insert_tag = gtk::TextTag::new(Some("insert"));
+        let _ = insert_tag.set_property("background", &"#d7f5c4".to_value());
+        tag_table.add(&delete_tag);
+        tag_table.add(&insert_tag);
+    }
+}
+
+/// Append the text to the end of the text view marking it by the tag.
+fn append(text_view: &gtk::TextView, text: &str, tag: Option<&str>) {
+    if let Some(buffer) = text_view.get_buffer() {
+        let start = buffer.get_end_iter().get_offset();
+        buffer.insert(&mut buffer.get_end_iter(), text);
+        if let Some(tag) = tag {
+            let start_iter = buffer.get_iter_at_offset(start);
+            let end_iter = buffer.get_end_iter();
+            buffer.apply_tag_by_name(tag, &start_iter, &end_iter);
+        }
+    }
+}
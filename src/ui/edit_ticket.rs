@@ -3,28 +3,51 @@
 //! TODO Write docs.
 
 use gtk;
-use chrono::{Datelike, UTC, TimeZone};
+use chrono::{DateTime, UTC};
 
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
 use super::MainUI;
-use document::Document;
-use super::utils::show_error_dialog;
-use user::User;
+use sed_bad_core::document::Document;
+use super::utils::{show_error_dialog, ask_confirmation, calendar_date, select_calendar_date,
+                   escape_markup};
+use sed_bad_core::user::User;
 use super::new_comment::NewComment;
-
-use category::Category;
-use metadata::Status;
-use comment::Comment;
-use permission::{NaivePermission, Permission};
+use super::comment_history::CommentHistory;
+use super::content_viewer::ContentViewer;
+use super::diff_viewer::DiffViewer;
+use super::image_diff_viewer::ImageDiffViewer;
+use super::merge_ticket::MergeTicket;
+use sed_bad_core::merge::changed_fields;
+
+use sed_bad_core::metadata::Status;
+use sed_bad_core::comment::{Comment, parse_mentions};
+use sed_bad_core::annotation::{Annotation, Anchor};
+use sed_bad_core::revision::Revision;
+use sed_bad_core::lock::Lock;
+use sed_bad_core::permission::{NaivePermission, Permission};
+use sed_bad_core::errors::DbError;
+
+/// Comments as shown: ids, times of editing, deletion and resolution flags.
+type CommentsStamp = Vec<(i64, Option<DateTime<UTC>>, bool, bool)>;
 
 #[derive(Clone)]
 pub struct EditTicket {
     pub main_ui: MainUI,
     pub doc: Rc<RefCell<Document>>,
+    // Check-out of the ticket, kept in the local database only.
+    pub lock: Rc<RefCell<Option<Lock>>>,
+    // Whether the lock is released after saving.
+    pub checking_in: Rc<Cell<bool>>,
 
     pub dialog: gtk::Dialog,
+    // Shown when other users change the ticket.
+    pub changed_info_bar: gtk::InfoBar,
+    pub changed_label: gtk::Label,
+    // Shown while the ticket is checked out.
+    pub lock_info_bar: gtk::InfoBar,
+    pub lock_label: gtk::Label,
 
     pub name_entry: gtk::Entry,
     pub name_label: gtk::Label,
@@ -56,15 +79,31 @@ pub struct EditTicket {
     pub perm_others_comment_but: gtk::ToggleButton,
 
     pub data_entry: gtk::Entry,
+    pub content_label: gtk::Label,
+    pub content_viewer: ContentViewer,
     pub scrolled_window_label: gtk::Label,
     pub scrolled_window: gtk::ScrolledWindow,
-    pub comments_text: gtk::TextView,
+    pub comments_tree_view: gtk::TreeView,
+    pub comments_tree_store: gtk::TreeStore,
+    pub comments_column: gtk::TreeViewColumn,
+    pub comments_cell: gtk::CellRendererText,
+    pub comments_stamp: Rc<RefCell<CommentsStamp>>,
     pub responsible_label: gtk::Label,
     pub responsible_combobox: gtk::ComboBoxText,
 
     pub button_box: gtk::ButtonBox,
     pub ok_button: gtk::Button,
     pub add_comment_button: gtk::Button,
+    pub reply_button: gtk::Button,
+    pub resolve_button: gtk::Button,
+    pub edit_comment_button: gtk::Button,
+    pub delete_comment_button: gtk::Button,
+    pub history_button: gtk::Button,
+    pub annotate_button: gtk::Button,
+    pub compare_button: gtk::Button,
+    pub check_out_button: gtk::Button,
+    pub check_in_button: gtk::Button,
+    pub break_lock_button: gtk::Button,
     pub cancel_button: gtk::Button,
 }
 
@@ -72,8 +111,14 @@ impl EditTicket {
     pub fn new(main_ui: MainUI, document: Document) -> Self {
         let tmp = EditTicket {
             main_ui: main_ui,
+            lock: Rc::new(RefCell::new(None)),
+            checking_in: Rc::new(Cell::new(false)),
 
             dialog: gtk::Dialog::new(),
+            changed_info_bar: gtk::InfoBar::new(),
+            changed_label: gtk::Label::new(None),
+            lock_info_bar: gtk::InfoBar::new(),
+            lock_label: gtk::Label::new(None),
 
             name_entry: gtk::Entry::new(),
             name_label: gtk::Label::new(Some("Name:")),
@@ -111,15 +156,33 @@ impl EditTicket {
             perm_others_comment_but: gtk::ToggleButton::new_with_label("Comment"),
 
             data_entry: gtk::Entry::new(),
+            content_label: gtk::Label::new(Some("Content:")),
+            content_viewer: ContentViewer::new(&document),
             scrolled_window_label: gtk::Label::new(Some("Commentaries:")),
             scrolled_window: gtk::ScrolledWindow::new(None, None),
-            comments_text: gtk::TextView::new(),
+            comments_tree_view: gtk::TreeView::new(),
+            comments_tree_store: gtk::TreeStore::new(&[gtk::Type::I64, // Id.
+                                                       gtk::Type::String, // Markup.
+                                                       gtk::Type::Bool]), // Topic.
+            comments_column: gtk::TreeViewColumn::new(),
+            comments_cell: gtk::CellRendererText::new(),
+            comments_stamp: Rc::new(RefCell::new(Vec::new())),
             responsible_label: gtk::Label::new(Some("Responsible:")),
             responsible_combobox: gtk::ComboBoxText::new(),
 
             button_box: gtk::ButtonBox::new(gtk::Orientation::Horizontal),
             ok_button: gtk::Button::new_with_mnemonic("_Ok"),
             add_comment_button: gtk::Button::new_with_mnemonic("_Add comment"),
+            reply_button: gtk::Button::new_with_mnemonic("_Reply"),
+            resolve_button: gtk::Button::new_with_mnemonic("Resolve/_Reopen"),
+            edit_comment_button: gtk::Button::new_with_mnemonic("_Edit comment"),
+            delete_comment_button: gtk::Button::new_with_mnemonic("_Delete comment"),
+            history_button: gtk::Button::new_with_mnemonic("_History"),
+            annotate_button: gtk::Button::new_with_mnemonic("A_nnotate selection"),
+            compare_button: gtk::Button::new_with_mnemonic("Com_pare..."),
+            check_out_button: gtk::Button::new_with_mnemonic("Check _out"),
+            check_in_button: gtk::Button::new_with_mnemonic("Check _in"),
+            break_lock_button: gtk::Button::new_with_mnemonic("_Break lock"),
             cancel_button: gtk::Button::new_with_mnemonic("_Cancel"),
             doc: Rc::new(RefCell::new(document)),
         };
@@ -127,12 +190,16 @@ impl EditTicket {
         tmp.setup();
         tmp.connect_signals();
         tmp.pack_and_show();
+        tmp.main_ui.edit_tickets.borrow_mut().push(tmp.clone());
 
         tmp
     }
 
     fn setup(&self) {
+        self.load_lock();
         self.dialog_setup();
+        self.changed_info_bar_setup();
+        self.lock_info_bar_setup();
         self.labels_setup();
         self.name_entry_setup();
         self.author_combobox_setup();
@@ -142,8 +209,11 @@ impl EditTicket {
 
         self.permission_setup();
         self.data_entry_setup();
-        self.comments_text_setup();
+        self.comments_tree_view_setup();
+        self.comments_tree_store_setup();
         self.responsible_combobox_setup();
+        self.buttons_setup();
+        self.lock_setup();
     }
 
     fn dialog_setup(&self) {
@@ -154,6 +224,73 @@ impl EditTicket {
         self.dialog.set_modal(true);
 
         self.dialog.set_default_size(500, 700);
+        self.main_ui.watch_activity(&self.dialog);
+        self.content_viewer.watch_activity(&self.main_ui);
+    }
+
+    fn changed_info_bar_setup(&self) {
+        use gtk::WidgetExt;
+
+        self.changed_info_bar.set_message_type(gtk::MessageType::Warning);
+        self.changed_info_bar.set_no_show_all(true);
+    }
+
+    fn lock_info_bar_setup(&self) {
+        use gtk::WidgetExt;
+
+        self.lock_info_bar.set_message_type(gtk::MessageType::Info);
+        self.lock_info_bar.set_no_show_all(true);
+        self.break_lock_button.set_no_show_all(true);
+    }
+
+    /// Show who checked out the ticket. The content is changed only after the check-out, other
+    /// users can't change the ticket until the check-in.
+    fn lock_setup(&self) {
+        use gtk::WidgetExt;
+
+        let editable = self.is_editable();
+        let current_user = self.main_ui.current_user.borrow();
+        let lock = self.lock.borrow();
+        let mine = lock.as_ref().map(|lock| lock.is_held_by(&current_user)).unwrap_or(false);
+        match *lock {
+            Some(ref lock) => {
+                let owner = if mine { "you" } else { lock.user().name() };
+                self.lock_label.set_text(&format!("Checked out by {} at {}.",
+                                                  owner,
+                                                  lock.c_time().format("%d %b %Y %H:%M")));
+                self.lock_info_bar.show();
+            }
+            None => self.lock_info_bar.hide(),
+        }
+        self.check_out_button.set_sensitive(editable && lock.is_none());
+        self.check_in_button.set_sensitive(mine);
+        self.break_lock_button.set_visible(current_user.is_root() && lock.is_some() && !mine);
+        self.content_viewer.set_editable(editable && mine);
+    }
+
+    /// Reload the lock of the ticket, `true` if it was changed.
+    fn load_lock(&self) -> bool {
+        let doc_id = self.doc.borrow().id();
+        let res = self.main_ui.storage.borrow().document_lock(doc_id);
+        match res {
+            Ok(lock) => {
+                let changed = lock_stamp(&lock) != lock_stamp(&self.lock.borrow());
+                *self.lock.borrow_mut() = lock;
+                changed
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Whether the current user may change the ticket and nobody else has checked it out.
+    fn is_editable(&self) -> bool {
+        let current_user = self.main_ui.current_user.borrow();
+        let locked = match *self.lock.borrow() {
+            Some(ref lock) => !lock.is_held_by(&current_user),
+            None => false,
+        };
+        let access = current_user.is_access_grant(&self.doc.borrow());
+        access && !locked
     }
 
     fn labels_setup(&self) {
@@ -172,7 +309,7 @@ impl EditTicket {
         use gtk::{EntryExt, WidgetExt};
 
         self.name_entry.set_text(self.doc.borrow().name());
-        if self.main_ui.current_user.borrow().is_access_grant(&self.doc.borrow()) {
+        if self.is_editable() {
             self.name_entry.set_sensitive(true);
         } else {
             self.name_entry.set_sensitive(false);
@@ -182,10 +319,12 @@ impl EditTicket {
     fn author_combobox_setup(&self) {
         use gtk::{ComboBoxExt, WidgetExt};
 
+        self.author_combobox.remove_all();
         self.author_combobox.append_text(self.doc.borrow().metadata().author().name());
         self.author_combobox.set_active(0);
 
-        match User::get_users(&self.main_ui.db.borrow()) {
+        let res = self.main_ui.storage.borrow().users();
+        match res {
             Ok(users) => {
                 for user in users {
                     if user.name() != self.doc.borrow().metadata().author().name() {
@@ -209,10 +348,12 @@ impl EditTicket {
     fn category_combobox_setup(&self) {
         use gtk::{ComboBoxExt, WidgetExt};
 
+        self.category_combobox.remove_all();
         self.category_combobox.append_text(self.doc.borrow().metadata().category().name());
         self.category_combobox.set_active(0);
 
-        match Category::get_categories(&self.main_ui.db.borrow()) {
+        let res = self.main_ui.storage.borrow().categories();
+        match res {
             Ok(categories) => {
                 for category in categories {
                     if category.name() != self.doc.borrow().metadata().category().name() {
@@ -226,7 +367,7 @@ impl EditTicket {
             }
         }
 
-        if self.main_ui.current_user.borrow().is_access_grant(&self.doc.borrow()) {
+        if self.is_editable() {
             self.category_combobox.set_sensitive(true);
         } else {
             self.category_combobox.set_sensitive(false);
@@ -241,7 +382,7 @@ impl EditTicket {
         self.status_combobox.append_text("Complete");
         self.status_combobox.set_active(self.doc.borrow().metadata().status().get_num() as i32);
 
-        if self.main_ui.current_user.borrow().is_access_grant(&self.doc.borrow()) {
+        if self.is_editable() {
             self.status_combobox.set_sensitive(true);
         } else {
             self.status_combobox.set_sensitive(false);
@@ -251,12 +392,10 @@ impl EditTicket {
     fn date_expired_calendar_setup(&self) {
         use gtk::WidgetExt;
 
-        self.date_expired_calendar
-            .select_month(self.doc.borrow().metadata().date_expired().month(),
-                          self.doc.borrow().metadata().date_expired().year() as u32);
-        self.date_expired_calendar.select_day(self.doc.borrow().metadata().date_expired().day());
+        select_calendar_date(&self.date_expired_calendar,
+                             self.doc.borrow().metadata().date_expired());
 
-        if self.main_ui.current_user.borrow().is_access_grant(&self.doc.borrow()) {
+        if self.is_editable() {
             self.date_expired_calendar.set_sensitive(true);
         } else {
             self.date_expired_calendar.set_sensitive(false);
@@ -266,37 +405,18 @@ impl EditTicket {
     fn permission_setup(&self) {
         use gtk::{ToggleButtonExt, WidgetExt};
 
-        let doc_bor = self.doc.borrow();
-        let permission = doc_bor.permission();
-        if permission.author().read {
-            self.perm_author_read_but.set_active(true);
-        }
-        if permission.author().write {
-            self.perm_author_write_but.set_active(true);
-        }
-        if permission.author().comment {
-            self.perm_author_comment_but.set_active(true);
-        }
-        if permission.responsible().read {
-            self.perm_responsible_read_but.set_active(true);
-        }
-        if permission.responsible().write {
-            self.perm_responsible_write_but.set_active(true);
-        }
-        if permission.responsible().comment {
-            self.perm_responsible_comment_but.set_active(true);
-        }
-        if permission.others().read {
-            self.perm_others_read_but.set_active(true);
-        }
-        if permission.others().write {
-            self.perm_others_write_but.set_active(true);
-        }
-        if permission.others().comment {
-            self.perm_others_comment_but.set_active(true);
-        }
-
-        if self.main_ui.current_user.borrow().is_access_grant(&self.doc.borrow()) {
+        let permission = self.doc.borrow().permission();
+        self.perm_author_read_but.set_active(permission.author().read);
+        self.perm_author_write_but.set_active(permission.author().write);
+        self.perm_author_comment_but.set_active(permission.author().comment);
+        self.perm_responsible_read_but.set_active(permission.responsible().read);
+        self.perm_responsible_write_but.set_active(permission.responsible().write);
+        self.perm_responsible_comment_but.set_active(permission.responsible().comment);
+        self.perm_others_read_but.set_active(permission.others().read);
+        self.perm_others_write_but.set_active(permission.others().write);
+        self.perm_others_comment_but.set_active(permission.others().comment);
+
+        if self.is_editable() {
             self.perm_author_button_box.set_sensitive(true);
             self.perm_responsible_button_box.set_sensitive(true);
             self.perm_others_button_box.set_sensitive(true);
@@ -309,37 +429,104 @@ impl EditTicket {
 
     fn data_entry_setup(&self) {}
 
-    fn comments_text_setup(&self) {
-        use gtk::WidgetExt;
+    fn comments_tree_view_setup(&self) {
+        self.comments_column.pack_start(&self.comments_cell, true);
+        self.comments_column.add_attribute(&self.comments_cell, "markup", 1);
+        self.comments_tree_view.append_column(&self.comments_column);
+        self.comments_tree_view.set_headers_visible(false);
+        self.comments_tree_view.set_model(Some(&self.comments_tree_store));
+    }
 
-        match Comment::get_by_doc_id(&self.main_ui.db.borrow(), self.doc.borrow().id()) {
-            Ok(comments) => {
-                let mut comments_str = String::default();
+    /// Show discussions as trees, resolved topics are collapsed.
+    fn comments_tree_store_setup(&self) {
+        let storage = self.main_ui.storage.borrow();
+        let doc_id = self.doc.borrow().id();
+        let res = storage.users().and_then(|users| {
+            let comments = storage.comments_by_doc_id(doc_id)?;
+            let annotations = match self.main_ui.local_db() {
+                Some(db) => Annotation::get_by_doc_id(&db, doc_id)?,
+                None => Vec::new(),
+            };
+            Ok((users, comments, annotations))
+        });
+        match res {
+            Ok((users, comments, annotations)) => {
+                *self.comments_stamp.borrow_mut() = comments_stamp(&comments);
+                let mut iters: Vec<(i64, gtk::TreeIter)> = Vec::new();
+                let mut resolved_topics: Vec<gtk::TreeIter> = Vec::new();
                 for comment in comments {
-                    comments_str.push_str(&format!("{} wrote at {}:\n-----\n{}\n",
-                                                   comment.author().name(),
-                                                   comment.c_time().to_rfc2822(),
-                                                   comment.text()));
+                    let parent = comment.parent_id()
+                        .and_then(|parent_id| iters.iter().find(|&&(id, _)| id == parent_id))
+                        .map(|&(_, ref iter)| iter.clone());
+                    let mut markup = if comment.is_deleted() {
+                        format!("<i>[deleted]</i> at {}",
+                                comment.m_time().unwrap_or(comment.c_time()).to_rfc2822())
+                    } else {
+                        format!("<b>{}</b> wrote at {}:\n{}{}",
+                                escape_markup(comment.author().name()),
+                                comment.c_time().to_rfc2822(),
+                                annotations.iter()
+                                    .find(|annotation| annotation.comment_id() == comment.id())
+                                    .map(anchor_markup)
+                                    .unwrap_or_else(String::new),
+                                text_markup(comment.text(), &users))
+                    };
+                    if let (false, Some(m_time)) = (comment.is_deleted(), comment.m_time()) {
+                        markup.push_str(&format!("\n<small>(edited at {})</small>",
+                                                 m_time.to_rfc2822()));
+                    }
+                    if comment.is_topic() && comment.resolved() {
+                        markup = format!("<span foreground=\"gray\">[Resolved] {}</span>", markup);
+                    }
+                    let iter = self.comments_tree_store
+                        .insert_with_values(parent.as_ref(),
+                                            None,
+                                            &[0, 1, 2],
+                                            &[&comment.id(), &markup, &comment.is_topic()]);
+                    if comment.is_topic() && comment.resolved() {
+                        resolved_topics.push(iter.clone());
+                    }
+                    iters.push((comment.id(), iter));
+                }
+                self.comments_tree_view.expand_all();
+                for iter in resolved_topics {
+                    if let Some(path) = self.comments_tree_store.get_path(&iter) {
+                        self.comments_tree_view.collapse_row(&path);
+                    }
                 }
-                let buffer_text = self.comments_text.get_buffer().unwrap();
-                buffer_text.set_text(&comments_str);
+                self.content_viewer.set_annotations(annotations);
             }
             Err(e) => {
                 show_error_dialog(&self.dialog,
                                   &format!("Error of reading comments from database.\n{}", e));
             }
         }
+    }
+
+    /// Id of the selected comment and of the topic containing it.
+    fn selected_comment(&self) -> Option<(i64, i64)> {
+        use gtk::TreeModelExt;
 
-        self.comments_text.set_sensitive(false);
+        self.comments_tree_view.get_selection().get_selected().map(|(tree_model, tree_iter)| {
+            let id = tree_model.get_value(&tree_iter, 0).get::<i64>().unwrap_or(0);
+            let mut topic_iter = tree_iter;
+            while let Some(parent) = tree_model.iter_parent(&topic_iter) {
+                topic_iter = parent;
+            }
+            let topic_id = tree_model.get_value(&topic_iter, 0).get::<i64>().unwrap_or(0);
+            (id, topic_id)
+        })
     }
 
     fn responsible_combobox_setup(&self) {
         use gtk::{ComboBoxExt, WidgetExt};
 
+        self.responsible_combobox.remove_all();
         self.responsible_combobox.append_text(self.doc.borrow().responsible().name());
         self.responsible_combobox.set_active(0);
 
-        match User::get_users(&self.main_ui.db.borrow()) {
+        let res = self.main_ui.storage.borrow().users();
+        match res {
             Ok(users) => {
                 for user in users {
                     if user.name() != self.doc.borrow().responsible().name() {
@@ -354,131 +541,441 @@ impl EditTicket {
 
         }
 
-        if self.main_ui.current_user.borrow().is_access_grant(&self.doc.borrow()) {
+        if self.is_editable() {
             self.responsible_combobox.set_sensitive(true);
         } else {
             self.responsible_combobox.set_sensitive(false);
         }
     }
 
+    /// Revisions and annotations are kept in the local database only.
+    fn buttons_setup(&self) {
+        use gtk::WidgetExt;
+
+        let local = self.main_ui.local_db().is_some();
+        self.history_button.set_sensitive(local);
+        self.annotate_button.set_sensitive(local);
+        self.compare_button.set_sensitive(local);
+    }
+
     fn connect_signals(&self) {
+        self.dialog_connect();
         self.ok_button_connect();
+        self.check_out_button_connect();
+        self.check_in_button_connect();
+        self.break_lock_button_connect();
         self.cancel_button_connect();
         self.add_comment_button_connect();
+        self.reply_button_connect();
+        self.resolve_button_connect();
+        self.edit_comment_button_connect();
+        self.delete_comment_button_connect();
+        self.history_button_connect();
+        self.annotate_button_connect();
+        self.compare_button_connect();
+        self.comments_tree_view_connect();
+    }
+
+    fn form_permission(&self) -> Permission {
+        use gtk::ToggleButtonExt;
+
+        let author_perm = NaivePermission::new(self.perm_author_read_but.get_active(),
+                                               self.perm_author_write_but.get_active(),
+                                               self.perm_author_comment_but.get_active());
+        let responsible_perm = NaivePermission::new(self.perm_responsible_read_but.get_active(),
+                                                    self.perm_responsible_write_but.get_active(),
+                                                    self.perm_responsible_comment_but
+                                                        .get_active());
+        let others_perm = NaivePermission::new(self.perm_others_read_but.get_active(),
+                                               self.perm_others_write_but.get_active(),
+                                               self.perm_others_comment_but.get_active());
+        Permission::from_naive(author_perm, responsible_perm, others_perm)
+    }
+
+    /// Text of the content if the user changed it.
+    fn changed_content(&self) -> Option<String> {
+        // Binary content which is not an image is not shown, so it can't be changed.
+        self.content_viewer.text().and_then(|text| {
+            let doc = self.doc.borrow();
+            match doc.data() {
+                Some(_) if doc.text().as_ref() == Some(&text) => None,
+                Some(_) if doc.text().is_none() => None,
+                None if text.is_empty() => None,
+                _ => Some(text),
+            }
+        })
+    }
+
+    /// Whether the user changed any field of the ticket in the dialog.
+    fn is_form_changed(&self) -> bool {
+        use gtk::{EntryExt, ComboBoxExt};
+
+        let doc = self.doc.borrow();
+        let metadata = doc.metadata();
+        self.name_entry.get_text().map(|name| name != doc.name()).unwrap_or(false) ||
+        self.author_combobox.get_active_text().map(|author| author != metadata.author().name())
+            .unwrap_or(false) ||
+        self.category_combobox.get_active_text()
+            .map(|category| category != metadata.category().name())
+            .unwrap_or(false) ||
+        self.responsible_combobox.get_active_text()
+            .map(|responsible| responsible != doc.responsible().name())
+            .unwrap_or(false) ||
+        Status::from_num(self.status_combobox.get_active() as i64) != metadata.status() ||
+        calendar_date(&self.date_expired_calendar) != metadata.date_expired() ||
+        self.form_permission() != doc.permission() ||
+        self.changed_content().is_some()
+    }
+
+    /// Show changes of other users: comments are reloaded when they are changed, fields are
+    /// reloaded unless the user has changed them too.
+    pub fn refresh(&self) {
+        use gtk::WidgetExt;
+
+        let doc_id = self.doc.borrow().id();
+        let res = self.main_ui.storage.borrow().comments_by_doc_id(doc_id);
+        if let Ok(comments) = res {
+            if comments_stamp(&comments) != *self.comments_stamp.borrow() {
+                self.update_main();
+            }
+        }
+
+        let lock_changed = self.load_lock();
+        let res = self.main_ui.storage.borrow().document_by_id(doc_id);
+        let doc = match res {
+            Ok(doc) => doc,
+            Err(DbError::NotFound) => {
+                self.changed_label.set_text("The ticket was deleted by another user.");
+                self.changed_info_bar.show();
+                return;
+            }
+            Err(_) => return,
+        };
+        let doc_changed = doc.metadata().m_time() != self.doc.borrow().metadata().m_time();
+        if !doc_changed && !lock_changed {
+            return;
+        }
+        if self.is_form_changed() {
+            if doc_changed {
+                self.changed_label.set_text("The ticket was changed by another user, the \
+                                             changes are merged on saving.");
+                self.changed_info_bar.show();
+            }
+            self.lock_setup();
+            return;
+        }
+        *self.doc.borrow_mut() = doc;
+        self.reload_form();
+    }
+
+    /// Show the fields of the ticket, changes in the form are lost.
+    fn reload_form(&self) {
+        use gtk::{ComboBoxExt, WidgetExt};
+
+        self.lock_setup();
+        self.name_entry_setup();
+        self.author_combobox_setup();
+        self.category_combobox_setup();
+        self.status_combobox.set_active(self.doc.borrow().metadata().status().get_num() as i32);
+        self.status_combobox.set_sensitive(self.is_editable());
+        self.date_expired_calendar_setup();
+        self.permission_setup();
+        self.responsible_combobox_setup();
+        let text = self.doc.borrow().text();
+        if let Some(text) = text {
+            self.content_viewer.set_text(&text);
+            // Annotations are relocated to the new text.
+            self.update_main();
+        }
+    }
+
+    /// Closed dialogs aren't refreshed.
+    fn dialog_connect(&self) {
+        use gtk::WidgetExt;
+
+        let rc: EditTicket = self.clone();
+        self.dialog.connect_destroy(move |dialog| {
+            rc.main_ui
+                .edit_tickets
+                .borrow_mut()
+                .retain(|edit_ticket| &edit_ticket.dialog != dialog);
+        });
     }
 
     fn ok_button_connect(&self) {
-        use gtk::{ButtonExt, EntryExt, ComboBoxExt, ToggleButtonExt, WidgetExt};
+        use gtk::{ButtonExt, WidgetExt};
 
         let rc: EditTicket = self.clone();
         self.ok_button.connect_clicked(move |_| {
-            let name = rc.name_entry.get_text().unwrap();
-            if name.is_empty() {
-                show_error_dialog(&rc.dialog, "Name of ticket is empty.");
-                return;
+            let (doc, changed) = match rc.form_document() {
+                Some(form) => form,
+                None => return,
+            };
+            rc.checking_in.set(false);
+            if changed {
+                let base = rc.doc.borrow().clone();
+                rc.save(&base, doc);
+            } else {
+                rc.dialog.destroy();
+            }
+        });
+    }
+
+    /// The ticket with the values of the form and whether they differ from the read ticket.
+    /// `None` if the form is invalid, the error is shown.
+    fn form_document(&self) -> Option<(Document, bool)> {
+        use gtk::{EntryExt, ComboBoxExt};
+
+        let name = self.name_entry.get_text().unwrap();
+        if name.is_empty() {
+            show_error_dialog(&self.dialog, "Name of ticket is empty.");
+            return None;
+        }
+        let author = self.author_combobox.get_active_text().unwrap();
+        let category = self.category_combobox.get_active_text().unwrap();
+        let status = Status::from_num(self.status_combobox.get_active() as i64);
+        let date_expired = calendar_date(&self.date_expired_calendar);
+        let permission = self.form_permission();
+        let responsible = self.responsible_combobox.get_active_text().unwrap();
+
+        // The read ticket is kept to merge the changes with changes of other users.
+        let mut doc = self.doc.borrow().clone();
+        let mut changed = false;
+
+        if doc.name() != name {
+            doc.set_name(name);
+            changed = true;
+        }
+
+        if doc.metadata().author().name() != author {
+            let res = self.main_ui.storage.borrow().user_by_name(&author);
+            match res {
+                Ok(author) => doc.metadata_mut().set_author(author),
+                Err(e) => {
+                    show_error_dialog(&self.dialog,
+                                      &format!("Error of reading the author.\n{}", e));
+                    return None;
+                }
+            }
+            changed = true;
+        }
+
+        if doc.metadata().category().name() != category {
+            let res = self.main_ui.storage.borrow().category_by_name(&category);
+            match res {
+                Ok(category) => doc.metadata_mut().set_category(category),
+                Err(e) => {
+                    show_error_dialog(&self.dialog,
+                                      &format!("Error of reading the category.\n{}", e));
+                    return None;
+                }
+            }
+            changed = true;
+        }
+
+        if doc.metadata().status() != status {
+            doc.metadata_mut().set_status(status);
+            changed = true;
+        }
+
+        if doc.metadata().date_expired() != date_expired {
+            doc.metadata_mut().set_date_expired(date_expired);
+            changed = true;
+        }
+
+        if doc.permission() != permission {
+            doc.set_permission(permission);
+            changed = true;
+        }
+
+        if doc.responsible().name() != responsible {
+            let res = self.main_ui.storage.borrow().user_by_name(&responsible);
+            match res {
+                Ok(responsible) => doc.set_responsible(responsible),
+                Err(e) => {
+                    show_error_dialog(&self.dialog,
+                                      &format!("Error of reading the responsible.\n{}", e));
+                    return None;
+                }
             }
-            let author = rc.author_combobox.get_active_text().unwrap();
-            let category = rc.category_combobox.get_active_text().unwrap();
-            let status = Status::from_num(rc.status_combobox.get_active() as i64);
-            let date_expired = rc.date_expired_calendar.get_date();
-            let date_expired = UTC.ymd(date_expired.0 as i32, date_expired.1, date_expired.2)
-                .and_hms(0, 0, 0);
-            let author_perm = NaivePermission::new(rc.perm_author_read_but.get_active(),
-                                                   rc.perm_author_write_but.get_active(),
-                                                   rc.perm_author_comment_but.get_active());
-            let responsible_perm = NaivePermission::new(rc.perm_responsible_read_but.get_active(),
-                                                        rc.perm_responsible_write_but.get_active(),
-                                                        rc.perm_responsible_comment_but
-                                                            .get_active());
-            let others_perm = NaivePermission::new(rc.perm_others_read_but.get_active(),
-                                                   rc.perm_others_write_but.get_active(),
-                                                   rc.perm_others_comment_but.get_active());
-            let permission = Permission::from_naive(author_perm, responsible_perm, others_perm);
-            let responsible = rc.responsible_combobox.get_active_text().unwrap();
-
-            let mut changed = false;
-
-            let name_changed = rc.doc.borrow().name() != name;
-            if name_changed {
-                rc.doc.borrow_mut().set_name(name);
-                changed = true;
-            }
-
-            let author_changed = rc.doc.borrow().metadata().author().name() != author;
-            if author_changed {
-                rc.doc
-                    .borrow_mut()
-                    .metadata_mut()
-                    .set_author(User::get_user(&rc.main_ui.db.borrow(), &author).unwrap());
-                changed = true;
-            }
-
-            let category_changed = rc.doc.borrow().metadata().category().name() != category;
-            if category_changed {
-                rc.doc
-                    .borrow_mut()
-                    .metadata_mut()
-                    .set_category(Category::get_category(&rc.main_ui.db.borrow(), &category)
-                        .unwrap());
-                changed = true;
-            }
-
-            let status_changed = rc.doc.borrow().metadata().status() != status;
-            if status_changed {
-                rc.doc.borrow_mut().metadata_mut().set_status(status);
-                changed = true;
-            }
-
-            let date_expired_changed = rc.doc.borrow().metadata().date_expired() != date_expired;
-            if date_expired_changed {
-                rc.doc
-                    .borrow_mut()
-                    .metadata_mut()
-                    .set_date_expired(date_expired);
-                changed = true;
-            }
-
-            let permission_changed = rc.doc.borrow().permission() != permission;
-            if permission_changed {
-                rc.doc.borrow_mut().set_permission(permission);
-                changed = true;
-            }
-
-            let responsible_changed = rc.doc.borrow().responsible().name() != responsible;
-            if responsible_changed {
-                rc.doc
-                    .borrow_mut()
-                    .set_responsible(User::get_user(&rc.main_ui.db.borrow(), &responsible)
-                        .unwrap());
-                changed = true;
+            changed = true;
+        }
+
+        if let Some(text) = self.changed_content() {
+            doc.set_data(Some(text.into_bytes()));
+            changed = true;
+        }
+
+        Some((doc, changed))
+    }
+
+    /// The latest ticket is read on check-out, its content becomes editable.
+    fn check_out_button_connect(&self) {
+        use gtk::ButtonExt;
+
+        let rc: EditTicket = self.clone();
+        self.check_out_button.connect_clicked(move |_| {
+            let doc_id = rc.doc.borrow().id();
+            let res = rc.main_ui
+                .storage
+                .borrow()
+                .check_out(doc_id, &rc.main_ui.current_user.borrow());
+            match res {
+                Ok(lock) => {
+                    if !lock.is_held_by(&rc.main_ui.current_user.borrow()) {
+                        show_error_dialog(&rc.dialog,
+                                          &format!("The ticket is already checked out by {}.",
+                                                   lock.user().name()));
+                    }
+                    *rc.lock.borrow_mut() = Some(lock);
+                    let res = rc.main_ui.storage.borrow().document_by_id(doc_id);
+                    if let Ok(doc) = res {
+                        *rc.doc.borrow_mut() = doc;
+                    }
+                    rc.reload_form();
+                }
+                Err(e) => {
+                    show_error_dialog(&rc.dialog,
+                                      &format!("Error of checking out the ticket.\n{}", e));
+                }
             }
+        });
+    }
+
+    /// Save the ticket and release the lock, the replaced content is kept as a revision.
+    fn check_in_button_connect(&self) {
+        use gtk::{ButtonExt, WidgetExt};
 
+        let rc: EditTicket = self.clone();
+        self.check_in_button.connect_clicked(move |_| {
+            let (doc, changed) = match rc.form_document() {
+                Some(form) => form,
+                None => return,
+            };
+            rc.checking_in.set(true);
             if changed {
-                let doc_bor_mut = rc.doc.borrow_mut();
-                match doc_bor_mut.update(&rc.main_ui.db.borrow()) {
-                    Ok(_) => {
-                        match doc_bor_mut.metadata().update(&rc.main_ui.db.borrow()) {
-                            Ok(_) => {
-                                rc.dialog.destroy();
-                                rc.main_ui.update_ui();
-                            }
-                            Err(e) => {
-                                show_error_dialog(&rc.dialog,
-                                                  &format!("Error of updating document.\n{}", e));
-                                return;
-                            }
-                        }
+                let base = rc.doc.borrow().clone();
+                rc.save(&base, doc);
+                return;
+            }
+            let res = rc.main_ui
+                .storage
+                .borrow()
+                .check_in(doc.id(), &rc.main_ui.current_user.borrow());
+            match res {
+                Ok(_) => {
+                    rc.dialog.destroy();
+                    rc.main_ui.update_ui();
+                }
+                Err(e) => {
+                    show_error_dialog(&rc.dialog,
+                                      &format!("Error of checking in the ticket.\n{}", e));
+                }
+            }
+        });
+    }
+
+    /// Root releases the lock of another user, e.g. forgotten for a long time. It's recorded in
+    /// the audit log.
+    fn break_lock_button_connect(&self) {
+        use gtk::ButtonExt;
+
+        let rc: EditTicket = self.clone();
+        self.break_lock_button.connect_clicked(move |_| {
+            let lock = match *rc.lock.borrow() {
+                Some(ref lock) => lock.clone(),
+                None => return,
+            };
+            let question = format!("Break the check-out of {} made at {}? Changes of the user \
+                                    which are not checked in can't be saved without merging.",
+                                   lock.user().name(),
+                                   lock.c_time().format("%d %b %Y %H:%M"));
+            if !ask_confirmation(&rc.dialog, &question) {
+                return;
+            }
+            let res = rc.main_ui
+                .storage
+                .borrow()
+                .break_lock(&lock, &rc.main_ui.current_user.borrow());
+            match res {
+                Ok(_) => {
+                    rc.load_lock();
+                    rc.reload_form();
+                }
+                Err(e) => {
+                    show_error_dialog(&rc.dialog, &format!("Error of breaking the lock.\n{}", e));
+                }
+            }
+        });
+    }
+
+    /// Save the ticket changed from `base`. If another user has saved the ticket meanwhile,
+    /// the changes are merged in `MergeTicket`.
+    pub fn save(&self, base: &Document, doc: Document) {
+        use gtk::WidgetExt;
+
+        // Nobody else may change the ticket while it's checked out.
+        if self.load_lock() {
+            self.lock_setup();
+        }
+        let (locked_by, release) = match *self.lock.borrow() {
+            Some(ref lock) if !lock.is_held_by(&self.main_ui.current_user.borrow()) => {
+                (Some(lock.user().name().to_string()), false)
+            }
+            Some(_) => (None, self.checking_in.get()),
+            None => (None, false),
+        };
+        if let Some(name) = locked_by {
+            show_error_dialog(&self.dialog,
+                              &format!("The ticket is checked out by {}, it can't be changed \
+                                        until the check-in.",
+                                       name));
+            return;
+        }
+
+        let current_user = self.main_ui.current_user.borrow().clone();
+        let res = self.main_ui.storage.borrow().update_document(&doc, &current_user).and_then(|_| {
+            if let Some(db) = self.main_ui.local_db() {
+                if doc.data() != base.data() {
+                    // The replaced content is kept as a revision.
+                    let mut revision = Revision::new(doc.id(), base.data(), &current_user);
+                    revision.save_to_db(&db)?;
+                    if let Some(text) = doc.text() {
+                        Annotation::relocate_all(&db, doc.id(), &text)?;
+                    }
+                }
+            }
+            if release {
+                self.main_ui.storage.borrow().check_in(doc.id(), &current_user)?;
+            }
+            Ok(())
+        });
+        match res {
+            Ok(_) => {
+                self.dialog.destroy();
+                self.main_ui.update_ui();
+            }
+            Err(DbError::Conflict) => {
+                let res = self.main_ui.storage.borrow().document_by_id(doc.id());
+                match res {
+                    Ok(ref theirs) if changed_fields(&doc, theirs).is_empty() => {
+                        // Both users made the same changes.
+                        self.dialog.destroy();
+                        self.main_ui.update_ui();
+                    }
+                    Ok(theirs) => {
+                        MergeTicket::new(self.clone(), base.clone(), doc, theirs);
                     }
                     Err(e) => {
-                        show_error_dialog(&rc.dialog,
-                                          &format!("Error of updating document.\n{}", e));
-                        return;
+                        show_error_dialog(&self.dialog,
+                                          &format!("Error of reading the ticket.\n{}", e));
                     }
                 }
-            } else {
-                rc.dialog.destroy();
             }
-        });
+            Err(e) => {
+                show_error_dialog(&self.dialog, &format!("Error of updating document.\n{}", e));
+            }
+        }
     }
 
     fn cancel_button_connect(&self) {
@@ -490,28 +987,222 @@ impl EditTicket {
         });
     }
 
+    /// Whether the current user has the right to comment the document.
+    fn is_comment_grant(&self) -> bool {
+        let doc_comment_perm = (self.doc.borrow().permission().author().comment(),
+                                self.doc.borrow().permission().responsible().comment(),
+                                self.doc.borrow().permission().others().comment());
+        let author_responsible_other = if self.doc.borrow().metadata().author().name() ==
+                                          self.main_ui.current_user.borrow().name() ||
+                                          self.main_ui.current_user.borrow().is_root() {
+            (1, 0, 0)
+        } else if self.doc.borrow().responsible().name() ==
+                                                 self.main_ui.current_user.borrow().name() {
+            (0, 1, 0)
+        } else {
+            (0, 0, 1)
+        };
+        doc_comment_perm.0 && author_responsible_other.0 == 1 ||
+        doc_comment_perm.1 && author_responsible_other.1 == 1 ||
+        doc_comment_perm.2 && author_responsible_other.2 == 1
+    }
+
     fn add_comment_button_connect(&self) {
         use gtk::ButtonExt;
 
         let rc: EditTicket = self.clone();
         self.add_comment_button.connect_clicked(move |_| {
-            let doc_comment_perm = (rc.doc.borrow().permission().author().comment(),
-                                    rc.doc.borrow().permission().responsible().comment(),
-                                    rc.doc.borrow().permission().others().comment());
-            let author_responsible_other = if rc.doc.borrow().metadata().author().name() ==
-                                              rc.main_ui.current_user.borrow().name() ||
-                                              rc.main_ui.current_user.borrow().is_root() {
-                (1, 0, 0)
-            } else if rc.doc.borrow().responsible().name() ==
-                                                     rc.main_ui.current_user.borrow().name() {
-                (0, 1, 0)
-            } else {
-                (0, 0, 1)
+            if rc.is_comment_grant() {
+                NewComment::new(rc.clone(), None);
+            }
+        });
+    }
+
+    fn reply_button_connect(&self) {
+        use gtk::ButtonExt;
+
+        let rc: EditTicket = self.clone();
+        self.reply_button.connect_clicked(move |_| {
+            match rc.selected_comment() {
+                Some((id, _)) => {
+                    if rc.is_comment_grant() {
+                        NewComment::new(rc.clone(), Some(id));
+                    }
+                }
+                None => {
+                    show_error_dialog(&rc.dialog, "No one comment is selecting.");
+                }
+            }
+        });
+    }
+
+    fn resolve_button_connect(&self) {
+        use gtk::ButtonExt;
+
+        let rc: EditTicket = self.clone();
+        self.resolve_button.connect_clicked(move |_| {
+            let topic_id = match rc.selected_comment() {
+                Some((_, topic_id)) => topic_id,
+                None => {
+                    show_error_dialog(&rc.dialog, "No one comment is selecting.");
+                    return;
+                }
             };
-            if doc_comment_perm.0 && author_responsible_other.0 == 1 ||
-               doc_comment_perm.1 && author_responsible_other.1 == 1 ||
-               doc_comment_perm.2 && author_responsible_other.2 == 1 {
-                NewComment::new(rc.clone());
+            let storage = rc.main_ui.storage.borrow();
+            let res = storage.comments_by_doc_id(rc.doc.borrow().id())
+                .and_then(|comments| {
+                    match comments.iter().find(|comment| comment.id() == topic_id) {
+                        Some(topic) => {
+                            // The author of the topic, the author of the document or root.
+                            let current_user = rc.main_ui.current_user.borrow();
+                            if topic.author() == &*current_user ||
+                               current_user.is_access_grant(&rc.doc.borrow()) {
+                                storage.set_comment_resolved(topic_id, !topic.resolved())
+                                    .map(|_| true)
+                            } else {
+                                Ok(false)
+                            }
+                        }
+                        None => Ok(false),
+                    }
+                });
+            match res {
+                Ok(true) => rc.update_main(),
+                Ok(false) => {
+                    show_error_dialog(&rc.dialog,
+                                      "Only the author of the topic or of the ticket can \
+                                       resolve it.")
+                }
+                Err(e) => {
+                    show_error_dialog(&rc.dialog,
+                                      &format!("Error of resolving the topic.\n{}", e));
+                }
+            }
+        });
+    }
+
+    /// Selected comment if the current user may edit or delete it.
+    fn selected_comment_for_editing(&self) -> Option<Comment> {
+        let id = match self.selected_comment() {
+            Some((id, _)) => id,
+            None => {
+                show_error_dialog(&self.dialog, "No one comment is selecting.");
+                return None;
+            }
+        };
+        let res = self.main_ui.storage.borrow().comment_by_id(id);
+        match res {
+            Ok(comment) => {
+                if comment.is_edit_grant(&self.main_ui.current_user.borrow()) &&
+                   self.is_comment_grant() {
+                    Some(comment)
+                } else {
+                    show_error_dialog(&self.dialog,
+                                      "Only the author of the comment can change it.");
+                    None
+                }
+            }
+            Err(e) => {
+                show_error_dialog(&self.dialog,
+                                  &format!("Error of reading the comment from database.\n{}", e));
+                None
+            }
+        }
+    }
+
+    fn edit_comment_button_connect(&self) {
+        use gtk::ButtonExt;
+
+        let rc: EditTicket = self.clone();
+        self.edit_comment_button.connect_clicked(move |_| {
+            if let Some(comment) = rc.selected_comment_for_editing() {
+                NewComment::edit(rc.clone(), &comment);
+            }
+        });
+    }
+
+    fn delete_comment_button_connect(&self) {
+        use gtk::ButtonExt;
+
+        let rc: EditTicket = self.clone();
+        self.delete_comment_button.connect_clicked(move |_| {
+            if let Some(mut comment) = rc.selected_comment_for_editing() {
+                if !ask_confirmation(&rc.dialog, "Delete the selected comment?") {
+                    return;
+                }
+                let res = rc.main_ui
+                    .storage
+                    .borrow()
+                    .delete_comment(&mut comment, &rc.main_ui.current_user.borrow());
+                match res {
+                    Ok(_) => rc.update_main(),
+                    Err(e) => {
+                        show_error_dialog(&rc.dialog,
+                                          &format!("Error of deleting the comment.\n{}", e));
+                    }
+                }
+            }
+        });
+    }
+
+    fn history_button_connect(&self) {
+        use gtk::ButtonExt;
+
+        let rc: EditTicket = self.clone();
+        self.history_button.connect_clicked(move |_| {
+            match rc.selected_comment() {
+                Some((id, _)) => {
+                    CommentHistory::new(rc.clone(), id);
+                }
+                None => {
+                    show_error_dialog(&rc.dialog, "No one comment is selecting.");
+                }
+            }
+        });
+    }
+
+    fn annotate_button_connect(&self) {
+        use gtk::ButtonExt;
+
+        let rc: EditTicket = self.clone();
+        self.annotate_button.connect_clicked(move |_| {
+            if !rc.is_comment_grant() {
+                return;
+            }
+            match rc.content_viewer.selected_anchor() {
+                Some(anchor) => {
+                    NewComment::annotate(rc.clone(), anchor);
+                }
+                None => {
+                    show_error_dialog(&rc.dialog,
+                                      "Select a part of the text or drag a rectangle on the \
+                                       image.");
+                }
+            }
+        });
+    }
+
+    fn compare_button_connect(&self) {
+        use gtk::ButtonExt;
+
+        let rc: EditTicket = self.clone();
+        self.compare_button.connect_clicked(move |_| {
+            if rc.doc.borrow().is_image() {
+                ImageDiffViewer::new(rc.clone());
+            } else {
+                DiffViewer::new(rc.clone());
+            }
+        });
+    }
+
+    /// Show the annotated part of the content of the activated comment.
+    fn comments_tree_view_connect(&self) {
+        use gtk::TreeViewSignals;
+
+        let rc: EditTicket = self.clone();
+        self.comments_tree_view.connect_row_activated(move |_, _, _| {
+            if let Some((id, _)) = rc.selected_comment() {
+                rc.content_viewer.show_annotation(id);
             }
         });
     }
@@ -554,21 +1245,36 @@ impl EditTicket {
 
         self.button_box.add(&self.ok_button);
         self.button_box.add(&self.add_comment_button);
+        self.button_box.add(&self.reply_button);
+        self.button_box.add(&self.resolve_button);
+        self.button_box.add(&self.edit_comment_button);
+        self.button_box.add(&self.delete_comment_button);
+        self.button_box.add(&self.history_button);
+        self.button_box.add(&self.annotate_button);
+        self.button_box.add(&self.compare_button);
+        self.button_box.add(&self.check_out_button);
+        self.button_box.add(&self.check_in_button);
+        self.button_box.add(&self.break_lock_button);
         self.button_box.add(&self.cancel_button);
     }
 
     fn scrolled_window_pack(&self) {
         use gtk::ContainerExt;
 
-        self.scrolled_window.add(&self.comments_text);
+        self.scrolled_window.add(&self.comments_tree_view);
     }
 
     fn dialog_pack(&self) {
-        use gtk::{DialogExt, BoxExt, WidgetExt};
+        use gtk::{DialogExt, BoxExt, ContainerExt, WidgetExt};
 
         let area = self.dialog.get_content_area();
 
-
+        self.changed_info_bar.add(&self.changed_label);
+        self.changed_label.show();
+        area.pack_start(&self.changed_info_bar, false, false, 0);
+        self.lock_info_bar.add(&self.lock_label);
+        self.lock_label.show();
+        area.pack_start(&self.lock_info_bar, false, false, 0);
         area.pack_start(&self.name_label, false, false, 0);
         area.pack_start(&self.name_entry, false, false, 0);
         // area.pack_start(&self.c_time_label, false, false, 0);
@@ -588,6 +1294,8 @@ impl EditTicket {
         area.pack_start(&self.permission_others_label, false, false, 0);
         area.pack_start(&self.perm_others_button_box, false, false, 0);
         // area.pack_start(&self.data_entry, false, false, 0);
+        area.pack_start(&self.content_label, false, false, 0);
+        area.pack_start(&self.content_viewer.widget, true, true, 0);
         area.pack_start(&self.scrolled_window_label, false, false, 0);
         area.pack_start(&self.scrolled_window, true, true, 0);
         area.pack_start(&self.responsible_label, false, false, 0);
@@ -598,6 +1306,50 @@ impl EditTicket {
     }
 
     pub fn update_main(&self) {
-        self.comments_text_setup();
+        self.comments_tree_store.clear();
+        self.comments_tree_store_setup();
+    }
+}
+
+/// User and time of the check-out to notice changes of the lock.
+fn lock_stamp(lock: &Option<Lock>) -> Option<(i64, DateTime<UTC>)> {
+    lock.as_ref().map(|lock| (lock.user().id(), lock.c_time()))
+}
+
+fn comments_stamp(comments: &[Comment]) -> CommentsStamp {
+    comments.iter()
+        .map(|comment| {
+            (comment.id(), comment.m_time(), comment.is_deleted(), comment.resolved())
+        })
+        .collect()
+}
+
+/// Escape the text of a comment for Pango markup, mentions of existing users are shown as links.
+fn text_markup(text: &str, users: &[User]) -> String {
+    let mut markup = String::with_capacity(text.len());
+    let mut pos = 0;
+    for (start, end) in parse_mentions(text) {
+        if !users.iter().any(|user| user.name() == &text[start + 1..end]) {
+            continue;
+        }
+        markup.push_str(&escape_markup(&text[pos..start]));
+        markup.push_str(&format!("<span foreground=\"#3465a4\" underline=\"single\">{}</span>",
+                                 escape_markup(&text[start..end])));
+        pos = end;
+    }
+    markup.push_str(&escape_markup(&text[pos..]));
+    markup
+}
+
+/// Quoted text of the annotation, orphaned annotations are marked.
+fn anchor_markup(annotation: &Annotation) -> String {
+    let quote = match *annotation.anchor() {
+        Anchor::Text { ref quote, .. } => format!("«{}»", escape_markup(quote)),
+        Anchor::Rect { .. } => "[area of the image]".to_string(),
+    };
+    if annotation.orphaned() {
+        format!("<span foreground=\"gray\"><s>{}</s> (the text was changed)</span>\n", quote)
+    } else {
+        format!("<span foreground=\"#cc0000\">{}</span>\n", quote)
     }
 }
@@ -5,7 +5,6 @@
 use gtk;
 
 use super::user_administration::UserAdministration;
-use user::User;
 use super::utils::show_error_dialog;
 
 #[derive(Clone)]
@@ -74,6 +73,7 @@ impl EditUser {
         self.dialog.set_title("Edit user");
         self.dialog.set_transient_for(Some(&self.user_adm.dialog));
         self.dialog.set_modal(true);
+        self.user_adm.main_ui.register_dialog(&self.dialog);
     }
 
     fn connect_signals(&self) {
@@ -96,10 +96,8 @@ impl EditUser {
                 }
                 let name = rc.name_entry.get_text().unwrap();
                 let pass = rc.pass_entry.get_text().unwrap();
-                if let Err(e) = User::update_by_id(&rc.user_adm.main_ui.db.borrow(),
-                                                   id,
-                                                   &name,
-                                                   &pass) {
+                let res = rc.user_adm.main_ui.storage.borrow().update_user(id, &name, &pass);
+                if let Err(e) = res {
                     show_error_dialog(&rc.dialog,
                                       &format!("Error of editing user's information.\n{}", e));
                 } else {
@@ -118,10 +116,8 @@ impl EditUser {
                     .unwrap();
                 let name = rc.name_entry.get_text().unwrap();
                 let pass = rc.pass_entry.get_text().unwrap();
-                if let Err(e) = User::update_by_id(&rc.user_adm.main_ui.db.borrow(),
-                                                   id,
-                                                   &name,
-                                                   &pass) {
+                let res = rc.user_adm.main_ui.storage.borrow().update_user(id, &name, &pass);
+                if let Err(e) = res {
                     show_error_dialog(&rc.dialog,
                                       &format!("Error of editing user's information.\n{}", e));
                 } else {
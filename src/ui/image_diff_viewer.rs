@@ -0,0 +1,322 @@
+//! Dialog of comparing graphical documents.
+//!
+//! The second image is aligned to the first one, they are shown one at a time (toggle), mixed
+//! (overlay), split by a movable column (slider) or as a difference heatmap.
+
+use gtk;
+use gdk_pixbuf::{Pixbuf, Colorspace};
+
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+
+use super::edit_ticket::EditTicket;
+use super::diff_viewer::{Source, list_sources};
+use super::utils::{show_error_dialog, pixbuf_from_data};
+use sed_bad_core::document::is_image;
+use sed_bad_core::image_diff::{self, Bitmap};
+use sed_bad_core::errors::DbResult;
+
+/// Maximum shift of scans in pixels when aligning.
+const MAX_SHIFT: i32 = 20;
+
+/// Images to show: the first one, the second one aligned to it and the heatmap.
+struct Images {
+    first: Bitmap,
+    second: Bitmap,
+    heatmap: Bitmap,
+}
+
+#[derive(Clone)]
+pub struct ImageDiffViewer {
+    edit_ticket: EditTicket,
+    sources: Rc<RefCell<Vec<Source>>>,
+    images: Rc<RefCell<Option<Images>>>,
+    show_second: Rc<Cell<bool>>,
+
+    dialog: gtk::Dialog,
+
+    header_box: gtk::Box,
+    old_combobox: gtk::ComboBoxText,
+    new_combobox: gtk::ComboBoxText,
+    mode_combobox: gtk::ComboBoxText,
+    toggle_button: gtk::Button,
+    scale: gtk::Scale,
+    similarity_label: gtk::Label,
+
+    scrolled_window: gtk::ScrolledWindow,
+    image: gtk::Image,
+}
+
+impl ImageDiffViewer {
+    pub fn new(edit_ticket: EditTicket) -> Self {
+        let tmp = ImageDiffViewer {
+            edit_ticket: edit_ticket.clone(),
+            sources: Rc::new(RefCell::new(Vec::new())),
+            images: Rc::new(RefCell::new(None)),
+            show_second: Rc::new(Cell::new(false)),
+
+            dialog: gtk::Dialog::new_with_buttons(Some("Compare images"),
+                                                  Some(&edit_ticket.dialog),
+                                                  gtk::DIALOG_MODAL,
+                                                  &[("Close", gtk::ResponseType::Close.into())]),
+
+            header_box: gtk::Box::new(gtk::Orientation::Horizontal, 5),
+            old_combobox: gtk::ComboBoxText::new(),
+            new_combobox: gtk::ComboBoxText::new(),
+            mode_combobox: gtk::ComboBoxText::new(),
+            toggle_button: gtk::Button::new_with_mnemonic("_Switch"),
+            scale: gtk::Scale::new_with_range(gtk::Orientation::Horizontal, 0.0, 1.0, 0.01),
+            similarity_label: gtk::Label::new(None),
+
+            scrolled_window: gtk::ScrolledWindow::new(None, None),
+            image: gtk::Image::new(),
+        };
+        tmp.setup();
+        tmp.connect_signals();
+        tmp.pack_and_show();
+        tmp.update_images();
+
+        tmp
+    }
+
+    fn setup(&self) {
+        self.setup_sources();
+        self.setup_mode_combobox();
+        self.setup_scale();
+        self.setup_dialog();
+    }
+
+    /// Image revisions of the document, its current content and other image documents.
+    fn setup_sources(&self) {
+        use gtk::ComboBoxExt;
+
+        let (sources, current) = match list_sources(&self.edit_ticket) {
+            Ok(res) => res,
+            Err(e) => {
+                show_error_dialog(&self.edit_ticket.dialog,
+                                  &format!("Error of reading revisions from database.\n{}", e));
+                return;
+            }
+        };
+        let mut current_index = 0;
+        for (i, (label, source)) in sources.into_iter().enumerate() {
+            // Revisions and documents which are not images aren't comparable.
+            if i != current {
+                match source.load(&self.edit_ticket) {
+                    Ok(Some(Some(ref data))) if is_image(data) => {}
+                    _ => continue,
+                }
+            } else {
+                current_index = self.sources.borrow().len() as i32;
+            }
+            self.old_combobox.append_text(&label);
+            self.new_combobox.append_text(&label);
+            self.sources.borrow_mut().push(source);
+        }
+
+        // The last image revision against the current content.
+        self.old_combobox.set_active(if current_index > 0 {
+            current_index - 1
+        } else {
+            current_index
+        });
+        self.new_combobox.set_active(current_index);
+    }
+
+    fn setup_mode_combobox(&self) {
+        use gtk::ComboBoxExt;
+
+        self.mode_combobox.append_text("Toggle");
+        self.mode_combobox.append_text("Overlay");
+        self.mode_combobox.append_text("Slider");
+        self.mode_combobox.append_text("Heatmap");
+        self.mode_combobox.set_active(0);
+    }
+
+    fn setup_scale(&self) {
+        use gtk::{RangeExt, ScaleExt, WidgetExt};
+
+        self.scale.set_value(0.5);
+        self.scale.set_draw_value(false);
+        self.scale.set_size_request(200, -1);
+    }
+
+    fn setup_dialog(&self) {
+        use gtk::WindowExt;
+
+        self.dialog.set_default_size(900, 700);
+        self.edit_ticket.main_ui.register_dialog(&self.dialog);
+    }
+
+    /// Bitmap of the source, `None` if it's not an image or the user may not read it.
+    fn load_bitmap(&self, source: &Source) -> DbResult<Option<Bitmap>> {
+        Ok(match source.load(&self.edit_ticket)? {
+            Some(Some(ref data)) if is_image(data) => decode(data),
+            _ => None,
+        })
+    }
+
+    /// Load, align and compare the selected images.
+    fn update_images(&self) {
+        use gtk::{ComboBoxExt, LabelExt};
+
+        let images = {
+            let sources = self.sources.borrow();
+            let old = sources.get(self.old_combobox.get_active() as usize);
+            let new = sources.get(self.new_combobox.get_active() as usize);
+            let (old, new) = match (old, new) {
+                (Some(old), Some(new)) => (old, new),
+                _ => return,
+            };
+            let bitmaps = self.load_bitmap(old)
+                .and_then(|old| self.load_bitmap(new).map(|new| (old, new)));
+            match bitmaps {
+                Ok((Some(first), Some(second))) => {
+                    let second = image_diff::match_to(&first, &second, MAX_SHIFT);
+                    let heatmap = image_diff::heatmap(&first, &second);
+                    Some(Images {
+                        first: first,
+                        second: second,
+                        heatmap: heatmap,
+                    })
+                }
+                Ok(_) => {
+                    show_error_dialog(&self.dialog,
+                                      "The image can't be decoded, or you may not read the \
+                                       document.");
+                    None
+                }
+                Err(e) => {
+                    show_error_dialog(&self.dialog,
+                                      &format!("Error of reading the content from database.\n{}",
+                                               e));
+                    None
+                }
+            }
+        };
+
+        match images {
+            Some(ref images) => {
+                let similarity = image_diff::similarity(&images.first, &images.second);
+                self.similarity_label.set_text(&format!("Similarity: {:.1}%", similarity * 100.0));
+            }
+            None => self.similarity_label.set_text(""),
+        }
+        *self.images.borrow_mut() = images;
+        self.show_second.set(false);
+        self.update_view();
+    }
+
+    /// Compose the shown image according to the mode.
+    fn update_view(&self) {
+        use gtk::{ComboBoxExt, RangeExt, WidgetExt};
+
+        let mode = self.mode_combobox.get_active();
+        self.toggle_button.set_visible(mode == 0);
+        self.scale.set_visible(mode == 1 || mode == 2);
+
+        let images = self.images.borrow();
+        let images = match *images {
+            Some(ref images) => images,
+            None => {
+                self.image.clear();
+                return;
+            }
+        };
+        let t = self.scale.get_value();
+        let bitmap = match mode {
+            0 if self.show_second.get() => images.second.clone(),
+            0 => images.first.clone(),
+            1 => image_diff::blend(&images.first, &images.second, t),
+            2 => {
+                image_diff::split(&images.first,
+                                  &images.second,
+                                  (images.first.width() as f64 * t) as usize)
+            }
+            _ => images.heatmap.clone(),
+        };
+        self.image.set_from_pixbuf(Some(&to_pixbuf(bitmap)));
+    }
+
+    fn connect_signals(&self) {
+        use gtk::{ComboBoxExt, ButtonExt, RangeExt, DialogExt, WidgetExt};
+
+        {
+            let rc = self.clone();
+            self.old_combobox.connect_changed(move |_| rc.update_images());
+        }
+        {
+            let rc = self.clone();
+            self.new_combobox.connect_changed(move |_| rc.update_images());
+        }
+        {
+            let rc = self.clone();
+            self.mode_combobox.connect_changed(move |_| rc.update_view());
+        }
+        {
+            let rc = self.clone();
+            self.toggle_button.connect_clicked(move |_| {
+                rc.show_second.set(!rc.show_second.get());
+                rc.update_view();
+            });
+        }
+        {
+            let rc = self.clone();
+            self.scale.connect_value_changed(move |_| rc.update_view());
+        }
+        self.dialog.connect_response(|dialog, _| {
+            dialog.destroy();
+        });
+    }
+
+    fn pack_and_show(&self) {
+        use gtk::{ContainerExt, DialogExt, BoxExt, WidgetExt};
+
+        self.header_box.pack_start(&self.old_combobox, true, true, 0);
+        self.header_box.pack_start(&self.new_combobox, true, true, 0);
+        self.header_box.pack_start(&self.mode_combobox, false, false, 0);
+        self.header_box.pack_start(&self.toggle_button, false, false, 0);
+        self.header_box.pack_start(&self.scale, false, false, 0);
+        self.header_box.pack_start(&self.similarity_label, false, false, 0);
+
+        self.scrolled_window.add(&self.image);
+
+        let area = self.dialog.get_content_area();
+        area.pack_start(&self.header_box, false, false, 5);
+        area.pack_start(&self.scrolled_window, true, true, 5);
+
+        self.dialog.show_all();
+        self.update_view();
+    }
+}
+
+/// Decode the image in memory.
+fn decode(data: &[u8]) -> Option<Bitmap> {
+    let pixbuf = match pixbuf_from_data(data) {
+        Some(pixbuf) => pixbuf,
+        None => return None,
+    };
+    let (width, height) = (pixbuf.get_width() as usize, pixbuf.get_height() as usize);
+    let rowstride = pixbuf.get_rowstride() as usize;
+    let n_channels = pixbuf.get_n_channels() as usize;
+    let data = unsafe { pixbuf.get_pixels() };
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * rowstride + x * n_channels;
+            pixels.extend_from_slice(&data[i..i + 3]);
+        }
+    }
+    Some(Bitmap::new(width, height, pixels))
+}
+
+fn to_pixbuf(bitmap: Bitmap) -> Pixbuf {
+    let (width, height) = (bitmap.width() as i32, bitmap.height() as i32);
+    Pixbuf::new_from_vec(bitmap.pixels().to_vec(),
+                         Colorspace::Rgb,
+                         false,
+                         8,
+                         width,
+                         height,
+                         width * 3)
+}
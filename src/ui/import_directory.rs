@@ -0,0 +1,271 @@
+//! Dialog of bulk import of documents from a directory tree.
+//!
+//! TODO Write docs.
+
+use gtk;
+use chrono::{UTC, Duration, Datelike};
+
+use super::MainUI;
+use super::utils::{show_error_dialog, calendar_date, select_calendar_date};
+use sed_bad_core::user::User;
+use sed_bad_core::import::{self, ImportOptions, Report};
+
+#[derive(Clone)]
+pub struct ImportDirectory {
+    main_ui: MainUI,
+    dialog: gtk::Dialog,
+
+    directory_label: gtk::Label,
+    directory_button: gtk::FileChooserButton,
+    category_label: gtk::Label,
+    category_entry: gtk::Entry,
+    expired_label: gtk::Label,
+    expired_calendar: gtk::Calendar,
+    responsible_label: gtk::Label,
+    responsible_combobox: gtk::ComboBoxText,
+    per_folder_check_button: gtk::CheckButton,
+    report_scrolled_window: gtk::ScrolledWindow,
+    report_text_view: gtk::TextView,
+    progress_bar: gtk::ProgressBar,
+
+    button_box: gtk::ButtonBox,
+    dry_run_button: gtk::Button,
+    import_button: gtk::Button,
+    close_button: gtk::Button,
+}
+
+impl ImportDirectory {
+    pub fn new(main_ui: MainUI) -> Self {
+        let tmp = ImportDirectory {
+            main_ui: main_ui,
+            dialog: gtk::Dialog::new(),
+
+            directory_label: gtk::Label::new(Some("Directory:")),
+            directory_button: gtk::FileChooserButton::new("Select directory",
+                                                          gtk::FileChooserAction::SelectFolder),
+            category_label: gtk::Label::new(Some("Category of files in the directory:")),
+            category_entry: gtk::Entry::new(),
+            expired_label: gtk::Label::new(Some("Expired date:")),
+            expired_calendar: gtk::Calendar::new(),
+            responsible_label: gtk::Label::new(Some("Responsible user:")),
+            responsible_combobox: gtk::ComboBoxText::new(),
+            per_folder_check_button:
+                gtk::CheckButton::new_with_label("One document per folder instead of one per \
+                                                  file"),
+            report_scrolled_window: gtk::ScrolledWindow::new(None, None),
+            report_text_view: gtk::TextView::new(),
+            progress_bar: gtk::ProgressBar::new(),
+
+            button_box: gtk::ButtonBox::new(gtk::Orientation::Horizontal),
+            dry_run_button: gtk::Button::new_with_mnemonic("_Dry run"),
+            import_button: gtk::Button::new_with_mnemonic("_Import"),
+            close_button: gtk::Button::new_with_mnemonic("_Close"),
+        };
+        tmp.setup();
+        tmp.connect_signals();
+        tmp.pack_and_show();
+
+        tmp
+    }
+
+    fn setup(&self) {
+        use gtk::{EntryExt, WidgetExt};
+
+        for label in &[&self.directory_label,
+                       &self.category_label,
+                       &self.expired_label,
+                       &self.responsible_label] {
+            label.set_halign(gtk::Align::Start);
+        }
+        self.category_entry.set_text("Imported");
+        select_calendar_date(&self.expired_calendar, UTC::now() + Duration::days(30));
+        self.report_text_view.set_editable(false);
+        self.progress_bar.set_show_text(true);
+        self.button_box.set_layout(gtk::ButtonBoxStyle::Spread);
+        self.setup_responsible_combobox();
+        self.setup_dialog();
+    }
+
+    fn setup_responsible_combobox(&self) {
+        use gtk::ComboBoxExt;
+
+        match User::get_users(&self.main_ui.db.borrow()) {
+            Ok(users) => {
+                for (i, user) in users.iter().enumerate() {
+                    self.responsible_combobox.append_text(user.name());
+                    if user == &*self.main_ui.current_user.borrow() {
+                        self.responsible_combobox.set_active(i as i32);
+                    }
+                }
+            }
+            Err(e) => {
+                show_error_dialog(&self.dialog,
+                                  &format!("Error of reading users from database.\n{}", e));
+            }
+        }
+    }
+
+    fn setup_dialog(&self) {
+        use gtk::WindowExt;
+
+        self.dialog.set_title("Import directory");
+        self.dialog.set_transient_for(Some(&self.main_ui.window));
+        self.dialog.set_modal(true);
+
+        self.dialog.set_default_size(500, 700);
+        self.main_ui.register_dialog(&self.dialog);
+    }
+
+    /// Options of the import, `None` if some of them aren't selected.
+    fn options(&self) -> Option<ImportOptions> {
+        use gtk::{EntryExt, ToggleButtonExt};
+
+        let category = self.category_entry.get_text().unwrap_or_else(String::new);
+        if category.is_empty() {
+            show_error_dialog(&self.dialog, "Category is empty.");
+            return None;
+        }
+        let responsible = match self.responsible_combobox.get_active_text() {
+            Some(name) => {
+                match User::get_user(&self.main_ui.db.borrow(), &name) {
+                    Ok(user) => user,
+                    Err(e) => {
+                        show_error_dialog(&self.dialog,
+                                          &format!("Error of searching user in database.\n{}", e));
+                        return None;
+                    }
+                }
+            }
+            None => {
+                show_error_dialog(&self.dialog, "Responsible user not selected.");
+                return None;
+            }
+        };
+        let expired_date = calendar_date(&self.expired_calendar);
+        Some(ImportOptions {
+            author: self.main_ui.current_user.borrow().clone(),
+            responsible: responsible,
+            default_category: category,
+            date_expired: (expired_date.year() as u32, expired_date.month(), expired_date.day()),
+            per_folder: self.per_folder_check_button.get_active(),
+        })
+    }
+
+    /// Scan the selected directory and show the report.
+    fn scan(&self, options: &ImportOptions) -> Option<Report> {
+        use gtk::FileChooserExt;
+
+        let dir = match self.directory_button.get_filename() {
+            Some(dir) => dir,
+            None => {
+                show_error_dialog(&self.dialog, "Directory not selected.");
+                return None;
+            }
+        };
+        let report = match import::scan(&dir, options) {
+            Ok(report) => report,
+            Err(e) => {
+                show_error_dialog(&self.dialog,
+                                  &format!("Error of scanning {}.\n{}", dir.display(), e));
+                return None;
+            }
+        };
+        match report.to_text(&self.main_ui.db.borrow()) {
+            Ok(text) => {
+                if let Some(buffer) = self.report_text_view.get_buffer() {
+                    buffer.set_text(&text);
+                }
+            }
+            Err(e) => {
+                show_error_dialog(&self.dialog,
+                                  &format!("Error of reading categories from database.\n{}", e));
+                return None;
+            }
+        }
+        Some(report)
+    }
+
+    fn connect_signals(&self) {
+        use gtk::{ButtonExt, WidgetExt};
+
+        {
+            let rc = self.clone();
+            self.dry_run_button.connect_clicked(move |_| {
+                if let Some(options) = rc.options() {
+                    rc.scan(&options);
+                }
+            });
+        }
+        {
+            let rc = self.clone();
+            self.import_button.connect_clicked(move |_| {
+                let options = match rc.options() {
+                    Some(options) => options,
+                    None => return,
+                };
+                let report = match rc.scan(&options) {
+                    Some(report) => report,
+                    None => return,
+                };
+                rc.import_button.set_sensitive(false);
+                let res = import::import(&rc.main_ui.db.borrow(),
+                                         &report,
+                                         &options,
+                                         &mut |done, total| {
+                    rc.progress_bar.set_fraction(done as f64 / total as f64);
+                    rc.progress_bar.set_text(Some(&format!("{}/{}", done, total)));
+                    while gtk::events_pending() {
+                        gtk::main_iteration();
+                    }
+                });
+                rc.import_button.set_sensitive(true);
+                match res {
+                    Ok(ids) => {
+                        rc.progress_bar
+                            .set_text(Some(&format!("Done, {} document(s) created.", ids.len())));
+                        rc.main_ui.update_ui();
+                    }
+                    Err(e) => {
+                        rc.progress_bar.set_fraction(0.0);
+                        rc.progress_bar.set_text(None);
+                        show_error_dialog(&rc.dialog,
+                                          &format!("Error of importing, nothing was \
+                                                    imported.\n{}",
+                                                   e));
+                    }
+                }
+            });
+        }
+        {
+            let rc = self.clone();
+            self.close_button.connect_clicked(move |_| {
+                rc.dialog.destroy();
+            });
+        }
+    }
+
+    fn pack_and_show(&self) {
+        use gtk::{ContainerExt, DialogExt, BoxExt, WidgetExt};
+
+        self.button_box.add(&self.dry_run_button);
+        self.button_box.add(&self.import_button);
+        self.button_box.add(&self.close_button);
+        self.report_scrolled_window.add(&self.report_text_view);
+
+        let area = self.dialog.get_content_area();
+        area.pack_start(&self.directory_label, false, false, 5);
+        area.pack_start(&self.directory_button, false, false, 5);
+        area.pack_start(&self.category_label, false, false, 5);
+        area.pack_start(&self.category_entry, false, false, 5);
+        area.pack_start(&self.expired_label, false, false, 5);
+        area.pack_start(&self.expired_calendar, false, false, 5);
+        area.pack_start(&self.responsible_label, false, false, 5);
+        area.pack_start(&self.responsible_combobox, false, false, 5);
+        area.pack_start(&self.per_folder_check_button, false, false, 5);
+        area.pack_start(&self.report_scrolled_window, true, true, 5);
+        area.pack_start(&self.progress_bar, false, false, 5);
+        area.pack_start(&self.button_box, false, false, 5);
+
+        self.dialog.show_all();
+    }
+}
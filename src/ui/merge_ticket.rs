@@ -0,0 +1,156 @@
+//! Dialog of merging changes of the ticket with changes saved by another user meanwhile.
+//!
+//! Every field which differs is shown with both values, the user chooses one of them.
+
+use gtk;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use super::edit_ticket::EditTicket;
+use sed_bad_core::document::Document;
+use sed_bad_core::merge::{self, Field, changed_fields};
+
+#[derive(Clone)]
+pub struct MergeTicket {
+    edit_ticket: EditTicket,
+    // The ticket as it was read, as the user changed it and as it is saved now.
+    base: Rc<Document>,
+    mine: Rc<Document>,
+    theirs: Rc<Document>,
+    // Buttons of the values of the user.
+    mine_buttons: Rc<RefCell<Vec<(Field, gtk::RadioButton)>>>,
+
+    dialog: gtk::Dialog,
+    info_label: gtk::Label,
+    scrolled_window: gtk::ScrolledWindow,
+    grid: gtk::Grid,
+}
+
+impl MergeTicket {
+    pub fn new(edit_ticket: EditTicket, base: Document, mine: Document, theirs: Document) -> Self {
+        let tmp = MergeTicket {
+            edit_ticket: edit_ticket.clone(),
+            base: Rc::new(base),
+            mine: Rc::new(mine),
+            theirs: Rc::new(theirs),
+            mine_buttons: Rc::new(RefCell::new(Vec::new())),
+
+            dialog: gtk::Dialog::new_with_buttons(Some("Merge changes"),
+                                                  Some(&edit_ticket.dialog),
+                                                  gtk::DIALOG_MODAL,
+                                                  &[("_Save", gtk::ResponseType::Ok.into()),
+                                                    ("_Cancel",
+                                                     gtk::ResponseType::Cancel.into())]),
+            info_label: gtk::Label::new(Some("The ticket was changed by another user while you \
+                                              edited it. Choose the value of every field, \
+                                              fields changed by both of you are in bold.")),
+            scrolled_window: gtk::ScrolledWindow::new(None, None),
+            grid: gtk::Grid::new(),
+        };
+        tmp.setup();
+        tmp.connect_signals();
+        tmp.pack_and_show();
+
+        tmp
+    }
+
+    fn setup(&self) {
+        self.setup_info_label();
+        self.setup_grid();
+        self.setup_dialog();
+    }
+
+    fn setup_info_label(&self) {
+        use gtk::WidgetExt;
+
+        self.info_label.set_line_wrap(true);
+        self.info_label.set_halign(gtk::Align::Start);
+    }
+
+    /// Rows of differing fields. A field changed by one side only has the changed value
+    /// chosen.
+    fn setup_grid(&self) {
+        use gtk::{ToggleButtonExt, WidgetExt};
+
+        self.grid.set_row_spacing(5);
+        self.grid.set_column_spacing(10);
+        for (column, title) in ["Field", "Your change", "Their change"].iter().enumerate() {
+            let label = gtk::Label::new(None);
+            label.set_markup(&format!("<b>{}</b>", title));
+            label.set_halign(gtk::Align::Start);
+            self.grid.attach(&label, column as i32, 0, 1, 1);
+        }
+        for (i, field) in changed_fields(&self.mine, &self.theirs).into_iter().enumerate() {
+            let row = i as i32 + 1;
+            let mine_changed = field.differs(&self.base, &self.mine);
+            let theirs_changed = field.differs(&self.base, &self.theirs);
+
+            let label = gtk::Label::new(Some(field.title()));
+            if mine_changed && theirs_changed {
+                label.set_markup(&format!("<b>{}</b>", field.title()));
+            }
+            label.set_halign(gtk::Align::Start);
+            label.set_valign(gtk::Align::Start);
+            self.grid.attach(&label, 0, row, 1, 1);
+
+            let mine_button = gtk::RadioButton::new_with_label(&[], &field.value(&self.mine));
+            let theirs_button =
+                gtk::RadioButton::new_with_label_from_widget(Some(&mine_button),
+                                                             &field.value(&self.theirs));
+            mine_button.set_valign(gtk::Align::Start);
+            theirs_button.set_valign(gtk::Align::Start);
+            if mine_changed {
+                mine_button.set_active(true);
+            } else {
+                theirs_button.set_active(true);
+            }
+            self.grid.attach(&mine_button, 1, row, 1, 1);
+            self.grid.attach(&theirs_button, 2, row, 1, 1);
+            self.mine_buttons.borrow_mut().push((field, mine_button));
+        }
+    }
+
+    fn setup_dialog(&self) {
+        use gtk::WindowExt;
+
+        self.dialog.set_default_size(700, 400);
+        self.edit_ticket.main_ui.register_dialog(&self.dialog);
+    }
+
+    /// The chosen values of the user are applied to the saved ticket, which is saved again.
+    fn merged(&self) -> Document {
+        use gtk::ToggleButtonExt;
+
+        let fields: Vec<Field> = self.mine_buttons
+            .borrow()
+            .iter()
+            .filter(|&&(_, ref button)| button.get_active())
+            .map(|&(field, _)| field)
+            .collect();
+        merge::merge(&self.mine, &self.theirs, &fields)
+    }
+
+    fn connect_signals(&self) {
+        use gtk::{DialogExt, WidgetExt};
+
+        let rc = self.clone();
+        self.dialog.connect_response(move |dialog, response| {
+            dialog.destroy();
+            if response == gtk::ResponseType::Ok.into() {
+                rc.edit_ticket.save(&rc.theirs, rc.merged());
+            }
+        });
+    }
+
+    fn pack_and_show(&self) {
+        use gtk::{ContainerExt, DialogExt, BoxExt, WidgetExt};
+
+        self.scrolled_window.add(&self.grid);
+        let area = self.dialog.get_content_area();
+        area.pack_start(&self.info_label, false, false, 5);
+        area.pack_start(&self.scrolled_window, true, true, 5);
+
+        self.dialog.show_all();
+    }
+}
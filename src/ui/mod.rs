@@ -9,42 +9,116 @@ mod utils;
 mod new_ticket;
 mod edit_ticket;
 mod new_comment;
+mod comment_history;
+mod content_viewer;
+mod diff_viewer;
+mod image_diff_viewer;
+mod merge_ticket;
+mod new_saved_search;
+mod agenda;
+mod notifications;
+mod import_directory;
+mod unlock;
 
 use gtk;
-use chrono::{UTC, TimeZone};
+use gdk;
+use chrono::{Datelike, UTC, TimeZone};
 
 use std::rc::Rc;
-use std::cell::RefCell;
-
-use user::User;
-use db::Db;
-use document::Document;
-use metadata::Status;
+use std::cell::{Cell, Ref, RefCell};
+use std::cmp;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use sed_bad_core::user::User;
+use sed_bad_core::db::Db;
+use sed_bad_core::storage::{self, Storage, MemoryStorage};
+use sed_bad_core::remote::RemoteStorage;
+use sed_bad_core::document::DocumentSummary;
+use sed_bad_core::errors::DbResult;
+use sed_bad_core::metadata::Status;
+use sed_bad_core::query::{Query, Context, Term, Field, Op, Value};
+use sed_bad_core::calendar::Span;
+use sed_bad_core::ical;
+use sed_bad_core::archive;
+use sed_bad_core::backup;
+use sed_bad_core::saved_search::SavedSearch;
+use sed_bad_core::notification::Notification;
+use sed_bad_core::session::Session;
+use sed_bad_core::config::Config;
+
+/// Number of tickets loaded at once, next pages are loaded on scrolling to the end of the list.
+const TICKETS_PAGE_SIZE: i64 = 200;
+/// Address of the iCalendar feed of deadlines.
+const ICAL_ADDR: &'static str = "127.0.0.1:8642";
+/// Period of checking the storage for changes of other users.
+const CHANGES_CHECK_SECS: u32 = 3;
+/// Longest period of checking an unreachable server.
+const CHANGES_MAX_DELAY_SECS: u64 = 60;
+/// Period of checking the inactivity of the user.
+const IDLE_CHECK_SECS: u32 = 30;
 
 #[derive(Clone)]
 pub struct MainUI {
     // Menu.
     current_user: Rc<RefCell<User>>,
     db: Rc<RefCell<Db>>,
+    /// Documents, users and comments: the local database or a server.
+    storage: Rc<RefCell<Box<Storage>>>,
+    /// Address of the server, `None` with the local database.
+    server: Rc<RefCell<Option<String>>>,
+    /// Whether the storage is the SQLite database of `db`, features which other storages don't
+    /// provide work with it only.
+    sqlite: Rc<Cell<bool>>,
+    /// Last seen `Storage::data_version`.
+    data_version: Rc<Cell<i64>>,
+    /// Versions of the server polled in another thread, `None` with the local database.
+    server_changes: Rc<RefCell<Option<Receiver<DbResult<i64>>>>>,
+    /// Open dialogs which are refreshed on changes of other users.
+    edit_tickets: Rc<RefCell<Vec<edit_ticket::EditTicket>>>,
+    notifications: Rc<RefCell<Option<notifications::Notifications>>>,
+    /// Other open dialogs, they are hidden while the session is locked.
+    dialogs: Rc<RefCell<Vec<gtk::Dialog>>>,
+    /// Session of the current user, recorded in the local database.
+    session: Rc<RefCell<Option<Session>>>,
+    /// Time of the last key press or click, in seconds.
+    last_activity: Rc<Cell<i64>>,
+    /// Whether the session is locked after inactivity.
+    locked: Rc<Cell<bool>>,
 
     menu_bar: gtk::MenuBar,
 
     toolbar: gtk::Toolbar,
     create_ticket_t_button: gtk::ToolButton,
     create_directory_t_button: gtk::ToolButton,
+    delete_directory_t_button: gtk::ToolButton,
+    agenda_t_button: gtk::ToolButton,
+    notifications_t_button: gtk::ToolButton,
     open_ticket_t_button: gtk::ToolButton,
     edit_access_t_button: gtk::ToolButton,
 
     file_menu_item: gtk::MenuItem,
     file_menu: gtk::Menu,
     log_in_menu_item: gtk::MenuItem,
+    log_out_menu_item: gtk::MenuItem,
     print_user_menu_item: gtk::MenuItem,
+    export_ical_menu_item: gtk::MenuItem,
+    subscribe_ical_menu_item: gtk::MenuItem,
+    import_directory_menu_item: gtk::MenuItem,
+    ical_server_started: Rc<Cell<bool>>,
     quit_menu_item: gtk::MenuItem,
 
     root_menu_item: gtk::MenuItem,
     root_menu: gtk::Menu,
     user_administration_menu_item: gtk::MenuItem,
     category_administration_menu_item: gtk::MenuItem,
+    export_archive_menu_item: gtk::MenuItem,
+    import_archive_menu_item: gtk::MenuItem,
+    backup_menu_item: gtk::MenuItem,
+    restore_backup_menu_item: gtk::MenuItem,
 
     window: gtk::Window,
     v_box: gtk::Box,
@@ -52,11 +126,20 @@ pub struct MainUI {
 
     dir_cal_box: gtk::Box,
     directories_list: gtk::ListBox,
+    saved_searches: Rc<RefCell<Vec<(SavedSearch, gtk::Label)>>>,
+    updating_directories: Rc<Cell<bool>>,
     calendar: gtk::Calendar,
+    calendar_span_combobox: gtk::ComboBoxText,
 
+    tickets_v_box: gtk::Box,
+    filter_entry: gtk::Entry,
+    filter_error_label: gtk::Label,
     tickets_scrolled_window: gtk::ScrolledWindow,
     tickets_tree_view: gtk::TreeView,
     tickets_list_store: gtk::ListStore,
+    tickets_query: Rc<RefCell<Option<Query>>>,
+    // Number of loaded tickets of the current view, -1 if all of them are loaded.
+    tickets_loaded: Rc<Cell<i64>>,
 
     id_column: gtk::TreeViewColumn,
     completed_column: gtk::TreeViewColumn,
@@ -81,9 +164,21 @@ impl MainUI {
 
         let tmp = MainUI {
             current_user: Rc::new(RefCell::new(User::default())),
-            db: Rc::new(RefCell::new(Db::new()
-                .and_then(|d| d.init_root())
+            db: Rc::new(RefCell::new(Config::load()
+                .and_then(|config| MainUI::open_db(&config))
                 .expect("Error of creating database."))),
+            // The storage is opened by the login.
+            storage: Rc::new(RefCell::new(Box::new(MemoryStorage::new()))),
+            server: Rc::new(RefCell::new(None)),
+            sqlite: Rc::new(Cell::new(false)),
+            data_version: Rc::new(Cell::new(0)),
+            server_changes: Rc::new(RefCell::new(None)),
+            edit_tickets: Rc::new(RefCell::new(Vec::new())),
+            notifications: Rc::new(RefCell::new(None)),
+            dialogs: Rc::new(RefCell::new(Vec::new())),
+            session: Rc::new(RefCell::new(None)),
+            last_activity: Rc::new(Cell::new(UTC::now().timestamp())),
+            locked: Rc::new(Cell::new(false)),
 
             menu_bar: gtk::MenuBar::new(),
 
@@ -100,6 +195,24 @@ impl MainUI {
                                                     .into())
                 ),
                 Some("Create directory")),
+            delete_directory_t_button: gtk::ToolButton::new(
+                Some(
+                    &gtk::Image::new_from_icon_name("edit-delete", gtk::IconSize::LargeToolbar
+                                                    .into())
+                ),
+                Some("Delete directory")),
+            agenda_t_button: gtk::ToolButton::new(
+                Some(
+                    &gtk::Image::new_from_icon_name("x-office-calendar",
+                                                    gtk::IconSize::LargeToolbar.into())
+                ),
+                Some("Agenda")),
+            notifications_t_button: gtk::ToolButton::new(
+                Some(
+                    &gtk::Image::new_from_icon_name("mail-unread", gtk::IconSize::LargeToolbar
+                                                    .into())
+                ),
+                Some("Notifications")),
             open_ticket_t_button: gtk::ToolButton::new(
                 Some(
                     &gtk::Image::new_from_icon_name("document-open", gtk::IconSize::LargeToolbar
@@ -116,7 +229,14 @@ impl MainUI {
             file_menu_item: gtk::MenuItem::new_with_mnemonic("_File"),
             file_menu: gtk::Menu::new(),
             log_in_menu_item: gtk::MenuItem::new_with_mnemonic("_Log in"),
+            log_out_menu_item: gtk::MenuItem::new_with_mnemonic("Log _out"),
             print_user_menu_item: gtk::MenuItem::new_with_mnemonic("_Print User"),
+            export_ical_menu_item: gtk::MenuItem::new_with_mnemonic("_Export deadlines..."),
+            subscribe_ical_menu_item: gtk::MenuItem::new_with_mnemonic("_Subscribe to \
+                                                                        deadlines..."),
+            import_directory_menu_item: gtk::MenuItem::new_with_mnemonic("_Import \
+                                                                          directory..."),
+            ical_server_started: Rc::new(Cell::new(false)),
             quit_menu_item: gtk::MenuItem::new_with_mnemonic("_Quit"),
 
             root_menu_item: gtk::MenuItem::new_with_mnemonic("_Root configuration"),
@@ -124,6 +244,10 @@ impl MainUI {
             user_administration_menu_item: gtk::MenuItem::new_with_mnemonic("_User administration"),
             category_administration_menu_item: gtk::MenuItem::new_with_mnemonic("_Category \
                                                                                  administration"),
+            export_archive_menu_item: gtk::MenuItem::new_with_mnemonic("_Export archive..."),
+            import_archive_menu_item: gtk::MenuItem::new_with_mnemonic("_Import archive..."),
+            backup_menu_item: gtk::MenuItem::new_with_mnemonic("_Back up now"),
+            restore_backup_menu_item: gtk::MenuItem::new_with_mnemonic("_Restore backup..."),
 
             window: gtk::Window::new(gtk::WindowType::Toplevel),
             v_box: gtk::Box::new(gtk::Orientation::Vertical, 0),
@@ -131,8 +255,14 @@ impl MainUI {
 
             dir_cal_box: gtk::Box::new(gtk::Orientation::Vertical, 0),
             directories_list: gtk::ListBox::new(),
+            saved_searches: Rc::new(RefCell::new(Vec::new())),
+            updating_directories: Rc::new(Cell::new(false)),
             calendar: gtk::Calendar::new(),
+            calendar_span_combobox: gtk::ComboBoxText::new(),
 
+            tickets_v_box: gtk::Box::new(gtk::Orientation::Vertical, 0),
+            filter_entry: gtk::Entry::new(),
+            filter_error_label: gtk::Label::new(None),
             tickets_scrolled_window: gtk::ScrolledWindow::new(None, None),
             tickets_tree_view: gtk::TreeView::new(),
             tickets_list_store: gtk::ListStore::new(&[gtk::Type::I64, // Id.
@@ -142,6 +272,8 @@ impl MainUI {
                                                       gtk::Type::String, // Create time.
                                                       gtk::Type::String, // Modification time.
                                                       gtk::Type::String]), // Responsible.
+            tickets_query: Rc::new(RefCell::new(None)),
+            tickets_loaded: Rc::new(Cell::new(-1)),
 
             id_column: gtk::TreeViewColumn::new(),
             completed_column: gtk::TreeViewColumn::new(),
@@ -163,6 +295,9 @@ impl MainUI {
         tmp.connect_signals();
         tmp.pack_and_show();
         tmp.update_ui();
+        tmp.schedule_backups();
+        tmp.watch_changes();
+        tmp.lock_when_idle();
 
         tmp
     }
@@ -171,9 +306,74 @@ impl MainUI {
         gtk::main();
     }
 
+    /// Storage of the configuration, the SQLite file of the user by default.
+    fn local_storage() -> DbResult<Box<Storage>> {
+        storage::open(&Config::load()?)
+    }
+
+    /// SQLite database of the configuration for sessions, saved searches and features only
+    /// SQLite provides, the file of the user if the storage is PostgreSQL.
+    fn open_db(config: &Config) -> DbResult<Db> {
+        let db = match config.database() {
+            Some(path) if !config.is_postgres() => Db::open(path)?,
+            _ => Db::new()?,
+        };
+        db.init_root()?.init_saved_searches()
+    }
+
+    /// Local database for features the server and PostgreSQL don't provide, `None` when the
+    /// storage is another one.
+    fn local_db(&self) -> Option<Ref<Db>> {
+        if self.sqlite.get() {
+            Some(self.db.borrow())
+        } else {
+            None
+        }
+    }
+
+    /// Work with the storage from now on, `server` is its address for a remote one.
+    fn set_storage(&self, storage: Box<Storage>, server: Option<String>) {
+        use gtk::WindowExt;
+
+        match server {
+            Some(ref server) => self.window.set_title(&format!("СЭД-БЭД — {}", server)),
+            None => self.window.set_title("СЭД-БЭД"),
+        }
+        let postgres = Config::load().map(|config| config.is_postgres()).unwrap_or(false);
+        self.sqlite.set(server.is_none() && !postgres);
+        *self.storage.borrow_mut() = storage;
+        *self.server.borrow_mut() = server;
+        // The thread polling the previous server stops.
+        *self.server_changes.borrow_mut() = None;
+    }
+
+    /// Poll the server for changes in another thread, so a slow server doesn't stop the
+    /// window. After errors the server is polled less often. The thread ends after the storage
+    /// is changed or the user logs out.
+    fn watch_server(&self, storage: RemoteStorage) {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut delay = CHANGES_CHECK_SECS as u64;
+            loop {
+                let res = storage.data_version();
+                delay = if res.is_ok() {
+                    CHANGES_CHECK_SECS as u64
+                } else {
+                    cmp::min(delay * 2, CHANGES_MAX_DELAY_SECS)
+                };
+                if sender.send(res).is_err() {
+                    return;
+                }
+                thread::sleep(Duration::from_secs(delay));
+            }
+        });
+        *self.server_changes.borrow_mut() = Some(receiver);
+    }
+
     fn setup(&self) {
         self.setup_columns();
-        self.setup_directories_list();
+        self.setup_filter();
+        self.setup_calendar_span_combobox();
         self.setup_tickets_list_store();
         self.setup_window();
     }
@@ -252,123 +452,92 @@ impl MainUI {
         self.tickets_tree_view.append_column(&self.responsible_column);
     }
 
+    fn setup_filter(&self) {
+        use gtk::{EntryExt, WidgetExt};
+
+        self.filter_entry.set_placeholder_text(Some("Filter: status:complete author:me \
+                                                     category:\"Contracts\" due:<2016-12-31"));
+        self.filter_error_label.set_halign(gtk::Align::Start);
+        self.filter_error_label.set_no_show_all(true);
+    }
+
+    /// Parse the query of the filter bar, on error point at the offending token.
+    fn filter_query(&self) -> Option<Query> {
+        use gtk::{EntryExt, EditableExt, WidgetExt};
+
+        let text = self.filter_entry.get_text().unwrap_or_default();
+        match Query::parse(&text) {
+            Ok(query) => {
+                self.filter_error_label.hide();
+                Some(query)
+            }
+            Err(e) => {
+                self.filter_error_label.set_text(&format!("Error of filter: {}.", e));
+                self.filter_error_label.show();
+                self.filter_entry.select_region(e.start as i32, e.end as i32);
+                None
+            }
+        }
+    }
+
     fn setup_tickets_list_store(&self) {
         self.tickets_tree_view.set_model(Some(&self.tickets_list_store));
-        match Document::get_docs(&self.db.borrow()) {
-            Ok(docs) => {
-                for doc in docs {
-                    if let Some(selected_row) = self.directories_list.get_selected_row() {
-                        match selected_row.get_index() {
-                            0 => {
-                                if self.current_user.borrow().name() ==
-                                   doc.metadata().author().name() {
-                                    self.tickets_list_store
-                                        .insert_with_values(None,
-                                                            &[0, 1, 2, 3, 4, 5, 6],
-                                                            &[&doc.id(),
-                                                              &(doc.metadata()
-                                                                  .status() ==
-                                                                Status::Complete),
-                                                              &doc.name(),
-                                                              &doc.metadata()
-                                                                  .author()
-                                                                  .name(),
-                                                              &doc.metadata()
-                                                                  .c_time()
-                                                                  .to_rfc2822(),
-                                                              &doc.metadata()
-                                                                  .m_time()
-                                                                  .to_rfc2822(),
-                                                              &doc.responsible()
-                                                                  .name()]);
-                                }
-                            }
-                            1 => {
-                                if self.current_user.borrow().name() == doc.responsible().name() {
-                                    self.tickets_list_store
-                                        .insert_with_values(None,
-                                                            &[0, 1, 2, 3, 4, 5, 6],
-                                                            &[&doc.id(),
-                                                              &(doc.metadata()
-                                                                  .status() ==
-                                                                Status::Complete),
-                                                              &doc.name(),
-                                                              &doc.metadata()
-                                                                  .author()
-                                                                  .name(),
-                                                              &doc.metadata()
-                                                                  .c_time()
-                                                                  .to_rfc2822(),
-                                                              &doc.metadata()
-                                                                  .m_time()
-                                                                  .to_rfc2822(),
-                                                              &doc.responsible()
-                                                                  .name()]);
-                                }
-                            }
-                            2 => {
-                                if self.current_user.borrow().name() == doc.responsible().name() ||
-                                   self.current_user.borrow().name() ==
-                                   doc.metadata().author().name() ||
-                                   self.current_user.borrow().is_root() {
-                                    self.tickets_list_store
-                                        .insert_with_values(None,
-                                                            &[0, 1, 2, 3, 4, 5, 6],
-                                                            &[&doc.id(),
-                                                              &(doc.metadata()
-                                                                  .status() ==
-                                                                Status::Complete),
-                                                              &doc.name(),
-                                                              &doc.metadata()
-                                                                  .author()
-                                                                  .name(),
-                                                              &doc.metadata()
-                                                                  .c_time()
-                                                                  .to_rfc2822(),
-                                                              &doc.metadata()
-                                                                  .m_time()
-                                                                  .to_rfc2822(),
-                                                              &doc.responsible()
-                                                                  .name()]);
-                                }
-                            }
-                            3 => {
-                                let cal_date = self.calendar.get_date();
-                                let this_date = UTC.ymd(cal_date.0 as i32, cal_date.1, cal_date.2)
-                                    .and_hms(0, 0, 0) ==
-                                                doc.metadata().date_expired();
-                                if (self.current_user.borrow().name() == doc.responsible().name() ||
-                                    self.current_user.borrow().name() ==
-                                    doc.metadata().author().name() ||
-                                    self.current_user.borrow().is_root()) &&
-                                   this_date {
-                                    self.tickets_list_store
-                                        .insert_with_values(None,
-                                                            &[0, 1, 2, 3, 4, 5, 6],
-                                                            &[&doc.id(),
-                                                              &(doc.metadata()
-                                                                  .status() ==
-                                                                Status::Complete),
-                                                              &doc.name(),
-                                                              &doc.metadata()
-                                                                  .author()
-                                                                  .name(),
-                                                              &doc.metadata()
-                                                                  .c_time()
-                                                                  .to_rfc2822(),
-                                                              &doc.metadata()
-                                                                  .m_time()
-                                                                  .to_rfc2822(),
-                                                              &doc.responsible()
-                                                                  .name()]);
-                                }
-                            }
-                            _ => {}
-                        }
+        let mut query = match self.selected_saved_search() {
+            Some(search) => {
+                match search.to_query() {
+                    Ok(query) => query,
+                    Err(e) => {
+                        utils::show_error_dialog(&self.window,
+                                                 &format!("Error of directory `{}`.\n{}",
+                                                          search.name(),
+                                                          e));
+                        return;
                     }
                 }
             }
+            None => return,
+        };
+        match self.filter_query() {
+            Some(filter) => query.extend(filter),
+            None => return,
+        }
+        *self.tickets_query.borrow_mut() = Some(query);
+        self.tickets_loaded.set(0);
+        self.load_tickets_page();
+    }
+
+    /// Append the next page of tickets of the current view, the rest is loaded on scrolling.
+    fn load_tickets_page(&self) {
+        let loaded = self.tickets_loaded.get();
+        if loaded < 0 {
+            return;
+        }
+        // Inserting rows may scroll the list, the page must not be loaded twice.
+        self.tickets_loaded.set(-1);
+        let tickets_query = self.tickets_query.borrow();
+        let query = match *tickets_query {
+            Some(ref query) => query,
+            None => return,
+        };
+        let current_user = self.current_user.borrow();
+        let res = self.storage
+            .borrow()
+            .list_documents(query, &self.query_context(&current_user), TICKETS_PAGE_SIZE, loaded);
+        match res {
+            Ok(summaries) => {
+                let count = summaries.len() as i64;
+                for summary in summaries {
+                    self.set_ticket_row(None, &summary);
+                }
+                // All tickets are loaded.
+                if count < TICKETS_PAGE_SIZE {
+                    self.tickets_loaded.set(-1);
+                } else {
+                    self.tickets_loaded.set(loaded + count);
+                }
+            }
             Err(e) => {
+                self.tickets_loaded.set(-1);
                 utils::show_error_dialog(&self.window,
                                          &format!("Error of reading from database (`docs` \
                                                    table).\n{}",
@@ -377,12 +546,112 @@ impl MainUI {
         }
     }
 
-    fn setup_directories_list(&self) {
-        self.directories_list.insert(&gtk::Label::new(Some("Inbox")), 0);
-        self.directories_list.insert(&gtk::Label::new(Some("Outbox")), 1);
-        self.directories_list.insert(&gtk::Label::new(Some("All")), 2);
-        self.directories_list.insert(&gtk::Label::new(Some("Calendar")), 3);
-        self.directories_list.select_row(self.directories_list.get_row_at_index(2).as_ref());
+    /// Fill the row of the ticket, a new row is appended without `iter`.
+    fn set_ticket_row(&self, iter: Option<&gtk::TreeIter>, summary: &DocumentSummary) {
+        let columns = [0, 1, 2, 3, 4, 5, 6];
+        let c_time = summary.c_time().to_rfc2822();
+        let m_time = summary.m_time().to_rfc2822();
+        let values: [&gtk::ToValue; 7] = [&summary.id(),
+                                          &(summary.status() == Status::Complete),
+                                          &summary.name(),
+                                          &summary.author(),
+                                          &c_time,
+                                          &m_time,
+                                          &summary.responsible()];
+        match iter {
+            Some(iter) => self.tickets_list_store.set(iter, &columns, &values),
+            None => {
+                self.tickets_list_store.insert_with_values(None, &columns, &values);
+            }
+        }
+    }
+
+    /// Bring the loaded tickets up to date in place, so the selection and the scrolling are
+    /// kept: changed rows are updated, rows which don't match the view any more are removed
+    /// and new ones are appended.
+    fn refresh_tickets_tree_view(&self) {
+        use gtk::TreeModelExt;
+
+        let loaded = self.tickets_loaded.get();
+        let limit = if loaded < 0 {
+            -1
+        } else {
+            cmp::max(loaded, TICKETS_PAGE_SIZE)
+        };
+        let tickets_query = self.tickets_query.borrow();
+        let query = match *tickets_query {
+            Some(ref query) => query,
+            None => return,
+        };
+        let current_user = self.current_user.borrow();
+        let res = self.storage
+            .borrow()
+            .list_documents(query, &self.query_context(&current_user), limit, 0);
+        let mut summaries = match res {
+            Ok(summaries) => summaries,
+            // The next check tries again, e.g. when the server is back.
+            Err(_) => return,
+        };
+        let count = summaries.len() as i64;
+
+        let mut rows = Vec::new();
+        if let Some(iter) = self.tickets_list_store.get_iter_first() {
+            loop {
+                let id = self.tickets_list_store.get_value(&iter, 0).get::<i64>().unwrap_or(0);
+                rows.push((id, iter.clone()));
+                if !self.tickets_list_store.iter_next(&iter) {
+                    break;
+                }
+            }
+        }
+        for (id, iter) in rows {
+            match summaries.iter().position(|summary| summary.id() == id) {
+                Some(i) => {
+                    let summary = summaries.remove(i);
+                    self.set_ticket_row(Some(&iter), &summary);
+                }
+                None => {
+                    self.tickets_list_store.remove(&iter);
+                }
+            }
+        }
+        for summary in summaries {
+            self.set_ticket_row(None, &summary);
+        }
+        if loaded >= 0 {
+            self.tickets_loaded.set(if count < limit { -1 } else { count });
+        }
+    }
+
+    fn setup_calendar_span_combobox(&self) {
+        use gtk::ComboBoxExt;
+
+        self.calendar_span_combobox.append_text("Day");
+        self.calendar_span_combobox.append_text("Week");
+        self.calendar_span_combobox.append_text("Month");
+        self.calendar_span_combobox.set_active(Span::Day.get_num() as i32);
+    }
+
+    fn calendar_span(&self) -> Span {
+        use gtk::ComboBoxExt;
+
+        Span::from_num(self.calendar_span_combobox.get_active() as i64)
+    }
+
+    /// Context of queries for the current user and the period selected in the calendar.
+    fn query_context<'a>(&self, current_user: &'a User) -> Context<'a> {
+        let mut context = Context::new(current_user);
+        context.selected_range = self.calendar_span().bounds(utils::calendar_date(&self.calendar));
+        context
+    }
+
+    fn selected_saved_search(&self) -> Option<SavedSearch> {
+        self.directories_list.get_selected_row().and_then(|row| {
+            self.saved_searches
+                .borrow()
+                .get(row.get_index() as usize)
+                .map(|&(ref search, _)| search.clone())
+        })
     }
 
     fn setup_window(&self) {
@@ -396,18 +665,66 @@ impl MainUI {
         self.connect_signals_calendar();
         self.connect_signals_quit_menu();
         self.connect_signals_log_in_menu();
+        self.connect_signals_log_out_menu();
         self.connect_signals_print_user_menu();
+        self.connect_signals_export_ical_menu();
+        self.connect_signals_subscribe_ical_menu();
+        self.connect_signals_import_directory_menu();
         self.connect_signals_user_administration_menu();
+        self.connect_signals_export_archive_menu();
+        self.connect_signals_import_archive_menu();
+        self.connect_signals_backup_menu();
+        self.connect_signals_restore_backup_menu();
         self.connect_signals_create_ticket_t_button();
+        self.connect_signals_create_directory_t_button();
+        self.connect_signals_delete_directory_t_button();
+        self.connect_signals_agenda_t_button();
+        self.connect_signals_notifications_t_button();
         self.connect_signals_directories_list();
+        self.connect_signals_filter_entry();
         self.connect_signals_tickets_tree_view();
+        self.connect_signals_tickets_scrolled_window();
         self.connect_signals_window();
     }
 
     fn connect_signals_calendar(&self) {
+        use gtk::ComboBoxExt;
+
+        {
+            let rc = self.clone();
+            self.calendar.connect_day_selected(move |_| {
+                rc.update_ui();
+            });
+        }
+        {
+            let rc = self.clone();
+            self.calendar.connect_month_changed(move |_| {
+                rc.update_calendar_marks();
+            });
+        }
+        {
+            let rc = self.clone();
+            self.calendar_span_combobox.connect_changed(move |_| {
+                rc.update_ui();
+            });
+        }
+    }
+
+    fn connect_signals_agenda_t_button(&self) {
+        use gtk::ToolButtonExt;
+
         let rc = self.clone();
-        self.calendar.connect_day_selected(move |_| {
-            rc.update_ui();
+        self.agenda_t_button.connect_clicked(move |_| {
+            agenda::Agenda::new(rc.clone());
+        });
+    }
+
+    fn connect_signals_notifications_t_button(&self) {
+        use gtk::ToolButtonExt;
+
+        let rc = self.clone();
+        self.notifications_t_button.connect_clicked(move |_| {
+            notifications::Notifications::new(rc.clone());
         });
     }
 
@@ -420,6 +737,15 @@ impl MainUI {
         });
     }
 
+    fn connect_signals_log_out_menu(&self) {
+        use gtk::MenuItemExt;
+
+        let rc = self.clone();
+        self.log_out_menu_item.connect_activate(move |_| {
+            rc.log_out();
+        });
+    }
+
     fn connect_signals_print_user_menu(&self) {
         use gtk::MenuItemExt;
 
@@ -429,10 +755,105 @@ impl MainUI {
         });
     }
 
+    fn connect_signals_export_ical_menu(&self) {
+        use gtk::{MenuItemExt, DialogExt, FileChooserExt, WidgetExt, ToggleButtonExt, Cast};
+
+        let rc = self.clone();
+        self.export_ical_menu_item.connect_activate(move |_| {
+            let dialog = gtk::FileChooserDialog::new(Some("Export deadlines"),
+                                                     Some(&rc.window),
+                                                     gtk::FileChooserAction::Save);
+            dialog.add_buttons(&[("Cancel", gtk::ResponseType::Cancel.into()),
+                                 ("Export", gtk::ResponseType::Accept.into())]);
+            dialog.set_current_name("deadlines.ics");
+            dialog.set_do_overwrite_confirmation(true);
+            let events_check_button = gtk::CheckButton::new_with_label("Export as events \
+                                                                         (VEVENT) instead of \
+                                                                         tasks (VTODO)");
+            dialog.set_extra_widget(&events_check_button);
+
+            rc.register_dialog(&dialog.clone().upcast::<gtk::Dialog>());
+            if dialog.run() == gtk::ResponseType::Accept.into() {
+                if let Some(path) = dialog.get_filename() {
+                    let component = if events_check_button.get_active() {
+                        ical::Component::Event
+                    } else {
+                        ical::Component::Todo
+                    };
+                    let res = ical::user_calendar(&rc.db.borrow(),
+                                                  &rc.current_user.borrow(),
+                                                  component)
+                        .map_err(|e| e.to_string())
+                        .and_then(|calendar| {
+                            File::create(&path)
+                                .and_then(|mut file| file.write_all(calendar.as_bytes()))
+                                .map_err(|e| e.to_string())
+                        });
+                    if let Err(e) = res {
+                        utils::show_error_dialog(&rc.window,
+                                                 &format!("Error of exporting deadlines.\n{}",
+                                                          e));
+                    }
+                }
+            }
+            dialog.destroy();
+        });
+    }
+
+    fn connect_signals_subscribe_ical_menu(&self) {
+        use gtk::MenuItemExt;
+
+        let rc = self.clone();
+        self.subscribe_ical_menu_item.connect_activate(move |_| {
+            if !rc.ical_server_started.get() {
+                let res = ical::serve(ICAL_ADDR, |e| {
+                    let _ = writeln!(io::stderr(), "Error of serving calendar.\n{}", e);
+                });
+                if let Err(e) = res {
+                    utils::show_error_dialog(&rc.window,
+                                             &format!("Error of starting calendar feed on \
+                                                       {}.\n{}",
+                                                      ICAL_ADDR,
+                                                      e));
+                    return;
+                }
+                rc.ical_server_started.set(true);
+            }
+            let res = ical::feed_token(&rc.db.borrow(), &rc.current_user.borrow());
+            match res {
+                Ok(token) => {
+                    utils::show_info_dialog(&rc.window,
+                                            &format!("Subscribe to the calendar of your \
+                                                      deadlines in your calendar application, \
+                                                      keep the address secret:\n\
+                                                      http://{}/{}.ics",
+                                                     ICAL_ADDR,
+                                                     token))
+                }
+                Err(e) => {
+                    utils::show_error_dialog(&rc.window,
+                                             &format!("Error of creating the calendar feed.\n{}",
+                                                      e))
+                }
+            }
+        });
+    }
+
+    fn connect_signals_import_directory_menu(&self) {
+        use gtk::MenuItemExt;
+
+        let rc = self.clone();
+        self.import_directory_menu_item.connect_activate(move |_| {
+            import_directory::ImportDirectory::new(rc.clone());
+        });
+    }
+
     fn connect_signals_quit_menu(&self) {
         use gtk::MenuItemExt;
 
-        self.quit_menu_item.connect_activate(|_| {
+        let rc = self.clone();
+        self.quit_menu_item.connect_activate(move |_| {
+            rc.end_session();
             gtk::main_quit();
         });
     }
@@ -446,6 +867,137 @@ impl MainUI {
         });
     }
 
+    fn connect_signals_export_archive_menu(&self) {
+        use gtk::{MenuItemExt, DialogExt, FileChooserExt, WidgetExt, Cast};
+
+        let rc = self.clone();
+        self.export_archive_menu_item.connect_activate(move |_| {
+            let dialog = gtk::FileChooserDialog::new(Some("Export archive"),
+                                                     Some(&rc.window),
+                                                     gtk::FileChooserAction::Save);
+            dialog.add_buttons(&[("Cancel", gtk::ResponseType::Cancel.into()),
+                                 ("Export", gtk::ResponseType::Accept.into())]);
+            dialog.set_current_name("sed_bad.tar");
+            dialog.set_do_overwrite_confirmation(true);
+
+            rc.register_dialog(&dialog.clone().upcast::<gtk::Dialog>());
+            if dialog.run() == gtk::ResponseType::Accept.into() {
+                if let Some(path) = dialog.get_filename() {
+                    let res = File::create(&path)
+                        .map_err(From::from)
+                        .and_then(|file| archive::export(&rc.db.borrow(), file));
+                    if let Err(e) = res {
+                        utils::show_error_dialog(&rc.window,
+                                                 &format!("Error of exporting archive.\n{}", e));
+                    }
+                }
+            }
+            dialog.destroy();
+        });
+    }
+
+    fn connect_signals_import_archive_menu(&self) {
+        use gtk::{MenuItemExt, DialogExt, FileChooserExt, WidgetExt, Cast};
+
+        let rc = self.clone();
+        self.import_archive_menu_item.connect_activate(move |_| {
+            let dialog = gtk::FileChooserDialog::new(Some("Import archive"),
+                                                     Some(&rc.window),
+                                                     gtk::FileChooserAction::Open);
+            dialog.add_buttons(&[("Cancel", gtk::ResponseType::Cancel.into()),
+                                 ("Import", gtk::ResponseType::Accept.into())]);
+
+            rc.register_dialog(&dialog.clone().upcast::<gtk::Dialog>());
+            if dialog.run() == gtk::ResponseType::Accept.into() {
+                if let Some(path) = dialog.get_filename() {
+                    let res = File::open(&path)
+                        .map_err(From::from)
+                        .and_then(|file| archive::import(&rc.db.borrow(), file));
+                    match res {
+                        Ok(summary) => {
+                            utils::show_info_dialog(&rc.window,
+                                                    &format!("The archive is imported.\n{}",
+                                                             summary.to_text()));
+                            rc.update_ui();
+                        }
+                        Err(e) => {
+                            utils::show_error_dialog(&rc.window,
+                                                     &format!("Error of importing archive, \
+                                                               nothing was imported.\n{}",
+                                                              e));
+                        }
+                    }
+                }
+            }
+            dialog.destroy();
+        });
+    }
+
+    fn connect_signals_backup_menu(&self) {
+        use gtk::MenuItemExt;
+
+        let rc = self.clone();
+        self.backup_menu_item.connect_activate(move |_| {
+            let res = backup::default_dir()
+                .and_then(|dir| backup::backup(&rc.db.borrow(), &dir, backup::BACKUPS_KEPT));
+            match res {
+                Ok(path) => {
+                    utils::show_info_dialog(&rc.window,
+                                            &format!("The backup is saved to {}.",
+                                                     path.display()))
+                }
+                Err(e) => {
+                    utils::show_error_dialog(&rc.window, &format!("Error of backing up.\n{}", e))
+                }
+            }
+        });
+    }
+
+    fn connect_signals_restore_backup_menu(&self) {
+        use gtk::{MenuItemExt, DialogExt, FileChooserExt, WidgetExt, Cast};
+
+        let rc = self.clone();
+        self.restore_backup_menu_item.connect_activate(move |_| {
+            let dialog = gtk::FileChooserDialog::new(Some("Restore backup"),
+                                                     Some(&rc.window),
+                                                     gtk::FileChooserAction::Open);
+            dialog.add_buttons(&[("Cancel", gtk::ResponseType::Cancel.into()),
+                                 ("Restore", gtk::ResponseType::Accept.into())]);
+            if let Ok(dir) = backup::default_dir() {
+                dialog.set_current_folder(&dir);
+            }
+
+            rc.register_dialog(&dialog.clone().upcast::<gtk::Dialog>());
+            let path = if dialog.run() == gtk::ResponseType::Accept.into() {
+                dialog.get_filename()
+            } else {
+                None
+            };
+            dialog.destroy();
+            let path = match path {
+                Some(path) => path,
+                None => return,
+            };
+            if !utils::ask_confirmation(&rc.window,
+                                        "All changes made after the backup will be lost. \
+                                         Restore the backup?") {
+                return;
+            }
+            let res = backup::restore(&mut rc.db.borrow_mut(), &path);
+            match res {
+                Ok(()) => {
+                    // Users of the backup may differ, so everyone logs in again.
+                    rc.log_out();
+                    utils::show_info_dialog(&rc.window, "The backup is restored, log in again.");
+                }
+                Err(e) => {
+                    utils::show_error_dialog(&rc.window,
+                                             &format!("Error of restoring the backup.\n{}", e))
+                }
+            }
+        });
+    }
+
     fn connect_signals_create_ticket_t_button(&self) {
         use gtk::ToolButtonExt;
 
@@ -455,13 +1007,74 @@ impl MainUI {
         });
     }
 
+    fn connect_signals_create_directory_t_button(&self) {
+        use gtk::{ToolButtonExt, EntryExt};
+
+        let rc = self.clone();
+        self.create_directory_t_button.connect_clicked(move |_| {
+            let query = rc.filter_entry.get_text().unwrap_or_default();
+            new_saved_search::NewSavedSearch::new(rc.clone(), &query);
+        });
+    }
+
+    fn connect_signals_delete_directory_t_button(&self) {
+        use gtk::ToolButtonExt;
+
+        let rc = self.clone();
+        self.delete_directory_t_button.connect_clicked(move |_| {
+            let search = match rc.selected_saved_search() {
+                Some(search) => search,
+                None => {
+                    utils::show_error_dialog(&rc.window, "No one directory is selecting.");
+                    return;
+                }
+            };
+            if search.is_predefined() {
+                utils::show_error_dialog(&rc.window, "Predefined directory unchanged!");
+                return;
+            }
+            if !search.is_deletion_grant(&rc.current_user.borrow()) {
+                utils::show_error_dialog(&rc.window,
+                                         "Only the owner can delete the directory.");
+                return;
+            }
+            if let Err(e) = SavedSearch::delete_by_id(&rc.db.borrow(), search.id()) {
+                utils::show_error_dialog(&rc.window,
+                                         &format!("Error of deleting directory.\n{}", e));
+                return;
+            }
+            rc.update_ui();
+        });
+    }
+
     fn connect_signals_directories_list(&self) {
         let rc = self.clone();
         self.directories_list.connect_row_selected(move |_, _| {
-            rc.update_ui();
+            if !rc.updating_directories.get() {
+                rc.update_ui();
+            }
         });
     }
 
+    fn connect_signals_filter_entry(&self) {
+        use gtk::EntryExt;
+
+        {
+            let rc = self.clone();
+            self.filter_entry.connect_activate(move |_| {
+                rc.update_ui();
+            });
+        }
+        {
+            let rc = self.clone();
+            self.filter_entry.connect_changed(move |entry| {
+                if entry.get_text().map(|text| text.is_empty()).unwrap_or(true) {
+                    rc.update_ui();
+                }
+            });
+        }
+    }
+
     fn connect_signals_tickets_tree_view(&self) {
         use gtk::{TreeViewSignals, TreeModelExt};
 
@@ -473,7 +1086,8 @@ impl MainUI {
                 let doc_id = tree_model.get_value(&tree_iter, 0)
                     .get::<i64>()
                     .unwrap();
-                match Document::get_by_id(&rc.db.borrow(), doc_id) {
+                let res = rc.storage.borrow().document_by_id(doc_id);
+                match res {
                     Ok(doc) => {
                         edit_ticket::EditTicket::new(rc.clone(), doc);
                     }
@@ -489,10 +1103,25 @@ impl MainUI {
         });
     }
 
+    fn connect_signals_tickets_scrolled_window(&self) {
+        if let Some(adjustment) = self.tickets_scrolled_window.get_vadjustment() {
+            let rc = self.clone();
+            adjustment.connect_value_changed(move |adjustment| {
+                let rest = adjustment.get_upper() - adjustment.get_value() -
+                           adjustment.get_page_size();
+                if rest < adjustment.get_page_size() {
+                    rc.load_tickets_page();
+                }
+            });
+        }
+    }
+
     fn connect_signals_window(&self) {
         use gtk::WidgetExt;
 
-        self.window.connect_delete_event(|_, _| {
+        let rc = self.clone();
+        self.window.connect_delete_event(move |_, _| {
+            rc.end_session();
             gtk::main_quit();
             gtk::Inhibit(false)
         });
@@ -517,6 +1146,10 @@ impl MainUI {
         self.file_menu_item.set_submenu(Some(&self.file_menu));
         self.file_menu.append(&self.print_user_menu_item);
         self.file_menu.append(&self.log_in_menu_item);
+        self.file_menu.append(&self.log_out_menu_item);
+        self.file_menu.append(&self.export_ical_menu_item);
+        self.file_menu.append(&self.subscribe_ical_menu_item);
+        self.file_menu.append(&self.import_directory_menu_item);
         self.file_menu.append(&self.quit_menu_item);
     }
 
@@ -526,6 +1159,10 @@ impl MainUI {
         self.root_menu_item.set_submenu(Some(&self.root_menu));
         self.root_menu.append(&self.user_administration_menu_item);
         self.root_menu.append(&self.category_administration_menu_item);
+        self.root_menu.append(&self.export_archive_menu_item);
+        self.root_menu.append(&self.import_archive_menu_item);
+        self.root_menu.append(&self.backup_menu_item);
+        self.root_menu.append(&self.restore_backup_menu_item);
     }
 
     fn pack_menu_bar(&self) {
@@ -540,6 +1177,9 @@ impl MainUI {
 
         self.toolbar.add(&self.create_ticket_t_button);
         self.toolbar.add(&self.create_directory_t_button);
+        self.toolbar.add(&self.delete_directory_t_button);
+        self.toolbar.add(&self.agenda_t_button);
+        self.toolbar.add(&self.notifications_t_button);
         self.toolbar.add(&gtk::SeparatorToolItem::new());
         self.toolbar.add(&self.open_ticket_t_button);
         self.toolbar.add(&self.edit_access_t_button);
@@ -559,10 +1199,10 @@ impl MainUI {
         use gtk::BoxExt;
 
         self.h_box.pack_start(&self.dir_cal_box, false, false, 0);
-        self.h_box.pack_start(&self.tickets_scrolled_window, true, true, 0);
+        self.h_box.pack_start(&self.tickets_v_box, true, true, 0);
 
         self.pack_dir_cal_box();
-        self.pack_tickets_scrolled_window();
+        self.pack_tickets_v_box();
     }
 
     fn pack_dir_cal_box(&self) {
@@ -570,6 +1210,17 @@ impl MainUI {
 
         self.dir_cal_box.pack_start(&self.directories_list, true, true, 0);
         self.dir_cal_box.pack_start(&self.calendar, false, false, 0);
+        self.dir_cal_box.pack_start(&self.calendar_span_combobox, false, false, 0);
+    }
+
+    fn pack_tickets_v_box(&self) {
+        use gtk::BoxExt;
+
+        self.tickets_v_box.pack_start(&self.filter_entry, false, false, 0);
+        self.tickets_v_box.pack_start(&self.filter_error_label, false, false, 0);
+        self.tickets_v_box.pack_start(&self.tickets_scrolled_window, true, true, 0);
+
+        self.pack_tickets_scrolled_window();
     }
 
     fn pack_tickets_scrolled_window(&self) {
@@ -586,6 +1237,225 @@ impl MainUI {
         self.window.show_all();
     }
 
+    /// Back up the database when the last backup is old, it's checked every ten minutes. Only
+    /// the SQLite database is backed up, a server or PostgreSQL is backed up by its admins.
+    fn schedule_backups(&self) {
+        let rc = self.clone();
+        let backup_if_due = move || {
+            let res = match rc.local_db() {
+                Some(db) => {
+                    backup::default_dir().and_then(|dir| {
+                        if backup::is_due(&dir)? {
+                            backup::backup(&db, &dir, backup::BACKUPS_KEPT)?;
+                        }
+                        Ok(())
+                    })
+                }
+                None => return gtk::Continue(true),
+            };
+            if let Err(e) = res {
+                utils::show_error_dialog(&rc.window,
+                                         &format!("Error of the scheduled backup.\n{}", e));
+            }
+            gtk::Continue(true)
+        };
+        backup_if_due();
+        gtk::timeout_add_seconds(10 * 60, backup_if_due);
+    }
+
+    /// Check the storage for changes of other users, it's done every few seconds. The versions
+    /// of a server come from `watch_server`.
+    fn watch_changes(&self) {
+        let rc = self.clone();
+        gtk::timeout_add_seconds(CHANGES_CHECK_SECS, move || {
+            if rc.current_user.borrow().name() == "" || rc.locked.get() {
+                return gtk::Continue(true);
+            }
+            let res = match *rc.server_changes.borrow() {
+                // The latest version polled by the thread, if there is a new one.
+                Some(ref receiver) => {
+                    match receiver.try_iter().last() {
+                        Some(res) => res,
+                        None => return gtk::Continue(true),
+                    }
+                }
+                None => rc.storage.borrow().data_version(),
+            };
+            // An unreachable server is checked again later.
+            if let Ok(version) = res {
+                if version != rc.data_version.get() {
+                    rc.data_version.set(version);
+                    rc.refresh();
+                }
+            }
+            gtk::Continue(true)
+        });
+    }
+
+    /// Lock the session after `idle_lock_minutes` of the configuration without key presses,
+    /// clicks and scrolling.
+    fn lock_when_idle(&self) {
+        let minutes = match Config::load().ok().and_then(|config| config.idle_lock_minutes()) {
+            Some(minutes) => minutes as i64,
+            None => return,
+        };
+        self.watch_activity(&self.window);
+        let rc = self.clone();
+        gtk::timeout_add_seconds(IDLE_CHECK_SECS, move || {
+            let logged_in = rc.current_user.borrow().name() != "";
+            let idle = UTC::now().timestamp() - rc.last_activity.get();
+            if logged_in && !rc.locked.get() && idle >= minutes * 60 {
+                rc.lock();
+            }
+            gtk::Continue(true)
+        });
+    }
+
+    /// Activity of the user in the window postpones the lock of the session.
+    pub fn watch_activity<W: gtk::IsA<gtk::Widget>>(&self, window: &W) {
+        use gtk::WidgetExt;
+
+        let rc = self.clone();
+        window.connect_event(move |_, event| {
+            match event.get_event_type() {
+                gdk::EventType::KeyPress |
+                gdk::EventType::ButtonPress |
+                gdk::EventType::Scroll => rc.last_activity.set(UTC::now().timestamp()),
+                _ => {}
+            }
+            gtk::Inhibit(false)
+        });
+    }
+
+    /// Watch the activity in the dialog and hide it while the session is locked, it's forgotten
+    /// when destroyed. Hiding ends `run()` of the dialog, e.g. of a file chooser, as cancelled.
+    pub fn register_dialog(&self, dialog: &gtk::Dialog) {
+        use gtk::WidgetExt;
+
+        self.watch_activity(dialog);
+        self.dialogs.borrow_mut().push(dialog.clone());
+        let dialogs = self.dialogs.clone();
+        dialog.connect_destroy(move |dialog| {
+            dialogs.borrow_mut().retain(|d| d != dialog);
+        });
+    }
+
+    /// Hide the tickets until the current user enters the password again.
+    fn lock(&self) {
+        use gtk::WidgetExt;
+
+        self.locked.set(true);
+        for dialog in self.dialogs.borrow().iter() {
+            dialog.hide();
+        }
+        for edit_ticket in self.edit_tickets.borrow().iter() {
+            edit_ticket.dialog.hide();
+        }
+        if let Some(ref notifications) = *self.notifications.borrow() {
+            notifications.dialog.hide();
+        }
+        self.update_ui();
+        unlock::Unlock::new(self.clone());
+    }
+
+    fn unlock(&self) {
+        use gtk::WidgetExt;
+
+        self.locked.set(false);
+        self.last_activity.set(UTC::now().timestamp());
+        self.update_ui();
+        for edit_ticket in self.edit_tickets.borrow().iter() {
+            edit_ticket.dialog.show();
+        }
+        if let Some(ref notifications) = *self.notifications.borrow() {
+            notifications.dialog.show();
+        }
+        for dialog in self.dialogs.borrow().iter() {
+            dialog.show();
+        }
+    }
+
+    /// The local database or the address of the server.
+    fn connection(&self) -> String {
+        self.server.borrow().clone().unwrap_or_else(|| auth::LOCAL_DATABASE.to_string())
+    }
+
+    /// Record the login of the current user, the previous session is ended.
+    fn start_session(&self) {
+        self.end_session();
+        self.locked.set(false);
+        self.last_activity.set(UTC::now().timestamp());
+        let res = Session::start(&self.db.borrow(),
+                                 self.current_user.borrow().name(),
+                                 &self.connection());
+        match res {
+            Ok(session) => *self.session.borrow_mut() = Some(session),
+            Err(e) => {
+                utils::show_error_dialog(&self.window,
+                                         &format!("Error of recording the session.\n{}", e))
+            }
+        }
+    }
+
+    /// Record the logout of the current user, if anybody is logged in.
+    fn end_session(&self) {
+        let session = self.session.borrow_mut().take();
+        if let Some(mut session) = session {
+            if let Err(e) = session.end(&self.db.borrow()) {
+                utils::show_error_dialog(&self.window,
+                                         &format!("Error of recording the session.\n{}", e));
+            }
+        }
+    }
+
+    /// Record the wrong password in the local database, sessions of servers too.
+    fn record_failed_login(&self, name: &str, connection: &str) {
+        if let Err(e) = Session::record_failed_login(&self.db.borrow(), name, connection) {
+            utils::show_error_dialog(&self.window,
+                                     &format!("Error of recording the failed login.\n{}", e));
+        }
+    }
+
+    /// End the session and close the windows of the current user.
+    fn log_out(&self) {
+        use gtk::WidgetExt;
+
+        self.end_session();
+        self.locked.set(false);
+        *self.server_changes.borrow_mut() = None;
+        // Dialogs of tickets go last, since the other dialogs refer to them.
+        let dialogs = self.dialogs.borrow().clone();
+        for dialog in dialogs {
+            dialog.destroy();
+        }
+        let edit_tickets = self.edit_tickets.borrow().clone();
+        for edit_ticket in edit_tickets {
+            edit_ticket.dialog.destroy();
+        }
+        let notifications = self.notifications.borrow_mut().take();
+        if let Some(notifications) = notifications {
+            notifications.dialog.destroy();
+        }
+        self.current_user.borrow_mut().set(User::default());
+        self.update_ui();
+    }
+
+    /// Show changes of other users without reloading the views.
+    fn refresh(&self) {
+        self.update_directories_list();
+        self.update_calendar_marks();
+        self.update_notifications_count();
+        self.refresh_tickets_tree_view();
+        let edit_tickets = self.edit_tickets.borrow().clone();
+        for edit_ticket in edit_tickets {
+            edit_ticket.refresh();
+        }
+        let notifications = self.notifications.borrow().clone();
+        if let Some(notifications) = notifications {
+            notifications.refresh();
+        }
+    }
+
     pub fn update_ui(&self) {
         self.update_menu_bar();
         self.update_toolbar();
@@ -602,13 +1472,24 @@ impl MainUI {
             self.root_menu_item.hide();
             self.print_user_menu_item.hide();
         }
+        self.log_out_menu_item.set_sensitive(self.current_user.borrow().name() != "");
+        // Deadlines, imports, archives and backups work with the local database only.
+        let local = self.local_db().is_some();
+        self.export_ical_menu_item.set_sensitive(local);
+        self.subscribe_ical_menu_item.set_sensitive(local);
+        self.import_directory_menu_item.set_sensitive(local);
+        self.export_archive_menu_item.set_sensitive(local);
+        self.import_archive_menu_item.set_sensitive(local);
+        self.backup_menu_item.set_sensitive(local);
+        self.restore_backup_menu_item.set_sensitive(local);
     }
 
     fn update_toolbar(&self) {
         use gtk::WidgetExt;
 
-        if self.current_user.borrow().name() != "" {
+        if self.current_user.borrow().name() != "" && !self.locked.get() {
             self.toolbar.show_all();
+            self.notifications_t_button.set_sensitive(self.local_db().is_some());
         } else {
             self.toolbar.hide();
         }
@@ -617,16 +1498,148 @@ impl MainUI {
     fn update_main(&self) {
         use gtk::WidgetExt;
 
-        if self.current_user.borrow().name() != "" {
+        if self.current_user.borrow().name() != "" && !self.locked.get() {
             self.h_box.show_all();
+            self.update_directories_list();
+            self.update_calendar_marks();
+            self.update_notifications_count();
             self.update_tickets_tree_view();
         } else {
             self.h_box.hide();
         }
     }
 
+    /// Reload saved searches of the current user, the selected row is kept.
+    fn update_directories_list(&self) {
+        use gtk::{ContainerExt, WidgetExt};
+
+        let searches = match SavedSearch::get_for_user(&self.db.borrow(),
+                                                       &self.current_user.borrow()) {
+            Ok(searches) => searches,
+            Err(e) => {
+                utils::show_error_dialog(&self.window,
+                                         &format!("Error of reading from database \
+                                                   (`saved_searches` table).\n{}",
+                                                  e));
+                return;
+            }
+        };
+        let changed = {
+            let cached = self.saved_searches.borrow();
+            cached.len() != searches.len() ||
+            cached.iter().zip(searches.iter()).any(|(&(ref old, _), new)| {
+                old.id() != new.id() || old.name() != new.name() || old.query() != new.query()
+            })
+        };
+        if changed {
+            self.updating_directories.set(true);
+            let selected = self.directories_list
+                .get_selected_row()
+                .map(|row| row.get_index())
+                .unwrap_or(2);
+            for row in self.directories_list.get_children() {
+                self.directories_list.remove(&row);
+            }
+            let mut entries = Vec::new();
+            for search in searches {
+                let label = gtk::Label::new(Some(search.name()));
+                label.set_halign(gtk::Align::Start);
+                self.directories_list.insert(&label, -1);
+                entries.push((search, label));
+            }
+            *self.saved_searches.borrow_mut() = entries;
+            self.directories_list.show_all();
+            let selected = if selected < self.saved_searches.borrow().len() as i32 {
+                selected
+            } else {
+                2
+            };
+            self.directories_list.select_row(self.directories_list
+                .get_row_at_index(selected)
+                .as_ref());
+            self.updating_directories.set(false);
+        }
+        self.update_directories_counts();
+    }
+
+    /// Show the number of unread notifications of the current user on the toolbar.
+    fn update_notifications_count(&self) {
+        use gtk::ToolButtonExt;
+
+        let count = match self.local_db() {
+            Some(db) => Notification::count_unread(&db, &self.current_user.borrow()).unwrap_or(0),
+            None => 0,
+        };
+        if count == 0 {
+            self.notifications_t_button.set_label(Some("Notifications"));
+        } else {
+            self.notifications_t_button.set_label(Some(&format!("Notifications ({})", count)));
+        }
+    }
+
+    /// Show the number of documents in every directory, taking the filter bar into account.
+    fn update_directories_counts(&self) {
+        use gtk::WidgetExt;
+
+        let filter = self.filter_query().unwrap_or_default();
+        let current_user = self.current_user.borrow();
+        let context = self.query_context(&current_user);
+        for &(ref search, ref label) in self.saved_searches.borrow().iter() {
+            let name = match search.owner() {
+                Some(owner) if owner != &*current_user => {
+                    format!("{} [{}]", search.name(), owner.name())
+                }
+                _ => search.name().to_string(),
+            };
+            let res = search.count(&**self.storage.borrow(), &filter, &context);
+            match res {
+                Ok(count) => {
+                    label.set_text(&format!("{} ({})", name, count));
+                    label.set_tooltip_text(None);
+                }
+                Err(e) => {
+                    label.set_text(&format!("{} (!)", name));
+                    label.set_tooltip_text(Some(&e.to_string()));
+                }
+            }
+        }
+    }
+
+    /// Mark days of the shown month which have deadlines of the current user.
+    fn update_calendar_marks(&self) {
+        self.calendar.clear_marks();
+        let (year, month, _) = self.calendar.get_date();
+        let (start, end) = Span::Month.bounds(UTC.ymd(year as i32, month + 1, 1).and_hms(0, 0, 0));
+        let mut query = Query::default();
+        query.push(Term {
+            field: Field::Involved,
+            op: Op::Eq,
+            value: Value::Me,
+        });
+        query.push(Term {
+            field: Field::Due,
+            op: Op::Ge,
+            value: Value::Date(start),
+        });
+        query.push(Term {
+            field: Field::Due,
+            op: Op::Lt,
+            value: Value::Date(end),
+        });
+        let current_user = self.current_user.borrow();
+        let res = self.storage
+            .borrow()
+            .list_documents(&query, &self.query_context(&current_user), -1, 0);
+        if let Ok(summaries) = res {
+            for summary in summaries {
+                self.calendar.mark_day(summary.date_expired().day());
+            }
+        }
+    }
+
     fn update_tickets_tree_view(&self) {
         self.tickets_list_store.clear();
+        *self.tickets_query.borrow_mut() = None;
         self.setup_tickets_list_store();
     }
 }
@@ -1,4 +1,4 @@
-//! Dialog of creating new comment.
+//! Dialog of creating new comment or editing existing one.
 //!
 //! TODO Write docs.
 
@@ -6,11 +6,19 @@ use gtk;
 
 use super::edit_ticket::EditTicket;
 use super::utils::show_error_dialog;
-use comment::Comment;
+use sed_bad_core::comment::Comment;
+use sed_bad_core::annotation::{Annotation, Anchor};
+use sed_bad_core::errors::DbResult;
 
 #[derive(Clone)]
 pub struct NewComment {
     edit_ticket: EditTicket,
+    // Comment which the new one replies to.
+    parent_id: Option<i64>,
+    // Comment being edited, `None` for a new comment.
+    comment_id: Option<i64>,
+    // Part of the content which the new comment annotates.
+    anchor: Option<Anchor>,
     dialog: gtk::Dialog,
 
     text_view: gtk::TextView,
@@ -22,10 +30,48 @@ pub struct NewComment {
 }
 
 impl NewComment {
-    pub fn new(edit_ticket: EditTicket) -> Self {
+    pub fn new(edit_ticket: EditTicket, parent_id: Option<i64>) -> Self {
+        NewComment::with_title(edit_ticket,
+                               parent_id,
+                               None,
+                               None,
+                               if parent_id.is_some() {
+                                   "Reply"
+                               } else {
+                                   "New comment"
+                               })
+    }
+
+    /// Dialog of editing the comment, the text is prefilled.
+    pub fn edit(edit_ticket: EditTicket, comment: &Comment) -> Self {
+        let tmp = NewComment::with_title(edit_ticket,
+                                         comment.parent_id(),
+                                         Some(comment.id()),
+                                         None,
+                                         "Edit comment");
+        if let Some(buffer) = tmp.text_view.get_buffer() {
+            buffer.set_text(comment.text());
+        }
+        tmp
+    }
+
+    /// Dialog of annotating a part of the content, the comment starts a topic.
+    pub fn annotate(edit_ticket: EditTicket, anchor: Anchor) -> Self {
+        NewComment::with_title(edit_ticket, None, None, Some(anchor), "Annotate")
+    }
+
+    fn with_title(edit_ticket: EditTicket,
+                  parent_id: Option<i64>,
+                  comment_id: Option<i64>,
+                  anchor: Option<Anchor>,
+                  title: &str)
+                  -> Self {
         let tmp = NewComment {
             edit_ticket: edit_ticket.clone(),
-            dialog: gtk::Dialog::new_with_buttons(Some("New comment"),
+            parent_id: parent_id,
+            comment_id: comment_id,
+            anchor: anchor,
+            dialog: gtk::Dialog::new_with_buttons(Some(title),
                                                   Some(&edit_ticket.dialog),
                                                   gtk::DIALOG_MODAL,
                                                   &[]),
@@ -53,6 +99,7 @@ impl NewComment {
         use gtk::WindowExt;
 
         self.dialog.set_default_size(500, 600);
+        self.edit_ticket.main_ui.register_dialog(&self.dialog);
     }
 
     fn connect_signals(&self) {
@@ -69,12 +116,70 @@ impl NewComment {
             let start_iter = buffer.get_start_iter();
             let end_iter = buffer.get_end_iter();
             let text = buffer.get_text(&start_iter, &end_iter, false).unwrap();
-            let new_comment = Comment::new(&rc.edit_ticket.main_ui.current_user.borrow(), text);
+            let current_user = rc.edit_ticket.main_ui.current_user.borrow();
 
-            let res = rc.edit_ticket
-                .doc
-                .borrow_mut()
-                .add_comment(&rc.edit_ticket.main_ui.db.borrow(), new_comment);
+            match rc.unknown_mentions(&text) {
+                Ok(ref names) if names.is_empty() => {}
+                Ok(names) => {
+                    show_error_dialog(&rc.dialog,
+                                      &format!("Unknown users are mentioned: {}.",
+                                               names.join(", ")));
+                    return;
+                }
+                Err(e) => {
+                    show_error_dialog(&rc.dialog,
+                                      &format!("Error of reading users from database.\n{}", e));
+                    return;
+                }
+            }
+
+            if let Some(comment_id) = rc.comment_id {
+                let storage = rc.edit_ticket.main_ui.storage.borrow();
+                // The storage notifies mentioned users.
+                let res = storage.comment_by_id(comment_id).and_then(|mut comment| {
+                    storage.edit_comment(&mut comment, &current_user, &text)
+                });
+                match res {
+                    Ok(_) => {
+                        rc.edit_ticket.update_main();
+                        rc.dialog.destroy();
+                    }
+                    Err(e) => {
+                        show_error_dialog(&rc.dialog,
+                                          &format!("Error of editing the comment.\n{}", e));
+                    }
+                }
+                return;
+            }
+
+            let mut new_comment = match rc.parent_id {
+                Some(parent_id) => Comment::new_reply(&current_user, text, parent_id),
+                None => Comment::new(&current_user, text),
+            };
+
+            let res = match rc.edit_ticket.main_ui.local_db() {
+                Some(db) => {
+                    rc.edit_ticket
+                        .doc
+                        .borrow_mut()
+                        .add_comment(&db, new_comment)
+                        .and_then(|id| match rc.anchor {
+                            Some(ref anchor) => {
+                                Annotation::new(id, anchor.clone()).save_to_db(&db)
+                            }
+                            None => Ok(()),
+                        })
+                }
+                None => {
+                    let doc_id = rc.edit_ticket.doc.borrow().id();
+                    rc.edit_ticket
+                        .main_ui
+                        .storage
+                        .borrow()
+                        .add_comment(doc_id, &mut new_comment)
+                        .map(|_| ())
+                }
+            };
             match res {
                 Ok(_) => {
                     rc.edit_ticket.update_main();
@@ -89,6 +194,18 @@ impl NewComment {
         });
     }
 
+    /// Mentioned names which don't belong to any user.
+    fn unknown_mentions(&self, text: &str) -> DbResult<Vec<String>> {
+        let users = self.edit_ticket.main_ui.storage.borrow().users()?;
+        let comment = Comment::new(&self.edit_ticket.main_ui.current_user.borrow(),
+                                   text.to_string());
+        Ok(comment.mentions()
+            .into_iter()
+            .filter(|&name| !users.iter().any(|user| user.name() == name))
+            .map(|name| format!("@{}", name))
+            .collect())
+    }
+
     fn cancel_button_connect(&self) {
         use gtk::{ButtonExt, WidgetExt};
 
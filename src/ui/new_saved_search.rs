@@ -0,0 +1,158 @@
+//! Dialog of saving a filter as new directory (saved search).
+//!
+//! TODO Write docs.
+
+use gtk;
+
+use super::MainUI;
+use super::utils::show_error_dialog;
+use sed_bad_core::query::Query;
+use sed_bad_core::saved_search::SavedSearch;
+
+#[derive(Clone)]
+pub struct NewSavedSearch {
+    main_ui: MainUI,
+    dialog: gtk::Dialog,
+
+    name_entry: gtk::Entry,
+    query_label: gtk::Label,
+    query_entry: gtk::Entry,
+    shared_check_button: gtk::CheckButton,
+
+    button_box: gtk::ButtonBox,
+    ok_button: gtk::Button,
+    cancel_button: gtk::Button,
+}
+
+impl NewSavedSearch {
+    pub fn new(main_ui: MainUI, query: &str) -> Self {
+        let tmp = NewSavedSearch {
+            main_ui: main_ui,
+            dialog: gtk::Dialog::new(),
+
+            name_entry: gtk::Entry::new(),
+            query_label: gtk::Label::new(Some("Query:")),
+            query_entry: gtk::Entry::new(),
+            shared_check_button: gtk::CheckButton::new_with_label("Share with other users"),
+
+            button_box: gtk::ButtonBox::new(gtk::Orientation::Horizontal),
+            ok_button: gtk::Button::new_with_mnemonic("_Ok"),
+            cancel_button: gtk::Button::new_with_mnemonic("_Cancel"),
+        };
+        tmp.setup(query);
+        tmp.connect_signals();
+        tmp.pack_and_show();
+
+        tmp
+    }
+
+    fn setup(&self, query: &str) {
+        self.setup_entries(query);
+        self.setup_query_label();
+        self.setup_button_box();
+        self.setup_dialog();
+    }
+
+    fn setup_entries(&self, query: &str) {
+        use gtk::EntryExt;
+
+        self.name_entry.set_placeholder_text(Some("Name of directory..."));
+        self.query_entry.set_placeholder_text(Some("status:complete author:me"));
+        self.query_entry.set_text(query);
+    }
+
+    fn setup_query_label(&self) {
+        use gtk::WidgetExt;
+
+        self.query_label.set_halign(gtk::Align::Start);
+    }
+
+    fn setup_button_box(&self) {
+        self.button_box.set_layout(gtk::ButtonBoxStyle::Spread);
+    }
+
+    fn setup_dialog(&self) {
+        use gtk::WindowExt;
+
+        self.dialog.set_title("New directory");
+        self.dialog.set_transient_for(Some(&self.main_ui.window));
+        self.dialog.set_modal(true);
+
+        self.dialog.set_default_size(400, 0);
+        self.main_ui.register_dialog(&self.dialog);
+    }
+
+    fn connect_signals(&self) {
+        self.connect_signals_ok_button();
+        self.connect_signals_cancel_button();
+    }
+
+    fn connect_signals_ok_button(&self) {
+        use gtk::{ButtonExt, EntryExt, EditableExt, ToggleButtonExt, WidgetExt};
+
+        let rc: NewSavedSearch = self.clone();
+        self.ok_button.connect_clicked(move |_| {
+            let name = rc.name_entry.get_text().unwrap_or_default();
+            if name.is_empty() {
+                show_error_dialog(&rc.dialog, "Name of the directory is empty.");
+                return;
+            }
+            let query = rc.query_entry.get_text().unwrap_or_default();
+            if let Err(e) = Query::parse(&query) {
+                rc.query_entry.select_region(e.start as i32, e.end as i32);
+                show_error_dialog(&rc.dialog, &format!("Error of query.\n{}", e));
+                return;
+            }
+
+            let mut search = SavedSearch::new(name,
+                                              query,
+                                              &rc.main_ui.current_user.borrow(),
+                                              rc.shared_check_button.get_active());
+            match search.save_to_db(&rc.main_ui.db.borrow()) {
+                Ok(_) => {
+                    rc.dialog.destroy();
+                    rc.main_ui.update_ui();
+                }
+                Err(e) => {
+                    show_error_dialog(&rc.dialog,
+                                      &format!("Error of saving directory to db.\n{}", e));
+                    return;
+                }
+            }
+        });
+    }
+
+    fn connect_signals_cancel_button(&self) {
+        use gtk::{ButtonExt, WidgetExt};
+
+        let rc = self.clone();
+        self.cancel_button.connect_clicked(move |_| {
+            rc.dialog.destroy();
+        });
+    }
+
+    fn pack_and_show(&self) {
+        self.pack_button_box();
+        self.pack_dialog();
+    }
+
+    fn pack_button_box(&self) {
+        use gtk::ContainerExt;
+
+        self.button_box.add(&self.ok_button);
+        self.button_box.add(&self.cancel_button);
+    }
+
+    fn pack_dialog(&self) {
+        use gtk::{DialogExt, BoxExt, WidgetExt};
+
+        let area = self.dialog.get_content_area();
+        area.pack_start(&self.name_entry, false, false, 10);
+        area.pack_start(&self.query_label, false, false, 10);
+        area.pack_start(&self.query_entry, false, false, 10);
+        area.pack_start(&self.shared_check_button, false, false, 10);
+        area.pack_start(&self.button_box, false, false, 10);
+
+        self.dialog.show_all();
+    }
+}
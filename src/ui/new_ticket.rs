@@ -3,15 +3,15 @@
 //! TODO Write docs.
 
 use gtk;
-use rusqlite;
+use chrono::Datelike;
 
 use super::MainUI;
-use super::utils::show_error_dialog;
-use category::Category;
-use user::User;
-use comment::Comment;
-use document::Document;
-use errors::DbError;
+use super::utils::{show_error_dialog, calendar_date};
+use sed_bad_core::category::Category;
+use sed_bad_core::user::User;
+use sed_bad_core::comment::Comment;
+use sed_bad_core::document::Document;
+use sed_bad_core::errors::DbError;
 
 #[derive(Clone)]
 pub struct NewTicket {
@@ -103,7 +103,8 @@ impl NewTicket {
     }
 
     fn setup_category_combobox(&self) {
-        match Category::get_categories(&self.main_ui.db.borrow()) {
+        let res = self.main_ui.storage.borrow().categories();
+        match res {
             Ok(categories) => {
                 for category in categories {
                     self.category_combobox.append_text(category.name());
@@ -123,7 +124,8 @@ impl NewTicket {
     }
 
     fn setup_responsible_combobox(&self) {
-        match User::get_users(&self.main_ui.db.borrow()) {
+        let res = self.main_ui.storage.borrow().users();
+        match res {
             Ok(users) => {
                 for user in users {
                     self.responsible_combobox.append_text(user.name());
@@ -144,6 +146,7 @@ impl NewTicket {
         self.dialog.set_modal(true);
 
         self.dialog.set_default_size(500, 600);
+        self.main_ui.register_dialog(&self.dialog);
     }
 
     fn connect_signals(&self) {
@@ -168,30 +171,20 @@ impl NewTicket {
                 show_error_dialog(&rc.dialog, "Category is empty or not selected.");
                 return;
             }
-            match Category::get_category(&rc.main_ui.db.borrow(), &category_str) {
+            let storage = rc.main_ui.storage.borrow();
+            match storage.category_by_name(&category_str) {
                 Ok(category_row) => {
                     category = category_row;
                 }
-                Err(DbError::SqliteError(e)) => {
-                    match e {
-                        rusqlite::Error::QueryReturnedNoRows => {
-                            let mut new_category: Category = Category::new(category_str);
-                            match new_category.save_to_db(&rc.main_ui.db.borrow()) {
-                                Ok(_) => {
-                                    category = new_category;
-                                }
-                                Err(e) => {
-                                    show_error_dialog(&rc.dialog,
-                                                      &format!("Error of creating new category \
-                                                                in database.\n{}",
-                                                               e));
-                                    return;
-                                }
-                            }
+                Err(DbError::NotFound) => {
+                    let mut new_category: Category = Category::new(category_str);
+                    match storage.add_category(&mut new_category) {
+                        Ok(_) => {
+                            category = new_category;
                         }
-                        _ => {
+                        Err(e) => {
                             show_error_dialog(&rc.dialog,
-                                              &format!("Error of searching category in \
+                                              &format!("Error of creating new category in \
                                                         database.\n{}",
                                                        e));
                             return;
@@ -206,10 +199,13 @@ impl NewTicket {
                     return;
                 }
             }
-            let expired_date = rc.expired_calendar.get_date();
+            let expired_date = calendar_date(&rc.expired_calendar);
+            let expired_date = (expired_date.year() as u32,
+                                expired_date.month(),
+                                expired_date.day());
             let responsible_user: User;
             if let Some(responsible_user_str) = rc.responsible_combobox.get_active_text() {
-                match User::get_user(&rc.main_ui.db.borrow(), &responsible_user_str) {
+                match storage.user_by_name(&responsible_user_str) {
                     Ok(res) => {
                         responsible_user = res;
                     }
@@ -241,7 +237,8 @@ impl NewTicket {
                                              responsible_user,
                                              expired_date,
                                              comment);
-            match document.save_to_db(&rc.main_ui.db.borrow()) {
+            let res = storage.add_document(&mut document);
+            match res {
                 Ok(_) => {
                     rc.dialog.destroy();
                     rc.main_ui.update_ui();
@@ -0,0 +1,259 @@
+//! Dialog of notifications of the current user about mentions in comments.
+//!
+//! TODO Write docs.
+
+use gtk;
+
+use super::MainUI;
+use super::utils::show_error_dialog;
+use super::edit_ticket::EditTicket;
+use sed_bad_core::document::Document;
+use sed_bad_core::notification::Notification;
+
+#[derive(Clone)]
+pub struct Notifications {
+    main_ui: MainUI,
+
+    pub dialog: gtk::Dialog,
+
+    scrolled_window: gtk::ScrolledWindow,
+    tree_view: gtk::TreeView,
+    list_store: gtk::ListStore,
+
+    time_column: gtk::TreeViewColumn,
+    author_column: gtk::TreeViewColumn,
+    doc_column: gtk::TreeViewColumn,
+
+    time_cell: gtk::CellRendererText,
+    author_cell: gtk::CellRendererText,
+    doc_cell: gtk::CellRendererText,
+
+    button_box: gtk::ButtonBox,
+    mark_all_read_button: gtk::Button,
+}
+
+impl Notifications {
+    pub fn new(main_ui: MainUI) -> Self {
+        let tmp = Notifications {
+            main_ui: main_ui.clone(),
+
+            dialog: gtk::Dialog::new_with_buttons(Some("Notifications"),
+                                                  Some(&main_ui.window),
+                                                  gtk::DIALOG_MODAL,
+                                                  &[("Close", gtk::ResponseType::Close.into())]),
+
+            scrolled_window: gtk::ScrolledWindow::new(None, None),
+            tree_view: gtk::TreeView::new(),
+            list_store: gtk::ListStore::new(&[gtk::Type::I64, // Id.
+                                              gtk::Type::I64, // Id of document.
+                                              gtk::Type::String, // Time.
+                                              gtk::Type::String, // Author of comment.
+                                              gtk::Type::String, // Name of document.
+                                              gtk::Type::I32]), // Font weight.
+
+            time_column: gtk::TreeViewColumn::new(),
+            author_column: gtk::TreeViewColumn::new(),
+            doc_column: gtk::TreeViewColumn::new(),
+
+            time_cell: gtk::CellRendererText::new(),
+            author_cell: gtk::CellRendererText::new(),
+            doc_cell: gtk::CellRendererText::new(),
+
+            button_box: gtk::ButtonBox::new(gtk::Orientation::Horizontal),
+            mark_all_read_button: gtk::Button::new_with_mnemonic("_Mark all as read"),
+        };
+        tmp.setup();
+        tmp.connect_signals();
+        tmp.pack_and_show();
+        *main_ui.notifications.borrow_mut() = Some(tmp.clone());
+
+        tmp
+    }
+
+    fn setup(&self) {
+        self.setup_columns();
+        self.setup_list_store();
+        self.setup_dialog();
+    }
+
+    fn setup_columns(&self) {
+        self.time_column.set_title("Time");
+        self.author_column.set_title("Mentioned by");
+        self.doc_column.set_title("Ticket");
+
+        self.time_column.set_resizable(true);
+        self.author_column.set_resizable(true);
+        self.doc_column.set_resizable(true);
+
+        self.time_column.pack_start(&self.time_cell, true);
+        self.author_column.pack_start(&self.author_cell, true);
+        self.doc_column.pack_start(&self.doc_cell, true);
+
+        self.time_column.add_attribute(&self.time_cell, "text", 2);
+        self.author_column.add_attribute(&self.author_cell, "text", 3);
+        self.doc_column.add_attribute(&self.doc_cell, "text", 4);
+
+        // Unread notifications are bold.
+        self.time_column.add_attribute(&self.time_cell, "weight", 5);
+        self.author_column.add_attribute(&self.author_cell, "weight", 5);
+        self.doc_column.add_attribute(&self.doc_cell, "weight", 5);
+
+        self.tree_view.append_column(&self.time_column);
+        self.tree_view.append_column(&self.author_column);
+        self.tree_view.append_column(&self.doc_column);
+    }
+
+    fn setup_list_store(&self) {
+        match Notification::get_for_user(&self.main_ui.db.borrow(),
+                                         &self.main_ui.current_user.borrow()) {
+            Ok(notifications) => {
+                for notification in notifications {
+                    self.insert_row(None, &notification);
+                }
+                self.tree_view.set_model(Some(&self.list_store));
+            }
+            Err(e) => {
+                show_error_dialog(&self.dialog,
+                                  &format!("Error of reading from database (`notifications` \
+                                            table).\n{}",
+                                           e));
+            }
+        }
+    }
+
+    fn insert_row(&self, position: Option<u32>, notification: &Notification) {
+        self.list_store.insert_with_values(position,
+                                           &[0, 1, 2, 3, 4, 5],
+                                           &[&notification.id(),
+                                             &notification.doc_id(),
+                                             &notification.c_time().to_rfc2822(),
+                                             &notification.author(),
+                                             &notification.doc_name(),
+                                             &weight(notification)]);
+    }
+
+    /// Add new notifications and update read ones in place, the selection is kept.
+    pub fn refresh(&self) {
+        use gtk::{TreeModelExt, ToValue};
+
+        let res = Notification::get_for_user(&self.main_ui.db.borrow(),
+                                             &self.main_ui.current_user.borrow());
+        let notifications = match res {
+            Ok(notifications) => notifications,
+            Err(_) => return,
+        };
+        let mut rows = Vec::new();
+        if let Some(iter) = self.list_store.get_iter_first() {
+            loop {
+                rows.push((self.list_store.get_value(&iter, 0).get::<i64>().unwrap_or(0),
+                           iter.clone()));
+                if !self.list_store.iter_next(&iter) {
+                    break;
+                }
+            }
+        }
+        for (position, notification) in notifications.iter().enumerate() {
+            match rows.iter().find(|&&(id, _)| id == notification.id()) {
+                Some(&(_, ref iter)) => {
+                    self.list_store.set_value(iter, 5, &weight(notification).to_value())
+                }
+                None => self.insert_row(Some(position as u32), notification),
+            }
+        }
+    }
+
+    fn setup_dialog(&self) {
+        use gtk::WindowExt;
+
+        self.dialog.set_default_size(600, 400);
+        self.main_ui.watch_activity(&self.dialog);
+    }
+
+    fn connect_signals(&self) {
+        self.connect_signals_dialog();
+        self.connect_signals_tree_view();
+        self.connect_signals_mark_all_read_button();
+    }
+
+    fn connect_signals_dialog(&self) {
+        use gtk::{DialogExt, WidgetExt};
+
+        let rc = self.clone();
+        self.dialog.connect_response(move |dialog, _| {
+            *rc.main_ui.notifications.borrow_mut() = None;
+            rc.main_ui.update_notifications_count();
+            dialog.destroy();
+        });
+    }
+
+    /// Open the ticket of the notification, if the user still may read it.
+    fn connect_signals_tree_view(&self) {
+        use gtk::{TreeViewSignals, TreeModelExt, ToValue};
+
+        let rc = self.clone();
+        self.tree_view.connect_row_activated(move |_, _, _| {
+            if let Some((tree_model, tree_iter)) = rc.tree_view.get_selection().get_selected() {
+                let id = tree_model.get_value(&tree_iter, 0).get::<i64>().unwrap_or(0);
+                let doc_id = tree_model.get_value(&tree_iter, 1).get::<i64>().unwrap_or(0);
+                let db = rc.main_ui.db.borrow();
+                let res = Notification::mark_read(&db, id)
+                    .and_then(|_| Document::get_by_id(&db, doc_id));
+                match res {
+                    Ok(doc) => {
+                        rc.list_store.set_value(&tree_iter, 5, &400i32.to_value());
+                        if rc.main_ui.current_user.borrow().is_read_grant(&doc) {
+                            EditTicket::new(rc.main_ui.clone(), doc);
+                        } else {
+                            show_error_dialog(&rc.dialog, "You can't read this ticket now.");
+                        }
+                    }
+                    Err(e) => {
+                        show_error_dialog(&rc.dialog,
+                                          &format!("Error of reading the ticket from \
+                                                    database.\n{}",
+                                                   e));
+                    }
+                }
+            }
+        });
+    }
+
+    fn connect_signals_mark_all_read_button(&self) {
+        use gtk::ButtonExt;
+
+        let rc = self.clone();
+        self.mark_all_read_button.connect_clicked(move |_| {
+            let res = Notification::mark_all_read(&rc.main_ui.db.borrow(),
+                                                  &rc.main_ui.current_user.borrow());
+            match res {
+                Ok(_) => {
+                    rc.list_store.clear();
+                    rc.setup_list_store();
+                }
+                Err(e) => {
+                    show_error_dialog(&rc.dialog,
+                                      &format!("Error of marking notifications as read.\n{}",
+                                               e));
+                }
+            }
+        });
+    }
+
+    fn pack_and_show(&self) {
+        use gtk::{ContainerExt, DialogExt, BoxExt, WidgetExt};
+
+        self.scrolled_window.add(&self.tree_view);
+        self.button_box.add(&self.mark_all_read_button);
+
+        let area = self.dialog.get_content_area();
+        area.pack_start(&self.scrolled_window, true, true, 5);
+        area.pack_start(&self.button_box, false, false, 5);
+
+        self.dialog.show_all();
+    }
+}
+
+/// Unread notifications are bold.
+fn weight(notification: &Notification) -> i32 {
+    if notification.read() { 400 } else { 700 }
+}
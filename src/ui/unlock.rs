@@ -0,0 +1,120 @@
+//! Dialog of the session locked after inactivity.
+//!
+//! The current user enters the password again to continue, or logs out so another user may log
+//! in.
+
+use gtk;
+
+use super::MainUI;
+use super::auth::AuthUI;
+use sed_bad_core::user::User;
+
+#[derive(Clone)]
+pub struct Unlock {
+    main_ui: MainUI,
+    dialog: gtk::Dialog,
+
+    info_bar: gtk::InfoBar,
+    info_label: gtk::Label,
+    label: gtk::Label,
+    pass_entry: gtk::Entry,
+}
+
+impl Unlock {
+    pub fn new(main_ui: MainUI) -> Self {
+        let tmp = Unlock {
+            main_ui: main_ui.clone(),
+            dialog: gtk::Dialog::new_with_buttons(Some("Session locked"),
+                                                  Some(&main_ui.window),
+                                                  gtk::DIALOG_MODAL,
+                                                  &[("_Unlock", gtk::ResponseType::Ok.into()),
+                                                    ("_Log out",
+                                                     gtk::ResponseType::Reject.into())]),
+
+            info_bar: gtk::InfoBar::new(),
+            info_label: gtk::Label::new(None),
+            label: gtk::Label::new(Some(&format!("The session of {} is locked after \
+                                                  inactivity. Enter the password to continue.",
+                                                 main_ui.current_user.borrow().name()))),
+            pass_entry: gtk::Entry::new(),
+        };
+        tmp.setup();
+        tmp.connect_signals();
+        tmp.pack_and_show();
+
+        tmp
+    }
+
+    fn setup(&self) {
+        use gtk::{EntryExt, WidgetExt, WindowExt};
+
+        self.info_bar.set_message_type(gtk::MessageType::Error);
+        self.info_bar.set_no_show_all(true);
+        self.label.set_line_wrap(true);
+        self.pass_entry.set_visibility(false);
+        self.pass_entry.set_input_purpose(gtk::InputPurpose::Password);
+        self.dialog.set_position(gtk::WindowPosition::CenterOnParent);
+    }
+
+    fn connect_signals(&self) {
+        use gtk::{DialogExt, EntryExt, WidgetExt};
+
+        {
+            let rc = self.clone();
+            self.pass_entry.connect_activate(move |_| {
+                rc.dialog.response(gtk::ResponseType::Ok.into());
+            });
+        }
+        // The session stays locked until the password or the logout.
+        self.dialog.connect_delete_event(|_, _| gtk::Inhibit(true));
+        let rc = self.clone();
+        self.dialog.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Ok.into() {
+                rc.unlock();
+            } else if response == gtk::ResponseType::Reject.into() {
+                dialog.destroy();
+                rc.main_ui.log_out();
+                AuthUI::init(rc.main_ui.clone());
+            }
+        });
+    }
+
+    /// Check the password of the current user, failed attempts are recorded.
+    fn unlock(&self) {
+        use gtk::{EntryExt, WidgetExt};
+
+        let pass = self.pass_entry.get_text().unwrap_or_default();
+        let name = self.main_ui.current_user.borrow().name().to_string();
+        let user = User::new(name.as_str(), pass.as_str());
+        let res = self.main_ui.storage.borrow().user_exists(&user);
+        match res {
+            Ok(true) => {
+                self.dialog.destroy();
+                self.main_ui.unlock();
+            }
+            Ok(false) => {
+                self.main_ui.record_failed_login(&name, &self.main_ui.connection());
+                self.pass_entry.set_text("");
+                self.info_label.set_label("Wrong password.");
+                self.info_bar.show();
+            }
+            Err(e) => {
+                self.info_label.set_label(&format!("Error of checking the password.\n{}", e));
+                self.info_bar.show();
+            }
+        }
+    }
+
+    fn pack_and_show(&self) {
+        use gtk::{BoxExt, ContainerExt, DialogExt, WidgetExt};
+
+        self.info_bar.add(&self.info_label);
+        self.info_label.show();
+        let area = self.dialog.get_content_area();
+        area.pack_start(&self.info_bar, false, false, 0);
+        area.pack_start(&self.label, false, false, 5);
+        area.pack_start(&self.pass_entry, false, false, 5);
+
+        self.dialog.show_all();
+    }
+}
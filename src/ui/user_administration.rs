@@ -5,7 +5,7 @@
 use gtk;
 
 use super::MainUI;
-use user::User;
+use sed_bad_core::user::User;
 use super::utils::show_error_dialog;
 use super::edit_user::EditUser;
 
@@ -139,7 +139,8 @@ impl UserAdministration {
     }
 
     fn setup_list_users_store(&self) {
-        match User::get_users(&self.main_ui.db.borrow()) {
+        let res = self.main_ui.storage.borrow().users();
+        match res {
             Ok(users) => {
                 for user in users {
                     self.list_users_store.insert_with_values(None,
@@ -168,6 +169,7 @@ impl UserAdministration {
 
         self.dialog.set_default_size(500, 600);
         self.dialog.set_focus(Some(&self.list_users_tree_view));
+        self.main_ui.register_dialog(&self.dialog);
     }
 
     fn connect_signals(&self) {
@@ -198,7 +200,8 @@ impl UserAdministration {
         self.new_user_entry.connect_activate(move |_| {
             if let Some(new_username) = rc.new_user_entry.get_text() {
                 let mut new_user = User::new(new_username.clone(), new_username);
-                match new_user.save_to_db(&rc.main_ui.db.borrow()) {
+                let res = rc.main_ui.storage.borrow().add_user(&mut new_user);
+                match res {
                     Ok(_) => {
                         rc.list_users_store.insert_with_values(None,
                                                                &[0, 1, 2, 3],
@@ -233,7 +236,8 @@ impl UserAdministration {
                     .map(|id| id.parse().unwrap())
                     .unwrap();
                 if user_id != 1 {
-                    match User::delete_by_id(&rc.main_ui.db.borrow(), user_id) {
+                    let res = rc.main_ui.storage.borrow().delete_user(user_id);
+                    match res {
                         Ok(_) => {
                             rc.list_users_store.remove(&tree_iter);
                             rc.update_ui();
@@ -3,6 +3,8 @@
 //! TODO Write docs.
 
 use gtk;
+use gdk_pixbuf::{Pixbuf, PixbufLoader};
+use chrono::{DateTime, UTC, TimeZone, Datelike};
 
 pub fn show_error_dialog<W: gtk::IsA<gtk::Window>>(parent: &W, message: &str) {
     use gtk::{DialogExt, WidgetExt};
@@ -15,3 +17,68 @@ pub fn show_error_dialog<W: gtk::IsA<gtk::Window>>(parent: &W, message: &str) {
     error_dialog.run();
     error_dialog.destroy();
 }
+
+pub fn show_info_dialog<W: gtk::IsA<gtk::Window>>(parent: &W, message: &str) {
+    use gtk::{DialogExt, WidgetExt};
+
+    let info_dialog = gtk::MessageDialog::new(Some(parent),
+                                              gtk::DIALOG_MODAL,
+                                              gtk::MessageType::Info,
+                                              gtk::ButtonsType::Ok,
+                                              message);
+    info_dialog.run();
+    info_dialog.destroy();
+}
+
+/// Ask the question, `true` if the user answers yes.
+pub fn ask_confirmation<W: gtk::IsA<gtk::Window>>(parent: &W, message: &str) -> bool {
+    use gtk::{DialogExt, WidgetExt};
+
+    let question_dialog = gtk::MessageDialog::new(Some(parent),
+                                                  gtk::DIALOG_MODAL,
+                                                  gtk::MessageType::Question,
+                                                  gtk::ButtonsType::YesNo,
+                                                  message);
+    let response = question_dialog.run();
+    question_dialog.destroy();
+    response == gtk::ResponseType::Yes.into()
+}
+
+/// Date selected in the calendar. Months of `gtk::Calendar` are counted from zero.
+pub fn calendar_date(calendar: &gtk::Calendar) -> DateTime<UTC> {
+    let (year, month, day) = calendar.get_date();
+    UTC.ymd(year as i32, month + 1, day).and_hms(0, 0, 0)
+}
+
+pub fn select_calendar_date(calendar: &gtk::Calendar, date: DateTime<UTC>) {
+    calendar.select_month(date.month() - 1, date.year() as u32);
+    calendar.select_day(date.day());
+}
+
+/// Escape text for Pango markup.
+pub fn escape_markup(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Decode the image in memory, `None` if it isn't an image of a known format.
+pub fn pixbuf_from_data(data: &[u8]) -> Option<Pixbuf> {
+    let loader = PixbufLoader::new();
+    let written = loader.loader_write(data);
+    // The loader is closed anyway, otherwise it complains on drop.
+    let closed = loader.close();
+    match (written, closed) {
+        (Ok(()), Ok(())) => loader.get_pixbuf(),
+        _ => None,
+    }
+}